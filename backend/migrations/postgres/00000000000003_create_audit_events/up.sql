@@ -0,0 +1,17 @@
+CREATE TABLE audit_events (
+    id SERIAL PRIMARY KEY,
+    occurred_at TIMESTAMP NOT NULL,
+    actor_user_id INTEGER,
+    action TEXT NOT NULL,
+    target TEXT,
+    ip_address TEXT,
+    user_agent TEXT,
+    outcome TEXT NOT NULL,
+    metadata TEXT NOT NULL DEFAULT '{}',
+    prev_hash TEXT NOT NULL,
+    hash TEXT NOT NULL UNIQUE
+);
+
+CREATE INDEX idx_audit_events_actor_user_id ON audit_events(actor_user_id);
+CREATE INDEX idx_audit_events_action ON audit_events(action);
+CREATE INDEX idx_audit_events_occurred_at ON audit_events(occurred_at);
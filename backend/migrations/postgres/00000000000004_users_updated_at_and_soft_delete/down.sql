@@ -0,0 +1,4 @@
+DROP TRIGGER users_set_updated_at ON users;
+DROP FUNCTION set_updated_at();
+DROP INDEX idx_users_deleted_at;
+ALTER TABLE users DROP COLUMN deleted_at;
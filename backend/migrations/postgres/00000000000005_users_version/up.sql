@@ -0,0 +1,2 @@
+-- Bumped by every update that clients can see; backs the ETag of a user
+ALTER TABLE users ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
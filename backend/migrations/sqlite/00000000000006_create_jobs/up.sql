@@ -0,0 +1,32 @@
+CREATE TABLE jobs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    kind TEXT NOT NULL,
+    payload TEXT NOT NULL,
+    -- pending, running, succeeded or dead
+    status TEXT NOT NULL DEFAULT 'pending',
+    attempts INTEGER NOT NULL DEFAULT 0,
+    max_attempts INTEGER NOT NULL,
+    run_at TIMESTAMP NOT NULL,
+    locked_by TEXT,
+    locked_at TIMESTAMP,
+    last_error TEXT,
+    unique_key TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    finished_at TIMESTAMP
+);
+
+CREATE INDEX idx_jobs_status_run_at ON jobs(status, run_at);
+CREATE INDEX idx_jobs_kind ON jobs(kind);
+
+-- At most one queued or running job per key
+CREATE UNIQUE INDEX idx_jobs_unique_key ON jobs(unique_key)
+    WHERE status IN ('pending', 'running');
+
+CREATE TRIGGER jobs_set_updated_at
+AFTER UPDATE ON jobs
+FOR EACH ROW
+WHEN NEW.updated_at = OLD.updated_at
+BEGIN
+    UPDATE jobs SET updated_at = strftime('%Y-%m-%d %H:%M:%f', 'now') WHERE id = NEW.id;
+END;
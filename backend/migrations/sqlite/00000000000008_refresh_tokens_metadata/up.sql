@@ -0,0 +1,4 @@
+-- Where a session was started from, and when it was last refreshed
+ALTER TABLE refresh_tokens ADD COLUMN ip_address TEXT;
+ALTER TABLE refresh_tokens ADD COLUMN user_agent TEXT;
+ALTER TABLE refresh_tokens ADD COLUMN last_used_at TIMESTAMP;
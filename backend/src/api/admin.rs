@@ -0,0 +1,377 @@
+use axum::{
+    extract::{Path, Query, State},
+    Extension, Json,
+};
+use diesel::prelude::*;
+use validator::{ValidationError, ValidationErrors};
+
+use super::auth::transact;
+use crate::{
+    audit::{self, AuditAction, AuditEntry, ChainVerification, ClientInfo},
+    auth::AuthUser,
+    db::{
+        schema::{audit_events, jobs},
+        Db, DbBackend, DbStats,
+    },
+    error::{AppError, AppResult, ProblemDetails},
+    events::{SessionRevoked, UserDeleted, UserRestored},
+    models::{
+        AuditEvent, AuditEventPage, AuditEventQuery, JobPage, JobQuery, JobRecord, JobResponse,
+        UserResponse,
+    },
+    queue::Queue,
+    scheduler::{JobRegistry, JobStatus},
+    state::AppState,
+};
+
+pub(super) const DEFAULT_PAGE_SIZE: i64 = 50;
+pub(super) const MAX_PAGE_SIZE: i64 = 200;
+
+/// Rows before 1-based `page`; a page too far out to address is a validation
+/// error rather than an overflow
+pub(super) fn page_offset(page: i64, per_page: i64) -> AppResult<i64> {
+    (page - 1).checked_mul(per_page).ok_or_else(|| {
+        let mut errors = ValidationErrors::new();
+        errors.add(
+            "page",
+            ValidationError::new("range").with_message("page is too large".into()),
+        );
+        AppError::Validation(errors)
+    })
+}
+
+/// List audit events, newest first, with optional filters
+#[utoipa::path(
+    get,
+    path = "/api/admin/audit-events",
+    tag = "admin",
+    params(AuditEventQuery),
+    security(("cookie_auth" = []), ("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "One page of audit events", body = AuditEventPage),
+        (status = 401, description = "Missing or invalid access token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Administrator access required", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "page is too large", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+pub async fn list_audit_events(
+    State(db): State<Db>,
+    Query(query): Query<AuditEventQuery>,
+) -> AppResult<Json<AuditEventPage>> {
+    let page = query.page.unwrap_or(1).max(1);
+    let per_page = query
+        .per_page
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    let offset = page_offset(page, per_page)?;
+
+    let (total, events) = db
+        .read(move |conn| {
+            let total: i64 = filtered_audit_events(&query).count().get_result(conn)?;
+
+            let events: Vec<AuditEvent> = filtered_audit_events(&query)
+                .order(audit_events::id.desc())
+                .limit(per_page)
+                .offset(offset)
+                .select(AuditEvent::as_select())
+                .load(conn)?;
+
+            Ok((total, events))
+        })
+        .await?;
+
+    Ok(Json(AuditEventPage {
+        events: events.into_iter().map(Into::into).collect(),
+        page,
+        per_page,
+        total,
+    }))
+}
+
+/// Walk the audit hash chain and report whether it has been tampered with
+#[utoipa::path(
+    get,
+    path = "/api/admin/audit-events/verify",
+    tag = "admin",
+    security(("cookie_auth" = []), ("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "Result of the chain verification", body = ChainVerification),
+        (status = 401, description = "Missing or invalid access token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Administrator access required", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+pub async fn verify_audit_events(State(db): State<Db>) -> AppResult<Json<ChainVerification>> {
+    let verification = db.read(|conn| Ok(audit::verify_chain(conn)?)).await?;
+
+    Ok(Json(verification))
+}
+
+/// Report database connection pool usage and checkout wait times
+#[utoipa::path(
+    get,
+    path = "/api/admin/db/pool",
+    tag = "admin",
+    security(("cookie_auth" = []), ("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "Writer and reader pool usage since startup", body = DbStats),
+        (status = 401, description = "Missing or invalid access token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Administrator access required", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+pub async fn pool_stats(State(db): State<Db>) -> Json<DbStats> {
+    Json(db.stats())
+}
+
+/// Report the last run and next run of every scheduled job
+#[utoipa::path(
+    get,
+    path = "/api/admin/scheduler",
+    tag = "admin",
+    security(("cookie_auth" = []), ("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "Scheduled jobs ordered by name; empty when the scheduler is disabled", body = Vec<JobStatus>),
+        (status = 401, description = "Missing or invalid access token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Administrator access required", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+pub async fn scheduled_jobs(State(jobs): State<JobRegistry>) -> Json<Vec<JobStatus>> {
+    Json(jobs.snapshot())
+}
+
+/// List queued jobs, newest first, with optional filters
+#[utoipa::path(
+    get,
+    path = "/api/admin/jobs",
+    tag = "admin",
+    params(JobQuery),
+    security(("cookie_auth" = []), ("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "One page of jobs", body = JobPage),
+        (status = 401, description = "Missing or invalid access token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Administrator access required", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "page is too large", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+pub async fn list_jobs(
+    State(db): State<Db>,
+    Query(query): Query<JobQuery>,
+) -> AppResult<Json<JobPage>> {
+    let page = query.page.unwrap_or(1).max(1);
+    let per_page = query
+        .per_page
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    let offset = page_offset(page, per_page)?;
+
+    let (total, records) = db
+        .read(move |conn| {
+            let total: i64 = filtered_jobs(&query).count().get_result(conn)?;
+
+            let records: Vec<JobRecord> = filtered_jobs(&query)
+                .order(jobs::id.desc())
+                .limit(per_page)
+                .offset(offset)
+                .select(JobRecord::as_select())
+                .load(conn)?;
+
+            Ok((total, records))
+        })
+        .await?;
+
+    Ok(Json(JobPage {
+        jobs: records.into_iter().map(Into::into).collect(),
+        page,
+        per_page,
+        total,
+    }))
+}
+
+/// Show one queued job, including its last error
+#[utoipa::path(
+    get,
+    path = "/api/admin/jobs/{id}",
+    tag = "admin",
+    params(("id" = i32, Path, description = "Job ID")),
+    security(("cookie_auth" = []), ("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "The job", body = JobResponse),
+        (status = 401, description = "Missing or invalid access token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Administrator access required", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "No job with this ID", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+pub async fn get_job(State(db): State<Db>, Path(id): Path<i32>) -> AppResult<Json<JobResponse>> {
+    let job = db
+        .read(move |conn| {
+            Ok(jobs::table
+                .find(id)
+                .select(JobRecord::as_select())
+                .first(conn)
+                .optional()?)
+        })
+        .await?
+        .ok_or(AppError::NotFound("Job"))?;
+
+    Ok(Json(job.into()))
+}
+
+/// Run a dead job again with a fresh set of attempts
+#[utoipa::path(
+    post,
+    path = "/api/admin/jobs/{id}/retry",
+    tag = "admin",
+    params(("id" = i32, Path, description = "Job ID")),
+    security(("cookie_auth" = []), ("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "The job, pending again", body = JobResponse),
+        (status = 401, description = "Missing or invalid access token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Administrator access required", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "No dead job with this ID", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 409, description = "An equivalent job is already pending or running", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+pub async fn retry_job(
+    State(queue): State<Queue>,
+    Path(id): Path<i32>,
+) -> AppResult<Json<JobResponse>> {
+    let job = queue.retry(id).await?.ok_or(AppError::NotFound("Job"))?;
+
+    Ok(Json(job.into()))
+}
+
+/// Soft-delete a user and revoke their sessions
+#[utoipa::path(
+    delete,
+    path = "/api/admin/users/{id}",
+    tag = "admin",
+    params(("id" = i32, Path, description = "User ID")),
+    security(("cookie_auth" = []), ("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "The deleted user", body = UserResponse),
+        (status = 401, description = "Missing or invalid access token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Administrator access required", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "No live user with this ID", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+pub async fn delete_user(
+    State(state): State<AppState>,
+    Extension(auth_user): Extension<AuthUser>,
+    client: ClientInfo,
+    Path(id): Path<i32>,
+) -> AppResult<Json<UserResponse>> {
+    let admin_id = admin_id(&auth_user)?;
+    let now = state.clock.now().naive_utc();
+    let (users, sessions) = (state.users.clone(), state.sessions.clone());
+    let user = transact(&state, &client, move |tx| {
+        let user = users
+            .soft_delete(tx.conn, id, now)?
+            .ok_or(AppError::NotFound("User"))?;
+        let revoked = sessions.delete_for_user(tx.conn, user.id)?;
+
+        tx.audit(
+            AuditEntry::success(AuditAction::AccountDeleted)
+                .actor(admin_id)
+                .target(user.email.clone())
+                .metadata(serde_json::json!({ "user_id": user.id, "sessions_revoked": revoked })),
+        )?;
+        if revoked > 0 {
+            tx.emit(SessionRevoked {
+                user_id: user.id,
+                sessions: revoked,
+                reason: "account_deleted".to_string(),
+            })?;
+        }
+        tx.emit(UserDeleted {
+            user: user.clone().into(),
+        })?;
+        Ok(user)
+    })
+    .await?;
+
+    Ok(Json(user.into()))
+}
+
+/// Restore a soft-deleted user
+#[utoipa::path(
+    post,
+    path = "/api/admin/users/{id}/restore",
+    tag = "admin",
+    params(("id" = i32, Path, description = "User ID")),
+    security(("cookie_auth" = []), ("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "The restored user", body = UserResponse),
+        (status = 401, description = "Missing or invalid access token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Administrator access required", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "No deleted user with this ID", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+pub async fn restore_user(
+    State(state): State<AppState>,
+    Extension(auth_user): Extension<AuthUser>,
+    client: ClientInfo,
+    Path(id): Path<i32>,
+) -> AppResult<Json<UserResponse>> {
+    let admin_id = admin_id(&auth_user)?;
+    let users = state.users.clone();
+    let user = transact(&state, &client, move |tx| {
+        let user = users
+            .restore(tx.conn, id)?
+            .ok_or(AppError::NotFound("User"))?;
+
+        tx.audit(
+            AuditEntry::success(AuditAction::AccountRestored)
+                .actor(admin_id)
+                .target(user.email.clone())
+                .metadata(serde_json::json!({ "user_id": user.id })),
+        )?;
+        tx.emit(UserRestored {
+            user: user.clone().into(),
+        })?;
+        Ok(user)
+    })
+    .await?;
+
+    Ok(Json(user.into()))
+}
+
+pub(super) fn admin_id(auth_user: &AuthUser) -> AppResult<i32> {
+    auth_user.0.sub.parse().map_err(|_| AppError::InvalidToken)
+}
+
+fn filtered_audit_events(query: &AuditEventQuery) -> audit_events::BoxedQuery<'_, DbBackend> {
+    let mut events = audit_events::table.into_boxed();
+
+    if let Some(actor_user_id) = query.actor_user_id {
+        events = events.filter(audit_events::actor_user_id.eq(actor_user_id));
+    }
+    if let Some(action) = &query.action {
+        events = events.filter(audit_events::action.eq(action));
+    }
+    if let Some(target) = &query.target {
+        events = events.filter(audit_events::target.eq(target));
+    }
+    if let Some(outcome) = &query.outcome {
+        events = events.filter(audit_events::outcome.eq(outcome));
+    }
+    if let Some(since) = query.since {
+        events = events.filter(audit_events::occurred_at.ge(since.naive_utc()));
+    }
+    if let Some(until) = query.until {
+        events = events.filter(audit_events::occurred_at.lt(until.naive_utc()));
+    }
+
+    events
+}
+
+fn filtered_jobs(query: &JobQuery) -> jobs::BoxedQuery<'_, DbBackend> {
+    let mut records = jobs::table.into_boxed();
+
+    if let Some(status) = query.status {
+        records = records.filter(jobs::status.eq(status.as_str()));
+    }
+    if let Some(kind) = &query.kind {
+        records = records.filter(jobs::kind.eq(kind));
+    }
+
+    records
+}
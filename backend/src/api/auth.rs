@@ -1,46 +1,79 @@
 use axum::{
     extract::State,
-    http::{header, StatusCode},
-    response::{IntoResponse, Response},
+    http::{header, HeaderMap, StatusCode},
+    response::{AppendHeaders, IntoResponse, Response},
     Extension, Json,
 };
-use diesel::prelude::*;
+use chrono::{DateTime, Utc};
 use validator::Validate;
 
+use super::{
+    conditional::{IfMatch, IfNoneMatch, Tagged},
+    extract::{JsonBody, OptionalJsonBody},
+};
 use crate::{
-    auth::{create_token, hash_password, verify_password, AuthUser},
-    db::{schema::{refresh_tokens, users}, DbPool},
+    api::conditional::Versioned,
+    audit::{self, AuditAction, AuditEntry, ClientInfo},
+    auth::{
+        csrf::{self, CSRF_COOKIE_MAX_AGE},
+        hash_password, verify_password, AuthUser, IssuedSession, SessionIssuer,
+    },
+    db::{self, DbConnection},
+    error::{AppError, AppResult, ProblemDetails},
+    events::{
+        Event, EventBus, LoginFailed, SessionRevoked, UserEmailChanged, UserLoggedIn,
+        UserRegistered,
+    },
     models::{
-        AuthResponse, LoginRequest, NewRefreshToken, NewUser, RefreshRequest, RegisterRequest,
-        User, UserResponse,
+        AuthResponse, CsrfTokenResponse, LoginRequest, NewUser, RefreshRequest, RegisterRequest,
+        UpdateProfileRequest, User, UserResponse,
     },
+    state::AppState,
 };
 
-const COOKIE_MAX_AGE: i64 = 15 * 60; // 15 minutes in seconds
-
 /// Register a new user
+///
+/// With `X-Refresh-Token-Transport: cookie`, or that transport configured,
+/// the refresh token is set as the `refresh_token` cookie instead of being
+/// returned.
+#[utoipa::path(
+    post,
+    path = "/api/auth/register",
+    tag = "auth",
+    request_body = RegisterRequest,
+    responses(
+        (status = 200, description = "User created and signed in; also sets the `access_token` cookie", body = AuthResponse,
+            headers(("Set-Cookie" = String, description = "HttpOnly `access_token` cookie, and `refresh_token` with the cookie transport"))),
+        (status = 400, description = "Unknown `X-Refresh-Token-Transport`", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 409, description = "Email or username already exists", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Invalid registration data", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
 pub async fn register(
-    State(pool): State<DbPool>,
-    Json(payload): Json<RegisterRequest>,
-) -> Result<Json<AuthResponse>, Response> {
+    State(state): State<AppState>,
+    State(issuer): State<SessionIssuer>,
+    client: ClientInfo,
+    headers: HeaderMap,
+    JsonBody(payload): JsonBody<RegisterRequest>,
+) -> AppResult<IssuedSession> {
+    let transport = issuer.transport(&headers)?;
+
     // Validate input
-    payload.validate().map_err(|e| {
-        (StatusCode::BAD_REQUEST, Json(serde_json::json!({
-            "error": "Validation error",
-            "details": e.to_string()
-        })))
-            .into_response()
-    })?;
+    if let Err(e) = payload.validate() {
+        audit(
+            &state,
+            &client,
+            AuditEntry::failure(AuditAction::Register)
+                .target(payload.email.to_lowercase())
+                .metadata(serde_json::json!({ "reason": "validation_error" })),
+        )
+        .await;
+        return Err(e.into());
+    }
 
     // Hash password
-    let password_hash = hash_password(&payload.password).map_err(|e| {
-        tracing::error!("Failed to hash password: {:?}", e);
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "Failed to process password",
-        )
-            .into_response()
-    })?;
+    let password = payload.password;
+    let password_hash = tokio::task::spawn_blocking(move || hash_password(&password)).await??;
 
     // Create new user
     let new_user = NewUser {
@@ -49,231 +82,502 @@ pub async fn register(
         password_hash,
     };
 
-    let mut conn = pool.get().map_err(|e| {
-        tracing::error!("Failed to get database connection: {:?}", e);
-        (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response()
-    })?;
-
-    // Insert user
-    let user: User = diesel::insert_into(users::table)
-        .values(&new_user)
-        .returning(User::as_select())
-        .get_result(&mut conn)
-        .map_err(|e| {
-            tracing::error!("Failed to insert user: {:?}", e);
-            match e {
-                diesel::result::Error::DatabaseError(
-                    diesel::result::DatabaseErrorKind::UniqueViolation,
-                    _,
-                ) => (StatusCode::CONFLICT, "Email or username already exists").into_response(),
-                _ => (StatusCode::INTERNAL_SERVER_ERROR, "Failed to create user").into_response(),
-            }
+    let locale = state
+        .outbox
+        .locale(
+            headers
+                .get(header::ACCEPT_LANGUAGE)
+                .and_then(|value| value.to_str().ok()),
+        )
+        .to_string();
+
+    // Insert user and start its session, with the audit entry and event
+    let email = new_user.email.clone();
+    let users = state.users.clone();
+    let session_client = client.clone();
+    let result = transact(&state, &client, move |tx| {
+        let user = users.insert(tx.conn, new_user)?;
+        tx.audit(
+            AuditEntry::success(AuditAction::Register)
+                .actor(user.id)
+                .target(user.email.clone()),
+        )?;
+        tx.emit(UserRegistered {
+            user: user.clone().into(),
+            locale,
         })?;
-
-    // Create tokens
-    let auth = create_auth_response(user, &mut conn).await?;
-    Ok(Json(auth))
+        issuer.start(tx.conn, user, &session_client, transport)
+    })
+    .await;
+    match result {
+        Err(e @ AppError::Conflict(_)) => {
+            audit(
+                &state,
+                &client,
+                AuditEntry::failure(AuditAction::Register)
+                    .target(email)
+                    .metadata(serde_json::json!({ "reason": "already_exists" })),
+            )
+            .await;
+            Err(e)
+        }
+        result => result,
+    }
 }
 
 /// Login with email and password
+///
+/// The refresh token is returned as in `register`.
+#[utoipa::path(
+    post,
+    path = "/api/auth/login",
+    tag = "auth",
+    request_body = LoginRequest,
+    responses(
+        (status = 200, description = "Signed in; also sets the `access_token` cookie", body = AuthResponse,
+            headers(("Set-Cookie" = String, description = "HttpOnly `access_token` cookie, and `refresh_token` with the cookie transport"))),
+        (status = 400, description = "Unknown `X-Refresh-Token-Transport`", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Invalid email or password", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Account is disabled", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
 pub async fn login(
-    State(pool): State<DbPool>,
-    Json(payload): Json<LoginRequest>,
-) -> Result<Response, Response> {
-    let mut conn = pool.get().map_err(|e| {
-        tracing::error!("Failed to get database connection: {:?}", e);
-        (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response()
-    })?;
+    State(state): State<AppState>,
+    State(issuer): State<SessionIssuer>,
+    client: ClientInfo,
+    headers: HeaderMap,
+    JsonBody(payload): JsonBody<LoginRequest>,
+) -> AppResult<IssuedSession> {
+    let transport = issuer.transport(&headers)?;
+    let email = payload.email.to_lowercase();
+    let login_failed = |user_id: Option<i32>, reason: &str| {
+        let mut entry = AuditEntry::failure(AuditAction::Login)
+            .target(email.clone())
+            .metadata(serde_json::json!({ "reason": reason }));
+        if let Some(user_id) = user_id {
+            entry = entry.actor(user_id);
+        }
+        let event = LoginFailed {
+            email: email.clone(),
+            user_id,
+            reason: reason.to_string(),
+        };
+        (entry, event)
+    };
 
     // Find user by email
-    let user: User = users::table
-        .filter(users::email.eq(payload.email.to_lowercase()))
-        .select(User::as_select())
-        .first(&mut conn)
-        .map_err(|_| {
-            (
-                StatusCode::UNAUTHORIZED,
-                "Invalid email or password",
-            )
-                .into_response()
-        })?;
+    let users = state.users.clone();
+    let lookup = email.clone();
+    let user = state
+        .db
+        .read(move |conn| users.find_by_email(conn, &lookup))
+        .await?;
+    let Some(user) = user else {
+        let (entry, event) = login_failed(None, "unknown_email");
+        transact(&state, &client, move |tx| {
+            tx.audit(entry)?;
+            tx.emit(event)
+        })
+        .await?;
+        return Err(AppError::InvalidCredentials);
+    };
 
     // Check if user is active
     if !user.is_active {
-        return Err((StatusCode::FORBIDDEN, "Account is disabled").into_response());
+        let (entry, event) = login_failed(Some(user.id), "account_disabled");
+        transact(&state, &client, move |tx| {
+            tx.audit(entry)?;
+            tx.emit(event)
+        })
+        .await?;
+        return Err(AppError::AccountDisabled);
     }
 
     // Verify password
-    let is_valid = verify_password(&payload.password, &user.password_hash).map_err(|e| {
-        tracing::error!("Failed to verify password: {:?}", e);
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "Failed to verify password",
-        )
-            .into_response()
-    })?;
-
-    if !is_valid {
-        return Err((StatusCode::UNAUTHORIZED, "Invalid email or password").into_response());
+    let password = payload.password;
+    let password_hash = user.password_hash.clone();
+    let valid =
+        tokio::task::spawn_blocking(move || verify_password(&password, &password_hash)).await??;
+    if !valid {
+        let (entry, event) = login_failed(Some(user.id), "invalid_password");
+        transact(&state, &client, move |tx| {
+            tx.audit(entry)?;
+            tx.emit(event)
+        })
+        .await?;
+        return Err(AppError::InvalidCredentials);
     }
 
-    // Create tokens and response
-    let auth_response = create_auth_response(user, &mut conn).await?;
-
-    // Create response with cookie
-    Ok(create_response_with_cookie(auth_response))
+    let entry = AuditEntry::success(AuditAction::Login)
+        .actor(user.id)
+        .target(email.clone());
+    let session_client = client.clone();
+    transact(&state, &client, move |tx| {
+        tx.audit(entry)?;
+        tx.emit(UserLoggedIn { user_id: user.id })?;
+        issuer.start(tx.conn, user, &session_client, transport)
+    })
+    .await
 }
 
 /// Refresh access token using refresh token
+///
+/// The token is read from the body, or from the `refresh_token` cookie when
+/// the body leaves it out; the cookie must then pass the CSRF check. It is
+/// returned as in `register`.
+#[utoipa::path(
+    post,
+    path = "/api/auth/refresh",
+    tag = "auth",
+    request_body = RefreshRequest,
+    security((), ("refresh_cookie" = [])),
+    responses(
+        (status = 200, description = "New access token issued; also sets the `access_token` cookie", body = AuthResponse,
+            headers(("Set-Cookie" = String, description = "HttpOnly `access_token` cookie, and `refresh_token` with the cookie transport"))),
+        (status = 400, description = "Unknown `X-Refresh-Token-Transport`", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Refresh token is missing, invalid or expired", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Account is disabled, or the cookie failed the CSRF check", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
 pub async fn refresh(
-    State(pool): State<DbPool>,
-    Json(payload): Json<RefreshRequest>,
-) -> Result<Response, Response> {
-    let mut conn = pool.get().map_err(|e| {
-        tracing::error!("Failed to get database connection: {:?}", e);
-        (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response()
-    })?;
+    State(state): State<AppState>,
+    State(issuer): State<SessionIssuer>,
+    client: ClientInfo,
+    headers: HeaderMap,
+    OptionalJsonBody(payload): OptionalJsonBody<RefreshRequest>,
+) -> AppResult<IssuedSession> {
+    let transport = issuer.transport(&headers)?;
 
     // Find refresh token
-    let refresh_token: crate::models::RefreshToken = refresh_tokens::table
-        .find(&payload.refresh_token)
-        .first(&mut conn)
-        .map_err(|_| {
-            (StatusCode::UNAUTHORIZED, "Invalid refresh token").into_response()
-        })?;
+    let refresh_token = issuer
+        .presented_session(&headers, payload.refresh_token)
+        .await?;
+    let Some(refresh_token) = refresh_token else {
+        audit(
+            &state,
+            &client,
+            AuditEntry::failure(AuditAction::Refresh)
+                .metadata(serde_json::json!({ "reason": "invalid_token" })),
+        )
+        .await;
+        return Err(AppError::InvalidRefreshToken);
+    };
+
+    let refresh_failed = |reason: &str| {
+        AuditEntry::failure(AuditAction::Refresh)
+            .actor(refresh_token.user_id)
+            .metadata(serde_json::json!({ "reason": reason }))
+    };
 
     // Check if token is expired
-    let now = chrono::Utc::now().naive_utc();
-    if refresh_token.expires_at < now {
+    let now = state.clock.now();
+    if refresh_token.expires_at < now.naive_utc() {
         // Delete expired token
-        let _ = diesel::delete(refresh_tokens::table.find(&refresh_token.id))
-            .execute(&mut conn);
-        return Err((StatusCode::UNAUTHORIZED, "Refresh token expired").into_response());
+        let sessions = state.sessions.clone();
+        let (id, user_id) = (refresh_token.id.clone(), refresh_token.user_id);
+        let entry = refresh_failed("token_expired");
+        transact(&state, &client, move |tx| {
+            if sessions.delete(tx.conn, &id)?.is_some() {
+                tx.emit(SessionRevoked {
+                    user_id,
+                    sessions: 1,
+                    reason: "expired".to_string(),
+                })?;
+            }
+            tx.audit(entry)
+        })
+        .await?;
+        return Err(AppError::RefreshTokenExpired);
     }
 
     // Get user
-    let user: User = users::table
-        .find(refresh_token.user_id)
-        .select(User::as_select())
-        .first(&mut conn)
-        .map_err(|_| {
-            (StatusCode::UNAUTHORIZED, "User not found").into_response()
-        })?;
+    let users = state.users.clone();
+    let user_id = refresh_token.user_id;
+    let user = state
+        .db
+        .read(move |conn| users.find_by_id(conn, user_id))
+        .await?;
+    let Some(user) = user else {
+        audit(&state, &client, refresh_failed("user_not_found")).await;
+        return Err(AppError::InvalidRefreshToken);
+    };
 
     // Check if user is active
     if !user.is_active {
-        return Err((StatusCode::FORBIDDEN, "Account is disabled").into_response());
+        audit(&state, &client, refresh_failed("account_disabled")).await;
+        return Err(AppError::AccountDisabled);
     }
 
-    // Create new access token
-    let access_token = create_token(user.id, user.email.clone()).map_err(|e| {
-        tracing::error!("Failed to create token: {:?}", e);
-        (StatusCode::INTERNAL_SERVER_ERROR, "Failed to create token").into_response()
-    })?;
-
-    // Return response with new access token
-    Ok(create_response_with_cookie(AuthResponse {
-        user: user.into(),
-        access_token: access_token.clone(),
-        refresh_token: refresh_token.id,
-    }))
+    let entry = AuditEntry::success(AuditAction::Refresh)
+        .actor(user.id)
+        .metadata(serde_json::json!({ "session_started_at": refresh_token.created_at }));
+    transact(&state, &client, move |tx| {
+        tx.audit(entry)?;
+        issuer.resume(tx.conn, user, &refresh_token, transport)
+    })
+    .await
 }
 
 /// Logout - invalidate refresh token
+///
+/// The token is read as in `refresh`. Both session cookies are cleared.
+#[utoipa::path(
+    post,
+    path = "/api/auth/logout",
+    tag = "auth",
+    request_body = RefreshRequest,
+    security((), ("refresh_cookie" = [])),
+    responses(
+        (status = 204, description = "Refresh token revoked",
+            headers(("Set-Cookie" = String, description = "Expired `access_token` and `refresh_token` cookies"))),
+        (status = 403, description = "The cookie failed the CSRF check", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
 pub async fn logout(
-    State(pool): State<DbPool>,
-    Json(payload): Json<RefreshRequest>,
-) -> Result<StatusCode, Response> {
-    let mut conn = pool.get().map_err(|e| {
-        tracing::error!("Failed to get database connection: {:?}", e);
-        (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response()
-    })?;
-
-    // Delete refresh token
-    diesel::delete(refresh_tokens::table.find(&payload.refresh_token))
-        .execute(&mut conn)
-        .map_err(|e| {
-            tracing::error!("Failed to delete refresh token: {:?}", e);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to logout",
-            )
-                .into_response()
-        })?;
+    State(state): State<AppState>,
+    State(issuer): State<SessionIssuer>,
+    client: ClientInfo,
+    headers: HeaderMap,
+    OptionalJsonBody(payload): OptionalJsonBody<RefreshRequest>,
+) -> AppResult<Response> {
+    // Delete refresh token; its owner becomes the audit event's actor
+    let presented = issuer
+        .presented_session(&headers, payload.refresh_token)
+        .await?;
+    let sessions = state.sessions.clone();
+    transact(&state, &client, move |tx| {
+        let deleted = match presented {
+            Some(session) => sessions.delete(tx.conn, &session.id)?,
+            None => None,
+        };
+
+        let Some(user_id) = deleted.map(|token| token.user_id) else {
+            return tx.audit(
+                AuditEntry::failure(AuditAction::Logout)
+                    .metadata(serde_json::json!({ "reason": "invalid_token" })),
+            );
+        };
+        tx.audit(AuditEntry::success(AuditAction::Logout).actor(user_id))?;
+        tx.emit(SessionRevoked {
+            user_id,
+            sessions: 1,
+            reason: "logout".to_string(),
+        })
+    })
+    .await?;
+
+    let cookies = issuer.end().map(|cookie| (header::SET_COOKIE, cookie));
+    Ok((StatusCode::NO_CONTENT, AppendHeaders(cookies)).into_response())
+}
 
-    Ok(StatusCode::NO_CONTENT)
+/// Get a CSRF token
+///
+/// Requests authenticated by the `access_token` cookie that are not `GET`,
+/// `HEAD` or `OPTIONS` must send the token in the returned header. The token
+/// is bound to the user of the session cookies, so fetch a new one after
+/// signing in. The current token is returned while its cookie is still valid
+/// for that user.
+#[utoipa::path(
+    get,
+    path = "/api/auth/csrf",
+    tag = "auth",
+    responses(
+        (status = 200, description = "The CSRF token; also sets the `csrf_token` cookie", body = CsrfTokenResponse,
+            headers(("Set-Cookie" = String, description = "`csrf_token` cookie"))),
+    )
+)]
+pub async fn csrf_token(
+    State(state): State<AppState>,
+    State(issuer): State<SessionIssuer>,
+    headers: HeaderMap,
+) -> AppResult<Response> {
+    let config = &state.config;
+    let session = issuer.principal(&headers).await?;
+    let token = csrf::cookie_token(config, &state.csrf, &headers, &session)
+        .map(str::to_string)
+        .unwrap_or_else(|| state.csrf.issue(&session));
+    let cookie = config
+        .cookie
+        .named(&config.csrf.cookie_name)
+        .set_cookie(&token, CSRF_COOKIE_MAX_AGE);
+
+    let body = CsrfTokenResponse {
+        token,
+        header: config.csrf.header_name.to_string(),
+    };
+    Ok(([(header::SET_COOKIE, cookie)], Json(body)).into_response())
 }
 
 /// Get current authenticated user
+///
+/// Send the last `ETag` in `If-None-Match` to get `304 Not Modified` while
+/// the profile is unchanged.
+#[utoipa::path(
+    get,
+    path = "/api/auth/me",
+    tag = "auth",
+    security(("cookie_auth" = []), ("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "The signed-in user", body = UserResponse,
+            headers(("ETag" = String, description = "Current version of the profile"))),
+        (status = 304, description = "The profile matches `If-None-Match`"),
+        (status = 401, description = "Missing or invalid access token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "User no longer exists", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
 pub async fn me(
-    State(pool): State<DbPool>,
+    State(state): State<AppState>,
     Extension(auth_user): Extension<AuthUser>,
-) -> Result<Json<UserResponse>, Response> {
-    let user_id: i32 = auth_user.0.sub.parse().map_err(|_| {
-        (StatusCode::UNAUTHORIZED, "Invalid user ID").into_response()
-    })?;
-
-    let mut conn = pool.get().map_err(|e| {
-        tracing::error!("Failed to get database connection: {:?}", e);
-        (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response()
-    })?;
-
-    let user: User = users::table
-        .find(user_id)
-        .select(User::as_select())
-        .first(&mut conn)
-        .map_err(|_| {
-        (StatusCode::NOT_FOUND, "User not found").into_response()
-    })?;
-
-    Ok(Json(user.into()))
+    if_none_match: IfNoneMatch,
+) -> AppResult<Tagged<UserResponse>> {
+    let user = current_user(&state, &auth_user).await?;
+
+    Ok(if_none_match.respond(user.etag(), user.into()))
+}
+
+/// Update the current user's profile
+#[utoipa::path(
+    patch,
+    path = "/api/auth/me",
+    tag = "auth",
+    request_body = UpdateProfileRequest,
+    params(("If-Match" = String, Header, description = "`ETag` of the profile being edited")),
+    security(("cookie_auth" = []), ("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "The updated user", body = UserResponse,
+            headers(("ETag" = String, description = "New version of the profile"))),
+        (status = 401, description = "Missing or invalid access token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Cookie-authenticated request failed the CSRF check", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "User no longer exists", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 409, description = "Email or username already exists", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 412, description = "The profile changed since it was fetched", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Invalid profile data", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 428, description = "`If-Match` header missing", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+pub async fn update_me(
+    State(state): State<AppState>,
+    Extension(auth_user): Extension<AuthUser>,
+    client: ClientInfo,
+    if_match: IfMatch,
+    JsonBody(payload): JsonBody<UpdateProfileRequest>,
+) -> AppResult<Tagged<UserResponse>> {
+    payload.validate()?;
+
+    let user = current_user(&state, &auth_user).await?;
+    if_match.check(&user.etag())?;
+
+    let changed: Vec<&str> = [
+        payload.username.as_ref().map(|_| "username"),
+        payload.email.as_ref().map(|_| "email"),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+
+    // Guarded by the version just checked, so a concurrent edit still fails
+    let users = state.users.clone();
+    let updated = transact(&state, &client, move |tx| {
+        let updated = users
+            .update(tx.conn, user.id, user.version, payload.into())?
+            .ok_or(AppError::PreconditionFailed)?;
+
+        tx.audit(
+            AuditEntry::success(AuditAction::ProfileUpdated)
+                .actor(updated.id)
+                .target(updated.email.clone())
+                .metadata(serde_json::json!({ "fields": changed })),
+        )?;
+        if updated.email != user.email {
+            tx.emit(UserEmailChanged {
+                user: updated.clone().into(),
+                previous_email: user.email,
+            })?;
+        }
+        Ok(updated)
+    })
+    .await?;
+
+    Ok(Tagged::new(updated.etag(), updated.into()))
 }
 
 // Helper functions
 
-async fn create_auth_response(
-    user: User,
-    conn: &mut diesel::r2d2::PooledConnection<diesel::r2d2::ConnectionManager<diesel::SqliteConnection>>,
-) -> Result<AuthResponse, Response> {
-    // Create access token
-    let access_token = create_token(user.id, user.email.clone()).map_err(|e| {
-        tracing::error!("Failed to create token: {:?}", e);
-        (StatusCode::INTERNAL_SERVER_ERROR, "Failed to create token").into_response()
-    })?;
-
-    // Create refresh token
-    let new_refresh_token = NewRefreshToken::new(user.id);
-    let refresh_token_id = new_refresh_token.id.clone();
-
-    diesel::insert_into(refresh_tokens::table)
-        .values(&new_refresh_token)
-        .execute(conn)
-        .map_err(|e| {
-            tracing::error!("Failed to create refresh token: {:?}", e);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Failed to create refresh token",
-            )
-                .into_response()
-        })?;
+/// Append a refused attempt to the audit log without failing the request
+///
+/// Changes record their entries with [`transact`] instead.
+pub(super) async fn audit(state: &AppState, client: &ClientInfo, entry: AuditEntry) {
+    let client = client.clone();
+    let now = state.clock.now();
+    let result = state
+        .db
+        .write(move |conn| Ok(audit::record(conn, &client, entry, now)?))
+        .await;
+    if let Err(e) = result {
+        tracing::error!("Failed to write audit event: {:?}", e);
+    }
+}
 
-    Ok(AuthResponse {
-        user: user.into(),
-        access_token,
-        refresh_token: refresh_token_id,
-    })
+/// Run `f` in one write transaction, so a change, its audit entries and its
+/// events commit or roll back together
+pub(super) async fn transact<F, T>(state: &AppState, client: &ClientInfo, f: F) -> AppResult<T>
+where
+    F: FnOnce(&mut Transaction<'_>) -> AppResult<T> + Send + 'static,
+    T: Send + 'static,
+{
+    let client = client.clone();
+    let events = state.events.clone();
+    let now = state.clock.now();
+    let result = state
+        .db
+        .write(move |conn| {
+            db::write_transaction(conn, |conn| {
+                f(&mut Transaction {
+                    conn,
+                    client: &client,
+                    events: &events,
+                    now,
+                })
+            })
+        })
+        .await?;
+    state.queue.wake();
+    Ok(result)
 }
 
-fn create_response_with_cookie(auth_response: AuthResponse) -> Response {
-    let cookie = format!(
-        "access_token={}; HttpOnly; SameSite=Lax; Path=/; Max-Age={}",
-        auth_response.access_token, COOKIE_MAX_AGE
-    );
+/// A write transaction opened by [`transact`]
+pub(super) struct Transaction<'a> {
+    pub conn: &'a mut DbConnection,
+    client: &'a ClientInfo,
+    events: &'a EventBus,
+    now: DateTime<Utc>,
+}
 
-    (
-        StatusCode::OK,
-        [(header::SET_COOKIE, cookie)],
-        Json(auth_response),
-    )
-        .into_response()
+impl Transaction<'_> {
+    /// Append an event to the audit log
+    pub fn audit(&mut self, entry: AuditEntry) -> AppResult<()> {
+        audit::record(self.conn, self.client, entry, self.now)?;
+        Ok(())
+    }
+
+    /// Publish a domain event
+    pub fn emit<E: Event>(&mut self, event: E) -> AppResult<()> {
+        self.events.record(self.conn, &event, self.now)
+    }
+}
+
+/// Load the signed-in user; deleted users no longer exist
+async fn current_user(state: &AppState, auth_user: &AuthUser) -> AppResult<User> {
+    let user_id: i32 = auth_user
+        .0
+        .sub
+        .parse()
+        .map_err(|_| AppError::InvalidToken)?;
+
+    let users = state.users.clone();
+    state
+        .db
+        .read(move |conn| users.find_by_id(conn, user_id))
+        .await?
+        .ok_or(AppError::NotFound("User"))
 }
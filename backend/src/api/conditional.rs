@@ -0,0 +1,236 @@
+//! ETags and conditional requests
+//!
+//! GET handlers answer with [`Tagged`] so unchanged resources come back as
+//! `304 Not Modified`; PATCH/PUT handlers check [`IfMatch`] before writing
+//! and guard the write itself on the version they checked.
+
+use std::{convert::Infallible, fmt::Display};
+
+use axum::{
+    extract::FromRequestParts,
+    http::{
+        header::{CACHE_CONTROL, ETAG, IF_MATCH, IF_NONE_MATCH},
+        request::Parts,
+        HeaderName, HeaderValue, StatusCode,
+    },
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+
+use crate::error::{AppError, AppResult};
+
+/// A strong entity tag, including its quotes
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ETag(String);
+
+impl ETag {
+    /// Tag for one version of a resource
+    ///
+    /// The kind and id keep tags distinct when one URL serves different
+    /// resources, as `/api/auth/me` does for each user.
+    pub fn versioned(kind: &str, id: impl Display, version: i32) -> Self {
+        Self(format!("\"{}-{}-{}\"", kind, id, version))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Resources whose ETag changes whenever their representation does
+pub trait Versioned {
+    fn etag(&self) -> ETag;
+}
+
+/// The entity tags listed in an `If-Match` or `If-None-Match` header
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum TagList {
+    Any,
+    Tags(Vec<String>),
+}
+
+impl TagList {
+    fn parse(value: &str) -> Self {
+        if value.trim() == "*" {
+            return TagList::Any;
+        }
+        TagList::Tags(
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|tag| !tag.is_empty())
+                .map(str::to_string)
+                .collect(),
+        )
+    }
+
+    fn from_header(parts: &Parts, name: HeaderName) -> Option<Self> {
+        let values: Vec<&str> = parts
+            .headers
+            .get_all(name)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .collect();
+        if values.is_empty() {
+            None
+        } else {
+            Some(Self::parse(&values.join(",")))
+        }
+    }
+
+    /// Strong comparison, as `If-Match` requires
+    fn matches_strong(&self, etag: &ETag) -> bool {
+        match self {
+            TagList::Any => true,
+            TagList::Tags(tags) => tags.iter().any(|tag| tag == etag.as_str()),
+        }
+    }
+
+    /// Weak comparison, as `If-None-Match` requires
+    fn matches_weak(&self, etag: &ETag) -> bool {
+        match self {
+            TagList::Any => true,
+            TagList::Tags(tags) => tags
+                .iter()
+                .any(|tag| tag.strip_prefix("W/").unwrap_or(tag) == etag.as_str()),
+        }
+    }
+}
+
+/// The request's `If-Match` header, if any
+#[derive(Clone, Debug)]
+pub struct IfMatch(Option<TagList>);
+
+impl IfMatch {
+    /// Allow a write only if the client has seen the current version
+    ///
+    /// A missing header is rejected with 428 so clients cannot skip the check
+    /// by accident; a stale one is rejected with 412.
+    pub fn check(&self, current: &ETag) -> AppResult<()> {
+        match &self.0 {
+            None => Err(AppError::PreconditionRequired),
+            Some(tags) if tags.matches_strong(current) => Ok(()),
+            Some(_) => Err(AppError::PreconditionFailed),
+        }
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for IfMatch {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Self(TagList::from_header(parts, IF_MATCH)))
+    }
+}
+
+/// The request's `If-None-Match` header, if any
+#[derive(Clone, Debug)]
+pub struct IfNoneMatch(Option<TagList>);
+
+impl IfNoneMatch {
+    /// Tag a response body, dropping it if the client already has this version
+    pub fn respond<T>(&self, etag: ETag, body: T) -> Tagged<T> {
+        let not_modified = self.0.as_ref().is_some_and(|tags| tags.matches_weak(&etag));
+        Tagged {
+            etag,
+            body: (!not_modified).then_some(body),
+        }
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for IfNoneMatch {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Self(TagList::from_header(parts, IF_NONE_MATCH)))
+    }
+}
+
+/// A JSON body sent with its `ETag`, or `304 Not Modified` without a body
+#[derive(Debug)]
+pub struct Tagged<T> {
+    etag: ETag,
+    body: Option<T>,
+}
+
+impl<T> Tagged<T> {
+    /// Always send the body, e.g. after a successful update
+    pub fn new(etag: ETag, body: T) -> Self {
+        Self {
+            etag,
+            body: Some(body),
+        }
+    }
+}
+
+impl<T: Serialize> IntoResponse for Tagged<T> {
+    fn into_response(self) -> Response {
+        let mut response = match self.body {
+            Some(body) => Json(body).into_response(),
+            None => StatusCode::NOT_MODIFIED.into_response(),
+        };
+        let headers = response.headers_mut();
+        if let Ok(etag) = HeaderValue::from_str(self.etag.as_str()) {
+            headers.insert(ETAG, etag);
+        }
+        // Let browsers keep a copy, but revalidate it on every use
+        headers.insert(CACHE_CONTROL, HeaderValue::from_static("private, no-cache"));
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn etag() -> ETag {
+        ETag::versioned("user", 1, 3)
+    }
+
+    #[test]
+    fn test_versioned_etag_is_quoted() {
+        assert_eq!(etag().as_str(), "\"user-1-3\"");
+    }
+
+    #[test]
+    fn test_if_match_requires_a_strong_match() {
+        assert!(matches!(
+            IfMatch(None).check(&etag()),
+            Err(AppError::PreconditionRequired)
+        ));
+        assert!(IfMatch(Some(TagList::Any)).check(&etag()).is_ok());
+        assert!(IfMatch(Some(TagList::parse("\"user-1-2\", \"user-1-3\"")))
+            .check(&etag())
+            .is_ok());
+        assert!(matches!(
+            IfMatch(Some(TagList::parse("W/\"user-1-3\""))).check(&etag()),
+            Err(AppError::PreconditionFailed)
+        ));
+        assert!(matches!(
+            IfMatch(Some(TagList::parse("\"user-1-2\""))).check(&etag()),
+            Err(AppError::PreconditionFailed)
+        ));
+    }
+
+    #[test]
+    fn test_if_none_match_uses_weak_comparison() {
+        let matching = IfNoneMatch(Some(TagList::parse("W/\"user-1-3\"")));
+        let response = matching.respond(etag(), "body").into_response();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers()[ETAG], "\"user-1-3\"");
+
+        let stale = IfNoneMatch(Some(TagList::parse("\"user-1-2\"")));
+        assert_eq!(
+            stale.respond(etag(), "body").into_response().status(),
+            StatusCode::OK
+        );
+        assert_eq!(
+            IfNoneMatch(None)
+                .respond(etag(), "body")
+                .into_response()
+                .status(),
+            StatusCode::OK
+        );
+    }
+}
@@ -0,0 +1,25 @@
+use axum::{extract::State, routing::get, Json, Router};
+
+use crate::{
+    mail::{Email, SharedMailer},
+    state::AppState,
+};
+
+/// Development helpers, mounted with `DEV_ENDPOINTS=true`
+///
+/// `/dev/mail` additionally requires `MAIL_TRANSPORT=memory`, so it never
+/// exposes mail that was meant to leave the server.
+pub fn router(state: &AppState) -> Router<AppState> {
+    let router = Router::new();
+
+    if state.config.dev_endpoints && state.mailer.captured().is_some() {
+        router.route("/dev/mail", get(captured_mail))
+    } else {
+        router
+    }
+}
+
+/// Messages caught by the in-memory mailer, newest first
+async fn captured_mail(State(mailer): State<SharedMailer>) -> Json<Vec<Email>> {
+    Json(mailer.captured().unwrap_or_default())
+}
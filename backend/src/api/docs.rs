@@ -0,0 +1,203 @@
+use axum::{
+    http::{header::CONTENT_SECURITY_POLICY, HeaderValue},
+    middleware,
+    response::Response,
+    routing::get,
+    Json, Router,
+};
+use utoipa::{
+    openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme},
+    Modify, OpenApi,
+};
+use utoipa_scalar::{Scalar, Servable};
+
+use super::{admin, auth, webhooks};
+use crate::{
+    auth::CookieOptions,
+    config::Config,
+    error::{FieldError, ProblemDetails},
+    state::AppState,
+};
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Web App Template API",
+        description = "Errors are returned as RFC 7807 `application/problem+json` documents."
+    ),
+    paths(
+        super::health_check,
+        super::hello,
+        auth::register,
+        auth::login,
+        auth::refresh,
+        auth::logout,
+        auth::csrf_token,
+        auth::me,
+        auth::update_me,
+        admin::list_audit_events,
+        admin::verify_audit_events,
+        admin::pool_stats,
+        admin::scheduled_jobs,
+        admin::list_jobs,
+        admin::get_job,
+        admin::retry_job,
+        admin::delete_user,
+        admin::restore_user,
+        webhooks::list_events,
+        webhooks::list_webhooks,
+        webhooks::create_webhook,
+        webhooks::delete_webhook,
+        webhooks::list_deliveries,
+        webhooks::get_delivery,
+        webhooks::redeliver,
+    ),
+    components(schemas(ProblemDetails, FieldError)),
+    modifiers(&SecuritySchemes),
+    tags(
+        (name = "auth", description = "Registration, login and sessions"),
+        (name = "admin", description = "Administrator-only endpoints"),
+        (name = "system", description = "Health and diagnostics"),
+    )
+)]
+pub struct ApiDoc;
+
+/// Registers the two ways `require_auth` accepts an access token, and the
+/// refresh token cookie
+///
+/// The cookies have their default names here; [`served_spec`] renames them.
+struct SecuritySchemes;
+
+impl Modify for SecuritySchemes {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "cookie_auth",
+            SecurityScheme::ApiKey(ApiKey::Cookie(ApiKeyValue::new("access_token"))),
+        );
+        components.add_security_scheme(
+            "refresh_cookie",
+            SecurityScheme::ApiKey(ApiKey::Cookie(ApiKeyValue::new("refresh_token"))),
+        );
+        components.add_security_scheme(
+            "bearer_auth",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .bearer_format("JWT")
+                    .build(),
+            ),
+        );
+    }
+}
+
+/// The generated OpenAPI document
+pub fn spec() -> utoipa::openapi::OpenApi {
+    let mut spec = ApiDoc::openapi();
+    // utoipa copies the license from Cargo.toml, which leaves an empty name here
+    if spec
+        .info
+        .license
+        .as_ref()
+        .is_some_and(|license| license.name.is_empty())
+    {
+        spec.info.license = None;
+    }
+    spec
+}
+
+/// The OpenAPI document with the cookie names this server reads
+///
+/// `openapi.json` is committed with the defaults, so it stays the same
+/// whatever the cookies are configured to be called.
+pub fn served_spec(cookie: &CookieOptions) -> utoipa::openapi::OpenApi {
+    let mut spec = spec();
+    let Some(components) = spec.components.as_mut() else {
+        return spec;
+    };
+    for (scheme, name) in [
+        ("cookie_auth", &cookie.name),
+        ("refresh_cookie", &cookie.refresh_name),
+    ] {
+        if let Some(SecurityScheme::ApiKey(ApiKey::Cookie(value))) =
+            components.security_schemes.get_mut(scheme)
+        {
+            *value = ApiKeyValue::new(name.as_str());
+        }
+    }
+    spec
+}
+
+/// Scalar loads its script, styles and fonts from CDNs, which the default
+/// `Content-Security-Policy` for API responses blocks
+const DOCS_CONTENT_SECURITY_POLICY: &str = "default-src 'none'; \
+    script-src https://cdn.jsdelivr.net; \
+    style-src 'unsafe-inline' https://cdn.jsdelivr.net https://fonts.scalar.com; \
+    font-src data: https://fonts.scalar.com; img-src data: https:; connect-src 'self'; \
+    frame-ancestors 'none'";
+
+/// Routes serving the OpenAPI document and, optionally, the interactive docs
+pub fn router(config: &Config) -> Router<AppState> {
+    let spec = served_spec(&config.cookie);
+    let router = Router::new().route(
+        "/api/openapi.json",
+        get({
+            let spec = spec.clone();
+            move || async move { Json(spec) }
+        }),
+    );
+
+    if config.api_docs {
+        let docs: Router<AppState> = Scalar::with_url("/api/docs", spec).into();
+        router.merge(docs.layer(middleware::map_response(docs_policy)))
+    } else {
+        router
+    }
+}
+
+async fn docs_policy(mut response: Response) -> Response {
+    response.headers_mut().insert(
+        CONTENT_SECURITY_POLICY,
+        HeaderValue::from_static(DOCS_CONTENT_SECURITY_POLICY),
+    );
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs, path::Path};
+
+    /// Fails when the committed `openapi.json` no longer matches the code.
+    /// Regenerate it with `UPDATE_OPENAPI=1 cargo test openapi`.
+    #[test]
+    fn test_openapi_spec_matches_committed_copy() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("openapi.json");
+        let generated = spec()
+            .to_pretty_json()
+            .expect("Failed to serialize OpenAPI document")
+            + "\n";
+
+        if env::var_os("UPDATE_OPENAPI").is_some() {
+            fs::write(&path, &generated).expect("Failed to write openapi.json");
+            return;
+        }
+
+        let committed = fs::read_to_string(&path).unwrap_or_default();
+        assert!(
+            committed == generated,
+            "openapi.json is out of date; run `UPDATE_OPENAPI=1 cargo test openapi` and commit the result"
+        );
+    }
+
+    #[test]
+    fn test_openapi_declares_auth_schemes() {
+        let spec = serde_json::to_value(spec()).unwrap();
+
+        assert_eq!(spec["openapi"], "3.1.0");
+        let schemes = &spec["components"]["securitySchemes"];
+        assert_eq!(schemes["cookie_auth"]["in"], "cookie");
+        assert_eq!(schemes["cookie_auth"]["name"], "access_token");
+        assert_eq!(schemes["bearer_auth"]["scheme"], "bearer");
+    }
+}
@@ -1,45 +1,117 @@
+pub mod admin;
 pub mod auth;
+pub mod conditional;
+pub mod dev;
+pub mod docs;
+pub mod extract;
+pub mod webhooks;
 
+use crate::{
+    auth::{require_admin, require_auth},
+    clock::SharedClock,
+    models::ApiResponse,
+    rate_limit::{Policy, RateLimiter},
+    state::AppState,
+};
 use axum::{
-    extract::State,
+    extract::{FromRef, State},
     http::StatusCode,
     middleware,
     response::IntoResponse,
-    routing::{get, post},
+    routing::{delete, get, post},
     Json, Router,
 };
-use std::time::{SystemTime, UNIX_EPOCH};
-
-use crate::{auth::require_auth, db::DbPool, models::ApiResponse};
 
-pub fn create_router(pool: DbPool) -> Router {
-    let public_routes = Router::new()
-        .route("/health", get(health_check))
-        .route("/api/hello", get(hello))
+pub fn create_router(state: AppState) -> Router {
+    let auth_routes = Router::new()
         .route("/api/auth/register", post(auth::register))
         .route("/api/auth/login", post(auth::login))
         .route("/api/auth/refresh", post(auth::refresh))
-        .route("/api/auth/logout", post(auth::logout));
+        .route("/api/auth/logout", post(auth::logout))
+        .route("/api/auth/csrf", get(auth::csrf_token));
 
     let protected_routes = Router::new()
-        .route("/api/auth/me", get(auth::me))
-        .route_layer(middleware::from_fn(require_auth));
+        .route("/api/auth/me", get(auth::me).patch(auth::update_me))
+        .route_layer(middleware::from_fn_with_state(state.clone(), require_auth));
 
-    Router::new()
-        .merge(public_routes)
+    let admin_routes = Router::new()
+        .route("/api/admin/audit-events", get(admin::list_audit_events))
+        .route(
+            "/api/admin/audit-events/verify",
+            get(admin::verify_audit_events),
+        )
+        .route("/api/admin/db/pool", get(admin::pool_stats))
+        .route("/api/admin/scheduler", get(admin::scheduled_jobs))
+        .route("/api/admin/jobs", get(admin::list_jobs))
+        .route("/api/admin/jobs/{id}", get(admin::get_job))
+        .route("/api/admin/jobs/{id}/retry", post(admin::retry_job))
+        .route("/api/admin/users/{id}", delete(admin::delete_user))
+        .route("/api/admin/users/{id}/restore", post(admin::restore_user))
+        .route(
+            "/api/admin/webhooks",
+            get(webhooks::list_webhooks).post(webhooks::create_webhook),
+        )
+        .route("/api/admin/webhooks/events", get(webhooks::list_events))
+        .route("/api/admin/webhooks/{id}", delete(webhooks::delete_webhook))
+        .route(
+            "/api/admin/webhooks/{id}/deliveries",
+            get(webhooks::list_deliveries),
+        )
+        .route(
+            "/api/admin/webhooks/deliveries/{id}",
+            get(webhooks::get_delivery),
+        )
+        .route(
+            "/api/admin/webhooks/deliveries/{id}/redeliver",
+            post(webhooks::redeliver),
+        )
+        .route_layer(middleware::from_fn_with_state(state.clone(), require_admin))
+        .route_layer(middleware::from_fn_with_state(state.clone(), require_auth));
+
+    let api_routes = Router::new()
+        .route("/api/hello", get(hello))
         .merge(protected_routes)
-        .with_state(pool)
+        .merge(admin_routes);
+
+    let limits = &state.config.rate_limit;
+    Router::new()
+        .route("/health", get(health_check))
+        .merge(rate_limited(auth_routes, &state, &limits.auth))
+        .merge(rate_limited(api_routes, &state, &limits.api))
+        .merge(docs::router(&state.config))
+        .merge(dev::router(&state))
+        .with_state(state)
+}
+
+/// Wrap `routes` in `policy`, outside any authentication, unless rate
+/// limiting is off
+fn rate_limited(routes: Router<AppState>, state: &AppState, policy: &Policy) -> Router<AppState> {
+    if !state.config.rate_limit.enabled {
+        return routes;
+    }
+    routes.route_layer(RateLimiter::from_ref(state).layer(policy))
 }
 
+/// Health check
+#[utoipa::path(
+    get,
+    path = "/health",
+    tag = "system",
+    responses((status = 200, description = "Service is up", body = String, content_type = "text/plain"))
+)]
 async fn health_check() -> impl IntoResponse {
     (StatusCode::OK, "OK")
 }
 
-async fn hello(State(_pool): State<DbPool>) -> impl IntoResponse {
-    let timestamp = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_secs() as i64;
+/// Example endpoint returning a greeting and the server time
+#[utoipa::path(
+    get,
+    path = "/api/hello",
+    tag = "system",
+    responses((status = 200, description = "Greeting with the server time", body = ApiResponse))
+)]
+async fn hello(State(clock): State<SharedClock>) -> impl IntoResponse {
+    let timestamp = clock.now().timestamp();
 
     let response = ApiResponse {
         message: "Hello from Axum!".to_string(),
@@ -0,0 +1,312 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Extension, Json,
+};
+use diesel::prelude::*;
+use validator::Validate;
+
+use super::{
+    admin::{admin_id, page_offset, DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE},
+    auth::transact,
+    extract::JsonBody,
+};
+use crate::{
+    audit::{AuditAction, AuditEntry, ClientInfo},
+    auth::AuthUser,
+    db::{
+        schema::{webhook_attempts, webhook_deliveries, webhook_endpoints},
+        Db, DbBackend,
+    },
+    error::{AppError, AppResult, ProblemDetails},
+    models::{
+        CreateWebhookRequest, CreatedWebhookResponse, NewWebhookEndpoint, WebhookAttempt,
+        WebhookDelivery, WebhookDeliveryDetail, WebhookDeliveryPage, WebhookDeliveryQuery,
+        WebhookDeliveryResponse, WebhookEndpoint, WebhookEndpointResponse,
+    },
+    state::AppState,
+    webhooks::{self, WebhookEvent, WebhookEventInfo},
+};
+
+/// List the events endpoints can subscribe to
+#[utoipa::path(
+    get,
+    path = "/api/admin/webhooks/events",
+    tag = "admin",
+    security(("cookie_auth" = []), ("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "The event catalogue", body = Vec<WebhookEventInfo>),
+        (status = 401, description = "Missing or invalid access token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Administrator access required", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+pub async fn list_events() -> Json<Vec<WebhookEventInfo>> {
+    Json(WebhookEvent::ALL.into_iter().map(Into::into).collect())
+}
+
+/// Register an endpoint; the response holds its signing secret
+#[utoipa::path(
+    post,
+    path = "/api/admin/webhooks",
+    tag = "admin",
+    request_body = CreateWebhookRequest,
+    security(("cookie_auth" = []), ("bearer_auth" = [])),
+    responses(
+        (status = 201, description = "The endpoint and its signing secret", body = CreatedWebhookResponse),
+        (status = 401, description = "Missing or invalid access token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Administrator access required", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Invalid URL or unknown event types", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+pub async fn create_webhook(
+    State(state): State<AppState>,
+    Extension(auth_user): Extension<AuthUser>,
+    client: ClientInfo,
+    JsonBody(payload): JsonBody<CreateWebhookRequest>,
+) -> AppResult<(StatusCode, Json<CreatedWebhookResponse>)> {
+    payload.validate()?;
+
+    let secret = webhooks::generate_secret();
+    let new_endpoint = NewWebhookEndpoint {
+        url: payload.url,
+        description: payload.description.unwrap_or_default(),
+        secret: secret.clone(),
+        events: serde_json::to_string(&payload.events).expect("strings serialize"),
+    };
+    let admin_id = admin_id(&auth_user)?;
+    let endpoint = transact(&state, &client, move |tx| {
+        let endpoint = diesel::insert_into(webhook_endpoints::table)
+            .values(new_endpoint)
+            .returning(WebhookEndpoint::as_returning())
+            .get_result(tx.conn)?;
+
+        tx.audit(
+            AuditEntry::success(AuditAction::WebhookCreated)
+                .actor(admin_id)
+                .target(endpoint.url.clone())
+                .metadata(serde_json::json!({
+                    "webhook_id": endpoint.id,
+                    "events": endpoint.events(),
+                })),
+        )?;
+        Ok(endpoint)
+    })
+    .await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(CreatedWebhookResponse {
+            endpoint: endpoint.into(),
+            secret,
+        }),
+    ))
+}
+
+/// List registered endpoints
+#[utoipa::path(
+    get,
+    path = "/api/admin/webhooks",
+    tag = "admin",
+    security(("cookie_auth" = []), ("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "All endpoints, oldest first", body = Vec<WebhookEndpointResponse>),
+        (status = 401, description = "Missing or invalid access token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Administrator access required", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+pub async fn list_webhooks(State(db): State<Db>) -> AppResult<Json<Vec<WebhookEndpointResponse>>> {
+    let endpoints = db
+        .read(|conn| {
+            Ok(webhook_endpoints::table
+                .order(webhook_endpoints::id)
+                .select(WebhookEndpoint::as_select())
+                .load(conn)?)
+        })
+        .await?;
+
+    Ok(Json(endpoints.into_iter().map(Into::into).collect()))
+}
+
+/// Remove an endpoint along with its delivery log
+#[utoipa::path(
+    delete,
+    path = "/api/admin/webhooks/{id}",
+    tag = "admin",
+    params(("id" = i32, Path, description = "Endpoint ID")),
+    security(("cookie_auth" = []), ("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "The removed endpoint", body = WebhookEndpointResponse),
+        (status = 401, description = "Missing or invalid access token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Administrator access required", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "No endpoint with this ID", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+pub async fn delete_webhook(
+    State(state): State<AppState>,
+    Extension(auth_user): Extension<AuthUser>,
+    client: ClientInfo,
+    Path(id): Path<i32>,
+) -> AppResult<Json<WebhookEndpointResponse>> {
+    // Queued deliveries find nothing to send and finish quietly
+    let admin_id = admin_id(&auth_user)?;
+    let endpoint = transact(&state, &client, move |tx| {
+        let endpoint = diesel::delete(webhook_endpoints::table.find(id))
+            .returning(WebhookEndpoint::as_returning())
+            .get_result(tx.conn)
+            .optional()?
+            .ok_or(AppError::NotFound("Webhook"))?;
+
+        tx.audit(
+            AuditEntry::success(AuditAction::WebhookDeleted)
+                .actor(admin_id)
+                .target(endpoint.url.clone())
+                .metadata(serde_json::json!({ "webhook_id": endpoint.id })),
+        )?;
+        Ok(endpoint)
+    })
+    .await?;
+
+    Ok(Json(endpoint.into()))
+}
+
+/// List an endpoint's deliveries, newest first, with optional filters
+#[utoipa::path(
+    get,
+    path = "/api/admin/webhooks/{id}/deliveries",
+    tag = "admin",
+    params(("id" = i32, Path, description = "Endpoint ID"), WebhookDeliveryQuery),
+    security(("cookie_auth" = []), ("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "One page of deliveries", body = WebhookDeliveryPage),
+        (status = 401, description = "Missing or invalid access token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Administrator access required", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "page is too large", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+pub async fn list_deliveries(
+    State(db): State<Db>,
+    Path(id): Path<i32>,
+    Query(query): Query<WebhookDeliveryQuery>,
+) -> AppResult<Json<WebhookDeliveryPage>> {
+    let page = query.page.unwrap_or(1).max(1);
+    let per_page = query
+        .per_page
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    let offset = page_offset(page, per_page)?;
+
+    let (total, deliveries) = db
+        .read(move |conn| {
+            let total: i64 = filtered_deliveries(id, &query).count().get_result(conn)?;
+
+            let deliveries: Vec<WebhookDelivery> = filtered_deliveries(id, &query)
+                .order(webhook_deliveries::id.desc())
+                .limit(per_page)
+                .offset(offset)
+                .select(WebhookDelivery::as_select())
+                .load(conn)?;
+
+            Ok((total, deliveries))
+        })
+        .await?;
+
+    Ok(Json(WebhookDeliveryPage {
+        deliveries: deliveries.into_iter().map(Into::into).collect(),
+        page,
+        per_page,
+        total,
+    }))
+}
+
+/// Show a delivery with every attempt made for it
+#[utoipa::path(
+    get,
+    path = "/api/admin/webhooks/deliveries/{id}",
+    tag = "admin",
+    params(("id" = i32, Path, description = "Delivery ID")),
+    security(("cookie_auth" = []), ("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "The delivery and its attempts", body = WebhookDeliveryDetail),
+        (status = 401, description = "Missing or invalid access token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Administrator access required", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "No delivery with this ID", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+pub async fn get_delivery(
+    State(db): State<Db>,
+    Path(id): Path<i32>,
+) -> AppResult<Json<WebhookDeliveryDetail>> {
+    let (delivery, attempts) = db
+        .read(move |conn| {
+            let Some(delivery) = webhook_deliveries::table
+                .find(id)
+                .select(WebhookDelivery::as_select())
+                .first(conn)
+                .optional()?
+            else {
+                return Ok(None);
+            };
+            let attempts: Vec<WebhookAttempt> = webhook_attempts::table
+                .filter(webhook_attempts::delivery_id.eq(id))
+                .order(webhook_attempts::id)
+                .select(WebhookAttempt::as_select())
+                .load(conn)?;
+
+            Ok(Some((delivery, attempts)))
+        })
+        .await?
+        .ok_or(AppError::NotFound("Delivery"))?;
+
+    Ok(Json(WebhookDeliveryDetail {
+        delivery: delivery.into(),
+        attempts: attempts.into_iter().map(Into::into).collect(),
+    }))
+}
+
+/// Send a delivery again with its original event ID and payload
+#[utoipa::path(
+    post,
+    path = "/api/admin/webhooks/deliveries/{id}/redeliver",
+    tag = "admin",
+    params(("id" = i32, Path, description = "Delivery ID")),
+    security(("cookie_auth" = []), ("bearer_auth" = [])),
+    responses(
+        (status = 200, description = "The delivery, pending again", body = WebhookDeliveryResponse),
+        (status = 401, description = "Missing or invalid access token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "Administrator access required", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "No delivery with this ID", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 409, description = "The delivery is still queued", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+pub async fn redeliver(
+    State(state): State<AppState>,
+    Path(id): Path<i32>,
+) -> AppResult<Json<WebhookDeliveryResponse>> {
+    let now = state.clock.now();
+    let delivery = state
+        .db
+        .write(move |conn| webhooks::redeliver(conn, id, now))
+        .await?
+        .ok_or(AppError::NotFound("Delivery"))?;
+    state.queue.wake();
+
+    Ok(Json(delivery.into()))
+}
+
+fn filtered_deliveries(
+    endpoint_id: i32,
+    query: &WebhookDeliveryQuery,
+) -> webhook_deliveries::BoxedQuery<'_, DbBackend> {
+    let mut deliveries = webhook_deliveries::table
+        .filter(webhook_deliveries::endpoint_id.eq(endpoint_id))
+        .into_boxed();
+
+    if let Some(status) = query.status {
+        deliveries = deliveries.filter(webhook_deliveries::status.eq(status.as_str()));
+    }
+    if let Some(event_type) = &query.event_type {
+        deliveries = deliveries.filter(webhook_deliveries::event_type.eq(event_type));
+    }
+
+    deliveries
+}
@@ -0,0 +1,182 @@
+use std::{convert::Infallible, sync::Arc};
+
+use axum::{extract::Request, middleware, response::IntoResponse, routing::Route, Router};
+use tower::{Layer, Service};
+
+use crate::{
+    api,
+    clock::SharedClock,
+    config::Config,
+    db::{Db, DbPool},
+    events::EventBus,
+    mail::SharedMailer,
+    queue::Queue,
+    repositories::{SharedSessionRepository, SharedUserRepository},
+    scheduler::JobRegistry,
+    security::add_security_headers,
+    state::AppState,
+};
+
+type RouterLayer = Box<dyn FnOnce(Router) -> Router + Send>;
+
+/// Entry point for assembling the application router
+///
+/// ```no_run
+/// use axum::{routing::get, Router};
+/// use webapp_backend::{App, AppState};
+///
+/// let reports: Router<AppState> = Router::new().route("/api/reports", get(|| async { "[]" }));
+/// let app = App::builder().router(reports).build();
+/// ```
+pub struct App;
+
+impl App {
+    pub fn builder() -> AppBuilder {
+        AppBuilder::default()
+    }
+}
+
+/// Builds the application `Router` from a config, a pool and any extra
+/// feature routers or layers
+#[derive(Default)]
+pub struct AppBuilder {
+    config: Option<Config>,
+    db: Option<Db>,
+    clock: Option<SharedClock>,
+    repositories: Option<(SharedUserRepository, SharedSessionRepository)>,
+    jobs: Option<JobRegistry>,
+    queue: Option<Queue>,
+    mailer: Option<SharedMailer>,
+    events: Option<EventBus>,
+    routers: Vec<Router<AppState>>,
+    layers: Vec<RouterLayer>,
+}
+
+impl AppBuilder {
+    /// Use this configuration instead of reading it from the environment
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    /// Use an existing pool for both reads and writes instead of opening
+    /// split writer and reader pools from `Config::database_url`
+    pub fn pool(mut self, pool: DbPool) -> Self {
+        self.db = Some(Db::single(pool));
+        self
+    }
+
+    /// Use already opened database pools
+    pub fn db(mut self, db: Db) -> Self {
+        self.db = Some(db);
+        self
+    }
+
+    /// Use this clock instead of the system time, e.g. a `FakeClock` in tests
+    pub fn clock(mut self, clock: SharedClock) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+
+    /// Load and store users and sessions through these repositories instead
+    /// of the Diesel ones
+    pub fn repositories(
+        mut self,
+        users: SharedUserRepository,
+        sessions: SharedSessionRepository,
+    ) -> Self {
+        self.repositories = Some((users, sessions));
+        self
+    }
+
+    /// Report the status of a running scheduler at `/api/admin/scheduler`
+    pub fn jobs(mut self, jobs: JobRegistry) -> Self {
+        self.jobs = Some(jobs);
+        self
+    }
+
+    /// Enqueue onto a queue whose workers run outside the app
+    pub fn queue(mut self, queue: Queue) -> Self {
+        self.queue = Some(queue);
+        self
+    }
+
+    /// Deliver mail with this mailer instead of one built from
+    /// `Config::mail`, e.g. to read it back in tests
+    pub fn mailer(mut self, mailer: SharedMailer) -> Self {
+        self.mailer = Some(mailer);
+        self
+    }
+
+    /// Publish events to these subscribers instead of `events::defaults`,
+    /// sharing the bus with workers that run `after_commit` subscribers
+    pub fn events(mut self, events: EventBus) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Merge an additional router; its handlers can extract `State<AppState>`
+    /// or any of its parts, such as `State<Db>`
+    pub fn router(mut self, router: Router<AppState>) -> Self {
+        self.routers.push(router);
+        self
+    }
+
+    /// Wrap every route, including the built-in ones, in a layer
+    ///
+    /// Layers are applied in the order they are added, inside the CORS layer.
+    pub fn layer<L>(mut self, layer: L) -> Self
+    where
+        L: Layer<Route> + Clone + Send + Sync + 'static,
+        L::Service: Service<Request> + Clone + Send + Sync + 'static,
+        <L::Service as Service<Request>>::Response: IntoResponse + 'static,
+        <L::Service as Service<Request>>::Error: Into<Infallible> + 'static,
+        <L::Service as Service<Request>>::Future: Send + 'static,
+    {
+        self.layers
+            .push(Box::new(move |router: Router| router.layer(layer)));
+        self
+    }
+
+    pub fn build(self) -> Router {
+        let config = self.config.unwrap_or_else(Config::from_env);
+        let db = self
+            .db
+            .unwrap_or_else(|| Db::open(&config.database_url, &config.db));
+
+        let mut state = AppState::new(config, db);
+        if let Some(clock) = self.clock {
+            state = state.with_clock(clock);
+        }
+        if let Some((users, sessions)) = self.repositories {
+            state = state.with_repositories(users, sessions);
+        }
+        if let Some(jobs) = self.jobs {
+            state = state.with_jobs(jobs);
+        }
+        if let Some(queue) = self.queue {
+            state = state.with_queue(queue);
+        }
+        if let Some(mailer) = self.mailer {
+            state = state.with_mailer(mailer);
+        }
+        if let Some(events) = self.events {
+            state = state.with_events(events);
+        }
+
+        let mut app = api::create_router(state.clone());
+        for router in self.routers {
+            app = app.merge(router.with_state(state.clone()));
+        }
+        for layer in self.layers {
+            app = layer(app);
+        }
+
+        let headers = Arc::new(state.config.security_headers.clone());
+        app.layer(state.config.cors.layer())
+            .layer(middleware::map_response_with_state(
+                headers,
+                add_security_headers,
+            ))
+    }
+}
@@ -0,0 +1,435 @@
+use chrono::{DateTime, NaiveDateTime, SubsecRound, Utc};
+use diesel::prelude::*;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use ts_rs::TS;
+use utoipa::ToSchema;
+
+use super::ClientInfo;
+use crate::{
+    db::{schema::audit_events, DbConnection},
+    models::{AuditEvent, NewAuditEvent},
+};
+
+/// `prev_hash` of the first event in the chain
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+const VERIFY_BATCH_SIZE: i64 = 500;
+
+/// Security-relevant actions recorded in the audit log
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditAction {
+    Register,
+    Login,
+    Refresh,
+    Logout,
+    AdminGranted,
+    AccountDeleted,
+    AccountRestored,
+    ProfileUpdated,
+    WebhookCreated,
+    WebhookDeleted,
+}
+
+impl AuditAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::Register => "auth.register",
+            AuditAction::Login => "auth.login",
+            AuditAction::Refresh => "auth.refresh",
+            AuditAction::Logout => "auth.logout",
+            AuditAction::AdminGranted => "account.admin_granted",
+            AuditAction::AccountDeleted => "account.deleted",
+            AuditAction::AccountRestored => "account.restored",
+            AuditAction::ProfileUpdated => "account.profile_updated",
+            AuditAction::WebhookCreated => "webhook.created",
+            AuditAction::WebhookDeleted => "webhook.deleted",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditOutcome {
+    Success,
+    Failure,
+}
+
+impl AuditOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditOutcome::Success => "success",
+            AuditOutcome::Failure => "failure",
+        }
+    }
+}
+
+/// An event to be appended to the audit log
+#[derive(Debug)]
+pub struct AuditEntry {
+    action: AuditAction,
+    outcome: AuditOutcome,
+    actor_user_id: Option<i32>,
+    target: Option<String>,
+    metadata: serde_json::Value,
+}
+
+impl AuditEntry {
+    pub fn success(action: AuditAction) -> Self {
+        Self::new(action, AuditOutcome::Success)
+    }
+
+    pub fn failure(action: AuditAction) -> Self {
+        Self::new(action, AuditOutcome::Failure)
+    }
+
+    fn new(action: AuditAction, outcome: AuditOutcome) -> Self {
+        Self {
+            action,
+            outcome,
+            actor_user_id: None,
+            target: None,
+            metadata: serde_json::json!({}),
+        }
+    }
+
+    pub fn actor(mut self, user_id: i32) -> Self {
+        self.actor_user_id = Some(user_id);
+        self
+    }
+
+    pub fn target(mut self, target: impl Into<String>) -> Self {
+        self.target = Some(target.into());
+        self
+    }
+
+    pub fn metadata(mut self, metadata: serde_json::Value) -> Self {
+        self.metadata = metadata;
+        self
+    }
+}
+
+/// Result of walking the audit chain from the first event to the last
+#[derive(Debug, PartialEq, Eq, Serialize, ToSchema, TS)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum ChainVerification {
+    Intact {
+        #[ts(type = "number")]
+        events: u64,
+        head_hash: String,
+    },
+    Broken {
+        event_id: i32,
+        reason: ChainBreak,
+    },
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, ToSchema, TS)]
+#[serde(rename_all = "snake_case")]
+pub enum ChainBreak {
+    /// The event does not point at the hash of the event before it
+    PrevHashMismatch,
+    /// The event's contents no longer match its stored hash
+    HashMismatch,
+}
+
+/// The fields covered by an event's hash, in canonical order
+#[derive(Serialize)]
+struct ChainFields<'a> {
+    prev_hash: &'a str,
+    occurred_at: String,
+    actor_user_id: Option<i32>,
+    action: &'a str,
+    target: Option<&'a str>,
+    ip_address: Option<&'a str>,
+    user_agent: Option<&'a str>,
+    outcome: &'a str,
+    metadata: &'a str,
+}
+
+impl ChainFields<'_> {
+    fn hash(&self) -> String {
+        let canonical = serde_json::to_vec(self).expect("audit fields are always serializable");
+        hex::encode(Sha256::digest(canonical))
+    }
+}
+
+impl<'a> From<&'a AuditEvent> for ChainFields<'a> {
+    fn from(event: &'a AuditEvent) -> Self {
+        Self {
+            prev_hash: &event.prev_hash,
+            occurred_at: format_timestamp(event.occurred_at),
+            actor_user_id: event.actor_user_id,
+            action: &event.action,
+            target: event.target.as_deref(),
+            ip_address: event.ip_address.as_deref(),
+            user_agent: event.user_agent.as_deref(),
+            outcome: &event.outcome,
+            metadata: &event.metadata,
+        }
+    }
+}
+
+fn format_timestamp(timestamp: NaiveDateTime) -> String {
+    timestamp.format("%Y-%m-%dT%H:%M:%S%.6f").to_string()
+}
+
+/// Run `f` in a transaction that excludes other audit writers from the start
+///
+/// Nested in a caller's transaction, which must then be a
+/// `db::write_transaction`, it runs in a savepoint.
+#[cfg(feature = "sqlite")]
+fn chain_transaction<T, F>(conn: &mut DbConnection, f: F) -> QueryResult<T>
+where
+    F: FnOnce(&mut DbConnection) -> QueryResult<T>,
+{
+    if crate::db::in_transaction(conn) {
+        conn.transaction(f)
+    } else {
+        conn.immediate_transaction(f)
+    }
+}
+
+#[cfg(feature = "postgres")]
+fn chain_transaction<T, F>(conn: &mut DbConnection, f: F) -> QueryResult<T>
+where
+    F: FnOnce(&mut DbConnection) -> QueryResult<T>,
+{
+    conn.transaction(|conn| {
+        // Readers may continue; other writers wait until this one commits
+        diesel::sql_query("LOCK TABLE audit_events IN EXCLUSIVE MODE").execute(conn)?;
+        f(conn)
+    })
+}
+
+/// Append an event to the audit log, chaining it to the current head
+///
+/// Holds the chain's write lock while appending so concurrent writers cannot
+/// fork the chain. `now` comes from the caller's clock.
+pub fn record(
+    conn: &mut DbConnection,
+    client: &ClientInfo,
+    entry: AuditEntry,
+    now: DateTime<Utc>,
+) -> QueryResult<AuditEvent> {
+    chain_transaction(conn, |conn| {
+        let prev_hash = audit_events::table
+            .order(audit_events::id.desc())
+            .select(audit_events::hash)
+            .first::<String>(conn)
+            .optional()?
+            .unwrap_or_else(|| GENESIS_HASH.to_string());
+
+        // Stored timestamps round-trip at microsecond precision
+        let occurred_at = now.naive_utc().trunc_subsecs(6);
+        let metadata = entry.metadata.to_string();
+
+        let hash = ChainFields {
+            prev_hash: &prev_hash,
+            occurred_at: format_timestamp(occurred_at),
+            actor_user_id: entry.actor_user_id,
+            action: entry.action.as_str(),
+            target: entry.target.as_deref(),
+            ip_address: client.ip_address.as_deref(),
+            user_agent: client.user_agent.as_deref(),
+            outcome: entry.outcome.as_str(),
+            metadata: &metadata,
+        }
+        .hash();
+
+        let new_event = NewAuditEvent {
+            occurred_at,
+            actor_user_id: entry.actor_user_id,
+            action: entry.action.as_str().to_string(),
+            target: entry.target,
+            ip_address: client.ip_address.clone(),
+            user_agent: client.user_agent.clone(),
+            outcome: entry.outcome.as_str().to_string(),
+            metadata,
+            prev_hash,
+            hash,
+        };
+
+        diesel::insert_into(audit_events::table)
+            .values(&new_event)
+            .returning(AuditEvent::as_select())
+            .get_result(conn)
+    })
+}
+
+/// Recompute every hash in the audit log and report the first broken link
+///
+/// Removing events from the end of the chain cannot be detected this way;
+/// compare the returned head hash against a copy kept elsewhere for that.
+pub fn verify_chain(conn: &mut DbConnection) -> QueryResult<ChainVerification> {
+    let mut expected_prev = GENESIS_HASH.to_string();
+    let mut last_id = 0;
+    let mut events = 0;
+
+    loop {
+        let batch: Vec<AuditEvent> = audit_events::table
+            .filter(audit_events::id.gt(last_id))
+            .order(audit_events::id.asc())
+            .limit(VERIFY_BATCH_SIZE)
+            .select(AuditEvent::as_select())
+            .load(conn)?;
+
+        if batch.is_empty() {
+            break;
+        }
+
+        for event in &batch {
+            if event.prev_hash != expected_prev {
+                return Ok(ChainVerification::Broken {
+                    event_id: event.id,
+                    reason: ChainBreak::PrevHashMismatch,
+                });
+            }
+            if ChainFields::from(event).hash() != event.hash {
+                return Ok(ChainVerification::Broken {
+                    event_id: event.id,
+                    reason: ChainBreak::HashMismatch,
+                });
+            }
+            expected_prev.clone_from(&event.hash);
+            last_id = event.id;
+            events += 1;
+        }
+    }
+
+    Ok(ChainVerification::Intact {
+        events,
+        head_hash: expected_prev,
+    })
+}
+
+// Postgres runs these through the integration tests, which need a server
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::*;
+    use diesel::connection::SimpleConnection;
+
+    fn setup() -> DbConnection {
+        let mut conn =
+            DbConnection::establish(":memory:").expect("Failed to open in-memory database");
+        conn.batch_execute(include_str!(
+            "../../migrations/sqlite/00000000000003_create_audit_events/up.sql"
+        ))
+        .expect("Failed to create audit_events table");
+        conn
+    }
+
+    fn client() -> ClientInfo {
+        ClientInfo {
+            ip_address: Some("203.0.113.7".to_string()),
+            user_agent: Some("test-agent".to_string()),
+        }
+    }
+
+    fn record_sample_events(conn: &mut DbConnection) -> Vec<AuditEvent> {
+        vec![
+            record(
+                conn,
+                &client(),
+                AuditEntry::success(AuditAction::Register).actor(1),
+                Utc::now(),
+            )
+            .expect("Failed to record event"),
+            record(
+                conn,
+                &client(),
+                AuditEntry::failure(AuditAction::Login)
+                    .target("test@example.com")
+                    .metadata(serde_json::json!({ "reason": "invalid_password" })),
+                Utc::now(),
+            )
+            .expect("Failed to record event"),
+            record(
+                conn,
+                &client(),
+                AuditEntry::success(AuditAction::Login).actor(1),
+                Utc::now(),
+            )
+            .expect("Failed to record event"),
+        ]
+    }
+
+    #[test]
+    fn test_events_are_chained() {
+        let mut conn = setup();
+        let events = record_sample_events(&mut conn);
+
+        assert_eq!(events[0].prev_hash, GENESIS_HASH);
+        assert_eq!(events[1].prev_hash, events[0].hash);
+        assert_eq!(events[2].prev_hash, events[1].hash);
+    }
+
+    #[test]
+    fn test_timestamps_and_hashes_follow_the_callers_clock() {
+        let at = DateTime::parse_from_rfc3339("2024-01-02T03:04:05.123456789Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let events: Vec<AuditEvent> = (0..2)
+            .map(|_| {
+                let entry = AuditEntry::success(AuditAction::Login).actor(1);
+                record(&mut setup(), &client(), entry, at).expect("Failed to record event")
+            })
+            .collect();
+
+        assert_eq!(events[0].occurred_at, at.naive_utc().trunc_subsecs(6));
+        assert_eq!(events[0].hash, events[1].hash);
+    }
+
+    #[test]
+    fn test_verify_intact_chain() {
+        let mut conn = setup();
+        let events = record_sample_events(&mut conn);
+
+        let result = verify_chain(&mut conn).expect("Failed to verify chain");
+        assert_eq!(
+            result,
+            ChainVerification::Intact {
+                events: 3,
+                head_hash: events[2].hash.clone(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_verify_detects_modified_event() {
+        let mut conn = setup();
+        let events = record_sample_events(&mut conn);
+
+        diesel::update(audit_events::table.find(events[1].id))
+            .set(audit_events::outcome.eq("success"))
+            .execute(&mut conn)
+            .expect("Failed to tamper with event");
+
+        let result = verify_chain(&mut conn).expect("Failed to verify chain");
+        assert_eq!(
+            result,
+            ChainVerification::Broken {
+                event_id: events[1].id,
+                reason: ChainBreak::HashMismatch,
+            }
+        );
+    }
+
+    #[test]
+    fn test_verify_detects_deleted_event() {
+        let mut conn = setup();
+        let events = record_sample_events(&mut conn);
+
+        diesel::delete(audit_events::table.find(events[1].id))
+            .execute(&mut conn)
+            .expect("Failed to delete event");
+
+        let result = verify_chain(&mut conn).expect("Failed to verify chain");
+        assert_eq!(
+            result,
+            ChainVerification::Broken {
+                event_id: events[2].id,
+                reason: ChainBreak::PrevHashMismatch,
+            }
+        );
+    }
+}
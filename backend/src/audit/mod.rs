@@ -0,0 +1,5 @@
+pub mod client;
+pub mod log;
+
+pub use client::ClientInfo;
+pub use log::{record, verify_chain, AuditAction, AuditEntry, ChainVerification};
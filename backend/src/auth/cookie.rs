@@ -0,0 +1,211 @@
+use std::{fmt, str::FromStr};
+
+use axum::http::{header, HeaderMap, HeaderName};
+
+use crate::config::Profile;
+
+/// `SameSite` attribute of the session cookies
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    /// Sent on cross-site requests too; browsers require `Secure` with it
+    None,
+}
+
+impl SameSite {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SameSite::Strict => "Strict",
+            SameSite::Lax => "Lax",
+            SameSite::None => "None",
+        }
+    }
+}
+
+impl fmt::Display for SameSite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for SameSite {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "strict" => Ok(SameSite::Strict),
+            "lax" => Ok(SameSite::Lax),
+            "none" => Ok(SameSite::None),
+            _ => Err("expected strict, lax or none".to_string()),
+        }
+    }
+}
+
+/// Request header that overrides `auth.refresh_token_transport`
+pub const REFRESH_TOKEN_TRANSPORT_HEADER: HeaderName =
+    HeaderName::from_static("x-refresh-token-transport");
+
+/// Where `login`, `register` and `refresh` return the refresh token
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RefreshTokenTransport {
+    /// In the JSON body, for native clients that store it themselves
+    #[default]
+    Body,
+    /// In an HttpOnly cookie scoped to `/api/auth`, out of reach of scripts
+    Cookie,
+}
+
+impl RefreshTokenTransport {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RefreshTokenTransport::Body => "body",
+            RefreshTokenTransport::Cookie => "cookie",
+        }
+    }
+}
+
+impl fmt::Display for RefreshTokenTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for RefreshTokenTransport {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "body" => Ok(RefreshTokenTransport::Body),
+            "cookie" => Ok(RefreshTokenTransport::Cookie),
+            _ => Err("expected body or cookie".to_string()),
+        }
+    }
+}
+
+/// Name and attributes of the `access_token` cookie
+#[derive(Clone, Debug)]
+pub struct CookieOptions {
+    pub name: String,
+    /// Name of the cookie holding the refresh token, with the cookie
+    /// transport
+    pub refresh_name: String,
+    /// Only send the cookie over HTTPS; on by default in `prod`
+    pub secure: bool,
+    pub same_site: SameSite,
+    /// Share the cookie with subdomains of this domain instead of only the
+    /// host that set it
+    pub domain: Option<String>,
+    pub path: String,
+}
+
+impl Default for CookieOptions {
+    fn default() -> Self {
+        Self::defaults(Profile::Dev)
+    }
+}
+
+impl CookieOptions {
+    pub fn defaults(profile: Profile) -> Self {
+        Self {
+            name: "access_token".to_string(),
+            refresh_name: "refresh_token".to_string(),
+            secure: profile == Profile::Prod,
+            same_site: SameSite::Lax,
+            domain: None,
+            path: "/".to_string(),
+        }
+    }
+
+    /// The same attributes for another cookie
+    pub fn named(&self, name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            ..self.clone()
+        }
+    }
+
+    /// The refresh token cookie, only sent back to the auth endpoints
+    ///
+    /// Always `Secure`, whatever `secure` says: it outlives the access token
+    /// by weeks, and browsers accept `Secure` cookies on `http://localhost`.
+    pub fn refresh_token(&self) -> Self {
+        Self {
+            name: self.refresh_name.clone(),
+            path: "/api/auth".to_string(),
+            secure: true,
+            ..self.clone()
+        }
+    }
+
+    /// `Set-Cookie` value storing `value` for `max_age` seconds
+    pub fn set_cookie(&self, value: &str, max_age: i64) -> String {
+        let mut cookie = format!(
+            "{}={}; HttpOnly; SameSite={}; Path={}; Max-Age={}",
+            self.name, value, self.same_site, self.path, max_age
+        );
+        if self.secure {
+            cookie.push_str("; Secure");
+        }
+        if let Some(domain) = &self.domain {
+            cookie.push_str("; Domain=");
+            cookie.push_str(domain);
+        }
+        cookie
+    }
+
+    /// The cookie's value in a request, if it was sent
+    pub fn find<'a>(&self, headers: &'a HeaderMap) -> Option<&'a str> {
+        headers
+            .get_all(header::COOKIE)
+            .iter()
+            .filter_map(|header| header.to_str().ok())
+            .flat_map(|cookies| cookies.split(';'))
+            .find_map(|cookie| {
+                let (name, value) = cookie.trim().split_once('=')?;
+                (name == self.name).then_some(value)
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_cookie_carries_the_configured_attributes() {
+        let dev = CookieOptions::defaults(Profile::Dev);
+        assert_eq!(
+            dev.set_cookie("abc", 900),
+            "access_token=abc; HttpOnly; SameSite=Lax; Path=/; Max-Age=900"
+        );
+
+        let prod = CookieOptions {
+            name: "session".to_string(),
+            same_site: SameSite::Strict,
+            domain: Some("example.com".to_string()),
+            ..CookieOptions::defaults(Profile::Prod)
+        };
+        assert_eq!(
+            prod.set_cookie("abc", 900),
+            "session=abc; HttpOnly; SameSite=Strict; Path=/; Max-Age=900; Secure; Domain=example.com"
+        );
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::COOKIE, "theme=dark; session=abc".parse().unwrap());
+        assert_eq!(prod.find(&headers), Some("abc"));
+        assert_eq!(dev.find(&headers), None);
+    }
+
+    #[test]
+    fn test_refresh_cookie_is_always_secure() {
+        let dev = CookieOptions {
+            refresh_name: "rt".to_string(),
+            ..CookieOptions::defaults(Profile::Dev)
+        };
+        assert_eq!(
+            dev.refresh_token().set_cookie("abc", 60),
+            "rt=abc; HttpOnly; SameSite=Lax; Path=/api/auth; Max-Age=60; Secure"
+        );
+    }
+}
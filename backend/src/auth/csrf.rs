@@ -0,0 +1,197 @@
+//! CSRF protection for requests authenticated by the session cookie
+//!
+//! Browsers attach cookies to cross-site requests, so an unsafe request that
+//! relies on the cookie must also show it came from the frontend:
+//!
+//! - `Sec-Fetch-Site`, or failing that `Origin`, must name this host or an
+//!   allowed CORS origin, and
+//! - the CSRF header must repeat the CSRF cookie, a random nonce signed with
+//!   a key derived from the JWT secret together with the session's user (a
+//!   signed double submit). Another site can neither read the cookie nor
+//!   mint a token that verifies, and a token planted from another session,
+//!   for example through a sibling subdomain, does not verify for this one.
+//!
+//! Requests with a bearer token are exempt: browsers never add one on their
+//! own.
+
+use axum::http::{
+    header::{HOST, ORIGIN},
+    HeaderMap, HeaderName, Method,
+};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::config::Config;
+
+type HmacSha256 = Hmac<Sha256>;
+
+const SEC_FETCH_SITE: HeaderName = HeaderName::from_static("sec-fetch-site");
+
+/// Cookie lifetime, matching the refresh token's
+pub const CSRF_COOKIE_MAX_AGE: i64 = 30 * 24 * 60 * 60;
+
+#[derive(Clone, Debug)]
+pub struct CsrfOptions {
+    /// Refused only by the `prod` profile when off
+    pub enabled: bool,
+    pub cookie_name: String,
+    pub header_name: HeaderName,
+}
+
+impl Default for CsrfOptions {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            cookie_name: "csrf_token".to_string(),
+            header_name: HeaderName::from_static("x-csrf-token"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum CsrfError {
+    #[error("cross-site request from {0}")]
+    CrossSite(String),
+    #[error("missing CSRF token; fetch one from /api/auth/csrf")]
+    MissingToken,
+    #[error("CSRF token does not match the cookie")]
+    Mismatch,
+}
+
+/// Issues and verifies signed tokens
+pub struct CsrfTokens {
+    mac: HmacSha256,
+}
+
+impl CsrfTokens {
+    pub fn from_secret(secret: &[u8]) -> Self {
+        // Separate from the JWT signing key, so one never verifies as the other
+        let mut derive = HmacSha256::new_from_slice(secret).expect("HMAC accepts any key length");
+        derive.update(b"csrf-token-key");
+        let key = derive.finalize().into_bytes();
+        Self {
+            mac: HmacSha256::new_from_slice(&key).expect("HMAC accepts any key length"),
+        }
+    }
+
+    /// A fresh `<nonce>.<signature>` token for `session`, the `sub` of the
+    /// signed-in user or empty when nobody is
+    pub fn issue(&self, session: &str) -> String {
+        let nonce = hex::encode(rand::random::<[u8; 16]>());
+        let signature = self.sign(&nonce, session).finalize().into_bytes();
+        format!("{}.{}", nonce, hex::encode(signature))
+    }
+
+    /// Whether `token` was issued for `session`
+    pub fn verify(&self, token: &str, session: &str) -> bool {
+        let Some((nonce, signature)) = token.split_once('.') else {
+            return false;
+        };
+        let Ok(signature) = hex::decode(signature) else {
+            return false;
+        };
+        self.sign(nonce, session).verify_slice(&signature).is_ok()
+    }
+
+    fn sign(&self, nonce: &str, session: &str) -> HmacSha256 {
+        // The nonce never contains a dot, so the input splits one way only
+        let mut mac = self.mac.clone();
+        mac.update(nonce.as_bytes());
+        mac.update(b".");
+        mac.update(session.as_bytes());
+        mac
+    }
+}
+
+/// The CSRF cookie's value, if it holds a valid token for `session`
+pub fn cookie_token<'a>(
+    config: &Config,
+    tokens: &CsrfTokens,
+    headers: &'a HeaderMap,
+    session: &str,
+) -> Option<&'a str> {
+    config
+        .cookie
+        .named(&config.csrf.cookie_name)
+        .find(headers)
+        .filter(|token| tokens.verify(token, session))
+}
+
+/// Check an unsafe request that was authenticated by a session cookie of
+/// `session`, the user's `sub`
+///
+/// Safe methods pass; they must not change anything.
+pub fn check(
+    config: &Config,
+    tokens: &CsrfTokens,
+    method: &Method,
+    headers: &HeaderMap,
+    session: &str,
+) -> Result<(), CsrfError> {
+    if !config.csrf.enabled || method.is_safe() {
+        return Ok(());
+    }
+    check_origin(config, headers)?;
+
+    let sent = headers
+        .get(&config.csrf.header_name)
+        .and_then(|value| value.to_str().ok())
+        .ok_or(CsrfError::MissingToken)?;
+    match cookie_token(config, tokens, headers, session) {
+        Some(cookie) if cookie == sent => Ok(()),
+        _ => Err(CsrfError::Mismatch),
+    }
+}
+
+fn check_origin(config: &Config, headers: &HeaderMap) -> Result<(), CsrfError> {
+    let header = |name| headers.get(name).and_then(|value| value.to_str().ok());
+    let origin = header(ORIGIN);
+    let allowed = |origin: &str| {
+        let same_host = origin
+            .split_once("://")
+            .is_some_and(|(_, host)| Some(host) == header(HOST));
+        same_host
+            || config
+                .cors
+                .allowed_origins
+                .iter()
+                .any(|allowed| allowed == origin)
+    };
+
+    match (header(SEC_FETCH_SITE), origin) {
+        (Some("same-origin" | "none"), _) => Ok(()),
+        // Older browsers and other clients: no Origin says nothing either way
+        (None, None) => Ok(()),
+        (_, Some(origin)) if allowed(origin) => Ok(()),
+        (_, origin) => Err(CsrfError::CrossSite(
+            origin.unwrap_or("another site").to_string(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokens_verify_only_with_the_issuing_key() {
+        let tokens = CsrfTokens::from_secret(b"secret");
+        let token = tokens.issue("7");
+        assert!(tokens.verify(&token, "7"));
+        assert_ne!(token, tokens.issue("7"));
+
+        let (nonce, _) = token.split_once('.').unwrap();
+        assert!(!tokens.verify(&format!("{}.{}", nonce, "00".repeat(32)), "7"));
+        assert!(!tokens.verify(nonce, "7"));
+        assert!(!CsrfTokens::from_secret(b"other").verify(&token, "7"));
+    }
+
+    #[test]
+    fn test_tokens_verify_only_for_the_issuing_session() {
+        let tokens = CsrfTokens::from_secret(b"secret");
+        let token = tokens.issue("7");
+        assert!(!tokens.verify(&token, "8"));
+        assert!(!tokens.verify(&token, ""));
+        assert!(!tokens.verify(&tokens.issue(""), "7"));
+    }
+}
@@ -1,21 +1,23 @@
-use chrono::{Duration, Utc};
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use chrono::{DateTime, Duration, Utc};
+use jsonwebtoken::{
+    decode, encode, errors::ErrorKind, DecodingKey, EncodingKey, Header, Validation,
+};
 use serde::{Deserialize, Serialize};
-use std::env;
 
 const ACCESS_TOKEN_DURATION_MINUTES: i64 = 15;
+/// Clock skew tolerated when checking `exp`, matching jsonwebtoken's default
+const EXPIRY_LEEWAY_SECONDS: i64 = 60;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Claims {
-    pub sub: String,      // Subject (user_id)
-    pub email: String,    // User email
-    pub exp: i64,         // Expiration time
-    pub iat: i64,         // Issued at
+    pub sub: String,   // Subject (user_id)
+    pub email: String, // User email
+    pub exp: i64,      // Expiration time
+    pub iat: i64,      // Issued at
 }
 
 impl Claims {
-    pub fn new(user_id: i32, email: String) -> Self {
-        let now = Utc::now();
+    pub fn new(user_id: i32, email: String, now: DateTime<Utc>) -> Self {
         let expiration = now + Duration::minutes(ACCESS_TOKEN_DURATION_MINUTES);
 
         Self {
@@ -27,53 +29,75 @@ impl Claims {
     }
 }
 
-/// Create a new JWT access token
-pub fn create_token(user_id: i32, email: String) -> Result<String, jsonwebtoken::errors::Error> {
-    let claims = Claims::new(user_id, email);
-    let secret = get_jwt_secret();
-    encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(secret.as_bytes()),
-    )
+/// Signing and verification keys for access tokens
+pub struct JwtKeys {
+    encoding: EncodingKey,
+    decoding: DecodingKey,
+}
+
+impl JwtKeys {
+    pub fn from_secret(secret: &[u8]) -> Self {
+        Self {
+            encoding: EncodingKey::from_secret(secret),
+            decoding: DecodingKey::from_secret(secret),
+        }
+    }
 }
 
-/// Verify and decode a JWT token
-pub fn verify_token(token: &str) -> Result<Claims, jsonwebtoken::errors::Error> {
-    let secret = get_jwt_secret();
-    let validation = Validation::default();
-    let token_data = decode::<Claims>(
-        token,
-        &DecodingKey::from_secret(secret.as_bytes()),
-        &validation,
-    )?;
-    Ok(token_data.claims)
+/// Create a new JWT access token issued at `now`
+pub fn create_token(
+    keys: &JwtKeys,
+    user_id: i32,
+    email: String,
+    now: DateTime<Utc>,
+) -> Result<String, jsonwebtoken::errors::Error> {
+    let claims = Claims::new(user_id, email, now);
+    encode(&Header::default(), &claims, &keys.encoding)
 }
 
-fn get_jwt_secret() -> String {
-    env::var("JWT_SECRET").unwrap_or_else(|_| {
-        tracing::warn!("JWT_SECRET not set, using default (NOT SECURE FOR PRODUCTION)");
-        "your-secret-key-change-this-in-production".to_string()
-    })
+/// Verify and decode a JWT token, checking expiry against `now`
+pub fn verify_token(
+    keys: &JwtKeys,
+    token: &str,
+    now: DateTime<Utc>,
+) -> Result<Claims, jsonwebtoken::errors::Error> {
+    // Expiry is checked below against the injected clock, not the system time
+    let mut validation = Validation::default();
+    validation.validate_exp = false;
+    validation.set_required_spec_claims(&["exp"]);
+
+    let claims = decode::<Claims>(token, &keys.decoding, &validation)?.claims;
+    if claims.exp < now.timestamp() - EXPIRY_LEEWAY_SECONDS {
+        return Err(ErrorKind::ExpiredSignature.into());
+    }
+    Ok(claims)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn keys() -> JwtKeys {
+        JwtKeys::from_secret(b"test-secret")
+    }
+
     #[test]
     fn test_create_token() {
-        let token = create_token(1, "test@example.com".to_string()).expect("Failed to create token");
+        let token = create_token(&keys(), 1, "test@example.com".to_string(), Utc::now())
+            .expect("Failed to create token");
         assert!(!token.is_empty());
     }
 
     #[test]
     fn test_verify_token_valid() {
+        let keys = keys();
         let user_id = 1;
         let email = "test@example.com".to_string();
-        let token = create_token(user_id, email.clone()).expect("Failed to create token");
+        let now = Utc::now();
+        let token =
+            create_token(&keys, user_id, email.clone(), now).expect("Failed to create token");
 
-        let claims = verify_token(&token).expect("Failed to verify token");
+        let claims = verify_token(&keys, &token, now).expect("Failed to verify token");
         assert_eq!(claims.sub, user_id.to_string());
         assert_eq!(claims.email, email);
     }
@@ -81,17 +105,41 @@ mod tests {
     #[test]
     fn test_verify_token_invalid() {
         let invalid_token = "invalid.token.here";
-        let result = verify_token(invalid_token);
+        let result = verify_token(&keys(), invalid_token, Utc::now());
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_verify_token_wrong_secret() {
+        let now = Utc::now();
+        let token = create_token(&keys(), 1, "test@example.com".to_string(), now).unwrap();
+
+        let result = verify_token(&JwtKeys::from_secret(b"other-secret"), &token, now);
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_verify_token_expired() {
+        let keys = keys();
+        let now = Utc::now();
+        let token = create_token(&keys, 1, "test@example.com".to_string(), now).unwrap();
+
+        let within_leeway =
+            now + Duration::minutes(ACCESS_TOKEN_DURATION_MINUTES) + Duration::seconds(30);
+        assert!(verify_token(&keys, &token, within_leeway).is_ok());
+
+        let expired = now + Duration::minutes(ACCESS_TOKEN_DURATION_MINUTES + 2);
+        let err = verify_token(&keys, &token, expired).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::ExpiredSignature);
+    }
+
     #[test]
     fn test_claims_contains_expiration() {
-        let claims = Claims::new(1, "test@example.com".to_string());
-        let now = Utc::now().timestamp();
-        let expected_exp = now + (ACCESS_TOKEN_DURATION_MINUTES * 60);
+        let now = Utc::now();
+        let claims = Claims::new(1, "test@example.com".to_string(), now);
+        let expected_exp = now.timestamp() + (ACCESS_TOKEN_DURATION_MINUTES * 60);
 
-        // Allow 2 second variance for test execution time
-        assert!((claims.exp - expected_exp).abs() < 2);
+        assert_eq!(claims.exp, expected_exp);
+        assert_eq!(claims.iat, now.timestamp());
     }
 }
@@ -1,29 +1,40 @@
+use std::sync::Arc;
+
+use super::{
+    csrf::{self, CsrfTokens},
+    jwt::{verify_token, Claims, JwtKeys},
+};
+use crate::{
+    clock::SharedClock,
+    config::Config,
+    db::Db,
+    error::{AppError, AppResult},
+    repositories::SharedUserRepository,
+};
 use axum::{
-    extract::Request,
-    http::{header, StatusCode},
+    extract::{Request, State},
+    http::header,
     middleware::Next,
-    response::{IntoResponse, Response},
+    response::Response,
 };
 
-use super::jwt::{verify_token, Claims};
-
 /// Extension type to store authenticated user claims
 #[derive(Clone, Debug)]
 pub struct AuthUser(pub Claims);
 
 /// Middleware to require authentication for a route
-pub async fn require_auth(mut request: Request, next: Next) -> Result<Response, Response> {
-    // Try to get token from cookie first
-    let token = request
-        .headers()
-        .get(header::COOKIE)
-        .and_then(|header| header.to_str().ok())
-        .and_then(|cookies| {
-            cookies
-                .split(';')
-                .find(|cookie| cookie.trim().starts_with("access_token="))
-                .map(|cookie| cookie.trim().trim_start_matches("access_token="))
-        })
+pub async fn require_auth(
+    State(keys): State<Arc<JwtKeys>>,
+    State(clock): State<SharedClock>,
+    State(config): State<Arc<Config>>,
+    State(csrf): State<Arc<CsrfTokens>>,
+    mut request: Request,
+    next: Next,
+) -> AppResult<Response> {
+    // Try to get token from cookie first; browsers send it on their own, so
+    // unsafe requests must also pass the CSRF check
+    let cookie = config.cookie.find(request.headers());
+    let token = cookie
         // Fallback to Authorization header
         .or_else(|| {
             request
@@ -33,25 +44,51 @@ pub async fn require_auth(mut request: Request, next: Next) -> Result<Response,
                 .and_then(|auth| auth.strip_prefix("Bearer "))
         });
 
-    let token = token.ok_or_else(|| {
-        (
-            StatusCode::UNAUTHORIZED,
-            "Missing authentication token".to_string(),
-        )
-            .into_response()
-    })?;
+    let token = token.ok_or(AppError::MissingToken)?;
 
-    let claims = verify_token(token).map_err(|e| {
+    let claims = verify_token(&keys, token, clock.now()).map_err(|e| {
         tracing::warn!("Invalid token: {:?}", e);
-        (
-            StatusCode::UNAUTHORIZED,
-            "Invalid or expired token".to_string(),
-        )
-            .into_response()
+        AppError::InvalidToken
     })?;
+    if cookie.is_some() {
+        csrf::check(
+            &config,
+            &csrf,
+            request.method(),
+            request.headers(),
+            &claims.sub,
+        )?;
+    }
 
     // Insert claims into request extensions
     request.extensions_mut().insert(AuthUser(claims));
 
     Ok(next.run(request).await)
 }
+
+/// Middleware to restrict a route to administrators
+///
+/// Must run after `require_auth`, which provides the `AuthUser` extension.
+pub async fn require_admin(
+    State(db): State<Db>,
+    State(users): State<SharedUserRepository>,
+    request: Request,
+    next: Next,
+) -> AppResult<Response> {
+    let user_id: i32 = request
+        .extensions()
+        .get::<AuthUser>()
+        .and_then(|auth_user| auth_user.0.sub.parse().ok())
+        .ok_or(AppError::MissingToken)?;
+
+    let is_admin = db
+        .read(move |conn| users.find_by_id(conn, user_id))
+        .await?
+        .is_some_and(|user| user.is_admin);
+
+    if !is_admin {
+        return Err(AppError::AdminRequired);
+    }
+
+    Ok(next.run(request).await)
+}
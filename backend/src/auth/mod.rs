@@ -1,7 +1,13 @@
+pub mod cookie;
+pub mod csrf;
 pub mod jwt;
 pub mod middleware;
 pub mod password;
+pub mod session;
 
-pub use jwt::{create_token, verify_token, Claims};
-pub use middleware::{require_auth, AuthUser};
+pub use cookie::{CookieOptions, RefreshTokenTransport, SameSite, REFRESH_TOKEN_TRANSPORT_HEADER};
+pub use csrf::{CsrfError, CsrfOptions, CsrfTokens};
+pub use jwt::{create_token, JwtKeys};
+pub use middleware::{require_admin, require_auth, AuthUser};
 pub use password::{hash_password, verify_password};
+pub use session::{IssuedSession, SessionIssuer};
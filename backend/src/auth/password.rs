@@ -62,6 +62,9 @@ mod tests {
         let password = "my_secure_password123";
         let hash1 = hash_password(password).expect("Failed to hash password");
         let hash2 = hash_password(password).expect("Failed to hash password");
-        assert_ne!(hash1, hash2, "Same password should produce different hashes due to different salts");
+        assert_ne!(
+            hash1, hash2,
+            "Same password should produce different hashes due to different salts"
+        );
     }
 }
@@ -0,0 +1,218 @@
+//! Issuing sessions
+//!
+//! `register`, `login` and `refresh` all end in [`SessionIssuer`], so they
+//! return the same body and set the same cookies. Sessions start and resume
+//! inside the handler's write transaction, next to its audit entry and
+//! events.
+
+use std::sync::Arc;
+
+use axum::{
+    http::{header, HeaderMap, Method, StatusCode},
+    response::{AppendHeaders, IntoResponse, Response},
+    Json,
+};
+
+use super::{
+    cookie::{RefreshTokenTransport, REFRESH_TOKEN_TRANSPORT_HEADER},
+    csrf::{self, CsrfTokens},
+    jwt::{create_token, verify_token, JwtKeys},
+};
+use crate::{
+    audit::ClientInfo,
+    clock::SharedClock,
+    config::Config,
+    db::{Db, DbConnection},
+    error::{AppError, AppResult},
+    models::{AuthResponse, NewRefreshToken, RefreshToken, User},
+    repositories::SharedSessionRepository,
+};
+
+/// Lifetime of the `access_token` cookie, matching the access token's
+pub const ACCESS_COOKIE_MAX_AGE: i64 = 15 * 60;
+
+/// Creates the tokens and cookies of a signed-in session
+#[derive(Clone)]
+pub struct SessionIssuer {
+    db: Db,
+    config: Arc<Config>,
+    keys: Arc<JwtKeys>,
+    csrf: Arc<CsrfTokens>,
+    clock: SharedClock,
+    sessions: SharedSessionRepository,
+}
+
+/// A session ready to be returned: the body and its `Set-Cookie` values
+#[derive(Debug)]
+pub struct IssuedSession {
+    pub body: AuthResponse,
+    pub cookies: Vec<String>,
+}
+
+impl IntoResponse for IssuedSession {
+    fn into_response(self) -> Response {
+        let cookies = self
+            .cookies
+            .into_iter()
+            .map(|cookie| (header::SET_COOKIE, cookie));
+        (StatusCode::OK, AppendHeaders(cookies), Json(self.body)).into_response()
+    }
+}
+
+impl SessionIssuer {
+    pub fn new(
+        db: Db,
+        config: Arc<Config>,
+        keys: Arc<JwtKeys>,
+        csrf: Arc<CsrfTokens>,
+        clock: SharedClock,
+        sessions: SharedSessionRepository,
+    ) -> Self {
+        Self {
+            db,
+            config,
+            keys,
+            csrf,
+            clock,
+            sessions,
+        }
+    }
+
+    /// Where to return the refresh token: as the request asks, else as
+    /// configured
+    pub fn transport(&self, headers: &HeaderMap) -> AppResult<RefreshTokenTransport> {
+        let Some(value) = headers.get(REFRESH_TOKEN_TRANSPORT_HEADER) else {
+            return Ok(self.config.refresh_token_transport);
+        };
+        value
+            .to_str()
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .ok_or_else(|| {
+                AppError::InvalidHeader(format!(
+                    "{} must be body or cookie",
+                    REFRESH_TOKEN_TRANSPORT_HEADER
+                ))
+            })
+    }
+
+    /// The session of the refresh token in the body, or else in its cookie
+    ///
+    /// Browsers send the cookie on their own, so it must pass the CSRF check
+    /// for the session's user.
+    pub async fn presented_session(
+        &self,
+        headers: &HeaderMap,
+        body: Option<String>,
+    ) -> AppResult<Option<RefreshToken>> {
+        if let Some(id) = body {
+            return self.find(id).await;
+        }
+        let Some(id) = self.config.cookie.refresh_token().find(headers) else {
+            return Ok(None);
+        };
+        let session = self.find(id.to_string()).await?;
+        if let Some(session) = &session {
+            let user = session.user_id.to_string();
+            csrf::check(&self.config, &self.csrf, &Method::POST, headers, &user)?;
+        }
+        Ok(session)
+    }
+
+    /// The user the request's session cookies belong to, as the `sub` CSRF
+    /// tokens are bound to; empty without a session
+    pub async fn principal(&self, headers: &HeaderMap) -> AppResult<String> {
+        let access_token = self.config.cookie.find(headers);
+        if let Some(claims) =
+            access_token.and_then(|token| verify_token(&self.keys, token, self.clock.now()).ok())
+        {
+            return Ok(claims.sub);
+        }
+        // The access token expires first; the refresh cookie outlives it
+        let session = match self.config.cookie.refresh_token().find(headers) {
+            Some(id) => self.find(id.to_string()).await?,
+            None => None,
+        };
+        Ok(session
+            .map(|session| session.user_id.to_string())
+            .unwrap_or_default())
+    }
+
+    /// Start a session for `user` with a new refresh token
+    pub fn start(
+        &self,
+        conn: &mut DbConnection,
+        user: User,
+        client: &ClientInfo,
+        transport: RefreshTokenTransport,
+    ) -> AppResult<IssuedSession> {
+        let new_token = NewRefreshToken {
+            ip_address: client.ip_address.clone(),
+            user_agent: client.user_agent.clone(),
+            ..NewRefreshToken::new(user.id, self.clock.now())
+        };
+        let session = self.sessions.insert(conn, new_token)?;
+        self.issue(user, &session, transport)
+    }
+
+    /// Continue `session` with a new access token
+    pub fn resume(
+        &self,
+        conn: &mut DbConnection,
+        user: User,
+        session: &RefreshToken,
+        transport: RefreshTokenTransport,
+    ) -> AppResult<IssuedSession> {
+        let now = self.clock.now().naive_utc();
+        self.sessions.mark_used(conn, &session.id, now)?;
+        self.issue(user, session, transport)
+    }
+
+    /// `Set-Cookie` values that remove both session cookies
+    pub fn end(&self) -> [String; 2] {
+        let cookie = &self.config.cookie;
+        [
+            cookie.set_cookie("", 0),
+            cookie.refresh_token().set_cookie("", 0),
+        ]
+    }
+
+    async fn find(&self, id: String) -> AppResult<Option<RefreshToken>> {
+        let sessions = self.sessions.clone();
+        self.db.read(move |conn| sessions.find(conn, &id)).await
+    }
+
+    fn issue(
+        &self,
+        user: User,
+        session: &RefreshToken,
+        transport: RefreshTokenTransport,
+    ) -> AppResult<IssuedSession> {
+        let now = self.clock.now();
+        let access_token = create_token(&self.keys, user.id, user.email.clone(), now)?;
+
+        let mut cookies = vec![self
+            .config
+            .cookie
+            .set_cookie(&access_token, ACCESS_COOKIE_MAX_AGE)];
+        let refresh_token = match transport {
+            RefreshTokenTransport::Body => Some(session.id.clone()),
+            RefreshTokenTransport::Cookie => {
+                // Until the refresh token expires
+                let max_age = (session.expires_at - now.naive_utc()).num_seconds().max(0);
+                let cookie = self.config.cookie.refresh_token();
+                cookies.push(cookie.set_cookie(&session.id, max_age));
+                None
+            }
+        };
+
+        Ok(IssuedSession {
+            body: AuthResponse {
+                user: user.into(),
+                access_token,
+                refresh_token,
+            },
+            cookies,
+        })
+    }
+}
@@ -0,0 +1,506 @@
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::Arc;
+
+use clap::{Args, Parser, Subcommand};
+use diesel::prelude::*;
+
+use crate::{
+    audit::{self, AuditAction, AuditEntry, ChainVerification, ClientInfo},
+    clock::{Clock, SystemClock},
+    codegen,
+    config::{Config, Overrides, INSECURE_JWT_SECRET},
+    db::{self, migrations, schema::users, Db, DbPool},
+    events::{self, DispatchEvent, EventBus},
+    mail::{self, MailTransport, Outbox, SendEmail, SharedMailer},
+    models::User,
+    queue::{Queue, QueueOptions, Workers},
+    repositories::DieselSessionRepository,
+    scheduler::{InvalidSchedule, JobRegistry, PurgeExpiredTokens, Scheduler, SchedulerOptions},
+    webhooks::{DeliverWebhook, Deliverer},
+    App,
+};
+
+#[derive(Debug, Parser)]
+#[command(version, about = "Web app template backend")]
+pub struct Cli {
+    #[command(flatten)]
+    pub config: ConfigArgs,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Settings that override the config file and environment
+#[derive(Debug, Default, Args)]
+pub struct ConfigArgs {
+    /// TOML file to read settings from [default: $APP_CONFIG]
+    #[arg(long, global = true, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+    /// dev, test or prod; prod refuses unsafe settings
+    #[arg(long, global = true)]
+    pub profile: Option<String>,
+    #[arg(long, global = true)]
+    pub host: Option<String>,
+    #[arg(long, global = true)]
+    pub port: Option<String>,
+    #[arg(long, global = true, value_name = "URL")]
+    pub database_url: Option<String>,
+    /// Override any setting, e.g. `--set queue.workers=4`
+    #[arg(long = "set", global = true, value_name = "KEY=VALUE", value_parser = parse_setting)]
+    pub settings: Vec<(String, String)>,
+}
+
+impl ConfigArgs {
+    pub fn overrides(&self) -> Overrides {
+        let flags = [
+            ("profile", &self.profile, "--profile"),
+            ("server.host", &self.host, "--host"),
+            ("server.port", &self.port, "--port"),
+            ("database.url", &self.database_url, "--database-url"),
+        ];
+        let mut overrides = Overrides {
+            file: self.config.clone(),
+            ..Overrides::default()
+        };
+        for (key, value, flag) in flags {
+            if let Some(value) = value {
+                overrides = overrides.set(key, value, flag);
+            }
+        }
+        for (key, value) in &self.settings {
+            overrides = overrides.set(key, value, &format!("--set {}", key));
+        }
+        overrides
+    }
+}
+
+fn parse_setting(arg: &str) -> Result<(String, String), String> {
+    arg.split_once('=')
+        .map(|(key, value)| (key.trim().to_string(), value.to_string()))
+        .ok_or_else(|| format!("expected KEY=VALUE, got {:?}", arg))
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the HTTP server (default)
+    Serve,
+    /// Inspect the security audit log
+    Audit {
+        #[command(subcommand)]
+        command: AuditCommand,
+    },
+    /// Manage administrator accounts
+    Admin {
+        #[command(subcommand)]
+        command: AdminCommand,
+    },
+    /// Apply, revert or inspect database migrations
+    Migrate {
+        #[command(subcommand)]
+        command: MigrateCommand,
+    },
+    /// Show or check the effective configuration
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    /// Generate the frontend TypeScript types and API client
+    Codegen {
+        /// Exit non-zero if the generated file is stale instead of writing it
+        #[arg(long)]
+        check: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum AuditCommand {
+    /// Recompute the audit hash chain and exit non-zero if it was tampered with
+    Verify,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum MigrateCommand {
+    /// Apply all pending migrations
+    Up,
+    /// Revert the most recent migrations
+    Down {
+        /// How many migrations to revert
+        #[arg(default_value_t = 1)]
+        count: usize,
+    },
+    /// List migrations and whether they have been applied
+    Status,
+    /// Revert the most recent migration and apply it again
+    Redo,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigCommand {
+    /// Print the merged configuration as TOML
+    Print {
+        /// Replace secrets such as `auth.jwt_secret` with a placeholder
+        #[arg(long)]
+        redacted: bool,
+    },
+    /// Exit non-zero if the configuration is invalid for its profile
+    Check,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum AdminCommand {
+    /// Give an existing user administrator access
+    Grant { email: String },
+}
+
+/// Run the selected command and report how it went
+pub async fn run(cli: Cli) -> ExitCode {
+    let config = match Config::load(&cli.config.overrides()) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match cli.command.unwrap_or(Command::Serve) {
+        Command::Serve => serve(config).await,
+        Command::Audit {
+            command: AuditCommand::Verify,
+        } => verify_audit_log(&db::establish_connection_pool(
+            &config.database_url,
+            &config.db,
+        )),
+        Command::Admin {
+            command: AdminCommand::Grant { email },
+        } => grant_admin(
+            &db::establish_connection_pool(&config.database_url, &config.db),
+            &email,
+        ),
+        Command::Migrate { command } => migrate(
+            &db::establish_connection_pool(&config.database_url, &config.db),
+            command,
+        ),
+        Command::Config { command } => print_config(&config, command),
+        Command::Codegen { check } => codegen(check),
+    }
+}
+
+pub async fn serve(config: Config) -> ExitCode {
+    let addr = config.address();
+    if config.jwt_secret == INSECURE_JWT_SECRET {
+        tracing::warn!("auth.jwt_secret not set, using default (NOT SECURE FOR PRODUCTION)");
+    }
+    let db = Db::open(&config.database_url, &config.db);
+
+    if let Err(e) = prepare_schema(db.write_pool(), config.run_migrations) {
+        tracing::error!("Refusing to start: {}", e);
+        return ExitCode::FAILURE;
+    }
+
+    let jobs = JobRegistry::default();
+    let scheduler = if config.scheduler.enabled {
+        match build_scheduler(&config.scheduler, &db, jobs.clone()) {
+            Ok(scheduler) => Some(scheduler.start()),
+            Err(e) => {
+                tracing::error!("Refusing to start: {}", e);
+                return ExitCode::FAILURE;
+            }
+        }
+    } else {
+        None
+    };
+    let shutdown_grace = config.scheduler.shutdown_grace;
+
+    let mailer = match config.mail.mailer() {
+        Ok(mailer) => mailer,
+        Err(e) => {
+            tracing::error!("Refusing to start: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    if config.mail.transport == MailTransport::Memory {
+        tracing::warn!("MAIL_TRANSPORT not set, keeping outgoing email in memory");
+    }
+
+    let queue = Queue::new(db.clone(), Arc::new(SystemClock));
+    let outbox = Outbox::new(queue.clone(), config.mail.from.clone());
+    let events = events::defaults(&outbox).build();
+    let workers = (config.queue.workers > 0)
+        .then(|| build_workers(&queue, &config.queue, mailer.clone(), &db, events.clone()).start());
+    let queue_grace = config.queue.shutdown_grace;
+
+    // Create router with all routes
+    let app = App::builder()
+        .config(config)
+        .db(db)
+        .jobs(jobs)
+        .queue(queue)
+        .mailer(mailer)
+        .events(events)
+        .build();
+
+    // Start server
+    let listener = tokio::net::TcpListener::bind(&addr)
+        .await
+        .expect("Failed to bind to address");
+
+    tracing::info!("Server listening on {}", addr);
+
+    // Client socket addresses are recorded in the audit log
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(shutdown_signal())
+    .await
+    .expect("Failed to start server");
+
+    if let Some(scheduler) = scheduler {
+        scheduler.shutdown(shutdown_grace).await;
+    }
+    if let Some(workers) = workers {
+        workers.shutdown(queue_grace).await;
+    }
+
+    ExitCode::SUCCESS
+}
+
+/// The built-in periodic jobs
+fn build_scheduler(
+    options: &SchedulerOptions,
+    db: &Db,
+    jobs: JobRegistry,
+) -> Result<Scheduler, InvalidSchedule> {
+    let sessions = Arc::new(DieselSessionRepository);
+    Scheduler::new(jobs).job(
+        PurgeExpiredTokens::new(db.clone(), sessions, Arc::new(SystemClock)),
+        &options.token_purge_schedule,
+        options.jitter,
+    )
+}
+
+/// Workers with a handler for every built-in job kind
+fn build_workers(
+    queue: &Queue,
+    options: &QueueOptions,
+    mailer: SharedMailer,
+    db: &Db,
+    events: EventBus,
+) -> Workers {
+    let deliverer = Deliverer::new(db.clone(), Arc::new(SystemClock));
+    queue
+        .workers(options.clone())
+        .handle(move |email: SendEmail| {
+            let mailer = mailer.clone();
+            async move { mail::deliver(mailer.as_ref(), email).await }
+        })
+        .handle(move |job: DeliverWebhook| {
+            let deliverer = deliverer.clone();
+            async move { deliverer.deliver(job).await }
+        })
+        .handle(move |job: DispatchEvent| {
+            let events = events.clone();
+            async move { events.dispatch(job).await }
+        })
+}
+
+/// Resolve on Ctrl+C or, on Unix, SIGTERM
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to listen for Ctrl+C");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to listen for SIGTERM")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+    tracing::info!("Shutting down");
+}
+
+/// Make sure the schema matches this binary, applying pending migrations
+/// when `run_migrations` is set
+fn prepare_schema(pool: &DbPool, run_migrations: bool) -> Result<(), migrations::MigrationError> {
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    if run_migrations {
+        for version in migrations::run_pending(&mut conn)? {
+            tracing::info!("Applied migration {}", version);
+        }
+        return Ok(());
+    }
+
+    migrations::ensure_not_ahead(&mut conn)?;
+    let pending = migrations::status(&mut conn)?
+        .into_iter()
+        .filter(|migration| !migration.applied)
+        .count();
+    if pending > 0 {
+        tracing::warn!(
+            "{} pending migrations; run `migrate up` or set RUN_MIGRATIONS=true",
+            pending
+        );
+    }
+    Ok(())
+}
+
+pub fn migrate(pool: &DbPool, command: MigrateCommand) -> ExitCode {
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    let result = match command {
+        MigrateCommand::Up => migrations::run_pending(&mut conn).map(|applied| {
+            if applied.is_empty() {
+                println!("No pending migrations");
+            }
+            for version in applied {
+                println!("Applied {}", version);
+            }
+        }),
+        MigrateCommand::Down { count } => migrations::revert(&mut conn, count).map(|reverted| {
+            if reverted.is_empty() {
+                println!("No migrations to revert");
+            }
+            for version in reverted {
+                println!("Reverted {}", version);
+            }
+        }),
+        MigrateCommand::Status => migrations::ensure_not_ahead(&mut conn)
+            .and_then(|()| migrations::status(&mut conn))
+            .map(|status| {
+                for migration in status {
+                    let mark = if migration.applied { "X" } else { " " };
+                    println!("[{}] {}", mark, migration.name);
+                }
+            }),
+        MigrateCommand::Redo => {
+            migrations::redo(&mut conn).map(|version| println!("Redid {}", version))
+        }
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Migration failed: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+pub fn verify_audit_log(pool: &DbPool) -> ExitCode {
+    let mut conn = pool.get().expect("Failed to get database connection");
+
+    match audit::verify_chain(&mut conn) {
+        Ok(ChainVerification::Intact { events, head_hash }) => {
+            println!(
+                "Audit log intact: {} events, head hash {}",
+                events, head_hash
+            );
+            ExitCode::SUCCESS
+        }
+        Ok(ChainVerification::Broken { event_id, reason }) => {
+            eprintln!(
+                "Audit log tampered: event {} fails check {:?}",
+                event_id, reason
+            );
+            ExitCode::FAILURE
+        }
+        Err(e) => {
+            eprintln!("Failed to read audit log: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+pub fn grant_admin(pool: &DbPool, email: &str) -> ExitCode {
+    let mut conn = pool.get().expect("Failed to get database connection");
+    let email = email.to_lowercase();
+
+    let user_id: Option<i32> = match diesel::update(User::live().filter(users::email.eq(&email)))
+        .set(users::is_admin.eq(true))
+        .returning(users::id)
+        .get_result(&mut conn)
+        .optional()
+    {
+        Ok(user_id) => user_id,
+        Err(e) => {
+            eprintln!("Failed to update user: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let Some(user_id) = user_id else {
+        eprintln!("No user with email {}", email);
+        return ExitCode::FAILURE;
+    };
+
+    if let Err(e) = audit::record(
+        &mut conn,
+        &ClientInfo::default(),
+        AuditEntry::success(AuditAction::AdminGranted)
+            .target(email.clone())
+            .metadata(serde_json::json!({ "user_id": user_id, "source": "cli" })),
+        SystemClock.now(),
+    ) {
+        eprintln!("Failed to write audit event: {}", e);
+        return ExitCode::FAILURE;
+    }
+
+    println!("Granted administrator access to {}", email);
+    ExitCode::SUCCESS
+}
+
+/// Runs after `Config::load` succeeded, so the configuration is valid
+pub fn print_config(config: &Config, command: ConfigCommand) -> ExitCode {
+    match command {
+        ConfigCommand::Print { redacted } => print!("{}", config.to_toml(redacted)),
+        ConfigCommand::Check => {
+            println!("Configuration is valid for the {} profile", config.profile)
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+pub fn codegen(check: bool) -> ExitCode {
+    if check {
+        return match codegen::typescript_is_current() {
+            Ok(true) => {
+                println!("{} is up to date", codegen::TYPESCRIPT_OUTPUT);
+                ExitCode::SUCCESS
+            }
+            Ok(false) => {
+                eprintln!(
+                    "{} is out of date; run `cargo run -- codegen`",
+                    codegen::TYPESCRIPT_OUTPUT
+                );
+                ExitCode::FAILURE
+            }
+            Err(e) => {
+                eprintln!("Failed to read {}: {}", codegen::TYPESCRIPT_OUTPUT, e);
+                ExitCode::FAILURE
+            }
+        };
+    }
+
+    match codegen::write_typescript() {
+        Ok(path) => {
+            println!("Wrote {}", path.display());
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("Failed to write {}: {}", codegen::TYPESCRIPT_OUTPUT, e);
+            ExitCode::FAILURE
+        }
+    }
+}
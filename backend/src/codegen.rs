@@ -0,0 +1,402 @@
+use std::{fmt::Write, fs, io, path::PathBuf};
+
+use serde_json::Value;
+use ts_rs::TS;
+
+use crate::{
+    api::docs,
+    audit::log::{ChainBreak, ChainVerification},
+    db::{DbStats, PoolStats},
+    error::{FieldError, ProblemDetails},
+    models::{
+        ApiResponse, AuditEventPage, AuditEventResponse, AuthResponse, CreateWebhookRequest,
+        CreatedWebhookResponse, CsrfTokenResponse, DeliveryStatus, JobPage, JobResponse, JobState,
+        LoginRequest, RefreshRequest, RegisterRequest, UpdateProfileRequest, UserResponse,
+        WebhookAttemptResponse, WebhookDeliveryDetail, WebhookDeliveryPage,
+        WebhookDeliveryResponse, WebhookEndpointResponse,
+    },
+    scheduler::JobStatus,
+    webhooks::WebhookEventInfo,
+};
+
+/// Generated TypeScript module, relative to the backend crate
+pub const TYPESCRIPT_OUTPUT: &str = "../frontend/src/api/generated.ts";
+
+const HEADER: &str = "\
+// This file is generated from the backend models and routes.
+// Do not edit it by hand; run `cargo run -- codegen` in `backend/` instead.
+
+import { ApiError } from './errors'
+";
+
+const CLIENT_PRELUDE: &str = "\
+export type QueryValue = string | number | boolean | null | undefined
+
+export interface ClientOptions {
+  /** Prefix for every request path, e.g. `https://api.example.com` */
+  baseUrl?: string
+  /** Alternative `fetch` implementation; defaults to the global one */
+  fetch?: typeof fetch
+  /** Extra headers sent with every request */
+  headers?: Record<string, string>
+}
+
+interface RequestOptions {
+  body?: unknown
+  query?: Record<string, QueryValue>
+  headers?: Record<string, string>
+  /** Send the CSRF token, for unsafe requests authenticated by the cookie */
+  csrf?: boolean
+}
+
+export class ApiClient {
+  private readonly options: ClientOptions
+  private csrf?: Promise<CsrfTokenResponse>
+
+  constructor(options: ClientOptions = {}) {
+    this.options = options
+  }
+
+  private async request<T>(
+    method: string,
+    path: string,
+    init: RequestOptions = {},
+    retry = true,
+  ): Promise<T> {
+    if (!init.csrf) {
+      return this.send(method, path, init)
+    }
+
+    this.csrf ??= this.send<CsrfTokenResponse>('GET', '/api/auth/csrf')
+    const { token, header } = await this.csrf.catch((error) => {
+      this.csrf = undefined
+      throw error
+    })
+    try {
+      const headers = { ...init.headers, [header]: token }
+      return await this.send(method, path, { ...init, headers })
+    } catch (error) {
+      // The cookie expired or was cleared: fetch a new token and retry once
+      if (!(error instanceof ApiError) || error.code !== 'csrf_failed') {
+        throw error
+      }
+      this.csrf = undefined
+      if (!retry) {
+        throw error
+      }
+      return this.request(method, path, init, false)
+    }
+  }
+
+  private async send<T>(method: string, path: string, init: RequestOptions = {}): Promise<T> {
+    const params = new URLSearchParams()
+    for (const [key, value] of Object.entries(init.query ?? {})) {
+      if (value !== undefined && value !== null) {
+        params.append(key, String(value))
+      }
+    }
+    const search = params.toString()
+
+    const doFetch = this.options.fetch ?? fetch
+    const response = await doFetch(`${this.options.baseUrl ?? ''}${path}${search ? `?${search}` : ''}`, {
+      method,
+      headers: {
+        'Content-Type': 'application/json',
+        ...this.options.headers,
+        ...init.headers,
+      },
+      body: init.body === undefined ? undefined : JSON.stringify(init.body),
+      credentials: 'include',
+    })
+
+    if (!response.ok) {
+      throw await ApiError.fromResponse(response)
+    }
+    if (response.status === 204) {
+      return undefined as T
+    }
+
+    const contentType = response.headers.get('Content-Type') ?? ''
+    return (contentType.includes('json') ? response.json() : response.text()) as Promise<T>
+  }
+";
+
+/// Render the TypeScript types and API client for the frontend
+pub fn generate_typescript() -> String {
+    let mut out = String::from(HEADER);
+
+    for decl in type_declarations() {
+        out.push('\n');
+        out.push_str("export ");
+        out.push_str(&decl);
+        out.push('\n');
+    }
+
+    out.push('\n');
+    out.push_str(CLIENT_PRELUDE);
+
+    let spec = serde_json::to_value(docs::spec()).expect("OpenAPI document is serializable");
+    let paths = spec["paths"]
+        .as_object()
+        .expect("OpenAPI document has paths");
+    for (path, item) in paths {
+        for method in ["get", "post", "put", "patch", "delete"] {
+            if let Some(operation) = item.get(method) {
+                write_operation(&mut out, method, path, operation);
+            }
+        }
+    }
+
+    out.push_str("}\n");
+    out
+}
+
+fn type_declarations() -> Vec<String> {
+    vec![
+        ApiResponse::decl(),
+        RegisterRequest::decl(),
+        UpdateProfileRequest::decl(),
+        LoginRequest::decl(),
+        RefreshRequest::decl(),
+        UserResponse::decl(),
+        AuthResponse::decl(),
+        CsrfTokenResponse::decl(),
+        FieldError::decl(),
+        ProblemDetails::decl(),
+        AuditEventResponse::decl(),
+        AuditEventPage::decl(),
+        ChainBreak::decl(),
+        ChainVerification::decl(),
+        PoolStats::decl(),
+        DbStats::decl(),
+        JobStatus::decl(),
+        JobState::decl(),
+        JobResponse::decl(),
+        JobPage::decl(),
+        WebhookEventInfo::decl(),
+        CreateWebhookRequest::decl(),
+        WebhookEndpointResponse::decl(),
+        CreatedWebhookResponse::decl(),
+        DeliveryStatus::decl(),
+        WebhookDeliveryResponse::decl(),
+        WebhookDeliveryPage::decl(),
+        WebhookAttemptResponse::decl(),
+        WebhookDeliveryDetail::decl(),
+    ]
+}
+
+fn write_operation(out: &mut String, method: &str, path: &str, operation: &Value) {
+    let operation_id = operation["operationId"]
+        .as_str()
+        .unwrap_or_else(|| panic!("{} {} has no operationId", method, path));
+
+    let mut args = Vec::new();
+    let mut url = path.to_string();
+    let mut query_fields = Vec::new();
+    let mut header_fields = Vec::new();
+
+    for parameter in operation["parameters"].as_array().into_iter().flatten() {
+        let name = parameter["name"].as_str().expect("parameter has a name");
+        let ts_type = schema_type(&parameter["schema"]);
+        match parameter["in"].as_str() {
+            Some("path") => {
+                args.push(format!("{}: {}", camel_case(name), ts_type));
+                url = url.replace(
+                    &format!("{{{}}}", name),
+                    &format!("${{encodeURIComponent(String({}))}}", camel_case(name)),
+                );
+            }
+            Some("query") => {
+                let optional = if parameter["required"] == Value::Bool(true) {
+                    ""
+                } else {
+                    "?"
+                };
+                query_fields.push(format!("{}{}: {}", name, optional, ts_type));
+            }
+            Some("header") => {
+                let arg = camel_case(&name.to_lowercase().replace('-', "_"));
+                args.push(format!("{}: {}", arg, ts_type));
+                header_fields.push(format!("'{}': {}", name, arg));
+            }
+            _ => {}
+        }
+    }
+
+    let body_type = operation["requestBody"]["content"]["application/json"]["schema"]
+        .as_object()
+        .map(|schema| schema_type(&Value::Object(schema.clone())));
+    if let Some(body_type) = &body_type {
+        args.push(format!("body: {}", body_type));
+    }
+    if !query_fields.is_empty() {
+        args.push(format!("query: {{ {} }} = {{}}", query_fields.join("; ")));
+    }
+
+    let mut init = Vec::new();
+    if body_type.is_some() {
+        init.push("body".to_string());
+    }
+    if !query_fields.is_empty() {
+        init.push("query".to_string());
+    }
+    if !header_fields.is_empty() {
+        init.push(format!("headers: {{ {} }}", header_fields.join(", ")));
+    }
+    // The cookie may authenticate it, so it needs the CSRF token
+    if operation.get("security").is_some() && method != "get" {
+        init.push("csrf: true".to_string());
+    }
+    let init = if init.is_empty() {
+        String::new()
+    } else {
+        format!(", {{ {} }}", init.join(", "))
+    };
+
+    if let Some(summary) = operation["summary"].as_str() {
+        let _ = writeln!(out, "\n  /** {} */", summary);
+    } else {
+        out.push('\n');
+    }
+    let _ = writeln!(
+        out,
+        "  {}({}): Promise<{}> {{\n    return this.request('{}', `{}`{})\n  }}",
+        camel_case(operation_id),
+        args.join(", "),
+        response_type(operation),
+        method.to_uppercase(),
+        url,
+        init,
+    );
+}
+
+/// TypeScript type of the first successful response
+fn response_type(operation: &Value) -> String {
+    let responses = operation["responses"]
+        .as_object()
+        .expect("operation has responses");
+
+    let Some((_, response)) = responses.iter().find(|(status, _)| status.starts_with('2')) else {
+        return "void".to_string();
+    };
+
+    match response["content"].as_object() {
+        Some(content) if content.contains_key("application/json") => {
+            schema_type(&content["application/json"]["schema"])
+        }
+        Some(content) if !content.is_empty() => "string".to_string(),
+        _ => "void".to_string(),
+    }
+}
+
+fn schema_type(schema: &Value) -> String {
+    if let Some(reference) = schema["$ref"].as_str() {
+        return reference
+            .rsplit('/')
+            .next()
+            .unwrap_or(reference)
+            .to_string();
+    }
+
+    let types: Vec<&str> = match &schema["type"] {
+        Value::String(ty) => vec![ty.as_str()],
+        Value::Array(types) => types.iter().filter_map(Value::as_str).collect(),
+        _ => Vec::new(),
+    };
+
+    let mut ts_types: Vec<String> = types
+        .into_iter()
+        .map(|ty| match ty {
+            "integer" | "number" => "number".to_string(),
+            "boolean" => "boolean".to_string(),
+            "null" => "null".to_string(),
+            "array" => format!("Array<{}>", schema_type(&schema["items"])),
+            "string" => "string".to_string(),
+            _ => "unknown".to_string(),
+        })
+        .collect();
+    ts_types.dedup();
+
+    if ts_types.is_empty() {
+        "unknown".to_string()
+    } else {
+        ts_types.join(" | ")
+    }
+}
+
+fn camel_case(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    let mut upper = false;
+    for c in name.chars() {
+        if c == '_' {
+            upper = true;
+        } else if upper {
+            out.extend(c.to_uppercase());
+            upper = false;
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+fn output_path() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(TYPESCRIPT_OUTPUT)
+}
+
+/// Write the generated module to the frontend
+pub fn write_typescript() -> io::Result<PathBuf> {
+    let path = output_path();
+    fs::write(&path, generate_typescript())?;
+    Ok(path)
+}
+
+/// Whether the committed module matches what the code would generate
+pub fn typescript_is_current() -> io::Result<bool> {
+    let committed = match fs::read_to_string(output_path()) {
+        Ok(committed) => committed,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e),
+    };
+    Ok(committed == generate_typescript())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generated_typescript_is_current() {
+        assert!(
+            typescript_is_current().expect("Failed to read generated TypeScript"),
+            "{} is out of date; run `cargo run -- codegen` and commit the result",
+            TYPESCRIPT_OUTPUT
+        );
+    }
+
+    #[test]
+    fn test_client_has_method_per_route() {
+        let generated = generate_typescript();
+
+        assert!(generated.contains("export type RegisterRequest = "));
+        assert!(generated.contains(
+            "  register(body: RegisterRequest): Promise<AuthResponse> {\n    return this.request('POST', `/api/auth/register`, { body })"
+        ));
+        assert!(generated.contains("  logout(body: RefreshRequest): Promise<void> {"));
+        assert!(generated.contains("  me(): Promise<UserResponse> {"));
+        assert!(generated.contains("  healthCheck(): Promise<string> {"));
+        assert!(generated.contains("  listAuditEvents(query: { actor_user_id?: "));
+        assert!(generated.contains(
+            "  updateMe(ifMatch: string, body: UpdateProfileRequest): Promise<UserResponse> {\n    return this.request('PATCH', `/api/auth/me`, { body, headers: { 'If-Match': ifMatch }, csrf: true })"
+        ));
+        // Only authenticated unsafe requests send the CSRF token
+        assert!(generated.contains("`/api/admin/webhooks`, { body, csrf: true })"));
+        assert!(!generated.contains("`/api/auth/login`, { body, csrf: true })"));
+    }
+
+    #[test]
+    fn test_camel_case() {
+        assert_eq!(camel_case("list_audit_events"), "listAuditEvents");
+        assert_eq!(camel_case("me"), "me");
+    }
+}
@@ -1,26 +0,0 @@
-use std::env;
-
-#[derive(Clone, Debug)]
-pub struct Config {
-    pub host: String,
-    pub port: u16,
-    pub database_url: String,
-}
-
-impl Config {
-    pub fn from_env() -> Self {
-        Self {
-            host: env::var("HOST").unwrap_or_else(|_| "127.0.0.1".to_string()),
-            port: env::var("PORT")
-                .unwrap_or_else(|_| "3000".to_string())
-                .parse()
-                .expect("PORT must be a number"),
-            database_url: env::var("DATABASE_URL")
-                .unwrap_or_else(|_| "database.db".to_string()),
-        }
-    }
-
-    pub fn address(&self) -> String {
-        format!("{}:{}", self.host, self.port)
-    }
-}
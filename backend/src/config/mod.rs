@@ -0,0 +1,935 @@
+//! Typed configuration, layered from lowest to highest precedence:
+//!
+//! 1. built-in defaults
+//! 2. a TOML file named by `--config` or `APP_CONFIG`
+//! 3. environment variables: `APP_<SECTION>__<KEY>` such as `APP_SERVER__PORT`,
+//!    or the older unprefixed names such as `PORT`
+//! 4. command line flags: `--host`, `--port`, `--database-url`, `--profile`
+//!    and `--set key=value`
+//!
+//! Every variable can instead be read from the file named by `<VAR>_FILE`,
+//! for Docker and Kubernetes secrets. [`Config::load`] reports all invalid
+//! values at once, then applies the [`Profile`]'s safety checks.
+
+mod sources;
+
+use std::{collections::BTreeMap, env, fmt, fs, io, path::PathBuf, str::FromStr, time::Duration};
+
+use axum::http::{HeaderName, HeaderValue, Method};
+
+use crate::{
+    auth::{
+        CookieOptions, CsrfOptions, RefreshTokenTransport, SameSite, REFRESH_TOKEN_TRANSPORT_HEADER,
+    },
+    db::DbOptions,
+    mail::{MailOptions, MailTransport},
+    queue::QueueOptions,
+    rate_limit::{KeyBy, Policy, RateLimitBackend, RateLimitOptions},
+    scheduler::SchedulerOptions,
+    security::{CorsOptions, SecurityHeaders},
+};
+
+use sources::{Layers, Value, SETTINGS};
+pub use sources::{Override, Overrides, CONFIG_FILE_ENV, ENV_PREFIX};
+
+#[cfg(feature = "sqlite")]
+const DEFAULT_DATABASE_URL: &str = "database.db";
+#[cfg(feature = "postgres")]
+const DEFAULT_DATABASE_URL: &str = "postgres://localhost/webapp";
+
+/// Used when no JWT secret is configured; refused by the `prod` profile
+pub const INSECURE_JWT_SECRET: &str = "your-secret-key-change-this-in-production";
+
+/// Shortest JWT secret the `prod` profile accepts, in bytes
+const MIN_PROD_SECRET_LEN: usize = 32;
+
+/// Which safety checks apply to the loaded configuration
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Profile {
+    /// Local development; nothing is refused
+    #[default]
+    Dev,
+    /// Automated tests; nothing is refused
+    Test,
+    /// Deployments: requires a real JWT secret and mail transport, and no
+    /// development endpoints
+    Prod,
+}
+
+impl Profile {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Profile::Dev => "dev",
+            Profile::Test => "test",
+            Profile::Prod => "prod",
+        }
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Profile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dev" => Ok(Profile::Dev),
+            "test" => Ok(Profile::Test),
+            "prod" => Ok(Profile::Prod),
+            _ => Err("expected dev, test or prod".to_string()),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("cannot read config file {}: {source}", path.display())]
+    Read { path: PathBuf, source: io::Error },
+    #[error("config file {} is not valid TOML: {message}", path.display())]
+    Parse { path: PathBuf, message: String },
+    #[error("invalid configuration:{}", bullets(.0))]
+    Invalid(Vec<String>),
+    #[error("configuration is not safe for the {profile} profile:{}", bullets(.problems))]
+    Unsafe {
+        profile: Profile,
+        problems: Vec<String>,
+    },
+}
+
+fn bullets(problems: &[String]) -> String {
+    problems
+        .iter()
+        .map(|problem| format!("\n  - {}", problem))
+        .collect()
+}
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// Safety checks applied when loading
+    pub profile: Profile,
+    pub host: String,
+    pub port: u16,
+    pub database_url: String,
+    /// Connection pool size, timeouts and SQLite pragmas
+    pub db: DbOptions,
+    /// Serve the interactive API reference at `/api/docs`
+    pub api_docs: bool,
+    /// Serve development helpers such as `/dev/mail`
+    pub dev_endpoints: bool,
+    /// Apply pending migrations when the server starts
+    pub run_migrations: bool,
+    /// Secret used to sign access tokens
+    pub jwt_secret: String,
+    /// Where the refresh token goes unless a request asks otherwise with
+    /// `X-Refresh-Token-Transport`
+    pub refresh_token_transport: RefreshTokenTransport,
+    /// Periodic background jobs
+    pub scheduler: SchedulerOptions,
+    /// Workers for the persistent job queue
+    pub queue: QueueOptions,
+    /// Sender address and delivery of outgoing email
+    pub mail: MailOptions,
+    /// Origins, methods and headers allowed cross-origin
+    pub cors: CorsOptions,
+    /// Name and attributes of the session cookie
+    pub cookie: CookieOptions,
+    /// Checks on unsafe requests authenticated by that cookie
+    pub csrf: CsrfOptions,
+    /// Request quotas per route group and where requests are counted
+    pub rate_limit: RateLimitOptions,
+    /// Headers such as HSTS and CSP added to every response
+    pub security_headers: SecurityHeaders,
+}
+
+impl Config {
+    /// Load from `APP_CONFIG` and the environment, panicking on errors
+    ///
+    /// For embedding the app as a library; the CLI uses [`Config::load`] to
+    /// report errors instead.
+    pub fn from_env() -> Self {
+        Self::load(&Overrides::default()).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Load every layer, with `overrides` from the command line on top
+    pub fn load(overrides: &Overrides) -> Result<Self, ConfigError> {
+        Self::load_from(overrides, &env::vars().collect())
+    }
+
+    /// Like [`Config::load`], with an explicit set of environment variables
+    pub fn load_from(
+        overrides: &Overrides,
+        env: &BTreeMap<String, String>,
+    ) -> Result<Self, ConfigError> {
+        let mut layers = Layers::default();
+        let file = overrides
+            .file
+            .clone()
+            .or_else(|| env.get(CONFIG_FILE_ENV).map(PathBuf::from));
+        if let Some(path) = file {
+            let contents = fs::read_to_string(&path).map_err(|source| ConfigError::Read {
+                path: path.clone(),
+                source,
+            })?;
+            let table = contents
+                .parse::<toml::Table>()
+                .map_err(|e| ConfigError::Parse {
+                    path: path.clone(),
+                    message: e.message().to_string(),
+                })?;
+            layers.file(path, table);
+        }
+        layers.env(env);
+        layers.overrides(overrides);
+
+        let mut resolver = Resolver {
+            values: layers.values,
+            problems: layers.problems,
+        };
+        let config = resolver.config();
+        if !resolver.problems.is_empty() {
+            return Err(ConfigError::Invalid(resolver.problems));
+        }
+
+        let problems = config.safety_problems();
+        if !problems.is_empty() {
+            return Err(ConfigError::Unsafe {
+                profile: config.profile,
+                problems,
+            });
+        }
+        Ok(config)
+    }
+
+    /// What the profile refuses in an otherwise valid configuration
+    fn safety_problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.profile != Profile::Prod {
+            return problems;
+        }
+
+        if self.jwt_secret == INSECURE_JWT_SECRET {
+            problems.push("auth.jwt_secret must be set".to_string());
+        } else if self.jwt_secret.len() < MIN_PROD_SECRET_LEN {
+            problems.push(format!(
+                "auth.jwt_secret must be at least {} bytes long",
+                MIN_PROD_SECRET_LEN
+            ));
+        }
+        if self.dev_endpoints {
+            problems.push("server.dev_endpoints must be off".to_string());
+        }
+        if self.mail.transport == MailTransport::Memory {
+            problems.push("mail.transport must be file or smtp, memory never delivers".to_string());
+        }
+        if !self.cookie.secure {
+            problems.push("cookie.secure must be on".to_string());
+        }
+        if !self.csrf.enabled {
+            problems.push("csrf.enabled must be on".to_string());
+        }
+        if !self.rate_limit.enabled {
+            problems.push("rate_limit.enabled must be on".to_string());
+        }
+        problems
+    }
+
+    pub fn address(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+
+    /// The effective configuration as a TOML file `load` would accept
+    ///
+    /// With `redacted`, secrets are replaced by a placeholder.
+    pub fn to_toml(&self, redacted: bool) -> String {
+        let mut root = toml::Table::new();
+        for (key, value) in self.entries() {
+            let secret = SETTINGS
+                .iter()
+                .any(|setting| setting.key == key && setting.secret);
+            let value = match value {
+                Some(_) if secret && redacted => toml::Value::from("<redacted>"),
+                Some(value) => value,
+                None => continue,
+            };
+            match key.split_once('.') {
+                Some((section, field)) => {
+                    let section = root
+                        .entry(section)
+                        .or_insert_with(|| toml::Table::new().into());
+                    if let toml::Value::Table(section) = section {
+                        section.insert(field.to_string(), value);
+                    }
+                }
+                None => {
+                    root.insert(key.to_string(), value);
+                }
+            }
+        }
+        toml::to_string(&root).expect("tables serialize")
+    }
+
+    /// Every setting with its current value, `None` if it does not apply
+    fn entries(&self) -> Vec<(&'static str, Option<toml::Value>)> {
+        fn secs(duration: Duration) -> Option<toml::Value> {
+            Some((duration.as_secs() as i64).into())
+        }
+        fn millis(duration: Duration) -> Option<toml::Value> {
+            Some((duration.as_millis() as i64).into())
+        }
+        fn value(value: impl Into<toml::Value>) -> Option<toml::Value> {
+            Some(value.into())
+        }
+
+        let (store, redis_url) = match &self.rate_limit.backend {
+            RateLimitBackend::Memory => ("memory", None),
+            RateLimitBackend::Redis(url) => ("redis", value(url.as_str())),
+        };
+        let (transport, dir, smtp_url) = match &self.mail.transport {
+            MailTransport::Memory => ("memory", None, None),
+            MailTransport::File(dir) => ("file", value(dir.display().to_string()), None),
+            MailTransport::Smtp(url) => ("smtp", None, value(url.as_str())),
+        };
+        fn list<T: AsRef<str>>(items: impl IntoIterator<Item = T>) -> Option<toml::Value> {
+            let items = items.into_iter().map(|item| item.as_ref().into());
+            Some(toml::Value::Array(items.collect()))
+        }
+        fn header(value: &Option<HeaderValue>) -> Option<toml::Value> {
+            let value = value
+                .as_ref()
+                .map(|value| value.as_bytes())
+                .unwrap_or_default();
+            Some(String::from_utf8_lossy(value).into_owned().into())
+        }
+
+        let db = &self.db;
+        let scheduler = &self.scheduler;
+        let queue = &self.queue;
+        let cors = &self.cors;
+        let cookie = &self.cookie;
+        let csrf = &self.csrf;
+        let rate_limit = &self.rate_limit;
+        let headers = &self.security_headers;
+        let origins = cors
+            .allowed_origins
+            .iter()
+            .map(|origin| String::from_utf8_lossy(origin.as_bytes()).into_owned());
+        vec![
+            ("profile", value(self.profile.as_str())),
+            ("server.host", value(self.host.as_str())),
+            ("server.port", value(i64::from(self.port))),
+            ("server.api_docs", value(self.api_docs)),
+            ("server.dev_endpoints", value(self.dev_endpoints)),
+            ("database.url", value(self.database_url.as_str())),
+            ("database.run_migrations", value(self.run_migrations)),
+            (
+                "database.max_connections",
+                value(i64::from(db.max_connections)),
+            ),
+            (
+                "database.connection_timeout_secs",
+                secs(db.connection_timeout),
+            ),
+            ("database.busy_timeout_ms", millis(db.busy_timeout)),
+            ("database.journal_mode", value(db.journal_mode.as_str())),
+            ("database.synchronous", value(db.synchronous.as_str())),
+            ("database.foreign_keys", value(db.foreign_keys)),
+            ("auth.jwt_secret", value(self.jwt_secret.as_str())),
+            (
+                "auth.refresh_token_transport",
+                value(self.refresh_token_transport.as_str()),
+            ),
+            ("scheduler.enabled", value(scheduler.enabled)),
+            (
+                "scheduler.token_purge_schedule",
+                value(scheduler.token_purge_schedule.as_str()),
+            ),
+            ("scheduler.jitter_secs", secs(scheduler.jitter)),
+            (
+                "scheduler.shutdown_grace_secs",
+                secs(scheduler.shutdown_grace),
+            ),
+            ("queue.workers", value(queue.workers as i64)),
+            ("queue.poll_interval_ms", millis(queue.poll_interval)),
+            ("queue.lease_secs", secs(queue.lease)),
+            ("queue.backoff_base_secs", secs(queue.backoff_base)),
+            ("queue.backoff_max_secs", secs(queue.backoff_max)),
+            ("queue.shutdown_grace_secs", secs(queue.shutdown_grace)),
+            ("mail.transport", value(transport)),
+            ("mail.dir", dir),
+            ("mail.smtp_url", smtp_url),
+            ("mail.from", value(self.mail.from.as_str())),
+            ("cors.allowed_origins", list(origins)),
+            ("cors.allowed_methods", list(&cors.allowed_methods)),
+            ("cors.allowed_headers", list(&cors.allowed_headers)),
+            ("cookie.name", value(cookie.name.as_str())),
+            ("cookie.refresh_name", value(cookie.refresh_name.as_str())),
+            ("cookie.secure", value(cookie.secure)),
+            ("cookie.same_site", value(cookie.same_site.as_str())),
+            ("cookie.domain", cookie.domain.as_deref().and_then(value)),
+            ("cookie.path", value(cookie.path.as_str())),
+            ("csrf.enabled", value(csrf.enabled)),
+            ("csrf.cookie_name", value(csrf.cookie_name.as_str())),
+            ("csrf.header_name", value(csrf.header_name.as_str())),
+            ("rate_limit.enabled", value(rate_limit.enabled)),
+            ("rate_limit.store", value(store)),
+            ("rate_limit.redis_url", redis_url),
+            ("rate_limit.auth", value(rate_limit.auth.quota.to_string())),
+            ("rate_limit.auth_by", value(rate_limit.auth.key_by.as_str())),
+            ("rate_limit.api", value(rate_limit.api.quota.to_string())),
+            ("rate_limit.api_by", value(rate_limit.api.key_by.as_str())),
+            ("rate_limit.api_keys", list(&rate_limit.api_keys)),
+            ("security_headers.hsts", header(&headers.hsts)),
+            (
+                "security_headers.content_security_policy",
+                header(&headers.content_security_policy),
+            ),
+            (
+                "security_headers.referrer_policy",
+                header(&headers.referrer_policy),
+            ),
+            (
+                "security_headers.permissions_policy",
+                header(&headers.permissions_policy),
+            ),
+            ("security_headers.nosniff", value(headers.nosniff)),
+        ]
+    }
+}
+
+/// Turns layered raw values into typed settings, collecting every problem
+struct Resolver {
+    values: BTreeMap<&'static str, Value>,
+    problems: Vec<String>,
+}
+
+impl Resolver {
+    fn invalid(&mut self, key: &str, message: impl fmt::Display) {
+        let value = &self.values[key];
+        let secret = SETTINGS
+            .iter()
+            .any(|setting| setting.key == key && setting.secret);
+        self.problems.push(if secret {
+            format!("{} from {}: {}", key, value.origin, message)
+        } else {
+            format!(
+                "{} = {:?} from {}: {}",
+                key, value.raw, value.origin, message
+            )
+        });
+    }
+
+    fn string(&self, key: &str) -> Option<String> {
+        self.values.get(key).map(|value| value.raw.clone())
+    }
+
+    fn parse<T>(&mut self, key: &str) -> Option<T>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        let raw = self.values.get(key)?.raw.trim().to_string();
+        raw.parse().map_err(|e| self.invalid(key, e)).ok()
+    }
+
+    fn flag(&mut self, key: &str) -> Option<bool> {
+        match self
+            .values
+            .get(key)?
+            .raw
+            .trim()
+            .to_ascii_lowercase()
+            .as_str()
+        {
+            "true" | "1" | "yes" | "on" => Some(true),
+            "false" | "0" | "no" | "off" => Some(false),
+            _ => {
+                self.invalid(key, "expected true or false");
+                None
+            }
+        }
+    }
+
+    /// Comma-separated in variables and flags, an array in the file
+    fn list<T>(&mut self, key: &str) -> Option<Vec<T>>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        let raw = self.values.get(key)?.raw.clone();
+        let mut items = Vec::new();
+        for item in raw
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+        {
+            match item.parse() {
+                Ok(item) => items.push(item),
+                Err(e) => self.invalid(key, format!("{:?}: {}", item, e)),
+            }
+        }
+        Some(items)
+    }
+
+    /// An empty value leaves the header out
+    fn header(&mut self, key: &str, default: Option<HeaderValue>) -> Option<HeaderValue> {
+        let Some(value) = self.values.get(key) else {
+            return default;
+        };
+        let raw = value.raw.trim().to_string();
+        if raw.is_empty() {
+            return None;
+        }
+        raw.parse().map_err(|e| self.invalid(key, e)).ok()
+    }
+
+    fn secs(&mut self, key: &str) -> Option<Duration> {
+        self.parse(key).map(Duration::from_secs)
+    }
+
+    fn millis(&mut self, key: &str) -> Option<Duration> {
+        self.parse(key).map(Duration::from_millis)
+    }
+
+    fn config(&mut self) -> Config {
+        let profile = self.parse("profile").unwrap_or_default();
+        let db = self.db_options();
+        let scheduler = self.scheduler_options();
+        let queue = self.queue_options();
+        let mail = self.mail_options();
+        let csrf = self.csrf_options();
+        let mut cors = self.cors_options();
+        // The frontend must be allowed to send these cross-origin
+        for required in [csrf.header_name.clone(), REFRESH_TOKEN_TRANSPORT_HEADER] {
+            if !cors.allowed_headers.contains(&required) {
+                cors.allowed_headers.push(required);
+            }
+        }
+        let cookie = self.cookie_options(profile);
+        let rate_limit = self.rate_limit_options();
+        let security_headers = self.security_headers(profile);
+        Config {
+            profile,
+            host: self
+                .string("server.host")
+                .unwrap_or_else(|| "127.0.0.1".to_string()),
+            port: self.parse("server.port").unwrap_or(3000),
+            database_url: self
+                .string("database.url")
+                .unwrap_or_else(|| DEFAULT_DATABASE_URL.to_string()),
+            db,
+            api_docs: self.flag("server.api_docs").unwrap_or(false),
+            dev_endpoints: self.flag("server.dev_endpoints").unwrap_or(false),
+            run_migrations: self.flag("database.run_migrations").unwrap_or(true),
+            jwt_secret: self
+                .string("auth.jwt_secret")
+                .unwrap_or_else(|| INSECURE_JWT_SECRET.to_string()),
+            refresh_token_transport: self
+                .parse("auth.refresh_token_transport")
+                .unwrap_or_default(),
+            scheduler,
+            queue,
+            mail,
+            cors,
+            cookie,
+            csrf,
+            rate_limit,
+            security_headers,
+        }
+    }
+
+    fn db_options(&mut self) -> DbOptions {
+        let defaults = DbOptions::default();
+        let max_connections = self.parse("database.max_connections");
+        if max_connections == Some(0) {
+            self.invalid("database.max_connections", "must be at least 1");
+        }
+        DbOptions {
+            max_connections: max_connections.unwrap_or(defaults.max_connections),
+            connection_timeout: self
+                .secs("database.connection_timeout_secs")
+                .unwrap_or(defaults.connection_timeout),
+            busy_timeout: self
+                .millis("database.busy_timeout_ms")
+                .unwrap_or(defaults.busy_timeout),
+            journal_mode: self
+                .parse("database.journal_mode")
+                .unwrap_or(defaults.journal_mode),
+            synchronous: self
+                .parse("database.synchronous")
+                .unwrap_or(defaults.synchronous),
+            foreign_keys: self
+                .flag("database.foreign_keys")
+                .unwrap_or(defaults.foreign_keys),
+        }
+    }
+
+    fn scheduler_options(&mut self) -> SchedulerOptions {
+        let defaults = SchedulerOptions::default();
+        let token_purge_schedule = self.string("scheduler.token_purge_schedule");
+        if let Some(expression) = &token_purge_schedule {
+            if let Err(e) = cron::Schedule::from_str(expression) {
+                self.invalid("scheduler.token_purge_schedule", e);
+            }
+        }
+        SchedulerOptions {
+            enabled: self.flag("scheduler.enabled").unwrap_or(defaults.enabled),
+            token_purge_schedule: token_purge_schedule.unwrap_or(defaults.token_purge_schedule),
+            jitter: self
+                .secs("scheduler.jitter_secs")
+                .unwrap_or(defaults.jitter),
+            shutdown_grace: self
+                .secs("scheduler.shutdown_grace_secs")
+                .unwrap_or(defaults.shutdown_grace),
+        }
+    }
+
+    fn queue_options(&mut self) -> QueueOptions {
+        let defaults = QueueOptions::default();
+        QueueOptions {
+            workers: self.parse("queue.workers").unwrap_or(defaults.workers),
+            poll_interval: self
+                .millis("queue.poll_interval_ms")
+                .unwrap_or(defaults.poll_interval),
+            lease: self.secs("queue.lease_secs").unwrap_or(defaults.lease),
+            backoff_base: self
+                .secs("queue.backoff_base_secs")
+                .unwrap_or(defaults.backoff_base),
+            backoff_max: self
+                .secs("queue.backoff_max_secs")
+                .unwrap_or(defaults.backoff_max),
+            shutdown_grace: self
+                .secs("queue.shutdown_grace_secs")
+                .unwrap_or(defaults.shutdown_grace),
+        }
+    }
+
+    fn mail_options(&mut self) -> MailOptions {
+        let defaults = MailOptions::default();
+        let transport = match self.string("mail.transport").as_deref().map(str::trim) {
+            None | Some("memory") => MailTransport::Memory,
+            Some("file") => MailTransport::File(
+                self.string("mail.dir")
+                    .unwrap_or_else(|| "mail".to_string())
+                    .into(),
+            ),
+            Some("smtp") => match self.string("mail.smtp_url") {
+                Some(url) => MailTransport::Smtp(url),
+                None => {
+                    self.invalid("mail.transport", "smtp needs mail.smtp_url");
+                    MailTransport::Memory
+                }
+            },
+            Some(_) => {
+                self.invalid("mail.transport", "expected memory, file or smtp");
+                MailTransport::Memory
+            }
+        };
+
+        let from = self.string("mail.from");
+        if let Some(address) = &from {
+            if let Err(e) = address.parse::<lettre::message::Mailbox>() {
+                self.invalid("mail.from", e);
+            }
+        }
+        MailOptions {
+            transport,
+            from: from.unwrap_or(defaults.from),
+        }
+    }
+
+    fn cors_options(&mut self) -> CorsOptions {
+        let defaults = CorsOptions::default();
+        let allowed_origins: Option<Vec<HeaderValue>> = self.list("cors.allowed_origins");
+        for origin in allowed_origins.iter().flatten() {
+            let origin = String::from_utf8_lossy(origin.as_bytes()).into_owned();
+            let host = origin.split_once("://").map(|(_, host)| host);
+            if origin == "*" {
+                self.invalid(
+                    "cors.allowed_origins",
+                    "list the origins, * cannot send cookies",
+                );
+            } else if host.is_none_or(|host| host.is_empty() || host.contains('/')) {
+                let message = format!("{:?} is not an origin like https://example.com", origin);
+                self.invalid("cors.allowed_origins", message);
+            }
+        }
+        CorsOptions {
+            allowed_origins: allowed_origins.unwrap_or(defaults.allowed_origins),
+            allowed_methods: self
+                .list::<Method>("cors.allowed_methods")
+                .unwrap_or(defaults.allowed_methods),
+            allowed_headers: self
+                .list::<HeaderName>("cors.allowed_headers")
+                .unwrap_or(defaults.allowed_headers),
+        }
+    }
+
+    fn cookie_options(&mut self, profile: Profile) -> CookieOptions {
+        let defaults = CookieOptions::defaults(profile);
+        let attribute = |value: &str| {
+            !value.is_empty() && value.chars().all(|c| c.is_ascii_graphic() && c != ';')
+        };
+
+        let name = self.cookie_name("cookie.name");
+        let refresh_name = self.cookie_name("cookie.refresh_name");
+        let domain = self.string("cookie.domain");
+        if domain.as_deref().is_some_and(|domain| !attribute(domain)) {
+            self.invalid("cookie.domain", "not a valid cookie attribute");
+        }
+        let path = self.string("cookie.path");
+        if path
+            .as_deref()
+            .is_some_and(|path| !attribute(path) || !path.starts_with('/'))
+        {
+            self.invalid(
+                "cookie.path",
+                "must start with / and contain no spaces or ;",
+            );
+        }
+
+        let cookie = CookieOptions {
+            name: name.unwrap_or(defaults.name),
+            refresh_name: refresh_name.unwrap_or(defaults.refresh_name),
+            secure: self.flag("cookie.secure").unwrap_or(defaults.secure),
+            same_site: self
+                .parse::<SameSite>("cookie.same_site")
+                .unwrap_or(defaults.same_site),
+            domain: domain.or(defaults.domain),
+            path: path.unwrap_or(defaults.path),
+        };
+        if cookie.same_site == SameSite::None && !cookie.secure {
+            self.invalid("cookie.same_site", "none requires cookie.secure");
+        }
+        if cookie.refresh_name == cookie.name {
+            // Blame whichever of the two was set; the defaults differ
+            let key = if self.values.contains_key("cookie.refresh_name") {
+                "cookie.refresh_name"
+            } else {
+                "cookie.name"
+            };
+            self.invalid(key, "cookie.name and cookie.refresh_name must differ");
+        }
+        cookie
+    }
+
+    fn cookie_name(&mut self, key: &str) -> Option<String> {
+        let name = self.string(key);
+        if name.as_ref().is_some_and(|name| {
+            name.is_empty()
+                || !name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c))
+        }) {
+            self.invalid(key, "use letters, digits, -, _ and . only");
+        }
+        name
+    }
+
+    fn csrf_options(&mut self) -> CsrfOptions {
+        let defaults = CsrfOptions::default();
+        CsrfOptions {
+            enabled: self.flag("csrf.enabled").unwrap_or(defaults.enabled),
+            cookie_name: self
+                .cookie_name("csrf.cookie_name")
+                .unwrap_or(defaults.cookie_name),
+            header_name: self
+                .parse("csrf.header_name")
+                .unwrap_or(defaults.header_name),
+        }
+    }
+
+    fn rate_limit_options(&mut self) -> RateLimitOptions {
+        let defaults = RateLimitOptions::default();
+        let backend = match self.string("rate_limit.store").as_deref().map(str::trim) {
+            None | Some("memory") => RateLimitBackend::Memory,
+            Some("redis") if cfg!(not(feature = "redis")) => {
+                self.invalid("rate_limit.store", "redis needs the redis feature");
+                RateLimitBackend::Memory
+            }
+            Some("redis") => match self.string("rate_limit.redis_url") {
+                Some(url) => RateLimitBackend::Redis(url),
+                None => {
+                    self.invalid("rate_limit.store", "redis needs rate_limit.redis_url");
+                    RateLimitBackend::Memory
+                }
+            },
+            Some(_) => {
+                self.invalid("rate_limit.store", "expected memory or redis");
+                RateLimitBackend::Memory
+            }
+        };
+        let auth = self.policy("auth", defaults.auth);
+        let api = self.policy("api", defaults.api);
+        let api_keys: Vec<String> = self.list("rate_limit.api_keys").unwrap_or_default();
+        for policy in [&auth, &api] {
+            if policy.key_by == KeyBy::ApiKey && api_keys.is_empty() {
+                let key = format!("rate_limit.{}_by", policy.name);
+                self.invalid(&key, "api_key needs rate_limit.api_keys");
+            }
+        }
+        RateLimitOptions {
+            enabled: self.flag("rate_limit.enabled").unwrap_or(defaults.enabled),
+            backend,
+            auth,
+            api,
+            api_keys,
+        }
+    }
+
+    /// `rate_limit.<name>` and `rate_limit.<name>_by`
+    fn policy(&mut self, name: &str, defaults: Policy) -> Policy {
+        let quota = self.parse(&format!("rate_limit.{}", name));
+        let key_by = self.parse(&format!("rate_limit.{}_by", name));
+        Policy {
+            quota: quota.unwrap_or(defaults.quota),
+            key_by: key_by.unwrap_or(defaults.key_by),
+            ..defaults
+        }
+    }
+
+    fn security_headers(&mut self, profile: Profile) -> SecurityHeaders {
+        let defaults = SecurityHeaders::defaults(profile);
+        SecurityHeaders {
+            hsts: self.header("security_headers.hsts", defaults.hsts),
+            content_security_policy: self.header(
+                "security_headers.content_security_policy",
+                defaults.content_security_policy,
+            ),
+            referrer_policy: self
+                .header("security_headers.referrer_policy", defaults.referrer_policy),
+            permissions_policy: self.header(
+                "security_headers.permissions_policy",
+                defaults.permissions_policy,
+            ),
+            nosniff: self
+                .flag("security_headers.nosniff")
+                .unwrap_or(defaults.nosniff),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(vars: &[(&str, &str)]) -> BTreeMap<String, String> {
+        vars.iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn problems(result: Result<Config, ConfigError>) -> Vec<String> {
+        match result {
+            Err(ConfigError::Invalid(problems)) => problems,
+            other => panic!("expected invalid configuration, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_prefixed_env_wins_over_legacy_names_and_flags_win_over_both() {
+        let vars = env(&[
+            ("PORT", "4000"),
+            ("APP_SERVER__PORT", "5000"),
+            ("HOST", "0.0.0.0"),
+        ]);
+
+        let config = Config::load_from(&Overrides::default(), &vars).unwrap();
+        assert_eq!((config.host.as_str(), config.port), ("0.0.0.0", 5000));
+
+        let overrides = Overrides::default().set("server.port", "6000", "--port");
+        let config = Config::load_from(&overrides, &vars).unwrap();
+        assert_eq!(config.port, 6000);
+    }
+
+    #[test]
+    fn test_every_problem_is_reported_with_its_origin() {
+        let vars = env(&[
+            ("PORT", "http"),
+            ("APP_QUEUE__WORKERZ", "4"),
+            ("DB_FOREIGN_KEYS", "maybe"),
+            ("JWT_SECRET", "secret"),
+            ("JWT_SECRET_FILE", "/run/secrets/jwt"),
+        ]);
+        let overrides = Overrides::default().set("mail.transport", "pigeon", "--set");
+
+        let problems = problems(Config::load_from(&overrides, &vars));
+        assert_eq!(problems.len(), 5, "{:#?}", problems);
+        assert!(problems.contains(
+            &"server.port = \"http\" from environment variable PORT: invalid digit found in string"
+                .to_string()
+        ));
+        assert!(problems
+            .contains(&"unknown setting in environment variable APP_QUEUE__WORKERZ".to_string()));
+        assert!(
+            problems.contains(&"set either JWT_SECRET or JWT_SECRET_FILE, not both".to_string())
+        );
+        assert!(problems
+            .iter()
+            .any(|p| p.starts_with("database.foreign_keys = \"maybe\"")));
+        assert!(problems
+            .iter()
+            .any(|p| p.contains("command line flag --set")));
+    }
+
+    #[test]
+    fn test_api_key_policies_need_known_keys() {
+        let vars = env(&[("APP_RATE_LIMIT__API_BY", "api_key")]);
+        let problems = problems(Config::load_from(&Overrides::default(), &vars));
+        assert_eq!(
+            problems,
+            ["rate_limit.api_by = \"api_key\" from environment variable APP_RATE_LIMIT__API_BY: \
+              api_key needs rate_limit.api_keys"]
+        );
+
+        let vars = env(&[
+            ("APP_RATE_LIMIT__API_BY", "api_key"),
+            ("APP_RATE_LIMIT__API_KEYS", "key-one, key-two"),
+        ]);
+        let config = Config::load_from(&Overrides::default(), &vars).unwrap();
+        assert_eq!(config.rate_limit.api_keys, ["key-one", "key-two"]);
+    }
+
+    #[test]
+    fn test_prod_profile_refuses_unsafe_defaults() {
+        let vars = env(&[("APP_PROFILE", "prod"), ("DEV_ENDPOINTS", "true")]);
+        let Err(ConfigError::Unsafe { profile, problems }) =
+            Config::load_from(&Overrides::default(), &vars)
+        else {
+            panic!("prod accepted the default secret");
+        };
+        assert_eq!(profile, Profile::Prod);
+        assert_eq!(problems.len(), 3, "{:#?}", problems);
+
+        let vars = env(&[
+            ("APP_PROFILE", "prod"),
+            ("JWT_SECRET", "0123456789abcdef0123456789abcdef"),
+            ("MAIL_TRANSPORT", "file"),
+        ]);
+        assert!(Config::load_from(&Overrides::default(), &vars).is_ok());
+
+        // The same settings pass every check in dev
+        let vars = env(&[("DEV_ENDPOINTS", "true")]);
+        let config = Config::load_from(&Overrides::default(), &vars).unwrap();
+        assert_eq!(config.profile, Profile::Dev);
+    }
+
+    #[test]
+    fn test_printed_config_loads_back_and_every_setting_is_listed() {
+        let config = Config::load_from(&Overrides::default(), &BTreeMap::new()).unwrap();
+        let printed: toml::Table = config.to_toml(false).parse().unwrap();
+        assert_eq!(printed["server"]["port"].as_integer(), Some(3000));
+        assert_eq!(printed["profile"].as_str(), Some("dev"));
+
+        let keys: Vec<_> = config.entries().into_iter().map(|(key, _)| key).collect();
+        let settings: Vec<_> = SETTINGS.iter().map(|setting| setting.key).collect();
+        assert_eq!(keys, settings);
+
+        let redacted = config.to_toml(true);
+        assert!(!redacted.contains(INSECURE_JWT_SECRET));
+        assert!(redacted.contains("jwt_secret = \"<redacted>\""));
+    }
+}
@@ -0,0 +1,319 @@
+//! Where setting values come from, lowest precedence first
+
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+/// Prefix of the environment variable for every setting, e.g. `APP_SERVER__PORT`
+pub const ENV_PREFIX: &str = "APP_";
+/// Environment variable naming the TOML file to read
+pub const CONFIG_FILE_ENV: &str = "APP_CONFIG";
+
+/// One configurable value, by its dotted TOML key
+pub(super) struct Setting {
+    pub key: &'static str,
+    /// Unprefixed variable read before configuration files existed
+    pub legacy_env: Option<&'static str>,
+    /// Hidden by `config print --redacted`
+    pub secret: bool,
+}
+
+/// A setting added after the prefixed variables, without a legacy name
+const fn prefixed(key: &'static str) -> Setting {
+    Setting {
+        key,
+        legacy_env: None,
+        secret: false,
+    }
+}
+
+/// A [`prefixed`] setting hidden by `config print --redacted`
+const fn prefixed_secret(key: &'static str) -> Setting {
+    Setting {
+        key,
+        legacy_env: None,
+        secret: true,
+    }
+}
+
+const fn plain(key: &'static str, legacy_env: &'static str) -> Setting {
+    Setting {
+        key,
+        legacy_env: Some(legacy_env),
+        secret: false,
+    }
+}
+
+const fn secret(key: &'static str, legacy_env: &'static str) -> Setting {
+    Setting {
+        key,
+        legacy_env: Some(legacy_env),
+        secret: true,
+    }
+}
+
+pub(super) const SETTINGS: &[Setting] = &[
+    prefixed("profile"),
+    plain("server.host", "HOST"),
+    plain("server.port", "PORT"),
+    plain("server.api_docs", "API_DOCS"),
+    plain("server.dev_endpoints", "DEV_ENDPOINTS"),
+    secret("database.url", "DATABASE_URL"),
+    plain("database.run_migrations", "RUN_MIGRATIONS"),
+    plain("database.max_connections", "DB_MAX_CONNECTIONS"),
+    plain(
+        "database.connection_timeout_secs",
+        "DB_CONNECTION_TIMEOUT_SECS",
+    ),
+    plain("database.busy_timeout_ms", "DB_BUSY_TIMEOUT_MS"),
+    plain("database.journal_mode", "DB_JOURNAL_MODE"),
+    plain("database.synchronous", "DB_SYNCHRONOUS"),
+    plain("database.foreign_keys", "DB_FOREIGN_KEYS"),
+    secret("auth.jwt_secret", "JWT_SECRET"),
+    prefixed("auth.refresh_token_transport"),
+    plain("scheduler.enabled", "SCHEDULER_ENABLED"),
+    plain("scheduler.token_purge_schedule", "TOKEN_PURGE_SCHEDULE"),
+    plain("scheduler.jitter_secs", "SCHEDULER_JITTER_SECS"),
+    plain(
+        "scheduler.shutdown_grace_secs",
+        "SCHEDULER_SHUTDOWN_GRACE_SECS",
+    ),
+    plain("queue.workers", "QUEUE_WORKERS"),
+    plain("queue.poll_interval_ms", "QUEUE_POLL_INTERVAL_MS"),
+    plain("queue.lease_secs", "QUEUE_LEASE_SECS"),
+    plain("queue.backoff_base_secs", "QUEUE_BACKOFF_BASE_SECS"),
+    plain("queue.backoff_max_secs", "QUEUE_BACKOFF_MAX_SECS"),
+    plain("queue.shutdown_grace_secs", "QUEUE_SHUTDOWN_GRACE_SECS"),
+    plain("mail.transport", "MAIL_TRANSPORT"),
+    plain("mail.dir", "MAIL_DIR"),
+    secret("mail.smtp_url", "SMTP_URL"),
+    plain("mail.from", "MAIL_FROM"),
+    prefixed("cors.allowed_origins"),
+    prefixed("cors.allowed_methods"),
+    prefixed("cors.allowed_headers"),
+    prefixed("cookie.name"),
+    prefixed("cookie.refresh_name"),
+    prefixed("cookie.secure"),
+    prefixed("cookie.same_site"),
+    prefixed("cookie.domain"),
+    prefixed("cookie.path"),
+    prefixed("csrf.enabled"),
+    prefixed("csrf.cookie_name"),
+    prefixed("csrf.header_name"),
+    prefixed("rate_limit.enabled"),
+    prefixed("rate_limit.store"),
+    prefixed_secret("rate_limit.redis_url"),
+    prefixed("rate_limit.auth"),
+    prefixed("rate_limit.auth_by"),
+    prefixed("rate_limit.api"),
+    prefixed("rate_limit.api_by"),
+    prefixed_secret("rate_limit.api_keys"),
+    prefixed("security_headers.hsts"),
+    prefixed("security_headers.content_security_policy"),
+    prefixed("security_headers.referrer_policy"),
+    prefixed("security_headers.permissions_policy"),
+    prefixed("security_headers.nosniff"),
+];
+
+pub(super) fn setting(key: &str) -> Option<&'static Setting> {
+    SETTINGS.iter().find(|setting| setting.key == key)
+}
+
+/// `server.port` -> `APP_SERVER__PORT`
+pub(super) fn env_name(key: &str) -> String {
+    format!(
+        "{}{}",
+        ENV_PREFIX,
+        key.to_ascii_uppercase().replace('.', "__")
+    )
+}
+
+/// Settings given on the command line, applied over the file and environment
+#[derive(Clone, Debug, Default)]
+pub struct Overrides {
+    /// TOML file to read instead of `APP_CONFIG`
+    pub file: Option<PathBuf>,
+    pub settings: Vec<Override>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Override {
+    /// Dotted key such as `server.port`
+    pub key: String,
+    pub value: String,
+    /// The flag that set it, for error messages
+    pub flag: String,
+}
+
+impl Overrides {
+    pub fn set(mut self, key: &str, value: &str, flag: &str) -> Self {
+        self.settings.push(Override {
+            key: key.to_string(),
+            value: value.to_string(),
+            flag: flag.to_string(),
+        });
+        self
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(super) enum Origin {
+    File(PathBuf),
+    Env(String),
+    /// `<VAR>_FILE` pointing at a secret
+    SecretFile {
+        var: String,
+        path: String,
+    },
+    Flag(String),
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Origin::File(path) => write!(f, "{}", path.display()),
+            Origin::Env(var) => write!(f, "environment variable {}", var),
+            Origin::SecretFile { var, path } => write!(f, "{} ({})", var, path),
+            Origin::Flag(flag) => write!(f, "command line flag {}", flag),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(super) struct Value {
+    pub raw: String,
+    pub origin: Origin,
+}
+
+/// Raw values after layering, plus every problem found on the way
+#[derive(Default)]
+pub(super) struct Layers {
+    pub values: BTreeMap<&'static str, Value>,
+    pub problems: Vec<String>,
+}
+
+impl Layers {
+    fn insert(&mut self, key: &str, raw: String, origin: Origin) {
+        match setting(key) {
+            Some(setting) => {
+                self.values.insert(setting.key, Value { raw, origin });
+            }
+            None => self
+                .problems
+                .push(format!("unknown setting `{}` in {}", key, origin)),
+        }
+    }
+
+    /// Flatten a parsed TOML document into dotted keys
+    pub fn file(&mut self, path: PathBuf, table: toml::Table) {
+        for (name, value) in table {
+            match value {
+                toml::Value::Table(section) => {
+                    for (field, value) in section {
+                        let key = format!("{}.{}", name, field);
+                        self.toml_value(&key, value, &path);
+                    }
+                }
+                value => self.toml_value(&name, value, &path),
+            }
+        }
+    }
+
+    fn toml_value(&mut self, key: &str, value: toml::Value, path: &Path) {
+        let origin = Origin::File(path.to_path_buf());
+        let raw = match value {
+            toml::Value::String(value) => value,
+            toml::Value::Integer(value) => value.to_string(),
+            toml::Value::Float(value) => value.to_string(),
+            toml::Value::Boolean(value) => value.to_string(),
+            toml::Value::Array(values) => {
+                let mut items = Vec::with_capacity(values.len());
+                for value in values {
+                    match value {
+                        toml::Value::String(value) => items.push(value),
+                        _ => {
+                            return self
+                                .problems
+                                .push(format!("`{}` in {} must be a list of strings", key, origin))
+                        }
+                    }
+                }
+                items.join(",")
+            }
+            _ => {
+                return self.problems.push(format!(
+                    "`{}` in {} must be a string, number or boolean",
+                    key, origin
+                ))
+            }
+        };
+        self.insert(key, raw, origin);
+    }
+
+    /// Read `APP_<SECTION>__<KEY>`, falling back to the legacy names
+    ///
+    /// Each variable may instead be given as `<VAR>_FILE`, naming a file that
+    /// holds the value. Unknown `APP_` variables are reported so typos do not
+    /// go unnoticed.
+    pub fn env(&mut self, env: &BTreeMap<String, String>) {
+        for setting in SETTINGS {
+            let names =
+                std::iter::once(env_name(setting.key)).chain(setting.legacy_env.map(String::from));
+            for name in names {
+                if let Some((raw, origin)) = self.env_var(env, &name) {
+                    self.insert(setting.key, raw, origin);
+                    break;
+                }
+            }
+        }
+
+        for name in env.keys().filter(|name| name.starts_with(ENV_PREFIX)) {
+            let base = name.strip_suffix("_FILE").unwrap_or(name);
+            let known = base == CONFIG_FILE_ENV
+                || SETTINGS.iter().any(|setting| env_name(setting.key) == base);
+            if !known {
+                self.problems
+                    .push(format!("unknown setting in environment variable {}", name));
+            }
+        }
+    }
+
+    fn env_var(&mut self, env: &BTreeMap<String, String>, name: &str) -> Option<(String, Origin)> {
+        let file_var = format!("{}_FILE", name);
+        match (env.get(name), env.get(&file_var)) {
+            (Some(_), Some(_)) => {
+                self.problems
+                    .push(format!("set either {} or {}, not both", name, file_var));
+                None
+            }
+            (Some(value), None) => Some((value.clone(), Origin::Env(name.to_string()))),
+            (None, Some(path)) => match fs::read_to_string(path) {
+                Ok(contents) => {
+                    let origin = Origin::SecretFile {
+                        var: file_var,
+                        path: path.clone(),
+                    };
+                    Some((contents.trim_end_matches(['\r', '\n']).to_string(), origin))
+                }
+                Err(e) => {
+                    self.problems
+                        .push(format!("cannot read {} ({}): {}", file_var, path, e));
+                    None
+                }
+            },
+            (None, None) => None,
+        }
+    }
+
+    pub fn overrides(&mut self, overrides: &Overrides) {
+        for entry in &overrides.settings {
+            self.insert(
+                &entry.key,
+                entry.value.clone(),
+                Origin::Flag(entry.flag.clone()),
+            );
+        }
+    }
+}
@@ -0,0 +1,217 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+use tokio::sync::Semaphore;
+use ts_rs::TS;
+use utoipa::ToSchema;
+
+use super::{DbConnection, DbPool};
+use crate::error::{AppError, AppResult};
+
+/// Checkouts slower than this are logged
+const SLOW_CHECKOUT: Duration = Duration::from_millis(250);
+
+/// Runs Diesel queries on Tokio's blocking threads instead of the async workers
+///
+/// At most `max_size` closures run at once; further callers wait
+/// asynchronously for a permit, so a saturated pool never ties up blocking
+/// threads or executor workers.
+#[derive(Clone)]
+pub struct AsyncPool {
+    pool: DbPool,
+    permits: Arc<Semaphore>,
+    metrics: Arc<PoolMetrics>,
+}
+
+impl AsyncPool {
+    pub fn new(pool: DbPool) -> Self {
+        let permits = Arc::new(Semaphore::new(pool.max_size() as usize));
+        Self {
+            pool,
+            permits,
+            metrics: Arc::default(),
+        }
+    }
+
+    /// The underlying synchronous pool
+    pub fn pool(&self) -> &DbPool {
+        &self.pool
+    }
+
+    /// Run `f` with a pooled connection on a blocking thread
+    pub async fn interact<F, T>(&self, f: F) -> AppResult<T>
+    where
+        F: FnOnce(&mut DbConnection) -> AppResult<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let started = Instant::now();
+        self.metrics.waiting.fetch_add(1, Ordering::Relaxed);
+        let permit = self.permits.clone().acquire_owned().await;
+        self.metrics.waiting.fetch_sub(1, Ordering::Relaxed);
+        let permit = permit.expect("pool semaphore is never closed");
+
+        let pool = self.pool.clone();
+        let metrics = self.metrics.clone();
+        tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            let conn = pool.get();
+            metrics.record_checkout(started.elapsed(), conn.is_ok());
+            let mut conn = conn?;
+            f(&mut conn)
+        })
+        .await
+        .map_err(AppError::from)?
+    }
+
+    /// Current pool occupancy and checkout wait times
+    pub fn stats(&self) -> PoolStats {
+        let state = self.pool.state();
+        self.metrics.snapshot(
+            self.pool.max_size(),
+            state.connections,
+            state.idle_connections,
+        )
+    }
+}
+
+#[derive(Debug, Default)]
+struct PoolMetrics {
+    waiting: AtomicU64,
+    checkouts: AtomicU64,
+    timeouts: AtomicU64,
+    wait_micros_total: AtomicU64,
+    wait_micros_max: AtomicU64,
+}
+
+impl PoolMetrics {
+    fn record_checkout(&self, wait: Duration, succeeded: bool) {
+        let micros = wait.as_micros().try_into().unwrap_or(u64::MAX);
+        self.checkouts.fetch_add(1, Ordering::Relaxed);
+        self.wait_micros_total.fetch_add(micros, Ordering::Relaxed);
+        self.wait_micros_max.fetch_max(micros, Ordering::Relaxed);
+
+        if !succeeded {
+            self.timeouts.fetch_add(1, Ordering::Relaxed);
+        } else if wait > SLOW_CHECKOUT {
+            tracing::warn!("Waited {:?} for a database connection", wait);
+        }
+    }
+
+    fn snapshot(&self, max_size: u32, connections: u32, idle_connections: u32) -> PoolStats {
+        let checkouts = self.checkouts.load(Ordering::Relaxed);
+        let wait_micros_total = self.wait_micros_total.load(Ordering::Relaxed);
+
+        PoolStats {
+            max_size,
+            connections,
+            idle_connections,
+            waiting: self.waiting.load(Ordering::Relaxed),
+            checkouts,
+            timeouts: self.timeouts.load(Ordering::Relaxed),
+            wait_ms_avg: if checkouts == 0 {
+                0.0
+            } else {
+                wait_micros_total as f64 / checkouts as f64 / 1000.0
+            },
+            wait_ms_max: self.wait_micros_max.load(Ordering::Relaxed) as f64 / 1000.0,
+        }
+    }
+}
+
+/// Snapshot of connection pool usage since startup
+#[derive(Debug, Serialize, ToSchema, TS)]
+pub struct PoolStats {
+    pub max_size: u32,
+    /// Open connections, busy or idle
+    pub connections: u32,
+    pub idle_connections: u32,
+    /// Callers currently queued for a connection
+    #[ts(type = "number")]
+    pub waiting: u64,
+    /// Connections handed out
+    #[ts(type = "number")]
+    pub checkouts: u64,
+    /// Checkouts that gave up after the pool's connection timeout
+    #[ts(type = "number")]
+    pub timeouts: u64,
+    /// Mean time from request to connection, in milliseconds
+    pub wait_ms_avg: f64,
+    /// Longest time from request to connection, in milliseconds
+    pub wait_ms_max: f64,
+}
+
+// Postgres runs these through the integration tests, which need a server
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::*;
+    use crate::db::{establish_connection_pool, DbOptions};
+    use diesel::{sql_query, RunQueryDsl};
+
+    fn async_pool(max_connections: u32) -> (AsyncPool, tempfile::TempDir) {
+        let dir = tempfile::tempdir().unwrap();
+        let url = dir.path().join("pool.db").to_string_lossy().into_owned();
+        let options = DbOptions {
+            max_connections,
+            ..DbOptions::default()
+        };
+        (
+            AsyncPool::new(establish_connection_pool(&url, &options)),
+            dir,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_interact_runs_queries_and_counts_checkouts() {
+        let (db, _dir) = async_pool(2);
+
+        let rows = db
+            .interact(|conn| Ok(sql_query("SELECT 1").execute(conn)?))
+            .await
+            .unwrap();
+        assert_eq!(rows, 0);
+
+        let stats = db.stats();
+        assert_eq!(stats.checkouts, 1);
+        assert_eq!(stats.timeouts, 0);
+        assert_eq!(stats.waiting, 0);
+    }
+
+    #[tokio::test]
+    async fn test_callers_queue_for_a_saturated_pool() {
+        let (db, _dir) = async_pool(1);
+
+        let slow = {
+            let db = db.clone();
+            tokio::spawn(async move {
+                db.interact(|_| {
+                    std::thread::sleep(Duration::from_millis(200));
+                    Ok(())
+                })
+                .await
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        // The executor stays responsive while the only connection is busy
+        let queued = {
+            let db = db.clone();
+            tokio::spawn(async move { db.interact(|_| Ok(())).await })
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(db.stats().waiting, 1);
+
+        slow.await.unwrap().unwrap();
+        queued.await.unwrap().unwrap();
+
+        let stats = db.stats();
+        assert_eq!(stats.checkouts, 2);
+        assert_eq!(stats.waiting, 0);
+        assert!(stats.wait_ms_max >= 100.0, "{:?}", stats);
+    }
+}
@@ -0,0 +1,155 @@
+use serde::Serialize;
+use ts_rs::TS;
+use utoipa::ToSchema;
+
+use super::{build_pool, AsyncPool, DbConnection, DbOptions, DbPool, PoolStats};
+use crate::error::AppResult;
+
+/// Database access routed by intent
+///
+/// SQLite allows one writer at a time, so `Db::open` gives writes a single
+/// connection, queued through its `AsyncPool`, and reads a separate pool of
+/// read-only connections. In WAL mode readers never block the writer, so
+/// writes no longer fail with "database is locked" under load. Postgres
+/// builds use one pool for both.
+#[derive(Clone)]
+pub struct Db {
+    writer: AsyncPool,
+    reader: AsyncPool,
+}
+
+impl Db {
+    /// Open the writer and reader pools for `database_url`
+    #[cfg(feature = "sqlite")]
+    pub fn open(database_url: &str, options: &DbOptions) -> Self {
+        Self {
+            writer: AsyncPool::new(build_pool(database_url, options, 1, false)),
+            reader: AsyncPool::new(build_pool(
+                database_url,
+                options,
+                options.max_connections,
+                true,
+            )),
+        }
+    }
+
+    #[cfg(feature = "postgres")]
+    pub fn open(database_url: &str, options: &DbOptions) -> Self {
+        Self::single(build_pool(
+            database_url,
+            options,
+            options.max_connections,
+            false,
+        ))
+    }
+
+    /// Use one pool for both reads and writes
+    pub fn single(pool: DbPool) -> Self {
+        let pool = AsyncPool::new(pool);
+        Self {
+            writer: pool.clone(),
+            reader: pool,
+        }
+    }
+
+    /// Run a query that only reads
+    ///
+    /// On split SQLite pools the connection is read-only, so accidental
+    /// writes fail instead of contending with the writer.
+    pub async fn read<F, T>(&self, f: F) -> AppResult<T>
+    where
+        F: FnOnce(&mut DbConnection) -> AppResult<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        self.reader.interact(f).await
+    }
+
+    /// Run a query that writes, one at a time on split SQLite pools
+    pub async fn write<F, T>(&self, f: F) -> AppResult<T>
+    where
+        F: FnOnce(&mut DbConnection) -> AppResult<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        self.writer.interact(f).await
+    }
+
+    /// The synchronous pool that may write, for code outside the async runtime
+    pub fn write_pool(&self) -> &DbPool {
+        self.writer.pool()
+    }
+
+    pub fn stats(&self) -> DbStats {
+        DbStats {
+            writer: self.writer.stats(),
+            reader: self.reader.stats(),
+        }
+    }
+}
+
+/// Usage of the writer and reader pools
+///
+/// Both report the same pool when reads and writes share one.
+#[derive(Debug, Serialize, ToSchema, TS)]
+pub struct DbStats {
+    pub writer: PoolStats,
+    pub reader: PoolStats,
+}
+
+// Postgres runs these through the integration tests, which need a server
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::*;
+    use crate::db::migrations;
+    use diesel::{sql_query, RunQueryDsl};
+
+    fn open() -> (Db, tempfile::TempDir) {
+        let dir = tempfile::tempdir().unwrap();
+        let url = dir.path().join("split.db").to_string_lossy().into_owned();
+        let db = Db::open(&url, &DbOptions::default());
+        migrations::run_pending(&mut db.write_pool().get().unwrap()).unwrap();
+        (db, dir)
+    }
+
+    #[tokio::test]
+    async fn test_reads_see_writes() {
+        let (db, _dir) = open();
+
+        db.write(|conn| {
+            Ok(sql_query(
+                "INSERT INTO users (username, email, password_hash) VALUES ('a', 'a@example.com', 'x')",
+            )
+            .execute(conn)?)
+        })
+        .await
+        .unwrap();
+
+        let count = db
+            .read(|conn| {
+                use crate::db::schema::users;
+                use diesel::QueryDsl;
+                Ok(users::table.count().get_result::<i64>(conn)?)
+            })
+            .await
+            .unwrap();
+        assert_eq!(count, 1);
+    }
+
+    #[tokio::test]
+    async fn test_reader_connections_are_read_only() {
+        let (db, _dir) = open();
+
+        let result = db
+            .read(|conn| {
+                Ok(sql_query(
+                    "INSERT INTO users (username, email, password_hash) VALUES ('a', 'a@example.com', 'x')",
+                )
+                .execute(conn)?)
+            })
+            .await;
+        assert!(result.is_err());
+
+        let stats = db.stats();
+        assert_eq!(stats.writer.max_size, 1);
+        assert_eq!(stats.reader.max_size, DbOptions::default().max_connections);
+    }
+}
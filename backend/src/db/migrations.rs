@@ -0,0 +1,180 @@
+use diesel::migration::{Migration, MigrationSource};
+use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+
+use super::{DbBackend, DbConnection};
+
+/// The migrations for the enabled backend, compiled into the binary
+#[cfg(feature = "sqlite")]
+pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations/sqlite");
+#[cfg(feature = "postgres")]
+pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations/postgres");
+
+type HarnessError = Box<dyn std::error::Error + Send + Sync>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum MigrationError {
+    #[error("{0}")]
+    Harness(HarnessError),
+    #[error("database schema is ahead of this binary; unknown migrations: {}", .0.join(", "))]
+    SchemaAhead(Vec<String>),
+    #[error("no migration to revert")]
+    NothingToRevert,
+}
+
+impl From<HarnessError> for MigrationError {
+    fn from(e: HarnessError) -> Self {
+        MigrationError::Harness(e)
+    }
+}
+
+/// One embedded migration and whether the database has applied it
+#[derive(Debug)]
+pub struct MigrationStatus {
+    pub name: String,
+    pub applied: bool,
+}
+
+fn embedded() -> Result<Vec<Box<dyn Migration<DbBackend>>>, MigrationError> {
+    Ok(MigrationSource::<DbBackend>::migrations(&MIGRATIONS)?)
+}
+
+fn applied_versions(conn: &mut DbConnection) -> Result<Vec<String>, MigrationError> {
+    Ok(conn
+        .applied_migrations()?
+        .into_iter()
+        .map(|version| version.to_string())
+        .collect())
+}
+
+/// Every embedded migration, oldest first
+pub fn status(conn: &mut DbConnection) -> Result<Vec<MigrationStatus>, MigrationError> {
+    let applied = applied_versions(conn)?;
+
+    Ok(embedded()?
+        .iter()
+        .map(|migration| MigrationStatus {
+            name: migration.name().to_string(),
+            applied: applied.contains(&migration.name().version().to_string()),
+        })
+        .collect())
+}
+
+/// Fail if the database has applied migrations this binary does not contain,
+/// i.e. it was migrated by a newer version
+pub fn ensure_not_ahead(conn: &mut DbConnection) -> Result<(), MigrationError> {
+    let known: Vec<String> = embedded()?
+        .iter()
+        .map(|migration| migration.name().version().to_string())
+        .collect();
+
+    let unknown: Vec<String> = applied_versions(conn)?
+        .into_iter()
+        .filter(|version| !known.contains(version))
+        .collect();
+
+    if unknown.is_empty() {
+        Ok(())
+    } else {
+        Err(MigrationError::SchemaAhead(unknown))
+    }
+}
+
+/// Apply all pending migrations, returning their versions
+pub fn run_pending(conn: &mut DbConnection) -> Result<Vec<String>, MigrationError> {
+    ensure_not_ahead(conn)?;
+
+    Ok(conn
+        .run_pending_migrations(MIGRATIONS)?
+        .into_iter()
+        .map(|version| version.to_string())
+        .collect())
+}
+
+/// Revert the `count` most recently applied migrations, returning their versions
+pub fn revert(conn: &mut DbConnection, count: usize) -> Result<Vec<String>, MigrationError> {
+    let mut reverted = Vec::with_capacity(count);
+    for _ in 0..count {
+        if applied_versions(conn)?.is_empty() {
+            break;
+        }
+        reverted.push(conn.revert_last_migration(MIGRATIONS)?.to_string());
+    }
+    Ok(reverted)
+}
+
+/// Revert the most recently applied migration and apply it again
+pub fn redo(conn: &mut DbConnection) -> Result<String, MigrationError> {
+    let version = revert(conn, 1)?
+        .pop()
+        .ok_or(MigrationError::NothingToRevert)?;
+
+    let migration = embedded()?
+        .into_iter()
+        .find(|migration| migration.name().version().to_string() == version)
+        .expect("reverted migration is embedded");
+    conn.run_migration(&migration)?;
+
+    Ok(version)
+}
+
+// Postgres runs these through the integration tests, which need a server
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::*;
+    use diesel::{sql_query, Connection, RunQueryDsl};
+
+    fn conn() -> DbConnection {
+        DbConnection::establish(":memory:").expect("Failed to open in-memory database")
+    }
+
+    #[test]
+    fn test_run_pending_applies_everything_once() {
+        let mut conn = conn();
+
+        let applied = run_pending(&mut conn).unwrap();
+        assert_eq!(applied.len(), embedded().unwrap().len());
+        assert!(status(&mut conn)
+            .unwrap()
+            .iter()
+            .all(|migration| migration.applied));
+
+        assert!(run_pending(&mut conn).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_revert_and_redo() {
+        let mut conn = conn();
+        run_pending(&mut conn).unwrap();
+
+        let reverted = revert(&mut conn, 2).unwrap();
+        assert_eq!(reverted.len(), 2);
+        let pending: Vec<_> = status(&mut conn)
+            .unwrap()
+            .into_iter()
+            .filter(|migration| !migration.applied)
+            .collect();
+        assert_eq!(pending.len(), 2);
+
+        let last_applied = applied_versions(&mut conn).unwrap();
+        let redone = redo(&mut conn).unwrap();
+        assert!(last_applied.contains(&redone));
+        assert_eq!(
+            applied_versions(&mut conn).unwrap().len(),
+            last_applied.len()
+        );
+    }
+
+    #[test]
+    fn test_schema_ahead_of_binary_is_rejected() {
+        let mut conn = conn();
+        run_pending(&mut conn).unwrap();
+        sql_query("INSERT INTO __diesel_schema_migrations (version) VALUES ('99999999999999')")
+            .execute(&mut conn)
+            .unwrap();
+
+        let err = run_pending(&mut conn).unwrap_err();
+        assert!(
+            matches!(&err, MigrationError::SchemaAhead(versions) if versions == &["99999999999999"])
+        );
+    }
+}
@@ -1,15 +1,248 @@
+pub mod async_pool;
+pub mod facade;
+pub mod migrations;
 pub mod schema;
 
+use std::{fmt, str::FromStr, time::Duration};
+
 use diesel::r2d2::{self, ConnectionManager};
-use diesel::sqlite::SqliteConnection;
-use std::env;
 
-pub type DbPool = r2d2::Pool<ConnectionManager<SqliteConnection>>;
+pub use async_pool::{AsyncPool, PoolStats};
+pub use facade::{Db, DbStats};
+
+#[cfg(all(feature = "sqlite", feature = "postgres"))]
+compile_error!("features `sqlite` and `postgres` are mutually exclusive; use `--no-default-features --features postgres`");
+#[cfg(not(any(feature = "sqlite", feature = "postgres")))]
+compile_error!("enable exactly one database feature: `sqlite` or `postgres`");
+
+/// The connection type of the database backend chosen at compile time
+#[cfg(feature = "sqlite")]
+pub type DbConnection = diesel::sqlite::SqliteConnection;
+#[cfg(feature = "postgres")]
+pub type DbConnection = diesel::pg::PgConnection;
+
+/// The Diesel backend matching `DbConnection`
+#[cfg(feature = "sqlite")]
+pub type DbBackend = diesel::sqlite::Sqlite;
+#[cfg(feature = "postgres")]
+pub type DbBackend = diesel::pg::Pg;
+
+pub type DbPool = r2d2::Pool<ConnectionManager<DbConnection>>;
+
+/// Run `f` in a transaction that will write
+///
+/// On SQLite it takes the write lock up front, so it waits for other writers
+/// at the start instead of failing with `SQLITE_BUSY` halfway through.
+pub fn write_transaction<T, E, F>(conn: &mut DbConnection, f: F) -> Result<T, E>
+where
+    F: FnOnce(&mut DbConnection) -> Result<T, E>,
+    E: From<diesel::result::Error>,
+{
+    #[cfg(feature = "sqlite")]
+    return conn.immediate_transaction(f);
+    #[cfg(feature = "postgres")]
+    return diesel::Connection::transaction(conn, f);
+}
+
+/// Whether `conn` is inside a transaction already
+pub fn in_transaction(conn: &mut DbConnection) -> bool {
+    use diesel::connection::{Connection, TransactionManager};
+
+    <DbConnection as Connection>::TransactionManager::transaction_manager_status_mut(conn)
+        .transaction_depth()
+        .is_ok_and(|depth| depth.is_some())
+}
+
+/// Pool sizing, plus the pragmas applied to every SQLite connection
+///
+/// The pragma settings are ignored when built for Postgres.
+#[derive(Clone, Debug)]
+pub struct DbOptions {
+    pub max_connections: u32,
+    /// How long to wait for a free connection from the pool
+    pub connection_timeout: Duration,
+    /// How long SQLite retries a locked database before `SQLITE_BUSY`
+    pub busy_timeout: Duration,
+    pub journal_mode: JournalMode,
+    pub synchronous: Synchronous,
+    /// Enforce `FOREIGN KEY` constraints, including `ON DELETE CASCADE`
+    pub foreign_keys: bool,
+}
+
+impl Default for DbOptions {
+    fn default() -> Self {
+        Self {
+            max_connections: 10,
+            connection_timeout: Duration::from_secs(30),
+            busy_timeout: Duration::from_secs(5),
+            journal_mode: JournalMode::Wal,
+            synchronous: Synchronous::Normal,
+            foreign_keys: true,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JournalMode {
+    Delete,
+    Truncate,
+    Persist,
+    Memory,
+    Wal,
+    Off,
+}
+
+impl JournalMode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            JournalMode::Delete => "DELETE",
+            JournalMode::Truncate => "TRUNCATE",
+            JournalMode::Persist => "PERSIST",
+            JournalMode::Memory => "MEMORY",
+            JournalMode::Wal => "WAL",
+            JournalMode::Off => "OFF",
+        }
+    }
+}
+
+impl FromStr for JournalMode {
+    type Err = UnknownPragmaValue;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().as_str() {
+            "DELETE" => Ok(JournalMode::Delete),
+            "TRUNCATE" => Ok(JournalMode::Truncate),
+            "PERSIST" => Ok(JournalMode::Persist),
+            "MEMORY" => Ok(JournalMode::Memory),
+            "WAL" => Ok(JournalMode::Wal),
+            "OFF" => Ok(JournalMode::Off),
+            _ => Err(UnknownPragmaValue(s.to_string())),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Synchronous {
+    Off,
+    Normal,
+    Full,
+    Extra,
+}
+
+impl Synchronous {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Synchronous::Off => "OFF",
+            Synchronous::Normal => "NORMAL",
+            Synchronous::Full => "FULL",
+            Synchronous::Extra => "EXTRA",
+        }
+    }
+}
+
+impl FromStr for Synchronous {
+    type Err = UnknownPragmaValue;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().as_str() {
+            "OFF" => Ok(Synchronous::Off),
+            "NORMAL" => Ok(Synchronous::Normal),
+            "FULL" => Ok(Synchronous::Full),
+            "EXTRA" => Ok(Synchronous::Extra),
+            _ => Err(UnknownPragmaValue(s.to_string())),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct UnknownPragmaValue(String);
+
+impl fmt::Display for UnknownPragmaValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown pragma value {:?}", self.0)
+    }
+}
+
+/// Applies the configured pragmas whenever the pool opens a connection
+#[cfg(feature = "sqlite")]
+#[derive(Debug)]
+struct SqlitePragmas {
+    options: DbOptions,
+    read_only: bool,
+}
+
+#[cfg(feature = "sqlite")]
+impl r2d2::CustomizeConnection<DbConnection, r2d2::Error> for SqlitePragmas {
+    fn on_acquire(&self, conn: &mut DbConnection) -> Result<(), r2d2::Error> {
+        use diesel::connection::SimpleConnection;
+
+        let options = &self.options;
+        // busy_timeout goes first so switching the journal mode can wait for locks
+        conn.batch_execute(&format!(
+            "PRAGMA busy_timeout = {}; PRAGMA journal_mode = {}; PRAGMA synchronous = {}; PRAGMA foreign_keys = {}; PRAGMA query_only = {};",
+            options.busy_timeout.as_millis(),
+            options.journal_mode.as_str(),
+            options.synchronous.as_str(),
+            if options.foreign_keys { "ON" } else { "OFF" },
+            if self.read_only { "ON" } else { "OFF" },
+        ))
+        .map_err(r2d2::Error::QueryError)
+    }
+}
+
+pub fn establish_connection_pool(database_url: &str, options: &DbOptions) -> DbPool {
+    build_pool(database_url, options, options.max_connections, false)
+}
+
+fn build_pool(database_url: &str, options: &DbOptions, max_size: u32, read_only: bool) -> DbPool {
+    let manager = ConnectionManager::<DbConnection>::new(database_url);
+    let builder = r2d2::Pool::builder()
+        .max_size(max_size)
+        .connection_timeout(options.connection_timeout);
+
+    #[cfg(feature = "sqlite")]
+    let builder = builder.connection_customizer(Box::new(SqlitePragmas {
+        options: options.clone(),
+        read_only,
+    }));
+    #[cfg(not(feature = "sqlite"))]
+    let _ = read_only;
+
+    builder.build(manager).expect("Failed to create pool")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "sqlite")]
+    fn pragma(conn: &mut DbConnection, name: &str) -> String {
+        use diesel::{dsl::sql, select, sql_types::Text, RunQueryDsl};
+
+        select(sql::<Text>(&format!("* FROM pragma_{}", name)))
+            .get_result(conn)
+            .unwrap()
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn test_pool_applies_pragmas() {
+        let dir = tempfile::tempdir().unwrap();
+        let url = dir.path().join("pragmas.db");
+        let pool = establish_connection_pool(&url.to_string_lossy(), &DbOptions::default());
+        let mut conn = pool.get().unwrap();
+
+        assert_eq!(pragma(&mut conn, "journal_mode"), "wal");
+        assert_eq!(pragma(&mut conn, "foreign_keys"), "1");
+        assert_eq!(pragma(&mut conn, "busy_timeout"), "5000");
+        // NORMAL
+        assert_eq!(pragma(&mut conn, "synchronous"), "1");
+    }
 
-pub fn establish_connection_pool() -> DbPool {
-    let database_url = env::var("DATABASE_URL").unwrap_or_else(|_| "database.db".to_string());
-    let manager = ConnectionManager::<SqliteConnection>::new(database_url);
-    r2d2::Pool::builder()
-        .build(manager)
-        .expect("Failed to create pool")
+    #[test]
+    fn test_parse_pragma_values() {
+        assert_eq!("wal".parse::<JournalMode>().unwrap(), JournalMode::Wal);
+        assert_eq!("Full".parse::<Synchronous>().unwrap(), Synchronous::Full);
+        assert!("fast".parse::<Synchronous>().is_err());
+    }
 }
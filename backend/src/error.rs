@@ -0,0 +1,337 @@
+use axum::{
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use jsonwebtoken::errors::ErrorKind as JwtErrorKind;
+use serde::Serialize;
+use ts_rs::TS;
+use utoipa::ToSchema;
+use validator::ValidationErrors;
+
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
+/// Errors returned by request handlers
+///
+/// Every variant maps to a stable `code` that clients can match on. Responses
+/// are RFC 7807 problem documents; internal failures are logged and reported
+/// without details.
+#[derive(Debug, thiserror::Error)]
+pub enum AppError {
+    #[error("{detail}")]
+    InvalidBody { status: StatusCode, detail: String },
+    #[error("Validation failed")]
+    Validation(#[from] ValidationErrors),
+    #[error("{0}")]
+    InvalidHeader(String),
+    #[error("Invalid email or password")]
+    InvalidCredentials,
+    #[error("Account is disabled")]
+    AccountDisabled,
+    #[error("Missing authentication token")]
+    MissingToken,
+    #[error("Invalid or expired token")]
+    InvalidToken,
+    #[error("Invalid refresh token")]
+    InvalidRefreshToken,
+    #[error("Refresh token expired")]
+    RefreshTokenExpired,
+    #[error("Administrator access required")]
+    AdminRequired,
+    #[error("CSRF check failed: {0}")]
+    Csrf(#[from] crate::auth::CsrfError),
+    #[error("{0} not found")]
+    NotFound(&'static str),
+    #[error("{0}")]
+    Conflict(&'static str),
+    #[error("Too many requests; retry in {0} seconds")]
+    RateLimited(u64),
+    #[error("Resource has changed; fetch it again and retry")]
+    PreconditionFailed,
+    #[error("This request requires an If-Match header")]
+    PreconditionRequired,
+    #[error("database error: {0}")]
+    Database(DieselError),
+    #[error("database pool error: {0}")]
+    Pool(#[from] diesel::r2d2::PoolError),
+    #[error("blocking task failed: {0}")]
+    Blocking(#[from] tokio::task::JoinError),
+    #[error("token error: {0}")]
+    Token(jsonwebtoken::errors::Error),
+    #[error("password hashing error: {0}")]
+    PasswordHash(argon2::password_hash::Error),
+    #[error("{0}")]
+    Mail(#[from] crate::mail::MailError),
+}
+
+pub type AppResult<T> = Result<T, AppError>;
+
+impl AppError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AppError::InvalidBody { status, .. } => *status,
+            AppError::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::InvalidHeader(_) => StatusCode::BAD_REQUEST,
+            AppError::InvalidCredentials
+            | AppError::MissingToken
+            | AppError::InvalidToken
+            | AppError::InvalidRefreshToken
+            | AppError::RefreshTokenExpired => StatusCode::UNAUTHORIZED,
+            AppError::AccountDisabled | AppError::AdminRequired | AppError::Csrf(_) => {
+                StatusCode::FORBIDDEN
+            }
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Conflict(_) => StatusCode::CONFLICT,
+            AppError::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
+            AppError::PreconditionFailed => StatusCode::PRECONDITION_FAILED,
+            AppError::PreconditionRequired => StatusCode::PRECONDITION_REQUIRED,
+            AppError::Database(_)
+            | AppError::Pool(_)
+            | AppError::Blocking(_)
+            | AppError::Token(_)
+            | AppError::PasswordHash(_)
+            | AppError::Mail(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Stable, machine-readable error code
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::InvalidBody { .. } => "invalid_body",
+            AppError::Validation(_) => "validation_failed",
+            AppError::InvalidHeader(_) => "invalid_header",
+            AppError::InvalidCredentials => "invalid_credentials",
+            AppError::AccountDisabled => "account_disabled",
+            AppError::MissingToken => "missing_token",
+            AppError::InvalidToken => "invalid_token",
+            AppError::InvalidRefreshToken => "invalid_refresh_token",
+            AppError::RefreshTokenExpired => "refresh_token_expired",
+            AppError::AdminRequired => "admin_required",
+            AppError::Csrf(_) => "csrf_failed",
+            AppError::NotFound(_) => "not_found",
+            AppError::Conflict(_) => "conflict",
+            AppError::RateLimited(_) => "rate_limited",
+            AppError::PreconditionFailed => "precondition_failed",
+            AppError::PreconditionRequired => "precondition_required",
+            AppError::Database(_)
+            | AppError::Pool(_)
+            | AppError::Blocking(_)
+            | AppError::Token(_)
+            | AppError::PasswordHash(_)
+            | AppError::Mail(_) => "internal_error",
+        }
+    }
+
+    fn problem(&self) -> ProblemDetails {
+        let status = self.status();
+        let code = self.code();
+
+        let detail = if status.is_server_error() {
+            "An unexpected error occurred".to_string()
+        } else {
+            self.to_string()
+        };
+
+        let errors = match self {
+            AppError::Validation(errors) => field_errors(errors),
+            _ => Vec::new(),
+        };
+
+        ProblemDetails {
+            problem_type: format!("urn:problem-type:{}", code),
+            title: status.canonical_reason().unwrap_or("Error"),
+            status: status.as_u16(),
+            detail,
+            code,
+            errors,
+        }
+    }
+}
+
+/// RFC 7807 problem document, extended with `code` and per-field `errors`
+#[derive(Debug, Serialize, ToSchema, TS)]
+pub struct ProblemDetails {
+    #[serde(rename = "type")]
+    pub problem_type: String,
+    pub title: &'static str,
+    pub status: u16,
+    pub detail: String,
+    pub code: &'static str,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[ts(as = "Option<Vec<FieldError>>", optional)]
+    pub errors: Vec<FieldError>,
+}
+
+#[derive(Debug, Serialize, ToSchema, TS)]
+pub struct FieldError {
+    pub field: String,
+    pub code: String,
+    pub message: String,
+}
+
+fn field_errors(errors: &ValidationErrors) -> Vec<FieldError> {
+    let mut fields: Vec<_> = errors.field_errors().into_iter().collect();
+    fields.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    fields
+        .into_iter()
+        .flat_map(|(field, errors)| {
+            errors.iter().map(move |error| FieldError {
+                field: field.to_string(),
+                code: error.code.to_string(),
+                message: error
+                    .message
+                    .as_ref()
+                    .map(|message| message.to_string())
+                    .unwrap_or_else(|| format!("{} is invalid", field)),
+            })
+        })
+        .collect()
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        if self.status().is_server_error() {
+            tracing::error!("{}", self);
+        }
+
+        let problem = self.problem();
+        (
+            self.status(),
+            [(header::CONTENT_TYPE, PROBLEM_JSON)],
+            Json(problem),
+        )
+            .into_response()
+    }
+}
+
+impl From<DieselError> for AppError {
+    fn from(error: DieselError) -> Self {
+        match error {
+            DieselError::NotFound => AppError::NotFound("Resource"),
+            DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+                AppError::Conflict("Resource already exists")
+            }
+            error => AppError::Database(error),
+        }
+    }
+}
+
+impl From<jsonwebtoken::errors::Error> for AppError {
+    fn from(error: jsonwebtoken::errors::Error) -> Self {
+        match error.kind() {
+            JwtErrorKind::InvalidToken
+            | JwtErrorKind::InvalidSignature
+            | JwtErrorKind::ExpiredSignature
+            | JwtErrorKind::ImmatureSignature
+            | JwtErrorKind::InvalidAlgorithm
+            | JwtErrorKind::InvalidIssuer
+            | JwtErrorKind::InvalidAudience
+            | JwtErrorKind::InvalidSubject
+            | JwtErrorKind::MissingRequiredClaim(_)
+            | JwtErrorKind::Base64(_)
+            | JwtErrorKind::Json(_)
+            | JwtErrorKind::Utf8(_) => AppError::InvalidToken,
+            _ => AppError::Token(error),
+        }
+    }
+}
+
+impl From<argon2::password_hash::Error> for AppError {
+    fn from(error: argon2::password_hash::Error) -> Self {
+        AppError::PasswordHash(error)
+    }
+}
+
+impl From<axum::extract::rejection::JsonRejection> for AppError {
+    fn from(rejection: axum::extract::rejection::JsonRejection) -> Self {
+        AppError::InvalidBody {
+            status: rejection.status(),
+            detail: rejection.body_text(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::to_bytes;
+    use validator::Validate;
+
+    #[derive(Validate)]
+    struct Form {
+        #[validate(length(min = 3, message = "Too short"))]
+        name: String,
+        #[validate(email)]
+        email: String,
+    }
+
+    async fn problem_body(error: AppError) -> (StatusCode, String, serde_json::Value) {
+        let response = error.into_response();
+        let status = response.status();
+        let content_type = response.headers()[header::CONTENT_TYPE]
+            .to_str()
+            .unwrap()
+            .to_string();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, content_type, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_problem_json_response() {
+        let (status, content_type, body) = problem_body(AppError::InvalidCredentials).await;
+
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(content_type, PROBLEM_JSON);
+        assert_eq!(body["status"], 401);
+        assert_eq!(body["code"], "invalid_credentials");
+        assert_eq!(body["type"], "urn:problem-type:invalid_credentials");
+        assert_eq!(body["detail"], "Invalid email or password");
+        assert!(body.get("errors").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_validation_errors_are_listed_per_field() {
+        let form = Form {
+            name: "ab".to_string(),
+            email: "not-an-email".to_string(),
+        };
+        let error: AppError = form.validate().unwrap_err().into();
+
+        let (status, _, body) = problem_body(error).await;
+
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["code"], "validation_failed");
+        assert_eq!(
+            body["errors"],
+            serde_json::json!([
+                { "field": "email", "code": "email", "message": "email is invalid" },
+                { "field": "name", "code": "length", "message": "Too short" },
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_internal_errors_hide_details() {
+        let error: AppError = DieselError::RollbackTransaction.into();
+
+        let (status, _, body) = problem_body(error).await;
+
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(body["code"], "internal_error");
+        assert_eq!(body["detail"], "An unexpected error occurred");
+    }
+
+    #[test]
+    fn test_unique_violation_maps_to_conflict() {
+        let error: AppError = DieselError::DatabaseError(
+            DatabaseErrorKind::UniqueViolation,
+            Box::new("UNIQUE constraint failed".to_string()),
+        )
+        .into();
+
+        assert_eq!(error.status(), StatusCode::CONFLICT);
+        assert_eq!(error.code(), "conflict");
+    }
+}
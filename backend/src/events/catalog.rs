@@ -0,0 +1,96 @@
+//! Events raised by the auth and admin handlers
+
+use serde::{Deserialize, Serialize};
+
+use super::Event;
+use crate::models::UserResponse;
+
+/// A user signed up
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct UserRegistered {
+    pub user: UserResponse,
+    /// Template locale negotiated from the sign-up request
+    pub locale: String,
+}
+
+impl Event for UserRegistered {
+    const NAME: &'static str = "user.registered";
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct UserLoggedIn {
+    pub user_id: i32,
+}
+
+impl Event for UserLoggedIn {
+    const NAME: &'static str = "user.logged_in";
+}
+
+/// A sign-in was refused; `user_id` is set if the email is known
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LoginFailed {
+    pub email: String,
+    pub user_id: Option<i32>,
+    /// `unknown_email`, `account_disabled` or `invalid_password`
+    pub reason: String,
+}
+
+impl Event for LoginFailed {
+    const NAME: &'static str = "user.login_failed";
+}
+
+/// Refresh tokens stopped working
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SessionRevoked {
+    pub user_id: i32,
+    /// How many sessions ended
+    pub sessions: usize,
+    /// `logout`, `expired` or `account_deleted`
+    pub reason: String,
+}
+
+impl Event for SessionRevoked {
+    const NAME: &'static str = "session.revoked";
+}
+
+/// A user's password hash was replaced
+///
+/// Nothing changes passwords yet; publish this from the flow that does.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PasswordChanged {
+    pub user_id: i32,
+}
+
+impl Event for PasswordChanged {
+    const NAME: &'static str = "user.password_changed";
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct UserEmailChanged {
+    pub user: UserResponse,
+    pub previous_email: String,
+}
+
+impl Event for UserEmailChanged {
+    const NAME: &'static str = "user.email_changed";
+}
+
+/// An administrator soft-deleted a user
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct UserDeleted {
+    pub user: UserResponse,
+}
+
+impl Event for UserDeleted {
+    const NAME: &'static str = "user.deleted";
+}
+
+/// An administrator restored a soft-deleted user
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct UserRestored {
+    pub user: UserResponse,
+}
+
+impl Event for UserRestored {
+    const NAME: &'static str = "user.restored";
+}
@@ -0,0 +1,188 @@
+//! In-process domain events
+//!
+//! Handlers publish typed events instead of calling every side effect
+//! themselves, and subscribers are registered on an [`EventBus`] at startup.
+//! Events are recorded in the transaction that makes the change they
+//! describe, together with its audit entry, so an event exists exactly when
+//! its change was committed:
+//!
+//! - `in_transaction` subscribers run on the recording connection, so their
+//!   writes commit or roll back together, and an error aborts the event.
+//! - Each `after_commit` subscriber gets a [`DispatchEvent`] job in the same
+//!   transaction. The jobs table is the outbox: once the event is committed
+//!   it reaches the subscriber even if the process dies right after, and a
+//!   failing subscriber is retried without re-running the others.
+
+mod catalog;
+mod subscribers;
+
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+};
+
+use chrono::{DateTime, Utc};
+use diesel::Connection;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    db::DbConnection,
+    error::{AppError, AppResult},
+    queue::{self, JobError, JobPayload},
+};
+
+pub use catalog::{
+    LoginFailed, PasswordChanged, SessionRevoked, UserDeleted, UserEmailChanged, UserLoggedIn,
+    UserRegistered, UserRestored,
+};
+pub use subscribers::defaults;
+
+/// A typed event, stored as JSON for `after_commit` subscribers
+pub trait Event: Serialize + DeserializeOwned + Send + Sync + 'static {
+    /// Stable name, recorded with every dispatch job
+    const NAME: &'static str;
+}
+
+type SyncHandler =
+    Box<dyn Fn(&mut DbConnection, &dyn Any, DateTime<Utc>) -> AppResult<()> + Send + Sync>;
+type HandlerFuture = Pin<Box<dyn Future<Output = Result<(), JobError>> + Send>>;
+type AsyncHandler = Box<dyn Fn(serde_json::Value) -> HandlerFuture + Send + Sync>;
+
+/// Registers subscribers; see [`EventBus::builder`]
+#[derive(Default)]
+pub struct EventBusBuilder {
+    in_transaction: HashMap<TypeId, Vec<SyncHandler>>,
+    after_commit: HashMap<TypeId, Vec<&'static str>>,
+    subscribers: HashMap<&'static str, AsyncHandler>,
+}
+
+impl EventBusBuilder {
+    /// Run `handler` inside the transaction that records each `E`
+    pub fn in_transaction<E, F>(mut self, handler: F) -> Self
+    where
+        E: Event,
+        F: Fn(&mut DbConnection, &E, DateTime<Utc>) -> AppResult<()> + Send + Sync + 'static,
+    {
+        self.in_transaction
+            .entry(TypeId::of::<E>())
+            .or_default()
+            .push(Box::new(move |conn, event, now| {
+                let event = event
+                    .downcast_ref::<E>()
+                    .expect("handlers are keyed by type");
+                handler(conn, event, now)
+            }));
+        self
+    }
+
+    /// Run `handler` on the queue workers once an `E` is committed
+    ///
+    /// `name` identifies the subscriber in stored jobs, so keep it stable.
+    /// Delivery is at-least-once: `JobError::Retry` runs it again later.
+    pub fn after_commit<E, F, Fut>(mut self, name: &'static str, handler: F) -> Self
+    where
+        E: Event,
+        F: Fn(E) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), JobError>> + Send + 'static,
+    {
+        let handler = Box::new(move |payload: serde_json::Value| -> HandlerFuture {
+            match serde_json::from_value::<E>(payload) {
+                Ok(event) => Box::pin(handler(event)),
+                Err(e) => {
+                    let error = JobError::Fatal(format!("invalid {} event: {}", E::NAME, e));
+                    Box::pin(async move { Err(error) })
+                }
+            }
+        });
+        if self.subscribers.insert(name, handler).is_some() {
+            panic!("Event subscriber {} is registered twice", name);
+        }
+        self.after_commit
+            .entry(TypeId::of::<E>())
+            .or_default()
+            .push(name);
+        self
+    }
+
+    pub fn build(self) -> EventBus {
+        EventBus {
+            inner: Arc::new(self),
+        }
+    }
+}
+
+/// Routes published events to their subscribers
+#[derive(Clone, Default)]
+pub struct EventBus {
+    inner: Arc<EventBusBuilder>,
+}
+
+impl EventBus {
+    pub fn builder() -> EventBusBuilder {
+        EventBusBuilder::default()
+    }
+
+    /// Record `event` on `conn` as of `now`
+    ///
+    /// Runs in a transaction, nested in the caller's if there is one, so
+    /// nothing is dispatched unless that commits. Call `Queue::wake` after
+    /// committing to start `after_commit` subscribers without waiting for
+    /// the next poll.
+    pub fn record<E: Event>(
+        &self,
+        conn: &mut DbConnection,
+        event: &E,
+        now: DateTime<Utc>,
+    ) -> AppResult<()> {
+        let handlers = self.inner.in_transaction.get(&TypeId::of::<E>());
+        let subscribers = self.inner.after_commit.get(&TypeId::of::<E>());
+        if handlers.is_none() && subscribers.is_none() {
+            return Ok(());
+        }
+
+        conn.transaction(|conn| {
+            for handler in handlers.into_iter().flatten() {
+                handler(conn, event, now)?;
+            }
+            if let Some(subscribers) = subscribers {
+                let payload = serde_json::to_value(event)
+                    .map_err(|e| diesel::result::Error::SerializationError(Box::new(e)))?;
+                for subscriber in subscribers {
+                    let job = DispatchEvent {
+                        subscriber: subscriber.to_string(),
+                        event: E::NAME.to_string(),
+                        payload: payload.clone(),
+                    };
+                    queue::enqueue(conn, &job, now)?;
+                }
+            }
+            Ok::<_, AppError>(())
+        })
+    }
+
+    /// Run the subscriber a dispatch job was queued for
+    pub async fn dispatch(&self, job: DispatchEvent) -> Result<(), JobError> {
+        let Some(handler) = self.inner.subscribers.get(job.subscriber.as_str()) else {
+            return Err(JobError::Fatal(format!(
+                "no subscriber named {} for {}",
+                job.subscriber, job.event
+            )));
+        };
+        handler(job.payload).await
+    }
+}
+
+/// One event waiting for one `after_commit` subscriber
+#[derive(Serialize, Deserialize)]
+pub struct DispatchEvent {
+    pub subscriber: String,
+    pub event: String,
+    pub payload: serde_json::Value,
+}
+
+impl JobPayload for DispatchEvent {
+    const KIND: &'static str = "dispatch_event";
+}
@@ -0,0 +1,44 @@
+//! The app's own reactions to events
+
+use serde_json::json;
+
+use super::{
+    EventBus, EventBusBuilder, UserDeleted, UserEmailChanged, UserRegistered, UserRestored,
+};
+use crate::{
+    mail::Outbox,
+    queue,
+    webhooks::{self, WebhookEvent},
+};
+
+/// Welcome email and outbound webhooks
+///
+/// Both only queue jobs, so they run in the event's transaction. Add your
+/// own subscribers to the returned builder.
+pub fn defaults(outbox: &Outbox) -> EventBusBuilder {
+    let outbox = outbox.clone();
+    EventBus::builder()
+        .in_transaction(move |conn, event: &UserRegistered, now| {
+            let user = &event.user;
+            let context = json!({ "username": user.username, "email": user.email });
+            let email = outbox.compose(&user.email, "welcome", &event.locale, context)?;
+            queue::enqueue(conn, &email, now)?;
+            Ok(())
+        })
+        .in_transaction(|conn, event: &UserRegistered, now| {
+            let data = json!({ "user": event.user });
+            webhooks::publish(conn, WebhookEvent::UserRegistered, data, now).map(drop)
+        })
+        .in_transaction(|conn, event: &UserEmailChanged, now| {
+            let data = json!({ "user": event.user, "previous_email": event.previous_email });
+            webhooks::publish(conn, WebhookEvent::UserEmailChanged, data, now).map(drop)
+        })
+        .in_transaction(|conn, event: &UserDeleted, now| {
+            let data = json!({ "user": event.user });
+            webhooks::publish(conn, WebhookEvent::UserDeleted, data, now).map(drop)
+        })
+        .in_transaction(|conn, event: &UserRestored, now| {
+            let data = json!({ "user": event.user });
+            webhooks::publish(conn, WebhookEvent::UserRestored, data, now).map(drop)
+        })
+}
@@ -0,0 +1,26 @@
+pub mod api;
+pub mod app;
+pub mod audit;
+pub mod auth;
+pub mod cli;
+pub mod clock;
+pub mod codegen;
+pub mod config;
+pub mod db;
+pub mod error;
+pub mod events;
+pub mod mail;
+pub mod models;
+pub mod queue;
+pub mod rate_limit;
+pub mod repositories;
+pub mod scheduler;
+pub mod security;
+pub mod state;
+pub mod webhooks;
+
+pub use app::{App, AppBuilder};
+pub use clock::{Clock, FakeClock, SharedClock, SystemClock};
+pub use config::Config;
+pub use db::DbPool;
+pub use state::AppState;
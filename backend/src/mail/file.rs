@@ -0,0 +1,43 @@
+use std::path::PathBuf;
+
+use async_trait::async_trait;
+
+use super::{Email, MailError, Mailer};
+
+/// Writes every message into a maildir, one `.eml` file each
+///
+/// Files are written to `tmp/` and then moved to `new/`, so mail clients and
+/// scripts watching the directory never see half-written messages.
+pub struct FileMailer {
+    dir: PathBuf,
+}
+
+impl FileMailer {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+}
+
+#[async_trait]
+impl Mailer for FileMailer {
+    async fn send(&self, email: &Email) -> Result<(), MailError> {
+        let message = email.to_message()?.formatted();
+        let name = format!(
+            "{}.{}.eml",
+            chrono::Utc::now().timestamp_micros(),
+            uuid::Uuid::new_v4().simple()
+        );
+        let tmp = self.dir.join("tmp");
+        let new = self.dir.join("new");
+        let unavailable = |e: std::io::Error| MailError::Unavailable(e.to_string());
+
+        tokio::fs::create_dir_all(&tmp).await.map_err(unavailable)?;
+        tokio::fs::create_dir_all(&new).await.map_err(unavailable)?;
+        tokio::fs::write(tmp.join(&name), message)
+            .await
+            .map_err(unavailable)?;
+        tokio::fs::rename(tmp.join(&name), new.join(&name))
+            .await
+            .map_err(unavailable)
+    }
+}
@@ -0,0 +1,38 @@
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+
+use super::{Email, MailError, Mailer};
+
+/// Most recent messages kept before the oldest are dropped
+const CAPACITY: usize = 200;
+
+/// Keeps sent messages in memory instead of delivering them
+#[derive(Clone, Default)]
+pub struct MemoryMailer {
+    messages: Arc<Mutex<Vec<Email>>>,
+}
+
+#[async_trait]
+impl Mailer for MemoryMailer {
+    async fn send(&self, email: &Email) -> Result<(), MailError> {
+        let mut messages = self.messages.lock().unwrap();
+        if messages.len() == CAPACITY {
+            messages.remove(0);
+        }
+        messages.push(email.clone());
+        Ok(())
+    }
+
+    fn captured(&self) -> Option<Vec<Email>> {
+        Some(
+            self.messages
+                .lock()
+                .unwrap()
+                .iter()
+                .rev()
+                .cloned()
+                .collect(),
+        )
+    }
+}
@@ -0,0 +1,81 @@
+use serde::{Deserialize, Serialize};
+
+use super::{Email, MailError, Mailer, Templates};
+use crate::{
+    error::AppResult,
+    queue::{JobError, JobPayload, Queue},
+};
+
+/// Queued message; run by [`deliver`]
+#[derive(Serialize, Deserialize)]
+pub struct SendEmail(pub Email);
+
+impl JobPayload for SendEmail {
+    const KIND: &'static str = "send_email";
+    // With the default backoff this keeps trying for about 20 minutes
+    const MAX_ATTEMPTS: i32 = 8;
+}
+
+/// Renders messages and queues them for the workers
+#[derive(Clone)]
+pub struct Outbox {
+    queue: Queue,
+    templates: &'static Templates,
+    from: String,
+}
+
+impl Outbox {
+    pub fn new(queue: Queue, from: String) -> Self {
+        Self {
+            queue,
+            templates: Templates::embedded(),
+            from,
+        }
+    }
+
+    /// Render `template` for `to` without queueing it
+    ///
+    /// Pass the result to `queue::enqueue` to send the message only if a
+    /// surrounding transaction commits.
+    pub fn compose(
+        &self,
+        to: &str,
+        template: &str,
+        locale: &str,
+        context: impl Serialize,
+    ) -> Result<SendEmail, MailError> {
+        let rendered = self.templates.render(template, locale, context)?;
+        Ok(SendEmail(Email {
+            from: self.from.clone(),
+            to: to.to_string(),
+            subject: rendered.subject,
+            text: rendered.text,
+            html: rendered.html,
+        }))
+    }
+
+    /// Render `template` for `to` and queue it
+    pub async fn send(
+        &self,
+        to: &str,
+        template: &str,
+        locale: &str,
+        context: impl Serialize,
+    ) -> AppResult<()> {
+        let email = self.compose(to, template, locale, context)?;
+        self.queue.enqueue(email).await?;
+        Ok(())
+    }
+
+    /// Best template locale for an `Accept-Language` header
+    pub fn locale(&self, accept_language: Option<&str>) -> &'static str {
+        self.templates.negotiate(accept_language)
+    }
+}
+
+/// Job handler that hands a queued message to `mailer`
+pub async fn deliver(mailer: &dyn Mailer, SendEmail(email): SendEmail) -> Result<(), JobError> {
+    mailer.send(&email).await?;
+    tracing::info!("Sent {:?} to {}", email.subject, email.to);
+    Ok(())
+}
@@ -0,0 +1,193 @@
+use std::{collections::BTreeSet, sync::LazyLock};
+
+use minijinja::{Environment, UndefinedBehavior};
+use serde::Serialize;
+
+use super::MailError;
+
+/// Used when none of the reader's languages has a template
+pub const DEFAULT_LOCALE: &str = "en";
+
+macro_rules! embed {
+    ($($path:literal),* $(,)?) => {
+        &[$((
+            $path,
+            include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/templates/mail/", $path)),
+        )),*]
+    };
+}
+
+/// Every template under `templates/mail`, compiled into the binary
+///
+/// Each message is three files per locale: `<locale>/<name>.subject.txt`,
+/// `<locale>/<name>.txt` and `<locale>/<name>.html`.
+const TEMPLATES: &[(&str, &str)] = embed![
+    "_layout.html",
+    "en/welcome.subject.txt",
+    "en/welcome.txt",
+    "en/welcome.html",
+    "de/welcome.subject.txt",
+    "de/welcome.txt",
+    "de/welcome.html",
+];
+
+static EMBEDDED: LazyLock<Templates> =
+    LazyLock::new(|| Templates::new(TEMPLATES).expect("Embedded mail templates must compile"));
+
+/// A rendered message, before it is addressed
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Rendered {
+    pub subject: String,
+    pub text: String,
+    pub html: String,
+}
+
+/// Subject, text and HTML templates for every message and locale
+pub struct Templates {
+    env: Environment<'static>,
+    locales: BTreeSet<&'static str>,
+}
+
+impl Templates {
+    /// The templates shipped in `templates/mail`
+    pub fn embedded() -> &'static Templates {
+        &EMBEDDED
+    }
+
+    /// Compile `(path, source)` pairs laid out like `templates/mail`
+    pub fn new(sources: &[(&'static str, &'static str)]) -> Result<Self, MailError> {
+        let mut env = Environment::new();
+        // A typo in a variable name should fail loudly, not send a blank
+        env.set_undefined_behavior(UndefinedBehavior::Strict);
+
+        let mut locales = BTreeSet::new();
+        for &(path, source) in sources {
+            env.add_template(path, source)
+                .map_err(|e| MailError::Template(e.to_string()))?;
+            if let Some((locale, _)) = path.split_once('/') {
+                locales.insert(locale);
+            }
+        }
+
+        Ok(Self { env, locales })
+    }
+
+    /// Render message `name` in `locale`, falling back to its language and
+    /// then to [`DEFAULT_LOCALE`]
+    pub fn render(
+        &self,
+        name: &str,
+        locale: &str,
+        context: impl Serialize,
+    ) -> Result<Rendered, MailError> {
+        let locale = self.resolve(name, locale);
+        let context = minijinja::Value::from_serialize(&context);
+        let render = |suffix: &str| {
+            self.env
+                .get_template(&format!("{}/{}.{}", locale, name, suffix))
+                .and_then(|template| template.render(&context))
+                .map_err(|e| MailError::Template(e.to_string()))
+        };
+
+        Ok(Rendered {
+            subject: render("subject.txt")?.trim().to_string(),
+            text: render("txt")?,
+            html: render("html")?,
+        })
+    }
+
+    /// Pick the best locale for an `Accept-Language` header
+    pub fn negotiate(&self, accept_language: Option<&str>) -> &'static str {
+        let mut ranges: Vec<(&str, f32)> = accept_language
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|range| {
+                let mut parts = range.split(';');
+                let tag = parts.next()?.trim();
+                let quality = parts
+                    .find_map(|param| param.trim().strip_prefix("q="))
+                    .map_or(Some(1.0), |q| q.parse().ok())?;
+                (!tag.is_empty() && quality > 0.0).then_some((tag, quality))
+            })
+            .collect();
+        // Stable, so equally weighted ranges keep the client's order
+        ranges.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        ranges
+            .into_iter()
+            .find_map(|(tag, _)| self.available(tag))
+            .unwrap_or(DEFAULT_LOCALE)
+    }
+
+    /// The locale directory serving `tag`, by exact match or language
+    fn available(&self, tag: &str) -> Option<&'static str> {
+        let tag = tag.to_lowercase();
+        let language = tag.split(['-', '_']).next().unwrap_or_default();
+        self.locales
+            .iter()
+            .find(|locale| locale.to_lowercase() == tag)
+            .or_else(|| self.locales.iter().find(|locale| **locale == language))
+            .copied()
+    }
+
+    fn resolve(&self, name: &str, locale: &str) -> &'static str {
+        [self.available(locale), Some(DEFAULT_LOCALE)]
+            .into_iter()
+            .flatten()
+            .find(|locale| {
+                self.env
+                    .get_template(&format!("{}/{}.subject.txt", locale, name))
+                    .is_ok()
+            })
+            .unwrap_or(DEFAULT_LOCALE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn welcome(locale: &str) -> Rendered {
+        Templates::embedded()
+            .render(
+                "welcome",
+                locale,
+                serde_json::json!({ "username": "<alice>", "email": "alice@example.com" }),
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn test_renders_each_locale_and_escapes_html_only() {
+        let en = welcome("en");
+        assert_eq!(en.subject, "Welcome, <alice>");
+        assert!(en.text.starts_with("Hi <alice>,"));
+        assert!(en.html.contains("<p>Hi &lt;alice&gt;,</p>"));
+        assert!(en.html.contains("<html lang=\"en\">"));
+
+        let de = welcome("de");
+        assert_eq!(de.subject, "Willkommen, <alice>");
+        assert!(de.html.contains("<html lang=\"de\">"));
+    }
+
+    #[test]
+    fn test_falls_back_to_language_then_default_locale() {
+        assert_eq!(welcome("de-AT"), welcome("de"));
+        assert_eq!(welcome("fr"), welcome("en"));
+    }
+
+    #[test]
+    fn test_missing_variables_fail() {
+        let result = Templates::embedded().render("welcome", "en", serde_json::json!({}));
+        assert!(matches!(result, Err(MailError::Template(_))));
+    }
+
+    #[test]
+    fn test_negotiates_by_quality() {
+        let templates = Templates::embedded();
+        assert_eq!(templates.negotiate(Some("fr-CH, de;q=0.9, en;q=0.8")), "de");
+        assert_eq!(templates.negotiate(Some("en;q=0.5, de-DE")), "de");
+        assert_eq!(templates.negotiate(Some("de;q=0, fr")), "en");
+        assert_eq!(templates.negotiate(None), "en");
+    }
+}
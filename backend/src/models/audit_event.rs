@@ -0,0 +1,101 @@
+use chrono::{DateTime, NaiveDateTime, Utc};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::db::schema::audit_events;
+
+#[derive(Debug, Queryable, Selectable)]
+#[diesel(table_name = audit_events)]
+pub struct AuditEvent {
+    pub id: i32,
+    pub occurred_at: NaiveDateTime,
+    pub actor_user_id: Option<i32>,
+    pub action: String,
+    pub target: Option<String>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub outcome: String,
+    pub metadata: String,
+    pub prev_hash: String,
+    pub hash: String,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = audit_events)]
+pub struct NewAuditEvent {
+    pub occurred_at: NaiveDateTime,
+    pub actor_user_id: Option<i32>,
+    pub action: String,
+    pub target: Option<String>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub outcome: String,
+    pub metadata: String,
+    pub prev_hash: String,
+    pub hash: String,
+}
+
+/// Filters accepted by the admin audit query endpoint
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AuditEventQuery {
+    pub actor_user_id: Option<i32>,
+    pub action: Option<String>,
+    pub target: Option<String>,
+    pub outcome: Option<String>,
+    /// Only events at or after this instant
+    pub since: Option<DateTime<Utc>>,
+    /// Only events before this instant
+    pub until: Option<DateTime<Utc>>,
+    /// 1-based page number
+    #[param(minimum = 1)]
+    pub page: Option<i64>,
+    #[param(minimum = 1, maximum = 200)]
+    pub per_page: Option<i64>,
+}
+
+#[derive(Debug, Serialize, ToSchema, TS)]
+pub struct AuditEventResponse {
+    pub id: i32,
+    pub occurred_at: NaiveDateTime,
+    pub actor_user_id: Option<i32>,
+    pub action: String,
+    pub target: Option<String>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub outcome: String,
+    #[schema(value_type = Object)]
+    #[ts(type = "Record<string, unknown>")]
+    pub metadata: serde_json::Value,
+    pub hash: String,
+}
+
+impl From<AuditEvent> for AuditEventResponse {
+    fn from(event: AuditEvent) -> Self {
+        Self {
+            id: event.id,
+            occurred_at: event.occurred_at,
+            actor_user_id: event.actor_user_id,
+            action: event.action,
+            target: event.target,
+            ip_address: event.ip_address,
+            user_agent: event.user_agent,
+            outcome: event.outcome,
+            metadata: serde_json::from_str(&event.metadata).unwrap_or(serde_json::Value::Null),
+            hash: event.hash,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema, TS)]
+pub struct AuditEventPage {
+    pub events: Vec<AuditEventResponse>,
+    #[ts(type = "number")]
+    pub page: i64,
+    #[ts(type = "number")]
+    pub per_page: i64,
+    #[ts(type = "number")]
+    pub total: i64,
+}
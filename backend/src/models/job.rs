@@ -0,0 +1,122 @@
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::db::schema::jobs;
+
+/// Lifecycle of a queued job
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema, TS)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    /// Waiting for `run_at`, including between retries
+    Pending,
+    /// Claimed by a worker
+    Running,
+    Succeeded,
+    /// Failed permanently or ran out of attempts; retry it from the admin API
+    Dead,
+}
+
+impl JobState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            JobState::Pending => "pending",
+            JobState::Running => "running",
+            JobState::Succeeded => "succeeded",
+            JobState::Dead => "dead",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Queryable, Selectable)]
+#[diesel(table_name = jobs)]
+pub struct JobRecord {
+    pub id: i32,
+    pub kind: String,
+    pub payload: String,
+    pub status: String,
+    pub attempts: i32,
+    pub max_attempts: i32,
+    pub run_at: NaiveDateTime,
+    pub locked_by: Option<String>,
+    pub locked_at: Option<NaiveDateTime>,
+    pub last_error: Option<String>,
+    pub unique_key: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub finished_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = jobs)]
+pub struct NewJobRecord {
+    pub kind: String,
+    pub payload: String,
+    pub max_attempts: i32,
+    pub run_at: NaiveDateTime,
+    pub unique_key: Option<String>,
+}
+
+/// Filters accepted by the admin job list endpoint
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct JobQuery {
+    pub status: Option<JobState>,
+    pub kind: Option<String>,
+    /// 1-based page number
+    #[param(minimum = 1)]
+    pub page: Option<i64>,
+    #[param(minimum = 1, maximum = 200)]
+    pub per_page: Option<i64>,
+}
+
+#[derive(Debug, Serialize, ToSchema, TS)]
+pub struct JobResponse {
+    pub id: i32,
+    pub kind: String,
+    #[schema(value_type = Object)]
+    #[ts(type = "unknown")]
+    pub payload: serde_json::Value,
+    pub status: String,
+    pub attempts: i32,
+    pub max_attempts: i32,
+    /// When the job is next due, if pending
+    pub run_at: NaiveDateTime,
+    pub last_error: Option<String>,
+    pub unique_key: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub finished_at: Option<NaiveDateTime>,
+}
+
+impl From<JobRecord> for JobResponse {
+    fn from(job: JobRecord) -> Self {
+        Self {
+            id: job.id,
+            kind: job.kind,
+            payload: serde_json::from_str(&job.payload).unwrap_or(serde_json::Value::Null),
+            status: job.status,
+            attempts: job.attempts,
+            max_attempts: job.max_attempts,
+            run_at: job.run_at,
+            last_error: job.last_error,
+            unique_key: job.unique_key,
+            created_at: job.created_at,
+            updated_at: job.updated_at,
+            finished_at: job.finished_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema, TS)]
+pub struct JobPage {
+    pub jobs: Vec<JobResponse>,
+    #[ts(type = "number")]
+    pub page: i64,
+    #[ts(type = "number")]
+    pub per_page: i64,
+    #[ts(type = "number")]
+    pub total: i64,
+}
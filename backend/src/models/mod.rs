@@ -1,14 +1,33 @@
+pub mod audit_event;
+pub mod job;
 pub mod refresh_token;
 pub mod user;
+pub mod webhook;
 
 use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+use utoipa::ToSchema;
 
+pub use audit_event::{
+    AuditEvent, AuditEventPage, AuditEventQuery, AuditEventResponse, NewAuditEvent,
+};
+pub use job::{JobPage, JobQuery, JobRecord, JobResponse, JobState, NewJobRecord};
 pub use refresh_token::{NewRefreshToken, RefreshRequest, RefreshToken};
-pub use user::{AuthResponse, LoginRequest, NewUser, RegisterRequest, User, UserResponse};
+pub use user::{
+    AuthResponse, CsrfTokenResponse, LoginRequest, NewUser, RegisterRequest, UpdateProfileRequest,
+    User, UserChanges, UserResponse,
+};
+pub use webhook::{
+    CreateWebhookRequest, CreatedWebhookResponse, DeliveryStatus, NewWebhookAttempt,
+    NewWebhookDelivery, NewWebhookEndpoint, WebhookAttempt, WebhookAttemptResponse,
+    WebhookDelivery, WebhookDeliveryDetail, WebhookDeliveryPage, WebhookDeliveryQuery,
+    WebhookDeliveryResponse, WebhookEndpoint, WebhookEndpointResponse,
+};
 
 // Example model - add your own models here
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema, TS)]
 pub struct ApiResponse {
     pub message: String,
+    #[ts(type = "number")]
     pub timestamp: i64,
 }
@@ -1,19 +1,25 @@
-use chrono::{Duration, NaiveDateTime, Utc};
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
 use diesel::prelude::*;
 use serde::Deserialize;
+use ts_rs::TS;
+use utoipa::ToSchema;
 use uuid::Uuid;
 
 use crate::db::schema::refresh_tokens;
 
 const REFRESH_TOKEN_DURATION_DAYS: i64 = 30;
 
-#[derive(Debug, Queryable, Selectable)]
+#[derive(Clone, Debug, Queryable, Selectable)]
 #[diesel(table_name = refresh_tokens)]
 pub struct RefreshToken {
     pub id: String,
     pub user_id: i32,
     pub expires_at: NaiveDateTime,
     pub created_at: NaiveDateTime,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    /// When the token last issued an access token, if ever
+    pub last_used_at: Option<NaiveDateTime>,
 }
 
 #[derive(Debug, Insertable)]
@@ -22,22 +28,29 @@ pub struct NewRefreshToken {
     pub id: String,
     pub user_id: i32,
     pub expires_at: NaiveDateTime,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
 }
 
 impl NewRefreshToken {
-    pub fn new(user_id: i32) -> Self {
-        let now = Utc::now().naive_utc();
-        let expires_at = now + Duration::days(REFRESH_TOKEN_DURATION_DAYS);
+    pub fn new(user_id: i32, now: DateTime<Utc>) -> Self {
+        let expires_at = now.naive_utc() + Duration::days(REFRESH_TOKEN_DURATION_DAYS);
 
         Self {
             id: Uuid::new_v4().to_string(),
             user_id,
             expires_at,
+            ip_address: None,
+            user_agent: None,
         }
     }
 }
 
-#[derive(Debug, Deserialize)]
+/// Body of `refresh` and `logout`; may be empty when the refresh token is in
+/// its cookie
+#[derive(Debug, Default, Deserialize, ToSchema, TS)]
 pub struct RefreshRequest {
-    pub refresh_token: String,
+    #[serde(default)]
+    #[ts(optional)]
+    pub refresh_token: Option<String>,
 }
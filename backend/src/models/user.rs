@@ -1,11 +1,16 @@
 use chrono::NaiveDateTime;
 use diesel::prelude::*;
 use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+use utoipa::ToSchema;
 use validator::Validate;
 
-use crate::db::schema::users;
+use crate::{
+    api::conditional::{ETag, Versioned},
+    db::schema::users,
+};
 
-#[derive(Debug, Queryable, Selectable, Serialize)]
+#[derive(Clone, Debug, Queryable, Selectable, Serialize)]
 #[diesel(table_name = users)]
 pub struct User {
     pub id: i32,
@@ -16,6 +21,31 @@ pub struct User {
     pub is_active: bool,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
+    pub is_admin: bool,
+    /// Set when the account is soft-deleted
+    pub deleted_at: Option<NaiveDateTime>,
+    /// Incremented by every update to the fields in `UserResponse`
+    pub version: i32,
+}
+
+/// Users that have not been soft-deleted
+pub type LiveUsers = diesel::dsl::Filter<users::table, diesel::dsl::IsNull<users::deleted_at>>;
+
+impl User {
+    /// Default scope for user queries; only admin restore looks past it
+    pub fn live() -> LiveUsers {
+        users::table.filter(users::deleted_at.is_null())
+    }
+
+    pub fn is_deleted(&self) -> bool {
+        self.deleted_at.is_some()
+    }
+}
+
+impl Versioned for User {
+    fn etag(&self) -> ETag {
+        ETag::versioned("user", self.id, self.version)
+    }
 }
 
 #[derive(Debug, Insertable)]
@@ -26,32 +56,87 @@ pub struct NewUser {
     pub password_hash: String,
 }
 
-#[derive(Debug, Deserialize, Validate)]
+/// Profile fields to overwrite; `None` leaves a field unchanged
+#[derive(Debug, Default, AsChangeset)]
+#[diesel(table_name = users)]
+pub struct UserChanges {
+    pub username: Option<String>,
+    pub email: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Validate, ToSchema, TS)]
 pub struct RegisterRequest {
-    #[validate(length(min = 3, max = 50, message = "Username must be between 3 and 50 characters"))]
+    #[validate(length(
+        min = 3,
+        max = 50,
+        message = "Username must be between 3 and 50 characters"
+    ))]
+    #[schema(min_length = 3, max_length = 50)]
     pub username: String,
 
     #[validate(email(message = "Invalid email address"))]
+    #[schema(format = Email)]
     pub email: String,
 
     #[validate(length(min = 8, message = "Password must be at least 8 characters"))]
+    #[schema(format = Password, min_length = 8)]
     pub password: String,
 }
 
-#[derive(Debug, Deserialize)]
+/// Fields omitted from the body are left unchanged
+#[derive(Debug, Deserialize, Validate, ToSchema, TS)]
+pub struct UpdateProfileRequest {
+    #[validate(length(
+        min = 3,
+        max = 50,
+        message = "Username must be between 3 and 50 characters"
+    ))]
+    #[schema(min_length = 3, max_length = 50)]
+    #[ts(optional)]
+    pub username: Option<String>,
+
+    #[validate(email(message = "Invalid email address"))]
+    #[schema(format = Email)]
+    #[ts(optional)]
+    pub email: Option<String>,
+}
+
+impl From<UpdateProfileRequest> for UserChanges {
+    fn from(request: UpdateProfileRequest) -> Self {
+        Self {
+            username: request.username,
+            email: request.email.map(|email| email.to_lowercase()),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema, TS)]
 pub struct LoginRequest {
+    #[schema(format = Email)]
     pub email: String,
+    #[schema(format = Password)]
     pub password: String,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema, TS)]
 pub struct AuthResponse {
     pub user: UserResponse,
     pub access_token: String,
-    pub refresh_token: String,
+    /// Left out when the refresh token was set as a cookie instead
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[ts(optional)]
+    pub refresh_token: Option<String>,
+}
+
+/// A CSRF token, also set as a cookie; send it back in `header`
+#[derive(Debug, Serialize, ToSchema, TS)]
+pub struct CsrfTokenResponse {
+    pub token: String,
+    /// Header that must carry the token, e.g. `x-csrf-token`
+    pub header: String,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema, TS)]
 pub struct UserResponse {
     pub id: i32,
     pub username: String,
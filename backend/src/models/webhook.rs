@@ -0,0 +1,277 @@
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+use utoipa::{IntoParams, ToSchema};
+use validator::{Validate, ValidationError};
+
+use crate::{
+    db::schema::{webhook_attempts, webhook_deliveries, webhook_endpoints},
+    webhooks::WebhookEvent,
+};
+
+#[derive(Clone, Debug, Queryable, Selectable)]
+#[diesel(table_name = webhook_endpoints)]
+pub struct WebhookEndpoint {
+    pub id: i32,
+    pub url: String,
+    pub description: String,
+    pub secret: String,
+    /// JSON array of event types
+    pub events: String,
+    pub active: bool,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+impl WebhookEndpoint {
+    pub fn events(&self) -> Vec<String> {
+        serde_json::from_str(&self.events).unwrap_or_default()
+    }
+
+    pub fn subscribes_to(&self, event: WebhookEvent) -> bool {
+        self.events().iter().any(|name| name == event.as_str())
+    }
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = webhook_endpoints)]
+pub struct NewWebhookEndpoint {
+    pub url: String,
+    pub description: String,
+    pub secret: String,
+    pub events: String,
+}
+
+#[derive(Debug, Deserialize, Validate, ToSchema, TS)]
+pub struct CreateWebhookRequest {
+    /// Receives a `POST` for every subscribed event
+    #[validate(url(message = "Invalid URL"), custom(function = "http_url"))]
+    pub url: String,
+
+    #[validate(length(max = 200, message = "Description must be at most 200 characters"))]
+    #[ts(optional)]
+    pub description: Option<String>,
+
+    /// Event types from `GET /api/admin/webhooks/events`
+    #[validate(
+        length(min = 1, message = "Subscribe to at least one event"),
+        custom(function = "known_events")
+    )]
+    pub events: Vec<String>,
+}
+
+fn http_url(url: &str) -> Result<(), ValidationError> {
+    if url.starts_with("https://") || url.starts_with("http://") {
+        Ok(())
+    } else {
+        Err(ValidationError::new("url").with_message("URL must use http or https".into()))
+    }
+}
+
+fn known_events(events: &[String]) -> Result<(), ValidationError> {
+    match events
+        .iter()
+        .find(|name| WebhookEvent::from_name(name).is_none())
+    {
+        Some(unknown) => Err(ValidationError::new("unknown_event")
+            .with_message(format!("Unknown event type {}", unknown).into())),
+        None => Ok(()),
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema, TS)]
+pub struct WebhookEndpointResponse {
+    pub id: i32,
+    pub url: String,
+    pub description: String,
+    pub events: Vec<String>,
+    pub active: bool,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+impl From<WebhookEndpoint> for WebhookEndpointResponse {
+    fn from(endpoint: WebhookEndpoint) -> Self {
+        Self {
+            events: endpoint.events(),
+            id: endpoint.id,
+            url: endpoint.url,
+            description: endpoint.description,
+            active: endpoint.active,
+            created_at: endpoint.created_at,
+            updated_at: endpoint.updated_at,
+        }
+    }
+}
+
+/// A new endpoint and the only copy of its signing secret the API returns
+#[derive(Debug, Serialize, ToSchema, TS)]
+pub struct CreatedWebhookResponse {
+    pub endpoint: WebhookEndpointResponse,
+    /// Key for verifying the `Webhook-Signature` header
+    pub secret: String,
+}
+
+/// Lifecycle of a delivery
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema, TS)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryStatus {
+    /// Not attempted yet
+    Pending,
+    /// The last attempt got a 2xx response
+    Succeeded,
+    /// The last attempt failed; retried until the job runs out of attempts
+    Failed,
+}
+
+impl DeliveryStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DeliveryStatus::Pending => "pending",
+            DeliveryStatus::Succeeded => "succeeded",
+            DeliveryStatus::Failed => "failed",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Queryable, Selectable)]
+#[diesel(table_name = webhook_deliveries)]
+pub struct WebhookDelivery {
+    pub id: i32,
+    pub endpoint_id: i32,
+    pub event_id: String,
+    pub event_type: String,
+    pub payload: String,
+    pub status: String,
+    pub attempts: i32,
+    pub last_attempt_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = webhook_deliveries)]
+pub struct NewWebhookDelivery<'a> {
+    pub endpoint_id: i32,
+    pub event_id: &'a str,
+    pub event_type: &'a str,
+    pub payload: &'a str,
+}
+
+/// Filters accepted by the delivery log endpoint
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct WebhookDeliveryQuery {
+    pub status: Option<DeliveryStatus>,
+    pub event_type: Option<String>,
+    /// 1-based page number
+    #[param(minimum = 1)]
+    pub page: Option<i64>,
+    #[param(minimum = 1, maximum = 200)]
+    pub per_page: Option<i64>,
+}
+
+#[derive(Debug, Serialize, ToSchema, TS)]
+pub struct WebhookDeliveryResponse {
+    pub id: i32,
+    pub endpoint_id: i32,
+    pub event_id: String,
+    pub event_type: String,
+    #[schema(value_type = Object)]
+    #[ts(type = "unknown")]
+    pub payload: serde_json::Value,
+    pub status: String,
+    pub attempts: i32,
+    pub last_attempt_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+impl From<WebhookDelivery> for WebhookDeliveryResponse {
+    fn from(delivery: WebhookDelivery) -> Self {
+        Self {
+            id: delivery.id,
+            endpoint_id: delivery.endpoint_id,
+            event_id: delivery.event_id,
+            event_type: delivery.event_type,
+            payload: serde_json::from_str(&delivery.payload).unwrap_or(serde_json::Value::Null),
+            status: delivery.status,
+            attempts: delivery.attempts,
+            last_attempt_at: delivery.last_attempt_at,
+            created_at: delivery.created_at,
+            updated_at: delivery.updated_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema, TS)]
+pub struct WebhookDeliveryPage {
+    pub deliveries: Vec<WebhookDeliveryResponse>,
+    #[ts(type = "number")]
+    pub page: i64,
+    #[ts(type = "number")]
+    pub per_page: i64,
+    #[ts(type = "number")]
+    pub total: i64,
+}
+
+#[derive(Clone, Debug, Queryable, Selectable)]
+#[diesel(table_name = webhook_attempts)]
+pub struct WebhookAttempt {
+    pub id: i32,
+    pub delivery_id: i32,
+    pub attempted_at: NaiveDateTime,
+    pub succeeded: bool,
+    pub response_status: Option<i32>,
+    pub response_body: Option<String>,
+    pub error: Option<String>,
+    pub duration_ms: i32,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = webhook_attempts)]
+pub struct NewWebhookAttempt {
+    pub delivery_id: i32,
+    pub attempted_at: NaiveDateTime,
+    pub succeeded: bool,
+    pub response_status: Option<i32>,
+    pub response_body: Option<String>,
+    pub error: Option<String>,
+    pub duration_ms: i32,
+}
+
+#[derive(Debug, Serialize, ToSchema, TS)]
+pub struct WebhookAttemptResponse {
+    pub id: i32,
+    pub attempted_at: NaiveDateTime,
+    pub succeeded: bool,
+    /// HTTP status, if the endpoint answered
+    pub response_status: Option<i32>,
+    /// Start of the response body
+    pub response_body: Option<String>,
+    /// Why no response arrived, e.g. a timeout
+    pub error: Option<String>,
+    pub duration_ms: i32,
+}
+
+impl From<WebhookAttempt> for WebhookAttemptResponse {
+    fn from(attempt: WebhookAttempt) -> Self {
+        Self {
+            id: attempt.id,
+            attempted_at: attempt.attempted_at,
+            succeeded: attempt.succeeded,
+            response_status: attempt.response_status,
+            response_body: attempt.response_body,
+            error: attempt.error,
+            duration_ms: attempt.duration_ms,
+        }
+    }
+}
+
+/// A delivery with every attempt made for it, oldest first
+#[derive(Debug, Serialize, ToSchema, TS)]
+pub struct WebhookDeliveryDetail {
+    pub delivery: WebhookDeliveryResponse,
+    pub attempts: Vec<WebhookAttemptResponse>,
+}
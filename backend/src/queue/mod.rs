@@ -0,0 +1,376 @@
+//! Persistent job queue in the `jobs` table
+//!
+//! Handlers enqueue typed payloads, either through `Queue` or with
+//! [`enqueue`] on a connection inside their own transaction, so the job is
+//! only queued if the transaction commits. `Workers` claim due jobs one at a
+//! time and run the handler registered for their kind. Execution is
+//! at-least-once: a job whose worker dies mid-run is claimed again once its
+//! lease expires, so handlers must be idempotent.
+
+mod worker;
+
+use std::{sync::Arc, time::Duration};
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use diesel::prelude::*;
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::sync::Notify;
+
+use crate::{
+    clock::SharedClock,
+    db::{schema::jobs, Db, DbConnection},
+    error::{AppError, AppResult},
+    models::{JobRecord, JobState, NewJobRecord},
+};
+
+pub use worker::{Workers, WorkersHandle};
+
+/// A typed job, stored as JSON
+pub trait JobPayload: Serialize + DeserializeOwned + Send + 'static {
+    /// Stable name that routes stored jobs to their handler
+    const KIND: &'static str;
+    /// Runs before the job is declared dead
+    const MAX_ATTEMPTS: i32 = 5;
+
+    /// Jobs with the same key are deduplicated while one is pending or
+    /// running; `None` never deduplicates
+    fn unique_key(&self) -> Option<String> {
+        None
+    }
+}
+
+/// Why a handler did not finish a job
+#[derive(Debug, thiserror::Error)]
+pub enum JobError {
+    /// Try again later, unless attempts are used up
+    #[error("{0}")]
+    Retry(String),
+    /// Retrying cannot help, e.g. the payload is invalid
+    #[error("{0}")]
+    Fatal(String),
+}
+
+impl From<AppError> for JobError {
+    fn from(error: AppError) -> Self {
+        JobError::Retry(error.to_string())
+    }
+}
+
+/// Worker pool settings
+#[derive(Clone, Debug)]
+pub struct QueueOptions {
+    /// Concurrent worker tasks in `serve`; 0 disables them
+    pub workers: usize,
+    /// How often idle workers look for due jobs
+    pub poll_interval: Duration,
+    /// A running job not finished within this long is claimed again
+    pub lease: Duration,
+    /// Delay before the first retry; doubles with every attempt
+    pub backoff_base: Duration,
+    pub backoff_max: Duration,
+    /// How long `serve` waits for running jobs when it stops
+    pub shutdown_grace: Duration,
+}
+
+impl Default for QueueOptions {
+    fn default() -> Self {
+        Self {
+            workers: 2,
+            poll_interval: Duration::from_secs(1),
+            lease: Duration::from_secs(5 * 60),
+            backoff_base: Duration::from_secs(10),
+            backoff_max: Duration::from_secs(60 * 60),
+            shutdown_grace: Duration::from_secs(10),
+        }
+    }
+}
+
+impl QueueOptions {
+    /// Delay before retrying a job that has failed `attempts` times
+    pub fn backoff(&self, attempts: i32) -> Duration {
+        let exponent = attempts.saturating_sub(1).clamp(0, 30) as u32;
+        self.backoff_base
+            .saturating_mul(2u32.saturating_pow(exponent))
+            .min(self.backoff_max)
+    }
+}
+
+/// Queue a job on `conn`, due at `run_at`
+///
+/// Call this inside a transaction to queue the job only if the transaction
+/// commits. Returns the job id, or `None` if an equivalent job (same
+/// `unique_key`) is already pending or running.
+pub fn enqueue<P: JobPayload>(
+    conn: &mut DbConnection,
+    payload: &P,
+    run_at: DateTime<Utc>,
+) -> AppResult<Option<i32>> {
+    let record = NewJobRecord {
+        kind: P::KIND.to_string(),
+        payload: serde_json::to_string(payload)
+            .map_err(|e| diesel::result::Error::SerializationError(Box::new(e)))?,
+        max_attempts: P::MAX_ATTEMPTS,
+        run_at: run_at.naive_utc(),
+        unique_key: payload.unique_key(),
+    };
+
+    Ok(diesel::insert_into(jobs::table)
+        .values(&record)
+        .on_conflict_do_nothing()
+        .returning(jobs::id)
+        .get_result(conn)
+        .optional()?)
+}
+
+/// Enqueues jobs from async code and wakes idle workers
+#[derive(Clone)]
+pub struct Queue {
+    db: Db,
+    clock: SharedClock,
+    notify: Arc<Notify>,
+}
+
+impl Queue {
+    pub fn new(db: Db, clock: SharedClock) -> Self {
+        Self {
+            db,
+            clock,
+            notify: Arc::new(Notify::new()),
+        }
+    }
+
+    /// Queue a job to run as soon as a worker is free
+    pub async fn enqueue<P: JobPayload>(&self, payload: P) -> AppResult<Option<i32>> {
+        self.enqueue_at(payload, self.clock.now()).await
+    }
+
+    /// Queue a job to run no earlier than `run_at`
+    pub async fn enqueue_at<P: JobPayload>(
+        &self,
+        payload: P,
+        run_at: DateTime<Utc>,
+    ) -> AppResult<Option<i32>> {
+        let id = self
+            .db
+            .write(move |conn| enqueue(conn, &payload, run_at))
+            .await?;
+        self.notify.notify_one();
+        Ok(id)
+    }
+
+    /// Put a dead job back in line with a fresh set of attempts
+    ///
+    /// Returns `None` if there is no dead job with this id; fails with a
+    /// conflict if an equivalent job has been queued since.
+    pub async fn retry(&self, id: i32) -> AppResult<Option<JobRecord>> {
+        let now = self.clock.now().naive_utc();
+        let job = self
+            .db
+            .write(move |conn| {
+                diesel::update(
+                    jobs::table
+                        .find(id)
+                        .filter(jobs::status.eq(JobState::Dead.as_str())),
+                )
+                .set((
+                    jobs::status.eq(JobState::Pending.as_str()),
+                    jobs::attempts.eq(0),
+                    jobs::run_at.eq(now),
+                    jobs::finished_at.eq(None::<NaiveDateTime>),
+                ))
+                .returning(JobRecord::as_returning())
+                .get_result(conn)
+                .optional()
+                .map_err(|e| match AppError::from(e) {
+                    AppError::Conflict(_) => {
+                        AppError::Conflict("An equivalent job is already queued")
+                    }
+                    e => e,
+                })
+            })
+            .await?;
+        if job.is_some() {
+            self.notify.notify_one();
+        }
+        Ok(job)
+    }
+
+    /// Wake an idle worker after jobs were queued with [`enqueue`] on a
+    /// connection, so they don't wait for the next poll
+    pub fn wake(&self) {
+        self.notify.notify_one();
+    }
+
+    /// Worker pool that runs this queue's jobs
+    pub fn workers(&self, options: QueueOptions) -> Workers {
+        Workers::new(self.clone(), options)
+    }
+}
+
+/// Claim the oldest due job for `worker`, including running jobs whose
+/// lease has expired
+///
+/// A job whose lease expired on its last attempt is marked dead instead, so
+/// a job that takes its worker down every time cannot run forever. SQLite
+/// takes the write lock before looking, so two workers can never pick the
+/// same row; Postgres skips rows other workers have locked.
+fn claim(
+    conn: &mut DbConnection,
+    worker: &str,
+    now: NaiveDateTime,
+    lease: Duration,
+) -> QueryResult<Option<JobRecord>> {
+    let lease_expired = chrono::Duration::from_std(lease)
+        .ok()
+        .and_then(|lease| now.checked_sub_signed(lease))
+        .unwrap_or(NaiveDateTime::MIN);
+
+    claim_transaction(conn, |conn| {
+        let abandoned = jobs::status
+            .eq(JobState::Running.as_str())
+            .and(jobs::locked_at.lt(lease_expired));
+        let buried = diesel::update(
+            jobs::table
+                .filter(abandoned)
+                .filter(jobs::attempts.ge(jobs::max_attempts)),
+        )
+        .set((
+            jobs::status.eq(JobState::Dead.as_str()),
+            jobs::last_error.eq("lease expired on the last attempt"),
+            jobs::finished_at.eq(now),
+            jobs::locked_by.eq(None::<String>),
+            jobs::locked_at.eq(None::<NaiveDateTime>),
+        ))
+        .execute(conn)?;
+        if buried > 0 {
+            tracing::error!(
+                "{} job(s) dead after their lease expired on each attempt",
+                buried
+            );
+        }
+
+        let due = jobs::table
+            .filter(
+                jobs::status
+                    .eq(JobState::Pending.as_str())
+                    .and(jobs::run_at.le(now))
+                    .or(abandoned),
+            )
+            .order((jobs::run_at, jobs::id))
+            .select(jobs::id)
+            .limit(1);
+        #[cfg(feature = "postgres")]
+        let due = due.for_update().skip_locked();
+
+        let Some(id) = due.first::<i32>(conn).optional()? else {
+            return Ok(None);
+        };
+
+        diesel::update(jobs::table.find(id))
+            .set((
+                jobs::status.eq(JobState::Running.as_str()),
+                jobs::attempts.eq(jobs::attempts + 1),
+                jobs::locked_by.eq(worker),
+                jobs::locked_at.eq(now),
+            ))
+            .returning(JobRecord::as_returning())
+            .get_result(conn)
+            .optional()
+    })
+}
+
+#[cfg(feature = "sqlite")]
+fn claim_transaction<T, F>(conn: &mut DbConnection, f: F) -> QueryResult<T>
+where
+    F: FnOnce(&mut DbConnection) -> QueryResult<T>,
+{
+    conn.immediate_transaction(f)
+}
+
+#[cfg(feature = "postgres")]
+fn claim_transaction<T, F>(conn: &mut DbConnection, f: F) -> QueryResult<T>
+where
+    F: FnOnce(&mut DbConnection) -> QueryResult<T>,
+{
+    conn.transaction(f)
+}
+
+/// Record the outcome of a run, unless another worker has claimed the job
+/// since because the lease ran out
+fn finish(
+    conn: &mut DbConnection,
+    job: &JobRecord,
+    worker: &str,
+    outcome: Outcome,
+) -> QueryResult<usize> {
+    let claimed = jobs::table
+        .find(job.id)
+        .filter(jobs::locked_by.eq(worker))
+        .filter(jobs::locked_at.eq(job.locked_at));
+    let unlock = (
+        jobs::locked_by.eq(None::<String>),
+        jobs::locked_at.eq(None::<NaiveDateTime>),
+    );
+
+    match outcome {
+        Outcome::Succeeded { at } => diesel::update(claimed)
+            .set((
+                jobs::status.eq(JobState::Succeeded.as_str()),
+                jobs::last_error.eq(None::<String>),
+                jobs::finished_at.eq(at),
+                unlock,
+            ))
+            .execute(conn),
+        Outcome::Retry { error, run_at } => diesel::update(claimed)
+            .set((
+                jobs::status.eq(JobState::Pending.as_str()),
+                jobs::last_error.eq(error),
+                jobs::run_at.eq(run_at),
+                unlock,
+            ))
+            .execute(conn),
+        Outcome::Dead { error, at } => diesel::update(claimed)
+            .set((
+                jobs::status.eq(JobState::Dead.as_str()),
+                jobs::last_error.eq(error),
+                jobs::finished_at.eq(at),
+                unlock,
+            ))
+            .execute(conn),
+    }
+}
+
+#[derive(Debug)]
+enum Outcome {
+    Succeeded {
+        at: NaiveDateTime,
+    },
+    Retry {
+        error: String,
+        run_at: NaiveDateTime,
+    },
+    Dead {
+        error: String,
+        at: NaiveDateTime,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_doubles_up_to_the_maximum() {
+        let options = QueueOptions {
+            backoff_base: Duration::from_secs(10),
+            backoff_max: Duration::from_secs(60),
+            ..QueueOptions::default()
+        };
+
+        assert_eq!(options.backoff(1), Duration::from_secs(10));
+        assert_eq!(options.backoff(2), Duration::from_secs(20));
+        assert_eq!(options.backoff(3), Duration::from_secs(40));
+        assert_eq!(options.backoff(4), Duration::from_secs(60));
+        assert_eq!(options.backoff(i32::MAX), Duration::from_secs(60));
+    }
+}
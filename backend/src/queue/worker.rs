@@ -0,0 +1,199 @@
+use std::{collections::HashMap, future::Future, pin::Pin, sync::Arc, time::Duration};
+
+use chrono::NaiveDateTime;
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
+
+use super::{claim, finish, JobError, JobPayload, Outcome, Queue, QueueOptions};
+use crate::{error::AppResult, models::JobRecord};
+
+type HandlerFuture = Pin<Box<dyn Future<Output = Result<(), JobError>> + Send>>;
+type Handler = Arc<dyn Fn(&str) -> HandlerFuture + Send + Sync>;
+
+/// Worker tasks and the handlers they route jobs to
+pub struct Workers {
+    queue: Queue,
+    options: QueueOptions,
+    handlers: HashMap<&'static str, Handler>,
+}
+
+impl Workers {
+    pub(super) fn new(queue: Queue, options: QueueOptions) -> Self {
+        Self {
+            queue,
+            options,
+            handlers: HashMap::new(),
+        }
+    }
+
+    /// Run jobs of type `P` with `handler`
+    ///
+    /// Jobs whose kind has no handler are declared dead when claimed.
+    pub fn handle<P, F, Fut>(mut self, handler: F) -> Self
+    where
+        P: JobPayload,
+        F: Fn(P) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), JobError>> + Send + 'static,
+    {
+        let handler = Arc::new(handler);
+        self.handlers.insert(
+            P::KIND,
+            Arc::new(move |payload: &str| -> HandlerFuture {
+                match serde_json::from_str::<P>(payload) {
+                    Ok(payload) => Box::pin(handler(payload)),
+                    Err(e) => {
+                        let error = JobError::Fatal(format!("invalid payload: {}", e));
+                        Box::pin(async move { Err(error) })
+                    }
+                }
+            }),
+        );
+        self
+    }
+
+    pub fn start(self) -> WorkersHandle {
+        let cancel = CancellationToken::new();
+        let tracker = TaskTracker::new();
+        let worker = Arc::new(Worker {
+            queue: self.queue,
+            options: self.options,
+            handlers: self.handlers,
+        });
+
+        let prefix = uuid::Uuid::new_v4().simple().to_string();
+        for index in 0..worker.options.workers {
+            let name = format!("{}-{}", &prefix[..8], index);
+            tracker.spawn(worker.clone().run(name, cancel.clone()));
+        }
+
+        WorkersHandle { cancel, tracker }
+    }
+}
+
+/// Stops running workers
+pub struct WorkersHandle {
+    cancel: CancellationToken,
+    tracker: TaskTracker,
+}
+
+impl WorkersHandle {
+    /// Stop claiming jobs and wait up to `grace` for the ones in progress
+    ///
+    /// Jobs abandoned after `grace` stay claimed until their lease expires,
+    /// then run again.
+    pub async fn shutdown(self, grace: Duration) {
+        self.cancel.cancel();
+        self.tracker.close();
+        if tokio::time::timeout(grace, self.tracker.wait())
+            .await
+            .is_err()
+        {
+            tracing::warn!(
+                "Queued jobs still running after {:?}; abandoning them",
+                grace
+            );
+        }
+    }
+}
+
+struct Worker {
+    queue: Queue,
+    options: QueueOptions,
+    handlers: HashMap<&'static str, Handler>,
+}
+
+impl Worker {
+    async fn run(self: Arc<Self>, name: String, cancel: CancellationToken) {
+        while !cancel.is_cancelled() {
+            match self.run_next(&name).await {
+                Ok(true) => continue,
+                Ok(false) => {}
+                Err(e) => tracing::error!("Worker {} failed to claim a job: {}", name, e),
+            }
+
+            tokio::select! {
+                _ = cancel.cancelled() => break,
+                _ = self.queue.notify.notified() => {}
+                _ = tokio::time::sleep(self.options.poll_interval) => {}
+            }
+        }
+    }
+
+    /// Claim and run one due job; `false` if there was none
+    async fn run_next(&self, name: &str) -> AppResult<bool> {
+        let now = self.queue.clock.now().naive_utc();
+        let lease = self.options.lease;
+        let worker = name.to_string();
+        let Some(job) = self
+            .queue
+            .db
+            .write(move |conn| Ok(claim(conn, &worker, now, lease)?))
+            .await?
+        else {
+            return Ok(false);
+        };
+
+        let result = match self.handlers.get(job.kind.as_str()) {
+            // A panicking handler fails the job instead of the worker
+            Some(handler) => match tokio::spawn(handler(&job.payload)).await {
+                Ok(result) => result,
+                Err(e) => Err(JobError::Retry(format!("handler panicked: {}", e))),
+            },
+            None => Err(JobError::Fatal(format!(
+                "no handler for job kind {}",
+                job.kind
+            ))),
+        };
+
+        let outcome = self.outcome(&job, result);
+        match &outcome {
+            Outcome::Succeeded { .. } => tracing::debug!("Job {} ({}) succeeded", job.id, job.kind),
+            Outcome::Retry { error, run_at } => tracing::warn!(
+                "Job {} ({}) failed attempt {}: {}; retrying at {}",
+                job.id,
+                job.kind,
+                job.attempts,
+                error,
+                run_at
+            ),
+            Outcome::Dead { error, .. } => tracing::error!(
+                "Job {} ({}) is dead after {} attempts: {}",
+                job.id,
+                job.kind,
+                job.attempts,
+                error
+            ),
+        }
+
+        let worker = name.to_string();
+        let finished = self
+            .queue
+            .db
+            .write(move |conn| Ok(finish(conn, &job, &worker, outcome)?))
+            .await?;
+        if finished == 0 {
+            tracing::warn!(
+                "Worker {} lost its claim on a job before finishing it",
+                name
+            );
+        }
+        Ok(true)
+    }
+
+    fn outcome(&self, job: &JobRecord, result: Result<(), JobError>) -> Outcome {
+        let now = self.queue.clock.now().naive_utc();
+        match result {
+            Ok(()) => Outcome::Succeeded { at: now },
+            Err(JobError::Retry(error)) if job.attempts < job.max_attempts => {
+                let run_at = chrono::Duration::from_std(self.options.backoff(job.attempts))
+                    .ok()
+                    .and_then(|delay| now.checked_add_signed(delay))
+                    .unwrap_or(NaiveDateTime::MAX);
+                Outcome::Retry { error, run_at }
+            }
+            Err(e) => Outcome::Dead {
+                error: e.to_string(),
+                at: now,
+            },
+        }
+    }
+}
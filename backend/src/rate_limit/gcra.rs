@@ -0,0 +1,197 @@
+//! The generic cell rate algorithm
+//!
+//! A key's whole state is one timestamp, its theoretical arrival time (TAT):
+//! when its bucket would be empty again if no more requests came. Each
+//! admitted request pushes it one emission interval further; a request is
+//! refused while that would put it more than a window ahead of now.
+
+use std::{fmt, str::FromStr, time::Duration};
+
+/// How many requests a key may make per period, all at once or spread out
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quota {
+    pub limit: u32,
+    pub period: Duration,
+}
+
+impl Quota {
+    pub fn new(limit: u32, period: Duration) -> Self {
+        Self { limit, period }
+    }
+
+    pub fn per_minute(limit: u32) -> Self {
+        Self::new(limit, Duration::from_secs(60))
+    }
+
+    /// Milliseconds one request adds to the TAT
+    pub fn interval_ms(&self) -> i64 {
+        (self.period.as_millis() as i64 / i64::from(self.limit.max(1))).max(1)
+    }
+
+    /// How far ahead of now the TAT may run
+    pub fn window_ms(&self) -> i64 {
+        self.interval_ms() * i64::from(self.limit)
+    }
+}
+
+const UNITS: [(&str, u64); 4] = [("day", 86_400), ("h", 3_600), ("min", 60), ("s", 1)];
+
+/// `20/min`, `5/s`, `1000/h`, `100/day` or a multiple such as `10/30s`
+impl FromStr for Quota {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("{:?} is not a quota like 20/min", s);
+        let (limit, period) = s.trim().split_once('/').ok_or_else(invalid)?;
+        let limit: u32 = limit.trim().parse().map_err(|_| invalid())?;
+        if limit == 0 {
+            return Err("the limit must be at least 1".to_string());
+        }
+
+        let period = period.trim();
+        let digits = period.len()
+            - period
+                .trim_start_matches(|c: char| c.is_ascii_digit())
+                .len();
+        let (count, unit) = period.split_at(digits);
+        let count: u64 = match count {
+            "" => 1,
+            count => count.parse().map_err(|_| invalid())?,
+        };
+        let unit = match unit {
+            "s" | "sec" | "second" => 1,
+            "min" | "minute" => 60,
+            "h" | "hour" => 3_600,
+            "d" | "day" => 86_400,
+            _ => return Err(invalid()),
+        };
+        if count == 0 {
+            return Err(invalid());
+        }
+        Ok(Self::new(limit, Duration::from_secs(count * unit)))
+    }
+}
+
+impl fmt::Display for Quota {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let secs = self.period.as_secs().max(1);
+        let (unit, size) = UNITS
+            .into_iter()
+            .find(|(_, size)| secs.is_multiple_of(*size))
+            .unwrap_or(("s", 1));
+        match secs / size {
+            1 => write!(f, "{}/{}", self.limit, unit),
+            count => write!(f, "{}/{}{}", self.limit, count, unit),
+        }
+    }
+}
+
+/// What a store did with one request
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Outcome {
+    pub allowed: bool,
+    /// The key's TAT afterwards, in milliseconds since the epoch
+    pub tat: i64,
+}
+
+/// Admit or refuse a request at `now` against the key's stored TAT
+pub fn update(quota: &Quota, now: i64, tat: Option<i64>) -> Outcome {
+    let tat = tat.unwrap_or(now).max(now);
+    let next = tat + quota.interval_ms();
+    if next - quota.window_ms() > now {
+        Outcome {
+            allowed: false,
+            tat,
+        }
+    } else {
+        Outcome {
+            allowed: true,
+            tat: next,
+        }
+    }
+}
+
+/// An outcome as the client sees it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Decision {
+    pub allowed: bool,
+    pub limit: u32,
+    pub remaining: u32,
+    /// Seconds until the full quota is available again
+    pub reset_secs: u64,
+    /// Seconds until a refused request would be admitted
+    pub retry_after_secs: Option<u64>,
+}
+
+impl Decision {
+    pub fn new(quota: &Quota, now: i64, outcome: Outcome) -> Self {
+        let ahead = (outcome.tat - now).max(0);
+        let remaining = (quota.window_ms() - ahead) / quota.interval_ms();
+        let retry_after = (!outcome.allowed)
+            .then(|| ceil_secs(ahead + quota.interval_ms() - quota.window_ms()).max(1));
+        Self {
+            allowed: outcome.allowed,
+            limit: quota.limit,
+            remaining: remaining.clamp(0, i64::from(quota.limit)) as u32,
+            reset_secs: ceil_secs(ahead),
+            retry_after_secs: retry_after,
+        }
+    }
+}
+
+fn ceil_secs(ms: i64) -> u64 {
+    (ms.max(0) as u64).div_ceil(1000)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quota_round_trips_through_its_text_form() {
+        for (text, limit, secs) in [
+            ("20/min", 20, 60),
+            ("5/s", 5, 1),
+            ("1000/h", 1000, 3_600),
+            ("100/day", 100, 86_400),
+            ("10/30s", 10, 30),
+        ] {
+            let quota: Quota = text.parse().unwrap();
+            assert_eq!(quota, Quota::new(limit, Duration::from_secs(secs)));
+            assert_eq!(quota.to_string(), text);
+        }
+        assert_eq!("3 / minute".parse(), Ok(Quota::per_minute(3)));
+        for invalid in ["20", "0/min", "x/min", "20/fortnight", "20/0s"] {
+            assert!(invalid.parse::<Quota>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_a_burst_of_the_limit_is_admitted_then_one_per_interval() {
+        let quota = Quota::per_minute(3);
+        let now = 1_000_000;
+
+        let mut tat = None;
+        let mut remaining = Vec::new();
+        for _ in 0..3 {
+            let outcome = update(&quota, now, tat);
+            assert!(outcome.allowed);
+            tat = Some(outcome.tat);
+            remaining.push(Decision::new(&quota, now, outcome).remaining);
+        }
+        assert_eq!(remaining, [2, 1, 0]);
+
+        let refused = update(&quota, now, tat);
+        assert_eq!(refused.tat, tat.unwrap());
+        let decision = Decision::new(&quota, now, refused);
+        assert!(!decision.allowed);
+        assert_eq!(decision.retry_after_secs, Some(20));
+        assert_eq!(decision.reset_secs, 60);
+
+        // One interval later exactly one more request fits
+        let later = now + quota.interval_ms();
+        let outcome = update(&quota, later, tat);
+        assert!(outcome.allowed);
+        assert!(!update(&quota, later, Some(outcome.tat)).allowed);
+    }
+}
@@ -0,0 +1,69 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+
+use super::{
+    gcra::{self, Outcome, Quota},
+    RateLimitError, RateLimitStore,
+};
+
+/// Milliseconds between sweeps for keys whose bucket is full again
+const SWEEP_INTERVAL_MS: i64 = 60_000;
+
+/// Keeps each key's state in this process
+///
+/// Every instance counts on its own, so `N` instances admit up to `N` times
+/// the quota; use the Redis store to share limits.
+#[derive(Clone, Default)]
+pub struct MemoryStore {
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Default)]
+struct State {
+    tats: HashMap<String, i64>,
+    /// When idle keys are next dropped, in milliseconds since the epoch
+    next_sweep: i64,
+}
+
+#[async_trait]
+impl RateLimitStore for MemoryStore {
+    async fn acquire(&self, key: &str, quota: &Quota, now: i64) -> Result<Outcome, RateLimitError> {
+        let mut state = self.state.lock().unwrap();
+        if now >= state.next_sweep {
+            // A TAT in the past is the same as no TAT at all
+            state.tats.retain(|_, tat| *tat > now);
+            state.next_sweep = now + SWEEP_INTERVAL_MS;
+        }
+        let outcome = gcra::update(quota, now, state.tats.get(key).copied());
+        if outcome.allowed {
+            state.tats.insert(key.to_string(), outcome.tat);
+        }
+        Ok(outcome)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_idle_keys_are_dropped_once_per_interval() {
+        let store = MemoryStore::default();
+        let quota = Quota::per_minute(60);
+        let keys = |store: &MemoryStore| store.state.lock().unwrap().tats.len();
+
+        for key in ["a", "b", "c"] {
+            store.acquire(key, &quota, 0).await.unwrap();
+        }
+        // Full again after a second, but kept until the next sweep
+        store.acquire("d", &quota, 5_000).await.unwrap();
+        assert_eq!(keys(&store), 4);
+
+        store.acquire("d", &quota, SWEEP_INTERVAL_MS).await.unwrap();
+        assert_eq!(keys(&store), 1);
+    }
+}
@@ -0,0 +1,363 @@
+//! Request rate limits
+//!
+//! Each route group is wrapped in a [`RateLimitLayer`] for a named
+//! [`Policy`]: a [`Quota`] enforced per client IP, signed-in user or API
+//! key. Limits use the generic cell rate algorithm in [`gcra`], so a client
+//! may spend its whole quota at once and then gets one request per interval.
+//! Every limited response carries `RateLimit-Limit`, `RateLimit-Remaining`,
+//! `RateLimit-Reset` and `RateLimit-Policy`; refused requests get `429` with
+//! `Retry-After`. State lives in a [`RateLimitStore`]: this process's memory,
+//! or with the `redis` feature a Redis server shared by every instance.
+
+pub mod gcra;
+mod memory;
+#[cfg(feature = "redis")]
+mod redis;
+
+use std::{
+    collections::HashSet,
+    convert::Infallible,
+    fmt,
+    future::Future,
+    net::SocketAddr,
+    pin::Pin,
+    str::FromStr,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use async_trait::async_trait;
+use axum::{
+    extract::{ConnectInfo, Request},
+    http::{header, HeaderMap, HeaderName, HeaderValue},
+    response::{IntoResponse, Response},
+};
+use sha2::{Digest, Sha256};
+use tower::{Layer, Service};
+
+use crate::{
+    auth::{jwt::verify_token, JwtKeys},
+    clock::SharedClock,
+    config::Config,
+    error::AppError,
+};
+
+pub use gcra::{Decision, Outcome, Quota};
+pub use memory::MemoryStore;
+#[cfg(feature = "redis")]
+pub use redis::RedisStore;
+
+/// Header identifying the API key a request is made with
+pub const API_KEY_HEADER: HeaderName = HeaderName::from_static("x-api-key");
+
+pub const RATELIMIT_LIMIT: HeaderName = HeaderName::from_static("ratelimit-limit");
+pub const RATELIMIT_REMAINING: HeaderName = HeaderName::from_static("ratelimit-remaining");
+pub const RATELIMIT_RESET: HeaderName = HeaderName::from_static("ratelimit-reset");
+pub const RATELIMIT_POLICY: HeaderName = HeaderName::from_static("ratelimit-policy");
+
+#[derive(Debug, thiserror::Error)]
+pub enum RateLimitError {
+    #[error("rate limit store unavailable: {0}")]
+    Store(String),
+}
+
+/// Keeps the GCRA state of every key
+#[async_trait]
+pub trait RateLimitStore: Send + Sync {
+    /// Count one request for `key` at `now`, in milliseconds since the epoch
+    async fn acquire(&self, key: &str, quota: &Quota, now: i64) -> Result<Outcome, RateLimitError>;
+}
+
+pub type SharedRateLimitStore = Arc<dyn RateLimitStore>;
+
+/// What a policy counts requests by
+///
+/// Requests without a valid session or a known API key are counted by IP
+/// instead, so a made-up key cannot buy a fresh quota.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum KeyBy {
+    /// The socket peer's address
+    #[default]
+    Ip,
+    /// The user of a valid access token, from the cookie or bearer header
+    User,
+    /// The `X-API-Key` header, if it is one of `RateLimitOptions::api_keys`
+    ApiKey,
+}
+
+impl KeyBy {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            KeyBy::Ip => "ip",
+            KeyBy::User => "user",
+            KeyBy::ApiKey => "api_key",
+        }
+    }
+}
+
+impl fmt::Display for KeyBy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for KeyBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ip" => Ok(KeyBy::Ip),
+            "user" => Ok(KeyBy::User),
+            "api_key" => Ok(KeyBy::ApiKey),
+            _ => Err("expected ip, user or api_key".to_string()),
+        }
+    }
+}
+
+/// A quota for one route group
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Policy {
+    /// Keeps the group's counts apart from other groups'
+    pub name: String,
+    pub quota: Quota,
+    pub key_by: KeyBy,
+}
+
+impl Policy {
+    pub fn new(name: impl Into<String>, quota: Quota, key_by: KeyBy) -> Self {
+        Self {
+            name: name.into(),
+            quota,
+            key_by,
+        }
+    }
+}
+
+/// Where rate limit state is kept
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RateLimitBackend {
+    /// In this process; each instance counts on its own
+    Memory,
+    /// On a Redis-compatible server, e.g. `redis://localhost:6379`;
+    /// needs the `redis` feature
+    Redis(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct RateLimitOptions {
+    pub enabled: bool,
+    pub backend: RateLimitBackend,
+    /// Sign-up, sign-in and the other `/api/auth` endpoints used without a
+    /// session
+    pub auth: Policy,
+    /// Every other `/api` route
+    pub api: Policy,
+    /// The keys `KeyBy::ApiKey` counts requests by
+    pub api_keys: Vec<String>,
+}
+
+impl Default for RateLimitOptions {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            backend: RateLimitBackend::Memory,
+            auth: Policy::new("auth", Quota::per_minute(20), KeyBy::Ip),
+            api: Policy::new("api", Quota::per_minute(600), KeyBy::User),
+            api_keys: Vec::new(),
+        }
+    }
+}
+
+impl RateLimitOptions {
+    /// Build the store for the configured backend
+    pub fn store(&self) -> Result<SharedRateLimitStore, RateLimitError> {
+        Ok(match &self.backend {
+            RateLimitBackend::Memory => Arc::new(MemoryStore::default()),
+            #[cfg(feature = "redis")]
+            RateLimitBackend::Redis(url) => Arc::new(RedisStore::from_url(url)?),
+            #[cfg(not(feature = "redis"))]
+            RateLimitBackend::Redis(_) => {
+                return Err(RateLimitError::Store(
+                    "built without the redis feature".to_string(),
+                ))
+            }
+        })
+    }
+}
+
+/// Counts requests against policies
+#[derive(Clone)]
+pub struct RateLimiter {
+    store: SharedRateLimitStore,
+    clock: SharedClock,
+    keys: Arc<JwtKeys>,
+    config: Arc<Config>,
+    /// Digests of the known API keys
+    api_keys: Arc<HashSet<String>>,
+}
+
+impl RateLimiter {
+    pub fn new(
+        store: SharedRateLimitStore,
+        clock: SharedClock,
+        keys: Arc<JwtKeys>,
+        config: Arc<Config>,
+    ) -> Self {
+        let api_keys = config.rate_limit.api_keys.iter().map(digest).collect();
+        Self {
+            store,
+            clock,
+            keys,
+            config,
+            api_keys: Arc::new(api_keys),
+        }
+    }
+
+    /// A layer enforcing `policy` on the routes it wraps
+    pub fn layer(&self, policy: &Policy) -> RateLimitLayer {
+        RateLimitLayer {
+            limiter: self.clone(),
+            policy: Arc::new(policy.clone()),
+        }
+    }
+
+    /// Who `request` counts against under `policy`
+    ///
+    /// `None` when it cannot be attributed to anyone, which only happens when
+    /// the server was not started with connection info; such requests are let
+    /// through.
+    pub fn key(&self, policy: &Policy, request: &Request) -> Option<String> {
+        let client = self.client(policy.key_by, request)?;
+        Some(format!("rate_limit:{}:{}", policy.name, client))
+    }
+
+    /// Count one request for `key` against `policy`
+    ///
+    /// `None` when the store fails; the request is let through.
+    pub async fn check(&self, policy: &Policy, key: &str) -> Option<Decision> {
+        let now = self.clock.now().timestamp_millis();
+        match self.store.acquire(key, &policy.quota, now).await {
+            Ok(outcome) => Some(Decision::new(&policy.quota, now, outcome)),
+            Err(e) => {
+                tracing::error!("Not rate limiting {}: {}", policy.name, e);
+                None
+            }
+        }
+    }
+
+    fn client(&self, key_by: KeyBy, request: &Request) -> Option<String> {
+        let headers = request.headers();
+        let identified = match key_by {
+            KeyBy::Ip => None,
+            KeyBy::User => self.user(headers).map(|user| format!("user:{}", user)),
+            // Hashed so the store never holds the key itself
+            KeyBy::ApiKey => headers
+                .get(API_KEY_HEADER)
+                .map(digest)
+                .filter(|key| self.api_keys.contains(key))
+                .map(|key| format!("key:{}", key)),
+        };
+        identified.or_else(|| {
+            request
+                .extensions()
+                .get::<ConnectInfo<SocketAddr>>()
+                .map(|ConnectInfo(addr)| format!("ip:{}", addr.ip()))
+        })
+    }
+
+    /// The user of the request's access token, if it is valid
+    fn user(&self, headers: &HeaderMap) -> Option<String> {
+        let token = self.config.cookie.find(headers).or_else(|| {
+            headers
+                .get(header::AUTHORIZATION)
+                .and_then(|header| header.to_str().ok())
+                .and_then(|auth| auth.strip_prefix("Bearer "))
+        })?;
+        let claims = verify_token(&self.keys, token, self.clock.now()).ok()?;
+        Some(claims.sub)
+    }
+}
+
+fn digest(key: impl AsRef<[u8]>) -> String {
+    hex::encode(Sha256::digest(key))
+}
+
+impl Decision {
+    fn write_headers(&self, policy: &Policy, headers: &mut HeaderMap) {
+        let window = (policy.quota.window_ms() + 999) / 1000;
+        headers.insert(RATELIMIT_LIMIT, self.limit.into());
+        headers.insert(RATELIMIT_REMAINING, self.remaining.into());
+        headers.insert(RATELIMIT_RESET, self.reset_secs.into());
+        if let Ok(value) = HeaderValue::from_str(&format!("{};w={}", self.limit, window)) {
+            headers.insert(RATELIMIT_POLICY, value);
+        }
+        if let Some(retry_after) = self.retry_after_secs {
+            headers.insert(header::RETRY_AFTER, retry_after.into());
+        }
+    }
+}
+
+/// Applies a [`Policy`] to the wrapped routes
+#[derive(Clone)]
+pub struct RateLimitLayer {
+    limiter: RateLimiter,
+    policy: Arc<Policy>,
+}
+
+impl<S> Layer<S> for RateLimitLayer {
+    type Service = RateLimit<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RateLimit {
+            inner,
+            limiter: self.limiter.clone(),
+            policy: self.policy.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RateLimit<S> {
+    inner: S,
+    limiter: RateLimiter,
+    policy: Arc<Policy>,
+}
+
+impl<S> Service<Request> for RateLimit<S>
+where
+    S: Service<Request, Response = Response, Error = Infallible> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, Infallible>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        // The clone may not be ready; keep the one that is
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let limiter = self.limiter.clone();
+        let policy = self.policy.clone();
+
+        let key = limiter.key(&policy, &request);
+        Box::pin(async move {
+            let decision = match key {
+                Some(key) => limiter.check(&policy, &key).await,
+                None => None,
+            };
+            let Some(decision) = decision else {
+                return inner.call(request).await;
+            };
+            let mut response = match decision.retry_after_secs {
+                Some(retry_after) => AppError::RateLimited(retry_after).into_response(),
+                None => inner.call(request).await?,
+            };
+            decision.write_headers(&policy, response.headers_mut());
+            Ok(response)
+        })
+    }
+}
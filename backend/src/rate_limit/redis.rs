@@ -0,0 +1,91 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use redis::{
+    aio::{ConnectionManager, ConnectionManagerConfig},
+    Client, Script,
+};
+use tokio::sync::OnceCell;
+
+use super::{
+    gcra::{Outcome, Quota},
+    RateLimitError, RateLimitStore,
+};
+
+/// `gcra::update` run atomically on the server, returning `{allowed, tat}`
+const GCRA: &str = r"
+local now = tonumber(ARGV[1])
+local interval = tonumber(ARGV[2])
+local window = tonumber(ARGV[3])
+local tat = tonumber(redis.call('GET', KEYS[1])) or now
+if tat < now then
+    tat = now
+end
+local next = tat + interval
+if next - window > now then
+    return {0, tat}
+end
+redis.call('SET', KEYS[1], string.format('%.0f', next), 'PX', string.format('%.0f', next - now))
+return {1, next}
+";
+
+/// Give up on the server quickly; requests are let through meanwhile
+const TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Shares each key's state between instances through a Redis-compatible
+/// server such as Redis, Valkey or KeyDB
+///
+/// Keys expire once their bucket is full again, so the server holds no
+/// state for idle clients.
+pub struct RedisStore {
+    client: Client,
+    connection: OnceCell<ConnectionManager>,
+    script: Script,
+}
+
+impl RedisStore {
+    /// A store for the server at `url`, e.g. `redis://localhost:6379`;
+    /// connects on first use
+    pub fn from_url(url: &str) -> Result<Self, RateLimitError> {
+        let client = Client::open(url).map_err(|e| RateLimitError::Store(e.to_string()))?;
+        Ok(Self {
+            client,
+            connection: OnceCell::new(),
+            script: Script::new(GCRA),
+        })
+    }
+
+    async fn connection(&self) -> Result<ConnectionManager, redis::RedisError> {
+        let config = ConnectionManagerConfig::new()
+            .set_connection_timeout(TIMEOUT)
+            .set_response_timeout(TIMEOUT)
+            .set_number_of_retries(1);
+        self.connection
+            .get_or_try_init(|| ConnectionManager::new_with_config(self.client.clone(), config))
+            .await
+            .cloned()
+    }
+}
+
+#[async_trait]
+impl RateLimitStore for RedisStore {
+    async fn acquire(&self, key: &str, quota: &Quota, now: i64) -> Result<Outcome, RateLimitError> {
+        let result = async {
+            let mut connection = self.connection().await?;
+            self.script
+                .key(key)
+                .arg(now)
+                .arg(quota.interval_ms())
+                .arg(quota.window_ms())
+                .invoke_async::<(i64, i64)>(&mut connection)
+                .await
+        };
+        let (allowed, tat) = result
+            .await
+            .map_err(|e| RateLimitError::Store(e.to_string()))?;
+        Ok(Outcome {
+            allowed: allowed == 1,
+            tat,
+        })
+    }
+}
@@ -0,0 +1,243 @@
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+
+use super::{SessionRepository, UserRepository};
+use crate::{
+    db::{
+        schema::{refresh_tokens, users},
+        DbConnection,
+    },
+    error::{AppError, AppResult},
+    models::{NewRefreshToken, NewUser, RefreshToken, User, UserChanges},
+};
+
+/// Users stored in the database
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DieselUserRepository;
+
+impl UserRepository for DieselUserRepository {
+    fn insert(&self, conn: &mut DbConnection, new_user: NewUser) -> AppResult<User> {
+        diesel::insert_into(users::table)
+            .values(&new_user)
+            .returning(User::as_returning())
+            .get_result(conn)
+            .map_err(taken)
+    }
+
+    fn find_by_id(&self, conn: &mut DbConnection, id: i32) -> AppResult<Option<User>> {
+        Ok(User::live()
+            .find(id)
+            .select(User::as_select())
+            .first(conn)
+            .optional()?)
+    }
+
+    fn find_by_email(&self, conn: &mut DbConnection, email: &str) -> AppResult<Option<User>> {
+        Ok(User::live()
+            .filter(users::email.eq(email))
+            .select(User::as_select())
+            .first(conn)
+            .optional()?)
+    }
+
+    fn update(
+        &self,
+        conn: &mut DbConnection,
+        id: i32,
+        version: i32,
+        changes: UserChanges,
+    ) -> AppResult<Option<User>> {
+        diesel::update(User::live().find(id).filter(users::version.eq(version)))
+            .set((changes, users::version.eq(users::version + 1)))
+            .returning(User::as_returning())
+            .get_result(conn)
+            .optional()
+            .map_err(taken)
+    }
+
+    fn soft_delete(
+        &self,
+        conn: &mut DbConnection,
+        id: i32,
+        now: NaiveDateTime,
+    ) -> AppResult<Option<User>> {
+        Ok(diesel::update(User::live().find(id))
+            .set(users::deleted_at.eq(now))
+            .returning(User::as_returning())
+            .get_result(conn)
+            .optional()?)
+    }
+
+    fn restore(&self, conn: &mut DbConnection, id: i32) -> AppResult<Option<User>> {
+        Ok(diesel::update(
+            users::table
+                .find(id)
+                .filter(users::deleted_at.is_not_null()),
+        )
+        .set(users::deleted_at.eq(None::<NaiveDateTime>))
+        .returning(User::as_returning())
+        .get_result(conn)
+        .optional()?)
+    }
+}
+
+/// Report unique violations on `users` in terms the client can act on
+fn taken(error: diesel::result::Error) -> AppError {
+    match AppError::from(error) {
+        AppError::Conflict(_) => AppError::Conflict("Email or username already exists"),
+        e => e,
+    }
+}
+
+/// Refresh tokens stored in the database
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DieselSessionRepository;
+
+impl SessionRepository for DieselSessionRepository {
+    fn insert(&self, conn: &mut DbConnection, token: NewRefreshToken) -> AppResult<RefreshToken> {
+        Ok(diesel::insert_into(refresh_tokens::table)
+            .values(&token)
+            .returning(RefreshToken::as_returning())
+            .get_result(conn)?)
+    }
+
+    fn find(&self, conn: &mut DbConnection, id: &str) -> AppResult<Option<RefreshToken>> {
+        Ok(refresh_tokens::table
+            .find(id)
+            .select(RefreshToken::as_select())
+            .first(conn)
+            .optional()?)
+    }
+
+    fn mark_used(&self, conn: &mut DbConnection, id: &str, now: NaiveDateTime) -> AppResult<()> {
+        diesel::update(refresh_tokens::table.find(id))
+            .set(refresh_tokens::last_used_at.eq(now))
+            .execute(conn)?;
+        Ok(())
+    }
+
+    fn delete(&self, conn: &mut DbConnection, id: &str) -> AppResult<Option<RefreshToken>> {
+        Ok(diesel::delete(refresh_tokens::table.find(id))
+            .returning(RefreshToken::as_returning())
+            .get_result(conn)
+            .optional()?)
+    }
+
+    fn delete_for_user(&self, conn: &mut DbConnection, user_id: i32) -> AppResult<usize> {
+        Ok(
+            diesel::delete(refresh_tokens::table.filter(refresh_tokens::user_id.eq(user_id)))
+                .execute(conn)?,
+        )
+    }
+
+    fn delete_expired(&self, conn: &mut DbConnection, now: NaiveDateTime) -> AppResult<usize> {
+        Ok(
+            diesel::delete(refresh_tokens::table.filter(refresh_tokens::expires_at.lt(now)))
+                .execute(conn)?,
+        )
+    }
+}
+
+// Postgres runs these through the integration tests, which need a server
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::*;
+    use crate::db::migrations;
+    use chrono::Utc;
+    use diesel::Connection;
+
+    fn conn() -> DbConnection {
+        let mut conn = DbConnection::establish(":memory:").unwrap();
+        migrations::run_pending(&mut conn).unwrap();
+        conn
+    }
+
+    fn new_user(username: &str, email: &str) -> NewUser {
+        NewUser {
+            username: username.to_string(),
+            email: email.to_string(),
+            password_hash: "hash".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_duplicate_user_is_a_conflict() {
+        let (repo, conn) = (DieselUserRepository, &mut conn());
+        repo.insert(conn, new_user("alice", "alice@example.com"))
+            .unwrap();
+
+        let result = repo.insert(conn, new_user("alice", "other@example.com"));
+        assert!(matches!(result, Err(AppError::Conflict(_))));
+    }
+
+    #[test]
+    fn test_soft_deleted_user_is_hidden_until_restored() {
+        let (repo, conn) = (DieselUserRepository, &mut conn());
+        let alice = repo
+            .insert(conn, new_user("alice", "alice@example.com"))
+            .unwrap();
+
+        let deleted = repo
+            .soft_delete(conn, alice.id, Utc::now().naive_utc())
+            .unwrap();
+        assert!(deleted.unwrap().is_deleted());
+        assert!(repo.find_by_id(conn, alice.id).unwrap().is_none());
+        assert!(repo
+            .find_by_email(conn, "alice@example.com")
+            .unwrap()
+            .is_none());
+        assert!(repo
+            .soft_delete(conn, alice.id, Utc::now().naive_utc())
+            .unwrap()
+            .is_none());
+
+        assert!(!repo.restore(conn, alice.id).unwrap().unwrap().is_deleted());
+        assert!(repo.find_by_id(conn, alice.id).unwrap().is_some());
+        assert!(repo.restore(conn, alice.id).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_update_requires_current_version() {
+        let (repo, conn) = (DieselUserRepository, &mut conn());
+        let alice = repo
+            .insert(conn, new_user("alice", "alice@example.com"))
+            .unwrap();
+        repo.insert(conn, new_user("bob", "bob@example.com"))
+            .unwrap();
+
+        let rename = || UserChanges {
+            username: Some("alicia".to_string()),
+            ..UserChanges::default()
+        };
+        let updated = repo.update(conn, alice.id, 1, rename()).unwrap().unwrap();
+        assert_eq!((updated.username.as_str(), updated.version), ("alicia", 2));
+        assert!(repo.update(conn, alice.id, 1, rename()).unwrap().is_none());
+
+        let steal = UserChanges {
+            email: Some("bob@example.com".to_string()),
+            ..UserChanges::default()
+        };
+        let result = repo.update(conn, alice.id, 2, steal);
+        assert!(matches!(result, Err(AppError::Conflict(_))));
+    }
+
+    #[test]
+    fn test_delete_session_returns_removed_token() {
+        let conn = &mut conn();
+        let alice = DieselUserRepository
+            .insert(conn, new_user("alice", "alice@example.com"))
+            .unwrap();
+        let repo = DieselSessionRepository;
+        let token = repo
+            .insert(conn, NewRefreshToken::new(alice.id, Utc::now()))
+            .unwrap();
+
+        assert_eq!(
+            repo.find(conn, &token.id).unwrap().unwrap().user_id,
+            alice.id
+        );
+        assert_eq!(repo.delete(conn, &token.id).unwrap().unwrap().id, token.id);
+        assert!(repo.delete(conn, &token.id).unwrap().is_none());
+        assert!(repo.find(conn, &token.id).unwrap().is_none());
+    }
+}
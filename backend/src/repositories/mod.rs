@@ -0,0 +1,65 @@
+//! Storage for users and sessions behind traits, so handlers do not depend
+//! on Diesel
+//!
+//! Every method runs on the caller's connection: reads inside `Db::read`,
+//! writes inside the handler's write transaction, so a change, its audit
+//! entry and its events commit or roll back together.
+
+pub mod database;
+
+use std::sync::Arc;
+
+use chrono::NaiveDateTime;
+
+use crate::{
+    db::DbConnection,
+    error::AppResult,
+    models::{NewRefreshToken, NewUser, RefreshToken, User, UserChanges},
+};
+
+pub use database::{DieselSessionRepository, DieselUserRepository};
+
+pub type SharedUserRepository = Arc<dyn UserRepository>;
+pub type SharedSessionRepository = Arc<dyn SessionRepository>;
+
+/// Soft-deleted users are invisible to every lookup except `restore`
+pub trait UserRepository: Send + Sync {
+    /// Insert a user; fails with `AppError::Conflict` if the email or
+    /// username is taken
+    fn insert(&self, conn: &mut DbConnection, new_user: NewUser) -> AppResult<User>;
+    fn find_by_id(&self, conn: &mut DbConnection, id: i32) -> AppResult<Option<User>>;
+    /// Look up a user by their (already lowercased) email
+    fn find_by_email(&self, conn: &mut DbConnection, email: &str) -> AppResult<Option<User>>;
+    /// Apply `changes` and bump the version, but only if the user is still
+    /// at `version`; `None` means it was deleted or changed in the meantime
+    fn update(
+        &self,
+        conn: &mut DbConnection,
+        id: i32,
+        version: i32,
+        changes: UserChanges,
+    ) -> AppResult<Option<User>>;
+    /// Mark a user deleted at `now`, returning it if it was live
+    fn soft_delete(
+        &self,
+        conn: &mut DbConnection,
+        id: i32,
+        now: NaiveDateTime,
+    ) -> AppResult<Option<User>>;
+    /// Undo a soft delete, returning the user if it was deleted
+    fn restore(&self, conn: &mut DbConnection, id: i32) -> AppResult<Option<User>>;
+}
+
+/// Refresh tokens, one per signed-in session
+pub trait SessionRepository: Send + Sync {
+    fn insert(&self, conn: &mut DbConnection, token: NewRefreshToken) -> AppResult<RefreshToken>;
+    fn find(&self, conn: &mut DbConnection, id: &str) -> AppResult<Option<RefreshToken>>;
+    /// Record that a token was just used to issue an access token
+    fn mark_used(&self, conn: &mut DbConnection, id: &str, now: NaiveDateTime) -> AppResult<()>;
+    /// Delete a token, returning it if it existed
+    fn delete(&self, conn: &mut DbConnection, id: &str) -> AppResult<Option<RefreshToken>>;
+    /// Delete every token belonging to a user, returning how many there were
+    fn delete_for_user(&self, conn: &mut DbConnection, user_id: i32) -> AppResult<usize>;
+    /// Delete every token that expired before `now`, returning how many
+    fn delete_expired(&self, conn: &mut DbConnection, now: NaiveDateTime) -> AppResult<usize>;
+}
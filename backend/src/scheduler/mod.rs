@@ -0,0 +1,476 @@
+//! In-process scheduler for periodic jobs
+//!
+//! Each registered job gets its own task that sleeps until the next time
+//! its cron expression fires, plus a random jitter so several instances do
+//! not hit the database at the same moment. A run that is still going when
+//! the next one is due makes the scheduler skip that tick instead of
+//! starting a second copy. `SchedulerHandle::shutdown` cancels pending
+//! ticks and gives running jobs a grace period to finish.
+
+mod purge;
+
+use std::{
+    collections::BTreeMap,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use cron::Schedule;
+use serde::Serialize;
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
+use ts_rs::TS;
+use utoipa::ToSchema;
+
+use crate::error::AppResult;
+
+pub use purge::PurgeExpiredTokens;
+
+/// Work run on a schedule
+#[async_trait]
+pub trait Job: Send + Sync + 'static {
+    /// Unique name, shown in the admin status
+    fn name(&self) -> &'static str;
+
+    /// Do one run and summarize it, e.g. "purged 12 tokens"
+    ///
+    /// Long runs should check `cancel` and return early once it fires.
+    async fn run(&self, cancel: CancellationToken) -> AppResult<String>;
+}
+
+/// Scheduler settings
+#[derive(Clone, Debug)]
+pub struct SchedulerOptions {
+    /// Run periodic jobs in `serve`
+    pub enabled: bool,
+    /// Cron expression for `PurgeExpiredTokens`, with a leading seconds field
+    pub token_purge_schedule: String,
+    /// Upper bound of the random delay added to every run
+    pub jitter: Duration,
+    /// How long shutdown waits for running jobs
+    pub shutdown_grace: Duration,
+}
+
+impl Default for SchedulerOptions {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            token_purge_schedule: "0 0 * * * *".to_string(),
+            jitter: Duration::from_secs(30),
+            shutdown_grace: Duration::from_secs(10),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("invalid cron expression {expression:?} for job {job}: {source}")]
+pub struct InvalidSchedule {
+    job: &'static str,
+    expression: String,
+    source: cron::error::Error,
+}
+
+/// Last known state of a scheduled job
+#[derive(Clone, Debug, Serialize, ToSchema, TS)]
+pub struct JobStatus {
+    pub name: String,
+    /// Cron expression, seconds first
+    pub schedule: String,
+    pub running: bool,
+    pub next_run_at: Option<DateTime<Utc>>,
+    pub last_started_at: Option<DateTime<Utc>>,
+    pub last_finished_at: Option<DateTime<Utc>>,
+    /// Whether the last run succeeded; `None` before the first run
+    pub last_succeeded: Option<bool>,
+    /// Summary or error message of the last run
+    pub last_message: Option<String>,
+    #[ts(type = "number")]
+    pub last_duration_ms: Option<u64>,
+    #[ts(type = "number")]
+    pub runs: u64,
+    #[ts(type = "number")]
+    pub failures: u64,
+    /// Ticks skipped because the previous run was still going
+    #[ts(type = "number")]
+    pub skipped: u64,
+}
+
+impl JobStatus {
+    fn new(name: &str, schedule: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            schedule: schedule.to_string(),
+            running: false,
+            next_run_at: None,
+            last_started_at: None,
+            last_finished_at: None,
+            last_succeeded: None,
+            last_message: None,
+            last_duration_ms: None,
+            runs: 0,
+            failures: 0,
+            skipped: 0,
+        }
+    }
+}
+
+/// Status of every registered job, shared between the scheduler and the
+/// admin API
+#[derive(Clone, Default)]
+pub struct JobRegistry {
+    jobs: Arc<Mutex<BTreeMap<&'static str, JobStatus>>>,
+}
+
+impl JobRegistry {
+    /// Current status of every job, ordered by name
+    pub fn snapshot(&self) -> Vec<JobStatus> {
+        self.jobs.lock().unwrap().values().cloned().collect()
+    }
+
+    fn insert(&self, name: &'static str, status: JobStatus) {
+        self.jobs.lock().unwrap().insert(name, status);
+    }
+
+    fn update(&self, name: &str, f: impl FnOnce(&mut JobStatus)) {
+        if let Some(status) = self.jobs.lock().unwrap().get_mut(name) {
+            f(status);
+        }
+    }
+}
+
+struct ScheduledJob {
+    job: Arc<dyn Job>,
+    schedule: Schedule,
+    jitter: Duration,
+}
+
+/// Collects jobs, then starts one task per job
+pub struct Scheduler {
+    registry: JobRegistry,
+    jobs: Vec<ScheduledJob>,
+}
+
+impl Scheduler {
+    /// Report job status to `registry`
+    pub fn new(registry: JobRegistry) -> Self {
+        Self {
+            registry,
+            jobs: Vec::new(),
+        }
+    }
+
+    /// Run `job` whenever `expression` fires, delayed by up to `jitter`
+    ///
+    /// Expressions have six or seven fields, starting with seconds:
+    /// `0 */15 * * * *` runs every fifteen minutes.
+    pub fn job(
+        mut self,
+        job: impl Job,
+        expression: &str,
+        jitter: Duration,
+    ) -> Result<Self, InvalidSchedule> {
+        let schedule = Schedule::from_str(expression).map_err(|source| InvalidSchedule {
+            job: job.name(),
+            expression: expression.to_string(),
+            source,
+        })?;
+        self.registry
+            .insert(job.name(), JobStatus::new(job.name(), expression));
+        self.jobs.push(ScheduledJob {
+            job: Arc::new(job),
+            schedule,
+            jitter,
+        });
+        Ok(self)
+    }
+
+    pub fn start(self) -> SchedulerHandle {
+        let cancel = CancellationToken::new();
+        let tracker = TaskTracker::new();
+
+        for job in self.jobs {
+            tracker.spawn(drive(
+                job,
+                self.registry.clone(),
+                cancel.clone(),
+                tracker.clone(),
+            ));
+        }
+
+        SchedulerHandle { cancel, tracker }
+    }
+}
+
+/// Stops a running scheduler
+pub struct SchedulerHandle {
+    cancel: CancellationToken,
+    tracker: TaskTracker,
+}
+
+impl SchedulerHandle {
+    /// Stop scheduling new runs and wait up to `grace` for running ones
+    ///
+    /// Jobs see their cancellation token fire at once; anything still
+    /// running after `grace` is abandoned when the runtime shuts down.
+    pub async fn shutdown(self, grace: Duration) {
+        self.cancel.cancel();
+        self.tracker.close();
+        if tokio::time::timeout(grace, self.tracker.wait())
+            .await
+            .is_err()
+        {
+            tracing::warn!(
+                "Scheduled jobs still running after {:?}; abandoning them",
+                grace
+            );
+        }
+    }
+}
+
+/// Wait for each tick of one job's schedule and start a run on it
+async fn drive(
+    scheduled: ScheduledJob,
+    registry: JobRegistry,
+    cancel: CancellationToken,
+    tracker: TaskTracker,
+) {
+    let name = scheduled.job.name();
+    let running = Arc::new(AtomicBool::new(false));
+
+    loop {
+        let now = Utc::now();
+        let Some(next) = scheduled.schedule.after(&now).next() else {
+            tracing::info!("Schedule for job {} has no further runs", name);
+            break;
+        };
+        let due = next + jitter(scheduled.jitter);
+        registry.update(name, |status| status.next_run_at = Some(due));
+
+        let delay = (due - now).to_std().unwrap_or_default();
+        tokio::select! {
+            _ = cancel.cancelled() => break,
+            _ = tokio::time::sleep(delay) => {}
+        }
+
+        if running.swap(true, Ordering::AcqRel) {
+            tracing::warn!("Skipping job {}: previous run is still going", name);
+            registry.update(name, |status| status.skipped += 1);
+            continue;
+        }
+
+        tracker.spawn(run_once(
+            scheduled.job.clone(),
+            registry.clone(),
+            cancel.child_token(),
+            running.clone(),
+        ));
+    }
+}
+
+async fn run_once(
+    job: Arc<dyn Job>,
+    registry: JobRegistry,
+    cancel: CancellationToken,
+    running: Arc<AtomicBool>,
+) {
+    let name = job.name();
+    let started = Instant::now();
+    registry.update(name, |status| {
+        status.running = true;
+        status.last_started_at = Some(Utc::now());
+    });
+
+    // A panicking job fails the run instead of leaving it marked as running
+    let run = {
+        let job = job.clone();
+        tokio::spawn(async move { job.run(cancel).await })
+    };
+    let result = match run.await {
+        Ok(result) => result.map_err(|e| e.to_string()),
+        Err(e) => Err(format!("job panicked: {}", e)),
+    };
+
+    let elapsed = started.elapsed();
+    match &result {
+        Ok(summary) => tracing::info!("Job {} finished in {:?}: {}", name, elapsed, summary),
+        Err(e) => tracing::error!("Job {} failed after {:?}: {}", name, elapsed, e),
+    }
+    registry.update(name, |status| {
+        status.running = false;
+        status.last_finished_at = Some(Utc::now());
+        status.last_duration_ms = Some(elapsed.as_millis() as u64);
+        status.runs += 1;
+        match result {
+            Ok(summary) => {
+                status.last_succeeded = Some(true);
+                status.last_message = Some(summary);
+            }
+            Err(e) => {
+                status.failures += 1;
+                status.last_succeeded = Some(false);
+                status.last_message = Some(e);
+            }
+        }
+    });
+    running.store(false, Ordering::Release);
+}
+
+fn jitter(max: Duration) -> chrono::Duration {
+    if max.is_zero() {
+        return chrono::Duration::zero();
+    }
+    chrono::Duration::milliseconds(rand::random_range(0..=max.as_millis() as i64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::AppError;
+    use std::sync::atomic::AtomicU32;
+
+    const EVERY_SECOND: &str = "* * * * * *";
+
+    /// Counts runs, sleeping `duration` in each unless cancelled
+    struct Sleepy {
+        runs: Arc<AtomicU32>,
+        duration: Duration,
+        fail: bool,
+    }
+
+    #[async_trait]
+    impl Job for Sleepy {
+        fn name(&self) -> &'static str {
+            "sleepy"
+        }
+
+        async fn run(&self, cancel: CancellationToken) -> AppResult<String> {
+            self.runs.fetch_add(1, Ordering::SeqCst);
+            tokio::select! {
+                _ = cancel.cancelled() => return Ok("cancelled".to_string()),
+                _ = tokio::time::sleep(self.duration) => {}
+            }
+            if self.fail {
+                Err(AppError::NotFound("Thing"))
+            } else {
+                Ok("done".to_string())
+            }
+        }
+    }
+
+    /// Panics on every run
+    struct Panicky {
+        runs: Arc<AtomicU32>,
+    }
+
+    #[async_trait]
+    impl Job for Panicky {
+        fn name(&self) -> &'static str {
+            "panicky"
+        }
+
+        async fn run(&self, _cancel: CancellationToken) -> AppResult<String> {
+            self.runs.fetch_add(1, Ordering::SeqCst);
+            panic!("boom");
+        }
+    }
+
+    fn sleepy(duration: Duration, fail: bool) -> (Sleepy, Arc<AtomicU32>) {
+        let runs = Arc::new(AtomicU32::new(0));
+        let job = Sleepy {
+            runs: runs.clone(),
+            duration,
+            fail,
+        };
+        (job, runs)
+    }
+
+    #[test]
+    fn test_invalid_expression_is_rejected() {
+        let (job, _) = sleepy(Duration::ZERO, false);
+        let error = Scheduler::new(JobRegistry::default())
+            .job(job, "every hour", Duration::ZERO)
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("sleepy"));
+    }
+
+    #[tokio::test]
+    async fn test_runs_are_recorded() {
+        let registry = JobRegistry::default();
+        let (job, runs) = sleepy(Duration::ZERO, true);
+        let handle = Scheduler::new(registry.clone())
+            .job(job, EVERY_SECOND, Duration::ZERO)
+            .unwrap()
+            .start();
+
+        tokio::time::sleep(Duration::from_millis(2100)).await;
+        handle.shutdown(Duration::from_secs(1)).await;
+
+        let status = &registry.snapshot()[0];
+        assert!(runs.load(Ordering::SeqCst) >= 1);
+        assert_eq!(status.runs, u64::from(runs.load(Ordering::SeqCst)));
+        assert_eq!(status.failures, status.runs);
+        assert_eq!(status.last_succeeded, Some(false));
+        assert_eq!(status.last_message.as_deref(), Some("Thing not found"));
+    }
+
+    #[tokio::test]
+    async fn test_overlapping_ticks_are_skipped_and_shutdown_cancels_runs() {
+        let registry = JobRegistry::default();
+        let (job, runs) = sleepy(Duration::from_secs(60), false);
+        let handle = Scheduler::new(registry.clone())
+            .job(job, EVERY_SECOND, Duration::ZERO)
+            .unwrap()
+            .start();
+
+        tokio::time::sleep(Duration::from_millis(3100)).await;
+        let before = registry.snapshot()[0].clone();
+        assert!(before.running);
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+        assert!(before.skipped >= 1);
+
+        let started = Instant::now();
+        handle.shutdown(Duration::from_secs(5)).await;
+        assert!(started.elapsed() < Duration::from_secs(1));
+
+        let after = &registry.snapshot()[0];
+        assert!(!after.running);
+        assert_eq!(after.last_message.as_deref(), Some("cancelled"));
+    }
+
+    #[tokio::test]
+    async fn test_panicking_job_fails_the_run_and_runs_again() {
+        let registry = JobRegistry::default();
+        let runs = Arc::new(AtomicU32::new(0));
+        let handle = Scheduler::new(registry.clone())
+            .job(Panicky { runs: runs.clone() }, EVERY_SECOND, Duration::ZERO)
+            .unwrap()
+            .start();
+
+        tokio::time::sleep(Duration::from_millis(2100)).await;
+        handle.shutdown(Duration::from_secs(1)).await;
+
+        let status = &registry.snapshot()[0];
+        assert!(runs.load(Ordering::SeqCst) >= 2);
+        assert!(!status.running);
+        assert_eq!(status.skipped, 0);
+        assert_eq!(status.failures, status.runs);
+        assert_eq!(status.last_succeeded, Some(false));
+        assert!(status.last_message.as_deref().unwrap().contains("boom"));
+    }
+
+    #[test]
+    fn test_jitter_stays_within_bound() {
+        assert_eq!(jitter(Duration::ZERO), chrono::Duration::zero());
+        for _ in 0..100 {
+            let delay = jitter(Duration::from_millis(50));
+            assert!(delay >= chrono::Duration::zero());
+            assert!(delay <= chrono::Duration::milliseconds(50));
+        }
+    }
+}
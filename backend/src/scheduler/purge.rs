@@ -0,0 +1,42 @@
+use async_trait::async_trait;
+use tokio_util::sync::CancellationToken;
+
+use super::Job;
+use crate::{clock::SharedClock, db::Db, error::AppResult, repositories::SharedSessionRepository};
+
+/// Deletes refresh tokens that have expired
+///
+/// `refresh` only removes an expired token when it is presented, so tokens
+/// abandoned by their clients would otherwise stay forever.
+pub struct PurgeExpiredTokens {
+    db: Db,
+    sessions: SharedSessionRepository,
+    clock: SharedClock,
+}
+
+impl PurgeExpiredTokens {
+    pub fn new(db: Db, sessions: SharedSessionRepository, clock: SharedClock) -> Self {
+        Self {
+            db,
+            sessions,
+            clock,
+        }
+    }
+}
+
+#[async_trait]
+impl Job for PurgeExpiredTokens {
+    fn name(&self) -> &'static str {
+        "purge_expired_tokens"
+    }
+
+    async fn run(&self, _cancel: CancellationToken) -> AppResult<String> {
+        let now = self.clock.now().naive_utc();
+        let sessions = self.sessions.clone();
+        let purged = self
+            .db
+            .write(move |conn| sessions.delete_expired(conn, now))
+            .await?;
+        Ok(format!("purged {} expired refresh tokens", purged))
+    }
+}
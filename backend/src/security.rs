@@ -0,0 +1,148 @@
+//! Browser-facing policy: CORS and the security headers added to every
+//! response
+
+use std::sync::Arc;
+
+use axum::{
+    extract::State,
+    http::{
+        header::{
+            HeaderName, HeaderValue, ACCEPT, AUTHORIZATION, CONTENT_SECURITY_POLICY, CONTENT_TYPE,
+            ETAG, IF_MATCH, IF_NONE_MATCH, REFERRER_POLICY, RETRY_AFTER, STRICT_TRANSPORT_SECURITY,
+            X_CONTENT_TYPE_OPTIONS,
+        },
+        Method,
+    },
+    response::Response,
+};
+use tower_http::cors::{AllowHeaders, AllowOrigin, CorsLayer};
+
+use crate::{
+    auth::REFRESH_TOKEN_TRANSPORT_HEADER,
+    config::Profile,
+    rate_limit::{RATELIMIT_LIMIT, RATELIMIT_POLICY, RATELIMIT_REMAINING, RATELIMIT_RESET},
+};
+
+const PERMISSIONS_POLICY: HeaderName = HeaderName::from_static("permissions-policy");
+static NOSNIFF: HeaderValue = HeaderValue::from_static("nosniff");
+
+/// Which cross-origin requests browsers may make with credentials
+#[derive(Clone, Debug)]
+pub struct CorsOptions {
+    /// Exact origins such as `https://app.example.com`; `*` is not allowed
+    /// because requests carry cookies
+    pub allowed_origins: Vec<HeaderValue>,
+    pub allowed_methods: Vec<Method>,
+    pub allowed_headers: Vec<HeaderName>,
+}
+
+impl Default for CorsOptions {
+    fn default() -> Self {
+        Self {
+            // Frontend dev server
+            allowed_origins: vec![HeaderValue::from_static("http://localhost:5173")],
+            allowed_methods: vec![
+                Method::GET,
+                Method::POST,
+                Method::PUT,
+                Method::PATCH,
+                Method::DELETE,
+            ],
+            allowed_headers: vec![
+                ACCEPT,
+                CONTENT_TYPE,
+                AUTHORIZATION,
+                IF_MATCH,
+                IF_NONE_MATCH,
+                HeaderName::from_static("x-csrf-token"),
+                REFRESH_TOKEN_TRANSPORT_HEADER,
+            ],
+        }
+    }
+}
+
+impl CorsOptions {
+    pub fn layer(&self) -> CorsLayer {
+        CorsLayer::new()
+            .allow_origin(AllowOrigin::list(self.allowed_origins.iter().cloned()))
+            .allow_methods(self.allowed_methods.clone())
+            .allow_headers(AllowHeaders::list(self.allowed_headers.iter().cloned()))
+            .expose_headers([
+                ETAG,
+                RATELIMIT_LIMIT,
+                RATELIMIT_REMAINING,
+                RATELIMIT_RESET,
+                RATELIMIT_POLICY,
+                RETRY_AFTER,
+            ])
+            .allow_credentials(true)
+    }
+}
+
+/// Headers added to every response that does not set its own
+///
+/// `None` leaves a header out.
+#[derive(Clone, Debug)]
+pub struct SecurityHeaders {
+    /// `Strict-Transport-Security`; only sent in `prod` by default, since
+    /// browsers would keep insisting on HTTPS for `localhost`
+    pub hsts: Option<HeaderValue>,
+    pub content_security_policy: Option<HeaderValue>,
+    pub referrer_policy: Option<HeaderValue>,
+    pub permissions_policy: Option<HeaderValue>,
+    /// `X-Content-Type-Options: nosniff`
+    pub nosniff: bool,
+}
+
+impl Default for SecurityHeaders {
+    fn default() -> Self {
+        Self::defaults(Profile::Dev)
+    }
+}
+
+impl SecurityHeaders {
+    pub fn defaults(profile: Profile) -> Self {
+        Self {
+            hsts: (profile == Profile::Prod)
+                .then(|| HeaderValue::from_static("max-age=31536000; includeSubDomains")),
+            // The API only returns data; `/api/docs` sets its own policy
+            content_security_policy: Some(HeaderValue::from_static(
+                "default-src 'none'; frame-ancestors 'none'",
+            )),
+            referrer_policy: Some(HeaderValue::from_static("no-referrer")),
+            permissions_policy: Some(HeaderValue::from_static(
+                "camera=(), geolocation=(), microphone=(), payment=()",
+            )),
+            nosniff: true,
+        }
+    }
+
+    fn headers(&self) -> impl Iterator<Item = (HeaderName, &HeaderValue)> {
+        [
+            (STRICT_TRANSPORT_SECURITY, self.hsts.as_ref()),
+            (
+                CONTENT_SECURITY_POLICY,
+                self.content_security_policy.as_ref(),
+            ),
+            (REFERRER_POLICY, self.referrer_policy.as_ref()),
+            (PERMISSIONS_POLICY, self.permissions_policy.as_ref()),
+            (X_CONTENT_TYPE_OPTIONS, self.nosniff.then_some(&NOSNIFF)),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| Some((name, value?)))
+    }
+}
+
+/// Response middleware for `axum::middleware::map_response_with_state`
+pub async fn add_security_headers(
+    State(headers): State<Arc<SecurityHeaders>>,
+    mut response: Response,
+) -> Response {
+    for (name, value) in headers.headers() {
+        response
+            .headers_mut()
+            .entry(name)
+            .or_insert_with(|| value.clone());
+    }
+    response
+}
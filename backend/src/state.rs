@@ -0,0 +1,205 @@
+use std::sync::Arc;
+
+use axum::extract::FromRef;
+
+use crate::{
+    auth::{CsrfTokens, JwtKeys, SessionIssuer},
+    clock::{SharedClock, SystemClock},
+    config::Config,
+    db::Db,
+    events::{self, EventBus},
+    mail::{Outbox, SharedMailer},
+    queue::Queue,
+    rate_limit::{RateLimiter, SharedRateLimitStore},
+    repositories::{
+        DieselSessionRepository, DieselUserRepository, SharedSessionRepository,
+        SharedUserRepository,
+    },
+    scheduler::JobRegistry,
+};
+
+/// State shared by every handler
+///
+/// Handlers extract only the parts they need, e.g. `State<Db>` or
+/// `State<SharedClock>`, through the `FromRef` impls below.
+#[derive(Clone)]
+pub struct AppState {
+    /// Async reads and writes, for handlers
+    pub db: Db,
+    pub config: Arc<Config>,
+    pub clock: SharedClock,
+    pub keys: Arc<JwtKeys>,
+    /// Signs the CSRF tokens of cookie-authenticated requests
+    pub csrf: Arc<CsrfTokens>,
+    pub users: SharedUserRepository,
+    pub sessions: SharedSessionRepository,
+    /// Status of the scheduled jobs, empty unless the scheduler runs
+    pub jobs: JobRegistry,
+    /// Background jobs, run by the workers `serve` starts
+    pub queue: Queue,
+    /// Renders and queues outgoing email
+    pub outbox: Outbox,
+    /// Delivers queued email; only read directly by `/dev/mail`
+    pub mailer: SharedMailer,
+    /// Subscribers to the events handlers publish
+    pub events: EventBus,
+    /// Request counts behind the rate limits
+    pub rate_limits: SharedRateLimitStore,
+}
+
+impl AppState {
+    pub fn new(config: Config, db: Db) -> Self {
+        let keys = JwtKeys::from_secret(config.jwt_secret.as_bytes());
+        let csrf = CsrfTokens::from_secret(config.jwt_secret.as_bytes());
+        let clock: SharedClock = Arc::new(SystemClock);
+        let queue = Queue::new(db.clone(), clock.clone());
+        let mailer = config.mail.mailer().expect("Invalid mail transport");
+        let outbox = Outbox::new(queue.clone(), config.mail.from.clone());
+        let rate_limits = config.rate_limit.store().expect("Invalid rate limit store");
+        Self {
+            users: Arc::new(DieselUserRepository),
+            sessions: Arc::new(DieselSessionRepository),
+            events: events::defaults(&outbox).build(),
+            outbox,
+            queue,
+            mailer,
+            db,
+            config: Arc::new(config),
+            clock,
+            keys: Arc::new(keys),
+            csrf: Arc::new(csrf),
+            jobs: JobRegistry::default(),
+            rate_limits,
+        }
+    }
+
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        let queue = Queue::new(self.db.clone(), clock.clone());
+        self.clock = clock;
+        self.with_queue(queue)
+    }
+
+    pub fn with_repositories(
+        mut self,
+        users: SharedUserRepository,
+        sessions: SharedSessionRepository,
+    ) -> Self {
+        self.users = users;
+        self.sessions = sessions;
+        self
+    }
+
+    pub fn with_jobs(mut self, jobs: JobRegistry) -> Self {
+        self.jobs = jobs;
+        self
+    }
+
+    /// Share a queue with workers started outside the app
+    pub fn with_queue(mut self, queue: Queue) -> Self {
+        self.outbox = Outbox::new(queue.clone(), self.config.mail.from.clone());
+        self.queue = queue;
+        self
+    }
+
+    /// Share a mailer with workers started outside the app
+    pub fn with_mailer(mut self, mailer: SharedMailer) -> Self {
+        self.mailer = mailer;
+        self
+    }
+
+    /// Replace the default subscribers, e.g. with ones the workers share
+    pub fn with_events(mut self, events: EventBus) -> Self {
+        self.events = events;
+        self
+    }
+}
+
+impl FromRef<AppState> for Db {
+    fn from_ref(state: &AppState) -> Self {
+        state.db.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<Config> {
+    fn from_ref(state: &AppState) -> Self {
+        state.config.clone()
+    }
+}
+
+impl FromRef<AppState> for SharedClock {
+    fn from_ref(state: &AppState) -> Self {
+        state.clock.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<JwtKeys> {
+    fn from_ref(state: &AppState) -> Self {
+        state.keys.clone()
+    }
+}
+
+impl FromRef<AppState> for SessionIssuer {
+    fn from_ref(state: &AppState) -> Self {
+        SessionIssuer::new(
+            state.db.clone(),
+            state.config.clone(),
+            state.keys.clone(),
+            state.csrf.clone(),
+            state.clock.clone(),
+            state.sessions.clone(),
+        )
+    }
+}
+
+impl FromRef<AppState> for RateLimiter {
+    fn from_ref(state: &AppState) -> Self {
+        RateLimiter::new(
+            state.rate_limits.clone(),
+            state.clock.clone(),
+            state.keys.clone(),
+            state.config.clone(),
+        )
+    }
+}
+
+impl FromRef<AppState> for Arc<CsrfTokens> {
+    fn from_ref(state: &AppState) -> Self {
+        state.csrf.clone()
+    }
+}
+
+impl FromRef<AppState> for SharedUserRepository {
+    fn from_ref(state: &AppState) -> Self {
+        state.users.clone()
+    }
+}
+
+impl FromRef<AppState> for SharedSessionRepository {
+    fn from_ref(state: &AppState) -> Self {
+        state.sessions.clone()
+    }
+}
+
+impl FromRef<AppState> for JobRegistry {
+    fn from_ref(state: &AppState) -> Self {
+        state.jobs.clone()
+    }
+}
+
+impl FromRef<AppState> for Queue {
+    fn from_ref(state: &AppState) -> Self {
+        state.queue.clone()
+    }
+}
+
+impl FromRef<AppState> for Outbox {
+    fn from_ref(state: &AppState) -> Self {
+        state.outbox.clone()
+    }
+}
+
+impl FromRef<AppState> for SharedMailer {
+    fn from_ref(state: &AppState) -> Self {
+        state.mailer.clone()
+    }
+}
@@ -0,0 +1,175 @@
+use std::{error::Error as _, time::Duration};
+
+use diesel::prelude::*;
+use reqwest::{header::CONTENT_TYPE, redirect};
+
+use super::{signature, DeliverWebhook};
+use crate::{
+    clock::SharedClock,
+    db::{
+        schema::{webhook_attempts, webhook_deliveries, webhook_endpoints},
+        Db,
+    },
+    error::AppResult,
+    models::{DeliveryStatus, NewWebhookAttempt, WebhookDelivery, WebhookEndpoint},
+    queue::JobError,
+};
+
+/// Endpoints must answer within this
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Characters of each response body kept in the delivery log
+const RESPONSE_BODY_LIMIT: usize = 1024;
+
+/// Posts deliveries to their endpoints and records the outcome
+#[derive(Clone)]
+pub struct Deliverer {
+    db: Db,
+    clock: SharedClock,
+    client: reqwest::Client,
+}
+
+impl Deliverer {
+    pub fn new(db: Db, clock: SharedClock) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            // A redirect could forward the signed payload somewhere unregistered
+            .redirect(redirect::Policy::none())
+            .user_agent(concat!("webapp-webhooks/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .expect("Failed to build the webhook HTTP client");
+        Self { db, clock, client }
+    }
+
+    /// Make one attempt at a delivery
+    ///
+    /// Anything but a 2xx response is retried by the queue. Deliveries whose
+    /// endpoint has since been removed are dropped.
+    pub async fn deliver(&self, job: DeliverWebhook) -> Result<(), JobError> {
+        let delivery_id = job.delivery_id;
+        let Some((delivery, endpoint)) = self
+            .db
+            .read(move |conn| -> AppResult<_> {
+                Ok(webhook_deliveries::table
+                    .inner_join(webhook_endpoints::table)
+                    .filter(webhook_deliveries::id.eq(delivery_id))
+                    .select((WebhookDelivery::as_select(), WebhookEndpoint::as_select()))
+                    .first::<(WebhookDelivery, WebhookEndpoint)>(conn)
+                    .optional()?)
+            })
+            .await?
+        else {
+            return Ok(());
+        };
+        if !endpoint.active {
+            return Err(JobError::Fatal(format!(
+                "Endpoint {} is inactive",
+                endpoint.id
+            )));
+        }
+
+        let attempted_at = self.clock.now();
+        let started = std::time::Instant::now();
+        let result = self
+            .client
+            .post(&endpoint.url)
+            .header(CONTENT_TYPE, "application/json")
+            .header("Webhook-Id", &delivery.event_id)
+            .header("Webhook-Event", &delivery.event_type)
+            .header("Webhook-Delivery", delivery.id.to_string())
+            .header(
+                signature::HEADER,
+                signature::sign(
+                    &endpoint.secret,
+                    attempted_at.timestamp(),
+                    &delivery.payload,
+                ),
+            )
+            .body(delivery.payload)
+            .send()
+            .await;
+        let outcome = match result {
+            Ok(response) => {
+                let status = response.status();
+                let body = response.text().await.unwrap_or_default();
+                Attempt {
+                    succeeded: status.is_success(),
+                    response_status: Some(i32::from(status.as_u16())),
+                    response_body: Some(body.chars().take(RESPONSE_BODY_LIMIT).collect()),
+                    error: None,
+                }
+            }
+            Err(e) => Attempt {
+                succeeded: false,
+                response_status: None,
+                response_body: None,
+                error: Some(describe(&e)),
+            },
+        };
+        let duration_ms = i32::try_from(started.elapsed().as_millis()).unwrap_or(i32::MAX);
+
+        let failure = match (&outcome.response_status, &outcome.error) {
+            _ if outcome.succeeded => None,
+            (Some(status), _) => Some(format!("Endpoint responded with {}", status)),
+            (None, error) => error.clone(),
+        };
+        let attempted_at = attempted_at.naive_utc();
+        self.db
+            .write(move |conn| {
+                conn.transaction(|conn| {
+                    diesel::insert_into(webhook_attempts::table)
+                        .values(NewWebhookAttempt {
+                            delivery_id,
+                            attempted_at,
+                            succeeded: outcome.succeeded,
+                            response_status: outcome.response_status,
+                            response_body: outcome.response_body,
+                            error: outcome.error,
+                            duration_ms,
+                        })
+                        .execute(conn)?;
+                    let status = if outcome.succeeded {
+                        DeliveryStatus::Succeeded
+                    } else {
+                        DeliveryStatus::Failed
+                    };
+                    diesel::update(webhook_deliveries::table.find(delivery_id))
+                        .set((
+                            webhook_deliveries::status.eq(status.as_str()),
+                            webhook_deliveries::attempts.eq(webhook_deliveries::attempts + 1),
+                            webhook_deliveries::last_attempt_at.eq(Some(attempted_at)),
+                        ))
+                        .execute(conn)?;
+                    Ok(())
+                })
+            })
+            .await?;
+
+        match failure {
+            None => Ok(()),
+            Some(reason) => Err(JobError::Retry(reason)),
+        }
+    }
+}
+
+struct Attempt {
+    succeeded: bool,
+    response_status: Option<i32>,
+    response_body: Option<String>,
+    error: Option<String>,
+}
+
+/// The error and its causes; reqwest's own message rarely says what failed
+fn describe(error: &reqwest::Error) -> String {
+    let mut message = if error.is_timeout() {
+        format!("Timed out after {}s", REQUEST_TIMEOUT.as_secs())
+    } else {
+        error.to_string()
+    };
+    let mut source = error.source();
+    while let Some(cause) = source {
+        message.push_str(": ");
+        message.push_str(&cause.to_string());
+        source = cause.source();
+    }
+    message
+}
@@ -0,0 +1,192 @@
+//! Outbound webhooks
+//!
+//! Admins register endpoints for events from the [`WebhookEvent`] catalogue.
+//! [`publish`] records one delivery per subscribed endpoint and queues a
+//! [`DeliverWebhook`] job for each, in the caller's transaction. The
+//! [`Deliverer`] posts the signed event, logs every attempt and leaves
+//! retries with backoff to the job queue.
+
+mod delivery;
+pub mod signature;
+
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+use utoipa::ToSchema;
+
+use crate::{
+    db::{
+        schema::{webhook_deliveries, webhook_endpoints},
+        DbConnection,
+    },
+    error::{AppError, AppResult},
+    models::{DeliveryStatus, NewWebhookDelivery, WebhookDelivery, WebhookEndpoint},
+    queue::{self, JobPayload},
+};
+
+pub use delivery::Deliverer;
+
+/// Events endpoints can subscribe to, named after the `api::auth` and admin
+/// actions that raise them
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WebhookEvent {
+    UserRegistered,
+    UserEmailChanged,
+    UserDeleted,
+    UserRestored,
+}
+
+impl WebhookEvent {
+    pub const ALL: [WebhookEvent; 4] = [
+        WebhookEvent::UserRegistered,
+        WebhookEvent::UserEmailChanged,
+        WebhookEvent::UserDeleted,
+        WebhookEvent::UserRestored,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            WebhookEvent::UserRegistered => "user.registered",
+            WebhookEvent::UserEmailChanged => "user.email_changed",
+            WebhookEvent::UserDeleted => "user.deleted",
+            WebhookEvent::UserRestored => "user.restored",
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            WebhookEvent::UserRegistered => "A user signed up",
+            WebhookEvent::UserEmailChanged => {
+                "A user changed their email address; includes `previous_email`"
+            }
+            WebhookEvent::UserDeleted => "An administrator deleted a user, disabling the account",
+            WebhookEvent::UserRestored => "An administrator restored a deleted user",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|event| event.as_str() == name)
+    }
+}
+
+/// Catalogue entry for `GET /api/admin/webhooks/events`
+#[derive(Debug, Serialize, ToSchema, TS)]
+pub struct WebhookEventInfo {
+    pub name: &'static str,
+    pub description: &'static str,
+}
+
+impl From<WebhookEvent> for WebhookEventInfo {
+    fn from(event: WebhookEvent) -> Self {
+        Self {
+            name: event.as_str(),
+            description: event.description(),
+        }
+    }
+}
+
+/// Body posted to endpoints
+#[derive(Debug, Serialize)]
+struct Envelope<'a> {
+    /// Same for every endpoint and every redelivery of this event
+    id: &'a str,
+    #[serde(rename = "type")]
+    event_type: &'static str,
+    created_at: DateTime<Utc>,
+    data: serde_json::Value,
+}
+
+/// Queue `event` for every active endpoint subscribed to it
+///
+/// Runs on the caller's connection, so inside a transaction nothing is sent
+/// unless it commits. Returns the number of deliveries queued.
+pub fn publish(
+    conn: &mut DbConnection,
+    event: WebhookEvent,
+    data: serde_json::Value,
+    now: DateTime<Utc>,
+) -> AppResult<usize> {
+    let endpoints: Vec<WebhookEndpoint> = webhook_endpoints::table
+        .filter(webhook_endpoints::active.eq(true))
+        .select(WebhookEndpoint::as_select())
+        .load(conn)?;
+    let subscribed: Vec<_> = endpoints
+        .into_iter()
+        .filter(|endpoint| endpoint.subscribes_to(event))
+        .collect();
+    if subscribed.is_empty() {
+        return Ok(0);
+    }
+
+    let event_id = uuid::Uuid::new_v4().to_string();
+    let payload = serde_json::to_string(&Envelope {
+        id: &event_id,
+        event_type: event.as_str(),
+        created_at: now,
+        data,
+    })
+    .map_err(|e| diesel::result::Error::SerializationError(Box::new(e)))?;
+
+    for endpoint in &subscribed {
+        let delivery_id: i32 = diesel::insert_into(webhook_deliveries::table)
+            .values(NewWebhookDelivery {
+                endpoint_id: endpoint.id,
+                event_id: &event_id,
+                event_type: event.as_str(),
+                payload: &payload,
+            })
+            .returning(webhook_deliveries::id)
+            .get_result(conn)?;
+        queue::enqueue(conn, &DeliverWebhook { delivery_id }, now)?;
+    }
+
+    Ok(subscribed.len())
+}
+
+/// Send a delivery again with the original event id and payload
+///
+/// Returns `None` if there is no such delivery; fails with a conflict while
+/// the delivery is still queued.
+pub fn redeliver(
+    conn: &mut DbConnection,
+    delivery_id: i32,
+    now: DateTime<Utc>,
+) -> AppResult<Option<WebhookDelivery>> {
+    conn.transaction(|conn| {
+        let Some(delivery) = diesel::update(webhook_deliveries::table.find(delivery_id))
+            .set(webhook_deliveries::status.eq(DeliveryStatus::Pending.as_str()))
+            .returning(WebhookDelivery::as_returning())
+            .get_result(conn)
+            .optional()?
+        else {
+            return Ok(None);
+        };
+
+        match queue::enqueue(conn, &DeliverWebhook { delivery_id }, now)? {
+            Some(_) => Ok(Some(delivery)),
+            None => Err(AppError::Conflict("This delivery is already queued")),
+        }
+    })
+}
+
+/// A random signing secret for a new endpoint
+pub fn generate_secret() -> String {
+    format!("whsec_{}", hex::encode(rand::random::<[u8; 32]>()))
+}
+
+/// Queued delivery; run by [`Deliverer::deliver`]
+#[derive(Serialize, Deserialize)]
+pub struct DeliverWebhook {
+    pub delivery_id: i32,
+}
+
+impl JobPayload for DeliverWebhook {
+    const KIND: &'static str = "deliver_webhook";
+    // With the default backoff this keeps trying for about 20 minutes
+    const MAX_ATTEMPTS: i32 = 8;
+
+    fn unique_key(&self) -> Option<String> {
+        Some(format!("webhook_delivery:{}", self.delivery_id))
+    }
+}
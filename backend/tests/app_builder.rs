@@ -0,0 +1,93 @@
+mod common;
+
+use axum::{
+    extract::State,
+    http::{HeaderValue, StatusCode},
+    middleware::map_response,
+    response::Response,
+    routing::get,
+    Router,
+};
+use diesel::prelude::*;
+use webapp_backend::{
+    db::{schema::users, Db},
+    App, AppState,
+};
+
+use common::{get as get_request, post_json, send, test_db};
+
+async fn user_count(State(db): State<Db>) -> String {
+    let count: i64 = db
+        .read(|conn| Ok(users::table.count().get_result(conn)?))
+        .await
+        .unwrap();
+    count.to_string()
+}
+
+async fn tag_response(mut response: Response) -> Response {
+    response
+        .headers_mut()
+        .insert("x-app", HeaderValue::from_static("template"));
+    response
+}
+
+#[tokio::test]
+async fn test_builtin_routes_are_served() {
+    let db = test_db();
+    let app = App::builder()
+        .config(db.config.clone())
+        .pool(db.pool.clone())
+        .build();
+
+    let (status, body) = send(&app, get_request("/health")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "OK");
+}
+
+#[tokio::test]
+async fn test_extra_router_shares_the_pool() {
+    let db = test_db();
+    let feature: Router<AppState> = Router::new().route("/api/users/count", get(user_count));
+    let app = App::builder()
+        .config(db.config.clone())
+        .pool(db.pool.clone())
+        .router(feature)
+        .build();
+
+    let (status, _) = send(
+        &app,
+        post_json(
+            "/api/auth/register",
+            serde_json::json!({
+                "username": "builder",
+                "email": "builder@example.com",
+                "password": "password123"
+            }),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, body) = send(&app, get_request("/api/users/count")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "1");
+}
+
+#[tokio::test]
+async fn test_layers_wrap_builtin_and_extra_routes() {
+    let db = test_db();
+    let feature: Router<AppState> = Router::new().route("/api/ping", get(|| async { "pong" }));
+    let app = App::builder()
+        .config(db.config.clone())
+        .pool(db.pool.clone())
+        .router(feature)
+        .layer(map_response(tag_response))
+        .build();
+
+    for uri in ["/health", "/api/ping"] {
+        let response = tower::ServiceExt::oneshot(app.clone(), get_request(uri))
+            .await
+            .unwrap();
+        assert_eq!(response.headers()["x-app"], "template", "{}", uri);
+    }
+}
@@ -0,0 +1,160 @@
+#![allow(dead_code)]
+
+pub mod smtp;
+
+use axum::{
+    body::{to_bytes, Body},
+    http::{Request, StatusCode},
+    Router,
+};
+use tower::ServiceExt;
+use webapp_backend::{
+    auth::{CookieOptions, CsrfOptions, RefreshTokenTransport},
+    config::Profile,
+    db::{self, DbOptions},
+    mail::MailOptions,
+    queue::QueueOptions,
+    rate_limit::RateLimitOptions,
+    scheduler::SchedulerOptions,
+    security::{CorsOptions, SecurityHeaders},
+    Config, DbPool,
+};
+
+/// A migrated, empty database that is removed again on drop
+///
+/// SQLite builds use a file in a temporary directory. Postgres builds create a
+/// fresh database on the server at `TEST_DATABASE_URL`.
+pub struct TestDb {
+    pub pool: DbPool,
+    pub config: Config,
+    _cleanup: Cleanup,
+}
+
+pub fn test_db() -> TestDb {
+    let (database_url, cleanup) = create_database();
+
+    // Small pools keep parallel tests under the Postgres connection limit
+    let options = DbOptions {
+        max_connections: 4,
+        ..DbOptions::default()
+    };
+    let pool = db::establish_connection_pool(&database_url, &options);
+    db::migrations::run_pending(&mut pool.get().expect("Failed to get connection"))
+        .expect("Failed to run migrations");
+
+    let config = Config {
+        profile: Profile::Test,
+        host: "127.0.0.1".to_string(),
+        port: 0,
+        database_url,
+        db: options,
+        api_docs: false,
+        dev_endpoints: false,
+        run_migrations: false,
+        jwt_secret: "test-secret".to_string(),
+        refresh_token_transport: RefreshTokenTransport::Body,
+        scheduler: SchedulerOptions::default(),
+        queue: QueueOptions::default(),
+        mail: MailOptions::default(),
+        cors: CorsOptions::default(),
+        cookie: CookieOptions::defaults(Profile::Test),
+        csrf: CsrfOptions::default(),
+        rate_limit: RateLimitOptions::default(),
+        security_headers: SecurityHeaders::defaults(Profile::Test),
+    };
+
+    TestDb {
+        pool,
+        config,
+        _cleanup: cleanup,
+    }
+}
+
+#[cfg(feature = "sqlite")]
+type Cleanup = tempfile::TempDir;
+
+#[cfg(feature = "sqlite")]
+fn create_database() -> (String, Cleanup) {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let database_url = dir.path().join("test.db").to_string_lossy().into_owned();
+    (database_url, dir)
+}
+
+#[cfg(feature = "postgres")]
+pub struct Cleanup {
+    admin_url: String,
+    name: String,
+}
+
+#[cfg(feature = "postgres")]
+impl Drop for Cleanup {
+    fn drop(&mut self) {
+        use diesel::{Connection, RunQueryDsl};
+
+        if let Ok(mut conn) = db::DbConnection::establish(&self.admin_url) {
+            let _ = diesel::sql_query(format!(
+                "DROP DATABASE IF EXISTS {} WITH (FORCE)",
+                self.name
+            ))
+            .execute(&mut conn);
+        }
+    }
+}
+
+#[cfg(feature = "postgres")]
+fn create_database() -> (String, Cleanup) {
+    use diesel::{Connection, RunQueryDsl};
+
+    let admin_url = std::env::var("TEST_DATABASE_URL")
+        .unwrap_or_else(|_| "postgres://postgres@localhost/postgres".to_string());
+    let name = format!("webapp_test_{}", uuid::Uuid::new_v4().simple());
+
+    let mut conn = db::DbConnection::establish(&admin_url)
+        .unwrap_or_else(|e| panic!("Failed to connect to {}: {}", admin_url, e));
+    diesel::sql_query(format!("CREATE DATABASE {}", name))
+        .execute(&mut conn)
+        .expect("Failed to create test database");
+
+    let (server, _) = admin_url
+        .rsplit_once('/')
+        .expect("TEST_DATABASE_URL must include a database name");
+    let database_url = format!("{}/{}", server, name);
+    (database_url, Cleanup { admin_url, name })
+}
+
+/// Send a request and return the status and body as text
+pub async fn send(app: &Router, request: Request<Body>) -> (StatusCode, String) {
+    let response = app.clone().oneshot(request).await.expect("Request failed");
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX)
+        .await
+        .expect("Failed to read body");
+    (status, String::from_utf8_lossy(&body).into_owned())
+}
+
+pub fn get(uri: &str) -> Request<Body> {
+    Request::get(uri).body(Body::empty()).unwrap()
+}
+
+pub fn post_json(uri: &str, body: serde_json::Value) -> Request<Body> {
+    Request::post(uri)
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+/// Extract a string field from a JSON response body
+pub fn json_field(body: &str, field: &str) -> String {
+    let value: serde_json::Value = serde_json::from_str(body).expect("Response is not JSON");
+    value[field]
+        .as_str()
+        .unwrap_or_else(|| panic!("Response has no string field {}: {}", field, body))
+        .to_string()
+}
+
+pub fn get_with_bearer(uri: &str, token: &str) -> Request<Body> {
+    Request::get(uri)
+        .header("authorization", format!("Bearer {}", token))
+        .body(Body::empty())
+        .unwrap()
+}
@@ -0,0 +1,124 @@
+//! A minimal SMTP server that records what it receives
+
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+};
+
+/// One accepted message
+#[derive(Clone, Debug)]
+pub struct Received {
+    pub from: String,
+    pub to: Vec<String>,
+    /// Headers and body, as sent after `DATA`
+    pub data: String,
+}
+
+/// Accepts every message unless told to reject recipients
+#[derive(Clone)]
+pub struct SmtpServer {
+    pub addr: SocketAddr,
+    received: Arc<Mutex<Vec<Received>>>,
+    rcpt_reply: Arc<Mutex<String>>,
+}
+
+impl SmtpServer {
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = Self {
+            addr: listener.local_addr().unwrap(),
+            received: Arc::default(),
+            rcpt_reply: Arc::new(Mutex::new("250 OK".to_string())),
+        };
+
+        let accepting = server.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(accepting.clone().session(stream));
+            }
+        });
+        server
+    }
+
+    /// `smtp://` URL for `SmtpMailer::from_url`
+    pub fn url(&self) -> String {
+        format!("smtp://{}", self.addr)
+    }
+
+    pub fn received(&self) -> Vec<Received> {
+        self.received.lock().unwrap().clone()
+    }
+
+    /// Answer `RCPT TO` with `reply`, e.g. `550 No such user`
+    pub fn reply_to_rcpt(&self, reply: &str) {
+        *self.rcpt_reply.lock().unwrap() = reply.to_string();
+    }
+
+    async fn session(self, stream: TcpStream) {
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        let mut from = String::new();
+        let mut to = Vec::new();
+
+        let _ = writer.write_all(b"220 localhost ESMTP stand-in\r\n").await;
+        while let Ok(Some(line)) = lines.next_line().await {
+            let command = line.to_ascii_uppercase();
+            let reply = if command.starts_with("EHLO") || command.starts_with("HELO") {
+                "250 localhost".to_string()
+            } else if command.starts_with("MAIL FROM:") {
+                from = address(&line);
+                to.clear();
+                "250 OK".to_string()
+            } else if command.starts_with("RCPT TO:") {
+                let reply = self.rcpt_reply.lock().unwrap().clone();
+                if reply.starts_with('2') {
+                    to.push(address(&line));
+                }
+                reply
+            } else if command == "DATA" {
+                let _ = writer
+                    .write_all(b"354 End data with <CR><LF>.<CR><LF>\r\n")
+                    .await;
+                let mut data = String::new();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    if line == "." {
+                        break;
+                    }
+                    data.push_str(line.strip_prefix('.').unwrap_or(&line));
+                    data.push('\n');
+                }
+                self.received.lock().unwrap().push(Received {
+                    from: from.clone(),
+                    to: to.clone(),
+                    data,
+                });
+                "250 Queued".to_string()
+            } else if command == "QUIT" {
+                let _ = writer.write_all(b"221 Bye\r\n").await;
+                return;
+            } else {
+                "250 OK".to_string()
+            };
+            if writer
+                .write_all(format!("{}\r\n", reply).as_bytes())
+                .await
+                .is_err()
+            {
+                return;
+            }
+        }
+    }
+}
+
+/// The address in `MAIL FROM:<a@b>` or `RCPT TO:<a@b>`
+fn address(line: &str) -> String {
+    line.split(['<', '>'])
+        .nth(1)
+        .unwrap_or_default()
+        .to_string()
+}
@@ -0,0 +1,54 @@
+mod common;
+
+use axum::http::StatusCode;
+use serde_json::json;
+use webapp_backend::{
+    audit::{self, ChainVerification},
+    App,
+};
+
+use common::{post_json, send, test_db};
+
+const REGISTRATIONS: usize = 24;
+
+/// Each registration writes a user, a refresh token and an audit event while
+/// other requests read. With SQLite's busy timeout disabled, any two writers
+/// meeting would fail straight away with "database is locked".
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_concurrent_registrations_do_not_lock_the_database() {
+    let mut db = test_db();
+    db.config.db.busy_timeout = std::time::Duration::ZERO;
+    // No `.pool()`: the builder opens the split writer and reader pools
+    let app = App::builder().config(db.config.clone()).build();
+
+    let requests: Vec<_> = (0..REGISTRATIONS)
+        .map(|i| {
+            let app = app.clone();
+            tokio::spawn(async move {
+                send(
+                    &app,
+                    post_json(
+                        "/api/auth/register",
+                        json!({
+                            "username": format!("user{}", i),
+                            "email": format!("user{}@example.com", i),
+                            "password": "password123",
+                        }),
+                    ),
+                )
+                .await
+            })
+        })
+        .collect();
+
+    for request in requests {
+        let (status, body) = request.await.unwrap();
+        assert_eq!(status, StatusCode::OK, "{}", body);
+    }
+
+    let verification = audit::verify_chain(&mut db.pool.get().unwrap()).unwrap();
+    assert!(matches!(
+        verification,
+        ChainVerification::Intact { events, .. } if events == REGISTRATIONS as u64
+    ));
+}
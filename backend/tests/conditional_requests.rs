@@ -0,0 +1,183 @@
+//! ETags on `/api/auth/me` and optimistic concurrency for profile edits
+
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    Router,
+};
+use serde_json::json;
+use tower::ServiceExt;
+use webapp_backend::App;
+
+use common::{json_field, post_json, send, test_db};
+
+struct Reply {
+    status: StatusCode,
+    etag: Option<String>,
+    body: String,
+}
+
+async fn call(app: &Router, request: Request<Body>) -> Reply {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let etag = response
+        .headers()
+        .get(header::ETAG)
+        .map(|value| value.to_str().unwrap().to_string());
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    Reply {
+        status,
+        etag,
+        body: String::from_utf8_lossy(&body).into_owned(),
+    }
+}
+
+fn get_me(token: &str, if_none_match: Option<&str>) -> Request<Body> {
+    let mut request =
+        Request::get("/api/auth/me").header("authorization", format!("Bearer {}", token));
+    if let Some(etag) = if_none_match {
+        request = request.header(header::IF_NONE_MATCH, etag);
+    }
+    request.body(Body::empty()).unwrap()
+}
+
+fn patch_me(token: &str, if_match: Option<&str>, body: serde_json::Value) -> Request<Body> {
+    let mut request = Request::patch("/api/auth/me")
+        .header("authorization", format!("Bearer {}", token))
+        .header("content-type", "application/json");
+    if let Some(etag) = if_match {
+        request = request.header(header::IF_MATCH, etag);
+    }
+    request.body(Body::from(body.to_string())).unwrap()
+}
+
+async fn register(app: &Router, username: &str) -> String {
+    let (status, body) = send(
+        app,
+        post_json(
+            "/api/auth/register",
+            json!({
+                "username": username,
+                "email": format!("{}@example.com", username),
+                "password": "password123",
+            }),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    json_field(&body, "access_token")
+}
+
+#[tokio::test]
+async fn test_me_supports_conditional_get() {
+    let db = test_db();
+    let app = App::builder()
+        .config(db.config.clone())
+        .pool(db.pool.clone())
+        .build();
+    let token = register(&app, "alice").await;
+
+    let first = call(&app, get_me(&token, None)).await;
+    assert_eq!(first.status, StatusCode::OK);
+    let etag = first.etag.expect("me has an ETag");
+
+    let cached = call(&app, get_me(&token, Some(&etag))).await;
+    assert_eq!(cached.status, StatusCode::NOT_MODIFIED);
+    assert_eq!(cached.etag.as_deref(), Some(etag.as_str()));
+    assert!(cached.body.is_empty());
+
+    let edited = call(
+        &app,
+        patch_me(&token, Some(&etag), json!({ "username": "alicia" })),
+    )
+    .await;
+    assert_eq!(edited.status, StatusCode::OK, "{}", edited.body);
+
+    let refreshed = call(&app, get_me(&token, Some(&etag))).await;
+    assert_eq!(refreshed.status, StatusCode::OK);
+    assert_eq!(json_field(&refreshed.body, "username"), "alicia");
+    assert_eq!(refreshed.etag, edited.etag);
+}
+
+#[tokio::test]
+async fn test_profile_updates_require_the_current_etag() {
+    let db = test_db();
+    let app = App::builder()
+        .config(db.config.clone())
+        .pool(db.pool.clone())
+        .build();
+    let token = register(&app, "alice").await;
+    let etag = call(&app, get_me(&token, None)).await.etag.unwrap();
+
+    let missing = call(
+        &app,
+        patch_me(&token, None, json!({ "username": "alicia" })),
+    )
+    .await;
+    assert_eq!(missing.status, StatusCode::PRECONDITION_REQUIRED);
+    assert_eq!(json_field(&missing.body, "code"), "precondition_required");
+
+    // Two editors start from the same version; the second one loses
+    let first = call(
+        &app,
+        patch_me(&token, Some(&etag), json!({ "username": "alicia" })),
+    )
+    .await;
+    assert_eq!(first.status, StatusCode::OK, "{}", first.body);
+    assert_ne!(first.etag.as_deref(), Some(etag.as_str()));
+
+    let second = call(
+        &app,
+        patch_me(&token, Some(&etag), json!({ "email": "Ally@Example.com" })),
+    )
+    .await;
+    assert_eq!(second.status, StatusCode::PRECONDITION_FAILED);
+    assert_eq!(json_field(&second.body, "code"), "precondition_failed");
+
+    let retried = call(
+        &app,
+        patch_me(
+            &token,
+            first.etag.as_deref(),
+            json!({ "email": "Ally@Example.com" }),
+        ),
+    )
+    .await;
+    assert_eq!(retried.status, StatusCode::OK, "{}", retried.body);
+    assert_eq!(json_field(&retried.body, "username"), "alicia");
+    assert_eq!(json_field(&retried.body, "email"), "ally@example.com");
+}
+
+#[tokio::test]
+async fn test_profile_update_rejects_taken_and_invalid_values() {
+    let db = test_db();
+    let app = App::builder()
+        .config(db.config.clone())
+        .pool(db.pool.clone())
+        .build();
+    register(&app, "bob").await;
+    let token = register(&app, "alice").await;
+    let etag = call(&app, get_me(&token, None)).await.etag.unwrap();
+
+    let taken = call(
+        &app,
+        patch_me(&token, Some(&etag), json!({ "username": "bob" })),
+    )
+    .await;
+    assert_eq!(taken.status, StatusCode::CONFLICT);
+
+    let invalid = call(
+        &app,
+        patch_me(&token, Some(&etag), json!({ "email": "nope" })),
+    )
+    .await;
+    assert_eq!(invalid.status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    // Neither attempt changed the profile
+    let unchanged = call(&app, get_me(&token, Some(&etag))).await;
+    assert_eq!(unchanged.status, StatusCode::NOT_MODIFIED);
+}
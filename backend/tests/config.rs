@@ -0,0 +1,132 @@
+//! Layered configuration through the `config` subcommand: file, environment,
+//! secrets files and flags, validation errors and profile checks
+
+use std::{fs, path::Path, process::Output};
+
+fn run(dir: &Path, env: &[(&str, &str)], args: &[&str]) -> Output {
+    // A clean environment and directory keep the developer's .env out
+    std::process::Command::new(env!("CARGO_BIN_EXE_webapp_backend"))
+        .env_clear()
+        .envs(env.iter().copied())
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .expect("Failed to run the backend binary")
+}
+
+fn stdout(output: &Output) -> toml::Table {
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8_lossy(&output.stdout).parse().unwrap()
+}
+
+#[test]
+fn test_config_print_merges_file_env_secrets_and_flags() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join("app.toml"),
+        r#"
+        [server]
+        host = "0.0.0.0"
+        port = 4000
+
+        [queue]
+        workers = 3
+        "#,
+    )
+    .unwrap();
+    fs::write(dir.path().join("jwt"), "from-a-secrets-file\n").unwrap();
+    let env = [
+        ("APP_CONFIG", "app.toml"),
+        ("APP_QUEUE__WORKERS", "7"),
+        ("QUEUE_WORKERS", "9"),
+        ("APP_AUTH__JWT_SECRET_FILE", "jwt"),
+    ];
+
+    let args = [
+        "config",
+        "print",
+        "--port",
+        "5000",
+        "--set",
+        "mail.from=Ops <ops@example.com>",
+    ];
+    let config = stdout(&run(dir.path(), &env, &args));
+    assert_eq!(config["server"]["host"].as_str(), Some("0.0.0.0"));
+    assert_eq!(config["server"]["port"].as_integer(), Some(5000));
+    assert_eq!(config["queue"]["workers"].as_integer(), Some(7));
+    assert_eq!(
+        config["auth"]["jwt_secret"].as_str(),
+        Some("from-a-secrets-file")
+    );
+    assert_eq!(
+        config["mail"]["from"].as_str(),
+        Some("Ops <ops@example.com>")
+    );
+
+    let config = stdout(&run(dir.path(), &env, &["config", "print", "--redacted"]));
+    assert_eq!(config["auth"]["jwt_secret"].as_str(), Some("<redacted>"));
+    assert_eq!(config["database"]["url"].as_str(), Some("<redacted>"));
+    assert_eq!(config["server"]["port"].as_integer(), Some(4000));
+}
+
+#[test]
+fn test_invalid_config_reports_every_problem_and_fails() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join("app.toml"),
+        "[server]\nport = 70000\nprot = 3000\n[database]\nforeign_keys = \"sometimes\"\n",
+    )
+    .unwrap();
+
+    let output = run(
+        dir.path(),
+        &[],
+        &["--config", "app.toml", "config", "check"],
+    );
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.starts_with("invalid configuration:"), "{}", stderr);
+    assert!(
+        stderr.contains("unknown setting `server.prot` in app.toml"),
+        "{}",
+        stderr
+    );
+    assert!(
+        stderr.contains("server.port = \"70000\" from app.toml"),
+        "{}",
+        stderr
+    );
+    assert!(
+        stderr.contains("database.foreign_keys = \"sometimes\""),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn test_prod_profile_refuses_what_dev_accepts() {
+    let dir = tempfile::tempdir().unwrap();
+    let env = [("DEV_ENDPOINTS", "true")];
+
+    let output = run(dir.path(), &env, &["config", "check"]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout).trim(),
+        "Configuration is valid for the dev profile"
+    );
+
+    let output = run(dir.path(), &env, &["--profile", "prod", "config", "check"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.starts_with("configuration is not safe for the prod profile:"));
+    assert!(stderr.contains("auth.jwt_secret must be set"), "{}", stderr);
+    assert!(
+        stderr.contains("server.dev_endpoints must be off"),
+        "{}",
+        stderr
+    );
+}
@@ -0,0 +1,177 @@
+//! CSRF checks on unsafe requests authenticated by the session cookie
+
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, HeaderMap, Request, StatusCode},
+    Router,
+};
+use serde_json::json;
+use tower::ServiceExt;
+use webapp_backend::App;
+
+use common::{get, json_field, post_json, send, test_db};
+
+async fn respond(app: &Router, request: Request<Body>) -> (StatusCode, HeaderMap, String) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let headers = response.headers().clone();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    (status, headers, String::from_utf8_lossy(&body).into_owned())
+}
+
+/// `name=value` of the cookie a response set
+fn set_cookie(headers: &HeaderMap) -> String {
+    let cookie = headers[header::SET_COOKIE].to_str().unwrap();
+    cookie.split(';').next().unwrap().to_string()
+}
+
+/// Register and sign in, returning the `access_token` cookie and bearer token
+async fn sign_in(app: &Router) -> (String, String) {
+    let user = json!({
+        "username": "alice",
+        "email": "alice@example.com",
+        "password": "password123",
+    });
+    let (status, body) = send(app, post_json("/api/auth/register", user)).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+
+    let credentials = json!({ "email": "alice@example.com", "password": "password123" });
+    let (status, headers, body) = respond(app, post_json("/api/auth/login", credentials)).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    (set_cookie(&headers), json_field(&body, "access_token"))
+}
+
+/// The profile's current `ETag`
+async fn etag(app: &Router, cookie: &str) -> String {
+    let request = Request::get("/api/auth/me")
+        .header(header::COOKIE, cookie)
+        .body(Body::empty())
+        .unwrap();
+    let (status, headers, body) = respond(app, request).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    headers[header::ETAG].to_str().unwrap().to_string()
+}
+
+/// Fetch a CSRF token as the holder of `cookie`
+fn get_csrf(cookie: &str) -> Request<Body> {
+    Request::get("/api/auth/csrf")
+        .header(header::COOKIE, cookie)
+        .body(Body::empty())
+        .unwrap()
+}
+
+/// Rename the user, sending `headers` along
+fn patch_me(etag: &str, headers: &[(&str, &str)]) -> Request<Body> {
+    let mut request = Request::patch("/api/auth/me")
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(header::IF_MATCH, etag);
+    for (name, value) in headers {
+        request = request.header(*name, *value);
+    }
+    request
+        .body(Body::from(json!({ "username": "alice2" }).to_string()))
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_cookie_requests_need_the_csrf_token() {
+    let db = test_db();
+    let app = App::builder()
+        .config(db.config.clone())
+        .pool(db.pool.clone())
+        .build();
+    let (session, _) = sign_in(&app).await;
+
+    // Safe methods pass without a token
+    let version = etag(&app, &session).await;
+
+    let request = patch_me(&version, &[("cookie", &session)]);
+    let (status, _, problem) = respond(&app, request).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(json_field(&problem, "code"), "csrf_failed");
+
+    // A token issued outside the session is refused
+    let (status, headers, anonymous) = respond(&app, get("/api/auth/csrf")).await;
+    assert_eq!(status, StatusCode::OK);
+    let cookies = format!("{}; {}", session, set_cookie(&headers));
+    let anonymous = json_field(&anonymous, "token");
+    let request = patch_me(
+        &version,
+        &[("cookie", &cookies), ("x-csrf-token", &anonymous)],
+    );
+    let (status, _, _) = respond(&app, request).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, headers, token) = respond(&app, get_csrf(&session)).await;
+    assert_eq!(status, StatusCode::OK);
+    let csrf_cookie = set_cookie(&headers);
+    assert!(csrf_cookie.starts_with("csrf_token="), "{}", csrf_cookie);
+    assert_eq!(json_field(&token, "header"), "x-csrf-token");
+    let token = json_field(&token, "token");
+
+    // A token that does not match the cookie is refused
+    let cookies = format!("{}; {}", session, csrf_cookie);
+    let request = patch_me(&version, &[("cookie", &session), ("x-csrf-token", &token)]);
+    let (status, _, _) = respond(&app, request).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let request = patch_me(&version, &[("cookie", &cookies), ("x-csrf-token", &token)]);
+    let (status, _, body) = respond(&app, request).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+
+    // The current token is handed out again while its cookie is valid
+    let (_, _, again) = respond(&app, get_csrf(&cookies)).await;
+    assert_eq!(json_field(&again, "token"), token);
+}
+
+#[tokio::test]
+async fn test_bearer_requests_are_exempt() {
+    let db = test_db();
+    let app = App::builder()
+        .config(db.config.clone())
+        .pool(db.pool.clone())
+        .build();
+    let (session, bearer) = sign_in(&app).await;
+    let version = etag(&app, &session).await;
+
+    let bearer = format!("Bearer {}", bearer);
+    let request = patch_me(&version, &[("authorization", &bearer)]);
+    let (status, _, body) = respond(&app, request).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+}
+
+#[tokio::test]
+async fn test_cross_site_requests_are_refused_even_with_a_token() {
+    let db = test_db();
+    let app = App::builder()
+        .config(db.config.clone())
+        .pool(db.pool.clone())
+        .build();
+    let (session, _) = sign_in(&app).await;
+    let version = etag(&app, &session).await;
+
+    let (_, headers, token) = respond(&app, get_csrf(&session)).await;
+    let cookies = format!("{}; {}", session, set_cookie(&headers));
+    let token = json_field(&token, "token");
+    let request = |site: &str, origin: &str| {
+        let headers = [
+            ("cookie", cookies.as_str()),
+            ("x-csrf-token", &token),
+            ("sec-fetch-site", site),
+            ("origin", origin),
+        ];
+        patch_me(&version, &headers)
+    };
+
+    let (status, _, problem) = respond(&app, request("cross-site", "https://evil.example")).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert!(problem.contains("https://evil.example"), "{}", problem);
+
+    // The frontend dev server is an allowed CORS origin
+    let (status, _, body) = respond(&app, request("same-site", "http://localhost:5173")).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+}
@@ -0,0 +1,106 @@
+//! Database behaviour the unit tests only cover on SQLite
+
+mod common;
+
+use std::process::ExitCode;
+
+use axum::http::StatusCode;
+use serde_json::json;
+use webapp_backend::{
+    audit::{self, ChainVerification},
+    cli,
+    db::migrations,
+    App,
+};
+
+use common::{get_with_bearer, json_field, post_json, send, test_db};
+
+#[tokio::test]
+async fn test_auth_events_form_an_intact_chain() {
+    let db = test_db();
+    let app = App::builder()
+        .config(db.config.clone())
+        .pool(db.pool.clone())
+        .build();
+
+    let (status, _) = send(
+        &app,
+        post_json(
+            "/api/auth/register",
+            json!({ "username": "alice", "email": "alice@example.com", "password": "password123" }),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send(
+        &app,
+        post_json(
+            "/api/auth/login",
+            json!({ "email": "alice@example.com", "password": "wrong-password" }),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let verification = audit::verify_chain(&mut db.pool.get().unwrap()).unwrap();
+    assert!(matches!(
+        verification,
+        ChainVerification::Intact { events: 2, .. }
+    ));
+}
+
+#[test]
+fn test_migrations_revert_and_reapply() {
+    let db = test_db();
+    let mut conn = db.pool.get().unwrap();
+    let total = migrations::status(&mut conn).unwrap().len();
+
+    assert_eq!(migrations::revert(&mut conn, total).unwrap().len(), total);
+    assert!(migrations::status(&mut conn)
+        .unwrap()
+        .iter()
+        .all(|migration| !migration.applied));
+
+    assert_eq!(migrations::run_pending(&mut conn).unwrap().len(), total);
+    migrations::redo(&mut conn).unwrap();
+    assert!(migrations::status(&mut conn)
+        .unwrap()
+        .iter()
+        .all(|migration| migration.applied));
+}
+
+#[tokio::test]
+async fn test_admin_can_read_pool_stats() {
+    let db = test_db();
+    let app = App::builder()
+        .config(db.config.clone())
+        .pool(db.pool.clone())
+        .build();
+
+    let credentials = json!({ "email": "admin@example.com", "password": "password123" });
+    let (status, _) = send(
+        &app,
+        post_json(
+            "/api/auth/register",
+            json!({ "username": "admin", "email": "admin@example.com", "password": "password123" }),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        cli::grant_admin(&db.pool, "admin@example.com"),
+        ExitCode::SUCCESS
+    );
+
+    let (_, body) = send(&app, post_json("/api/auth/login", credentials)).await;
+    let access_token = json_field(&body, "access_token");
+
+    let (status, body) = send(&app, get_with_bearer("/api/admin/db/pool", &access_token)).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let stats: serde_json::Value = serde_json::from_str(&body).unwrap();
+    // `.pool()` shares one pool between reads and writes
+    assert_eq!(stats["writer"], stats["reader"]);
+    assert_eq!(stats["writer"]["max_size"], db.config.db.max_connections);
+    assert!(stats["writer"]["checkouts"].as_u64().unwrap() > 0);
+    assert_eq!(stats["writer"]["timeouts"], 0);
+}
@@ -0,0 +1,338 @@
+//! The domain event bus: in-transaction subscribers, post-commit dispatch
+//! through the outbox, and the events the auth handlers publish
+
+mod common;
+
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use axum::http::StatusCode;
+use chrono::{NaiveDateTime, Utc};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use webapp_backend::{
+    db::{
+        schema::{audit_events, jobs, users},
+        Db, DbConnection, DbPool,
+    },
+    error::{AppError, AppResult},
+    events::{DispatchEvent, Event, EventBus, LoginFailed, UserLoggedIn, UserRegistered},
+    models::{NewRefreshToken, RefreshToken},
+    queue::{self, JobError, JobPayload, Queue, QueueOptions, WorkersHandle},
+    repositories::{DieselSessionRepository, DieselUserRepository, SessionRepository},
+    App, SystemClock,
+};
+
+use common::{json_field, post_json, send, test_db};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct OrderPlaced {
+    order: i32,
+}
+
+impl Event for OrderPlaced {
+    const NAME: &'static str = "order.placed";
+}
+
+#[derive(Serialize, Deserialize)]
+struct Invoice {
+    order: i32,
+}
+
+impl JobPayload for Invoice {
+    const KIND: &'static str = "invoice";
+}
+
+fn count_jobs(pool: &DbPool, kind: &str) -> i64 {
+    let mut conn = pool.get().unwrap();
+    jobs::table
+        .filter(jobs::kind.eq(kind))
+        .count()
+        .get_result(&mut conn)
+        .unwrap()
+}
+
+fn start_workers(pool: &DbPool, events: EventBus) -> WorkersHandle {
+    let queue = Queue::new(Db::single(pool.clone()), Arc::new(SystemClock));
+    let options = QueueOptions {
+        poll_interval: Duration::from_millis(20),
+        backoff_base: Duration::ZERO,
+        ..QueueOptions::default()
+    };
+    queue
+        .workers(options)
+        .handle(move |job: DispatchEvent| {
+            let events = events.clone();
+            async move { events.dispatch(job).await }
+        })
+        .start()
+}
+
+async fn wait_until(done: impl Fn() -> bool) {
+    for _ in 0..250 {
+        if done() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("Timed out waiting for event subscribers");
+}
+
+#[test]
+fn test_in_transaction_subscribers_commit_or_roll_back_with_the_event() {
+    let db = test_db();
+    let bus = EventBus::builder()
+        .in_transaction(|conn, event: &OrderPlaced, now| {
+            queue::enqueue(conn, &Invoice { order: event.order }, now)?;
+            Ok(())
+        })
+        .in_transaction(|_, event: &OrderPlaced, _| match event.order {
+            13 => Err(AppError::Conflict("Unlucky order")),
+            _ => Ok(()),
+        })
+        .after_commit("ship", |_: OrderPlaced| async { Ok(()) })
+        .build();
+    let mut conn = db.pool.get().unwrap();
+
+    bus.record(&mut conn, &OrderPlaced { order: 1 }, Utc::now())
+        .unwrap();
+    assert_eq!(count_jobs(&db.pool, "invoice"), 1);
+    assert_eq!(count_jobs(&db.pool, "dispatch_event"), 1);
+
+    // A failing subscriber undoes the others' writes and the outbox entry
+    let error = bus
+        .record(&mut conn, &OrderPlaced { order: 13 }, Utc::now())
+        .unwrap_err();
+    assert!(matches!(error, AppError::Conflict(_)));
+    assert_eq!(count_jobs(&db.pool, "invoice"), 1);
+    assert_eq!(count_jobs(&db.pool, "dispatch_event"), 1);
+
+    // So does the caller's transaction
+    let result = conn.transaction(|conn| {
+        bus.record(conn, &OrderPlaced { order: 2 }, Utc::now())?;
+        Err::<(), _>(AppError::Conflict("Changed my mind"))
+    });
+    assert!(result.is_err());
+    assert_eq!(count_jobs(&db.pool, "invoice"), 1);
+    assert_eq!(count_jobs(&db.pool, "dispatch_event"), 1);
+}
+
+#[tokio::test]
+async fn test_outbox_delivers_after_a_restart_and_retries_each_subscriber() {
+    let db = test_db();
+    let shipped = Arc::new(Mutex::new(Vec::new()));
+    let attempts = Arc::new(AtomicUsize::new(0));
+    let subscribers = || {
+        let shipped = shipped.clone();
+        let attempts = attempts.clone();
+        EventBus::builder()
+            .after_commit("ship", move |event: OrderPlaced| {
+                let shipped = shipped.clone();
+                async move {
+                    shipped.lock().unwrap().push(event.order);
+                    Ok(())
+                }
+            })
+            .after_commit("bill", move |_: OrderPlaced| {
+                let attempt = attempts.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    match attempt {
+                        0 => Err(JobError::Retry("payment provider is down".to_string())),
+                        _ => Ok(()),
+                    }
+                }
+            })
+            .build()
+    };
+
+    // Recorded while no workers run, as if the process died after commit
+    let mut conn = db.pool.get().unwrap();
+    subscribers()
+        .record(&mut conn, &OrderPlaced { order: 7 }, Utc::now())
+        .unwrap();
+    drop(conn);
+    assert_eq!(count_jobs(&db.pool, "dispatch_event"), 2);
+
+    let workers = start_workers(&db.pool, subscribers());
+    wait_until(|| attempts.load(Ordering::SeqCst) == 2).await;
+    workers.shutdown(Duration::from_secs(5)).await;
+
+    // The retry of "bill" did not run "ship" again
+    assert_eq!(*shipped.lock().unwrap(), [7]);
+}
+
+#[tokio::test]
+async fn test_auth_handlers_publish_events() {
+    let db = test_db();
+    let registered = Arc::new(Mutex::new(Vec::new()));
+    let logins = Arc::new(Mutex::new(Vec::new()));
+    let failures = Arc::new(Mutex::new(Vec::new()));
+    let events = {
+        let (registered, logins, failures) = (registered.clone(), logins.clone(), failures.clone());
+        EventBus::builder()
+            .in_transaction(move |_, event: &UserRegistered, _| {
+                registered.lock().unwrap().push(event.clone());
+                Ok(())
+            })
+            .after_commit("logins", move |event: UserLoggedIn| {
+                let logins = logins.clone();
+                async move {
+                    logins.lock().unwrap().push(event.user_id);
+                    Ok(())
+                }
+            })
+            .after_commit("failures", move |event: LoginFailed| {
+                let failures = failures.clone();
+                async move {
+                    failures.lock().unwrap().push(event.reason);
+                    Ok(())
+                }
+            })
+            .build()
+    };
+    let queue = Queue::new(Db::single(db.pool.clone()), Arc::new(SystemClock));
+    let app = App::builder()
+        .config(db.config.clone())
+        .pool(db.pool.clone())
+        .queue(queue)
+        .events(events.clone())
+        .build();
+    let workers = start_workers(&db.pool, events);
+
+    let user = json!({
+        "username": "alice",
+        "email": "alice@example.com",
+        "password": "password123",
+    });
+    let (status, body) = send(&app, post_json("/api/auth/register", user)).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+
+    let login = |password: &str| {
+        let credentials = json!({ "email": "alice@example.com", "password": password });
+        post_json("/api/auth/login", credentials)
+    };
+    let (status, _) = send(&app, login("password123")).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send(&app, login("wrong-password")).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    wait_until(|| !logins.lock().unwrap().is_empty() && !failures.lock().unwrap().is_empty()).await;
+    workers.shutdown(Duration::from_secs(5)).await;
+
+    let registered = registered.lock().unwrap();
+    assert_eq!(registered.len(), 1);
+    assert_eq!(registered[0].user.email, "alice@example.com");
+    assert_eq!(registered[0].locale, "en");
+    assert_eq!(*logins.lock().unwrap(), [registered[0].user.id]);
+    assert_eq!(*failures.lock().unwrap(), ["invalid_password"]);
+}
+
+#[tokio::test]
+async fn test_a_failing_subscriber_undoes_the_change_and_its_audit_entry() {
+    let db = test_db();
+    let events = EventBus::builder()
+        .in_transaction(|_, _: &UserRegistered, _| Err(AppError::PreconditionFailed))
+        .build();
+    let app = App::builder()
+        .config(db.config.clone())
+        .pool(db.pool.clone())
+        .events(events)
+        .build();
+
+    let user = json!({
+        "username": "alice",
+        "email": "alice@example.com",
+        "password": "password123",
+    });
+    let (status, _) = send(&app, post_json("/api/auth/register", user)).await;
+    assert_eq!(status, StatusCode::PRECONDITION_FAILED);
+
+    let mut conn = db.pool.get().unwrap();
+    let user_rows: i64 = users::table.count().get_result(&mut conn).unwrap();
+    let successes: i64 = audit_events::table
+        .filter(audit_events::outcome.eq("success"))
+        .count()
+        .get_result(&mut conn)
+        .unwrap();
+    assert_eq!((user_rows, successes), (0, 0));
+}
+
+/// Sessions that can be read and deleted but never started or resumed
+struct FrozenSessions;
+
+impl SessionRepository for FrozenSessions {
+    fn insert(&self, _: &mut DbConnection, _: NewRefreshToken) -> AppResult<RefreshToken> {
+        Err(AppError::Database(
+            diesel::result::Error::BrokenTransactionManager,
+        ))
+    }
+
+    fn find(&self, conn: &mut DbConnection, id: &str) -> AppResult<Option<RefreshToken>> {
+        DieselSessionRepository.find(conn, id)
+    }
+
+    fn mark_used(&self, _: &mut DbConnection, _: &str, _: NaiveDateTime) -> AppResult<()> {
+        Err(AppError::Database(
+            diesel::result::Error::BrokenTransactionManager,
+        ))
+    }
+
+    fn delete(&self, conn: &mut DbConnection, id: &str) -> AppResult<Option<RefreshToken>> {
+        DieselSessionRepository.delete(conn, id)
+    }
+
+    fn delete_for_user(&self, conn: &mut DbConnection, user_id: i32) -> AppResult<usize> {
+        DieselSessionRepository.delete_for_user(conn, user_id)
+    }
+
+    fn delete_expired(&self, conn: &mut DbConnection, now: NaiveDateTime) -> AppResult<usize> {
+        DieselSessionRepository.delete_expired(conn, now)
+    }
+}
+
+#[tokio::test]
+async fn test_a_session_that_fails_to_start_undoes_the_login_and_refresh() {
+    let db = test_db();
+    let app = App::builder()
+        .config(db.config.clone())
+        .pool(db.pool.clone())
+        .build();
+    let frozen = App::builder()
+        .config(db.config.clone())
+        .pool(db.pool.clone())
+        .repositories(Arc::new(DieselUserRepository), Arc::new(FrozenSessions))
+        .build();
+
+    let user = json!({
+        "username": "alice",
+        "email": "alice@example.com",
+        "password": "password123",
+    });
+    let (status, body) = send(&app, post_json("/api/auth/register", user)).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let refresh_token = json_field(&body, "refresh_token");
+    let dispatches = count_jobs(&db.pool, DispatchEvent::KIND);
+
+    let credentials = json!({ "email": "alice@example.com", "password": "password123" });
+    let (status, _) = send(&frozen, post_json("/api/auth/login", credentials)).await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    let body = json!({ "refresh_token": refresh_token });
+    let (status, _) = send(&frozen, post_json("/api/auth/refresh", body)).await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+
+    // Neither the audit log nor subscribers saw a session that never existed
+    let mut conn = db.pool.get().unwrap();
+    let entries: i64 = audit_events::table
+        .filter(audit_events::action.eq_any(["auth.login", "auth.refresh"]))
+        .count()
+        .get_result(&mut conn)
+        .unwrap();
+    assert_eq!(entries, 0);
+    assert_eq!(count_jobs(&db.pool, DispatchEvent::KIND), dispatches);
+}
@@ -0,0 +1,113 @@
+mod common;
+
+use std::thread;
+
+use chrono::Utc;
+use diesel::prelude::*;
+#[cfg(feature = "sqlite")]
+use webapp_backend::db::{self, DbOptions};
+use webapp_backend::{
+    db::{
+        schema::{refresh_tokens, users},
+        DbPool,
+    },
+    models::{NewRefreshToken, NewUser},
+};
+
+use common::test_db;
+
+fn insert_user_with_tokens(pool: &DbPool, tokens: usize) -> i32 {
+    let mut conn = pool.get().unwrap();
+    let user_id = diesel::insert_into(users::table)
+        .values(&NewUser {
+            username: "alice".to_string(),
+            email: "alice@example.com".to_string(),
+            password_hash: "hash".to_string(),
+        })
+        .returning(users::id)
+        .get_result(&mut conn)
+        .unwrap();
+
+    for _ in 0..tokens {
+        diesel::insert_into(refresh_tokens::table)
+            .values(&NewRefreshToken::new(user_id, Utc::now()))
+            .execute(&mut conn)
+            .unwrap();
+    }
+    user_id
+}
+
+fn token_count(pool: &DbPool) -> i64 {
+    refresh_tokens::table
+        .count()
+        .get_result(&mut pool.get().unwrap())
+        .unwrap()
+}
+
+#[test]
+fn test_deleting_user_cascades_to_refresh_tokens() {
+    let db = test_db();
+    let user_id = insert_user_with_tokens(&db.pool, 3);
+    assert_eq!(token_count(&db.pool), 3);
+
+    diesel::delete(users::table.find(user_id))
+        .execute(&mut db.pool.get().unwrap())
+        .unwrap();
+
+    assert_eq!(token_count(&db.pool), 0);
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn test_cascade_needs_foreign_keys_pragma() {
+    let db = test_db();
+    // A second pool on the same file with enforcement switched off
+    let options = DbOptions {
+        foreign_keys: false,
+        ..db.config.db.clone()
+    };
+    let pool = db::establish_connection_pool(&db.config.database_url, &options);
+    let user_id = insert_user_with_tokens(&pool, 2);
+
+    diesel::delete(users::table.find(user_id))
+        .execute(&mut pool.get().unwrap())
+        .unwrap();
+
+    assert_eq!(token_count(&pool), 2);
+}
+
+#[test]
+fn test_refresh_token_requires_existing_user() {
+    let db = test_db();
+
+    let result = diesel::insert_into(refresh_tokens::table)
+        .values(&NewRefreshToken::new(42, Utc::now()))
+        .execute(&mut db.pool.get().unwrap());
+
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_concurrent_writers_wait_instead_of_failing() {
+    let db = test_db();
+    let user_id = insert_user_with_tokens(&db.pool, 0);
+
+    let writers: Vec<_> = (0..8)
+        .map(|_| {
+            let pool = db.pool.clone();
+            thread::spawn(move || {
+                for _ in 0..25 {
+                    diesel::insert_into(refresh_tokens::table)
+                        .values(&NewRefreshToken::new(user_id, Utc::now()))
+                        .execute(&mut pool.get().unwrap())
+                        .expect("Write failed under contention");
+                }
+            })
+        })
+        .collect();
+    for writer in writers {
+        writer.join().unwrap();
+    }
+
+    assert_eq!(token_count(&db.pool), 200);
+}
@@ -0,0 +1,220 @@
+//! Mail delivery through SMTP and the maildir, and the welcome email outbox
+
+mod common;
+
+use std::{sync::Arc, time::Duration};
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Router,
+};
+use serde_json::json;
+use webapp_backend::{
+    db::Db,
+    mail::{self, Email, FileMailer, MailError, Mailer, MemoryMailer, SharedMailer, SmtpMailer},
+    queue::{JobError, Queue, QueueOptions, WorkersHandle},
+    App, SystemClock,
+};
+
+use common::{get, send, smtp::SmtpServer, test_db};
+
+fn email(to: &str) -> Email {
+    Email {
+        from: "Web App <noreply@example.com>".to_string(),
+        to: to.to_string(),
+        subject: "Hello".to_string(),
+        text: "Plain body".to_string(),
+        html: "<p>HTML body</p>".to_string(),
+    }
+}
+
+fn register_request(username: &str, accept_language: &str) -> Request<Body> {
+    Request::post("/api/auth/register")
+        .header("content-type", "application/json")
+        .header("accept-language", accept_language)
+        .body(Body::from(
+            json!({
+                "username": username,
+                "email": format!("{}@example.com", username),
+                "password": "password123",
+            })
+            .to_string(),
+        ))
+        .unwrap()
+}
+
+/// Start workers on the app's database that deliver mail with `mailer`
+fn start_workers(queue: &Queue, mailer: SharedMailer) -> WorkersHandle {
+    let options = QueueOptions {
+        poll_interval: Duration::from_millis(20),
+        ..QueueOptions::default()
+    };
+    queue
+        .workers(options)
+        .handle(move |email| {
+            let mailer = mailer.clone();
+            async move { mail::deliver(mailer.as_ref(), email).await }
+        })
+        .start()
+}
+
+#[tokio::test]
+async fn test_smtp_mailer_sends_text_and_html() {
+    let server = SmtpServer::start().await;
+    let mailer = SmtpMailer::from_url(&server.url()).unwrap();
+
+    mailer.send(&email("alice@example.com")).await.unwrap();
+
+    let received = server.received();
+    assert_eq!(received.len(), 1);
+    assert_eq!(received[0].from, "noreply@example.com");
+    assert_eq!(received[0].to, ["alice@example.com"]);
+    let data = &received[0].data;
+    assert!(data.contains("Subject: Hello"), "{}", data);
+    assert!(
+        data.contains("Content-Type: multipart/alternative"),
+        "{}",
+        data
+    );
+    assert!(data.contains("Plain body"), "{}", data);
+    assert!(data.contains("<p>HTML body</p>"), "{}", data);
+}
+
+#[tokio::test]
+async fn test_smtp_failures_decide_whether_jobs_retry() {
+    let server = SmtpServer::start().await;
+    let mailer = SmtpMailer::from_url(&server.url()).unwrap();
+
+    server.reply_to_rcpt("550 No such user");
+    let error = mailer.send(&email("nobody@example.com")).await.unwrap_err();
+    assert!(matches!(error, MailError::Rejected(_)), "{:?}", error);
+    assert!(matches!(JobError::from(error), JobError::Fatal(_)));
+
+    server.reply_to_rcpt("451 Try again later");
+    let error = mailer.send(&email("alice@example.com")).await.unwrap_err();
+    assert!(matches!(error, MailError::Unavailable(_)), "{:?}", error);
+    assert!(matches!(JobError::from(error), JobError::Retry(_)));
+
+    // Nothing listens on port 9 locally
+    let unreachable = SmtpMailer::from_url("smtp://127.0.0.1:9").unwrap();
+    let error = unreachable
+        .send(&email("alice@example.com"))
+        .await
+        .unwrap_err();
+    assert!(matches!(error, MailError::Unavailable(_)), "{:?}", error);
+
+    assert!(server.received().is_empty());
+}
+
+#[tokio::test]
+async fn test_file_mailer_writes_a_maildir() {
+    let dir = tempfile::tempdir().unwrap();
+    let mailer = FileMailer::new(dir.path().to_path_buf());
+
+    mailer.send(&email("alice@example.com")).await.unwrap();
+    mailer.send(&email("bob@example.com")).await.unwrap();
+
+    let delivered: Vec<_> = std::fs::read_dir(dir.path().join("new"))
+        .unwrap()
+        .map(|entry| std::fs::read_to_string(entry.unwrap().path()).unwrap())
+        .collect();
+    assert_eq!(delivered.len(), 2);
+    assert!(delivered
+        .iter()
+        .any(|message| message.contains("To: bob@example.com")));
+    assert_eq!(
+        std::fs::read_dir(dir.path().join("tmp")).unwrap().count(),
+        0
+    );
+}
+
+#[tokio::test]
+async fn test_registration_sends_welcome_email_in_the_readers_language() {
+    let db = test_db();
+    let server = SmtpServer::start().await;
+    let queue = Queue::new(Db::single(db.pool.clone()), Arc::new(SystemClock));
+    let app = App::builder()
+        .config(db.config.clone())
+        .pool(db.pool.clone())
+        .queue(queue.clone())
+        .build();
+    let workers = start_workers(
+        &queue,
+        Arc::new(SmtpMailer::from_url(&server.url()).unwrap()),
+    );
+
+    let (status, body) = send(&app, register_request("alice", "fr-CH, de;q=0.9")).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+
+    for _ in 0..250 {
+        if !server.received().is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    workers.shutdown(Duration::from_secs(5)).await;
+
+    let received = server.received();
+    assert_eq!(received.len(), 1);
+    assert_eq!(received[0].to, ["alice@example.com"]);
+    assert!(
+        received[0].data.contains("Subject: Willkommen, alice"),
+        "{}",
+        received[0].data
+    );
+}
+
+async fn captured_mail(app: &Router) -> Vec<serde_json::Value> {
+    for _ in 0..250 {
+        let (status, body) = send(app, get("/dev/mail")).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        let messages: Vec<serde_json::Value> = serde_json::from_str(&body).unwrap();
+        if !messages.is_empty() {
+            return messages;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("No mail was captured");
+}
+
+#[tokio::test]
+async fn test_dev_mail_lists_captured_messages() {
+    let db = test_db();
+    let mut config = db.config.clone();
+    config.dev_endpoints = true;
+    let mailer: SharedMailer = Arc::new(MemoryMailer::default());
+    let queue = Queue::new(Db::single(db.pool.clone()), Arc::new(SystemClock));
+    let app = App::builder()
+        .config(config)
+        .pool(db.pool.clone())
+        .queue(queue.clone())
+        .mailer(mailer.clone())
+        .build();
+    let workers = start_workers(&queue, mailer);
+
+    let (status, body) = send(&app, register_request("alice", "en-US")).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let messages = captured_mail(&app).await;
+    workers.shutdown(Duration::from_secs(5)).await;
+
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0]["to"], "alice@example.com");
+    assert_eq!(messages[0]["subject"], "Welcome, alice");
+    assert!(messages[0]["html"]
+        .as_str()
+        .unwrap()
+        .contains("<strong>alice@example.com</strong>"));
+}
+
+#[tokio::test]
+async fn test_dev_mail_is_off_by_default() {
+    let db = test_db();
+    let app = App::builder()
+        .config(db.config.clone())
+        .pool(db.pool.clone())
+        .build();
+
+    let (status, _) = send(&app, get("/dev/mail")).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
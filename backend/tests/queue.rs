@@ -0,0 +1,479 @@
+//! The persistent job queue, its workers and the admin job endpoints
+
+mod common;
+
+use std::{
+    collections::HashMap,
+    process::ExitCode,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Router,
+};
+use chrono::{TimeZone, Utc};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use webapp_backend::{
+    cli,
+    db::{schema::jobs, Db, DbPool},
+    error::AppError,
+    models::{JobRecord, JobState},
+    queue::{self, JobError, JobPayload, Queue, QueueOptions},
+    App, Clock, FakeClock, SystemClock,
+};
+
+use common::{get_with_bearer, json_field, post_json, send, test_db};
+
+#[derive(Serialize, Deserialize)]
+struct Greet {
+    name: String,
+}
+
+impl JobPayload for Greet {
+    const KIND: &'static str = "greet";
+
+    fn unique_key(&self) -> Option<String> {
+        Some(format!("greet:{}", self.name))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Flaky;
+
+impl JobPayload for Flaky {
+    const KIND: &'static str = "flaky";
+    const MAX_ATTEMPTS: i32 = 2;
+}
+
+fn fast_options(workers: usize) -> QueueOptions {
+    QueueOptions {
+        workers,
+        poll_interval: Duration::from_millis(20),
+        ..QueueOptions::default()
+    }
+}
+
+fn load_job(pool: &DbPool, id: i32) -> JobRecord {
+    let mut conn = pool.get().unwrap();
+    jobs::table
+        .find(id)
+        .select(JobRecord::as_select())
+        .first(&mut conn)
+        .unwrap()
+}
+
+/// Poll a job until `done` holds for it
+async fn wait_for(pool: &DbPool, id: i32, done: impl Fn(&JobRecord) -> bool) -> JobRecord {
+    for _ in 0..250 {
+        let job = load_job(pool, id);
+        if done(&job) {
+            return job;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!(
+        "Job {} never reached the expected state: {:?}",
+        id,
+        load_job(pool, id)
+    );
+}
+
+#[tokio::test]
+async fn test_worker_runs_typed_job() {
+    let db = test_db();
+    let queue = Queue::new(Db::single(db.pool.clone()), Arc::new(SystemClock));
+    let greeted = Arc::new(Mutex::new(Vec::new()));
+
+    let seen = greeted.clone();
+    let workers = queue
+        .workers(fast_options(1))
+        .handle(move |greet: Greet| {
+            let seen = seen.clone();
+            async move {
+                seen.lock().unwrap().push(greet.name);
+                Ok(())
+            }
+        })
+        .start();
+
+    let id = queue
+        .enqueue(Greet {
+            name: "alice".to_string(),
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    let job = wait_for(&db.pool, id, |job| {
+        job.status == JobState::Succeeded.as_str()
+    })
+    .await;
+    workers.shutdown(Duration::from_secs(5)).await;
+
+    assert_eq!(*greeted.lock().unwrap(), ["alice"]);
+    assert_eq!(job.attempts, 1);
+    assert!(job.finished_at.is_some());
+    assert!(job.locked_by.is_none());
+}
+
+#[tokio::test]
+async fn test_unique_jobs_are_deduplicated_while_queued() {
+    let db = test_db();
+    let queue = Queue::new(Db::single(db.pool.clone()), Arc::new(SystemClock));
+
+    let first = queue
+        .enqueue(Greet {
+            name: "alice".to_string(),
+        })
+        .await
+        .unwrap();
+    let duplicate = queue
+        .enqueue(Greet {
+            name: "alice".to_string(),
+        })
+        .await
+        .unwrap();
+    let other = queue
+        .enqueue(Greet {
+            name: "bob".to_string(),
+        })
+        .await
+        .unwrap();
+    assert!(first.is_some());
+    assert_eq!(duplicate, None);
+    assert!(other.is_some());
+
+    // Once the job has run, the same key can be queued again
+    let workers = queue
+        .workers(fast_options(1))
+        .handle(|_: Greet| async { Ok(()) })
+        .start();
+    wait_for(&db.pool, first.unwrap(), |job| {
+        job.status == JobState::Succeeded.as_str()
+    })
+    .await;
+    workers.shutdown(Duration::from_secs(5)).await;
+
+    let again = queue
+        .enqueue(Greet {
+            name: "alice".to_string(),
+        })
+        .await
+        .unwrap();
+    assert!(again.is_some());
+}
+
+#[tokio::test]
+async fn test_failing_job_backs_off_then_dies() {
+    let db = test_db();
+    // Whole seconds, so timestamps compare equal after a Postgres round trip
+    let clock = Arc::new(FakeClock::new(
+        Utc.with_ymd_and_hms(2030, 1, 1, 12, 0, 0).unwrap(),
+    ));
+    let queue = Queue::new(Db::single(db.pool.clone()), clock.clone());
+    let options = QueueOptions {
+        backoff_base: Duration::from_secs(60),
+        ..fast_options(1)
+    };
+    let workers = queue
+        .workers(options)
+        .handle(|_: Flaky| async { Err(JobError::Retry("upstream unavailable".to_string())) })
+        .start();
+
+    let id = queue.enqueue(Flaky).await.unwrap().unwrap();
+    let job = wait_for(&db.pool, id, |job| {
+        job.attempts == 1 && job.locked_by.is_none()
+    })
+    .await;
+    assert_eq!(job.status, JobState::Pending.as_str());
+    assert_eq!(job.last_error.as_deref(), Some("upstream unavailable"));
+    assert_eq!(
+        job.run_at,
+        clock.now().naive_utc() + chrono::Duration::seconds(60)
+    );
+
+    // Not due yet, so the worker leaves it alone
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(load_job(&db.pool, id).attempts, 1);
+
+    clock.advance(chrono::Duration::seconds(61));
+    let job = wait_for(&db.pool, id, |job| job.status == JobState::Dead.as_str()).await;
+    workers.shutdown(Duration::from_secs(5)).await;
+
+    assert_eq!(job.attempts, 2);
+    assert_eq!(job.last_error.as_deref(), Some("upstream unavailable"));
+    assert!(job.finished_at.is_some());
+}
+
+#[tokio::test]
+async fn test_jobs_whose_lease_expires_on_the_last_attempt_are_dead() {
+    let db = test_db();
+    let clock = Arc::new(FakeClock::new(
+        Utc.with_ymd_and_hms(2030, 1, 1, 12, 0, 0).unwrap(),
+    ));
+    let queue = Queue::new(Db::single(db.pool.clone()), clock.clone());
+
+    // Both were claimed by a worker that died; Flaky allows two attempts
+    let poison = queue.enqueue(Flaky).await.unwrap().unwrap();
+    let survivor = queue
+        .enqueue(Greet {
+            name: "alice".to_string(),
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    let crashed_at = clock.now().naive_utc() - chrono::Duration::minutes(10);
+    for (id, attempts) in [(poison, 2), (survivor, 1)] {
+        let mut conn = db.pool.get().unwrap();
+        diesel::update(jobs::table.find(id))
+            .set((
+                jobs::status.eq(JobState::Running.as_str()),
+                jobs::attempts.eq(attempts),
+                jobs::locked_by.eq("crashed"),
+                jobs::locked_at.eq(crashed_at),
+            ))
+            .execute(&mut conn)
+            .unwrap();
+    }
+
+    let runs = Arc::new(Mutex::new(Vec::new()));
+    let (flaky_runs, greet_runs) = (runs.clone(), runs.clone());
+    let workers = queue
+        .workers(fast_options(1))
+        .handle(move |_: Flaky| {
+            flaky_runs.lock().unwrap().push("flaky");
+            async { Ok(()) }
+        })
+        .handle(move |_: Greet| {
+            greet_runs.lock().unwrap().push("greet");
+            async { Ok(()) }
+        })
+        .start();
+    let job = wait_for(&db.pool, survivor, |job| {
+        job.status == JobState::Succeeded.as_str()
+    })
+    .await;
+    workers.shutdown(Duration::from_secs(5)).await;
+    assert_eq!(job.attempts, 2);
+
+    let job = load_job(&db.pool, poison);
+    assert_eq!(job.status, JobState::Dead.as_str());
+    assert_eq!(job.attempts, 2);
+    assert_eq!(
+        job.last_error.as_deref(),
+        Some("lease expired on the last attempt")
+    );
+    assert_eq!(job.finished_at, Some(clock.now().naive_utc()));
+    assert!(job.locked_by.is_none());
+    assert_eq!(*runs.lock().unwrap(), ["greet"]);
+}
+
+#[tokio::test]
+async fn test_enqueue_follows_the_surrounding_transaction() {
+    let db = test_db();
+    let mut conn = db.pool.get().unwrap();
+
+    let rolled_back = conn.transaction::<(), AppError, _>(|conn| {
+        queue::enqueue(
+            conn,
+            &Greet {
+                name: "alice".to_string(),
+            },
+            Utc::now(),
+        )?;
+        Err(AppError::Conflict("rolled back"))
+    });
+    assert!(rolled_back.is_err());
+    let count: i64 = jobs::table.count().get_result(&mut conn).unwrap();
+    assert_eq!(count, 0);
+
+    conn.transaction::<_, AppError, _>(|conn| {
+        queue::enqueue(
+            conn,
+            &Greet {
+                name: "alice".to_string(),
+            },
+            Utc::now(),
+        )
+    })
+    .unwrap();
+    let count: i64 = jobs::table.count().get_result(&mut conn).unwrap();
+    assert_eq!(count, 1);
+}
+
+#[tokio::test]
+async fn test_concurrent_workers_claim_each_job_once() {
+    #[derive(Serialize, Deserialize)]
+    struct Count(i32);
+
+    impl JobPayload for Count {
+        const KIND: &'static str = "count";
+    }
+
+    let db = test_db();
+    let queue = Queue::new(Db::single(db.pool.clone()), Arc::new(SystemClock));
+    let runs: Arc<Mutex<HashMap<i32, usize>>> = Arc::default();
+
+    let mut ids = Vec::new();
+    for n in 0..40 {
+        ids.push(queue.enqueue(Count(n)).await.unwrap().unwrap());
+    }
+
+    // Two pools, as if two server processes shared the database
+    let pools: Vec<_> = (0..2)
+        .map(|_| {
+            let runs = runs.clone();
+            queue
+                .workers(fast_options(4))
+                .handle(move |Count(n): Count| {
+                    let runs = runs.clone();
+                    async move {
+                        *runs.lock().unwrap().entry(n).or_default() += 1;
+                        tokio::time::sleep(Duration::from_millis(5)).await;
+                        Ok(())
+                    }
+                })
+                .start()
+        })
+        .collect();
+
+    for id in ids {
+        wait_for(&db.pool, id, |job| {
+            job.status == JobState::Succeeded.as_str()
+        })
+        .await;
+    }
+    for workers in pools {
+        workers.shutdown(Duration::from_secs(5)).await;
+    }
+
+    let runs = runs.lock().unwrap();
+    assert_eq!(runs.len(), 40);
+    assert!(runs.values().all(|&count| count == 1), "{:?}", runs);
+}
+
+/// Register, promote and log in an administrator
+async fn admin_token(app: &Router, pool: &DbPool) -> String {
+    let (status, body) = send(
+        app,
+        post_json(
+            "/api/auth/register",
+            json!({
+                "username": "admin",
+                "email": "admin@example.com",
+                "password": "password123",
+            }),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(
+        cli::grant_admin(pool, "admin@example.com"),
+        ExitCode::SUCCESS
+    );
+
+    let (_, body) = send(
+        app,
+        post_json(
+            "/api/auth/login",
+            json!({ "email": "admin@example.com", "password": "password123" }),
+        ),
+    )
+    .await;
+    json_field(&body, "access_token")
+}
+
+fn retry_request(id: i32, token: &str) -> Request<Body> {
+    Request::post(format!("/api/admin/jobs/{}/retry", id))
+        .header("authorization", format!("Bearer {}", token))
+        .body(Body::empty())
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_admin_lists_and_retries_dead_jobs() {
+    let db = test_db();
+    let app = App::builder()
+        .config(db.config.clone())
+        .pool(db.pool.clone())
+        .build();
+    let token = admin_token(&app, &db.pool).await;
+    let queue = Queue::new(Db::single(db.pool.clone()), Arc::new(SystemClock));
+
+    // Nothing handles greetings, so both jobs die on their first attempt
+    let alice = queue
+        .enqueue(Greet {
+            name: "alice".to_string(),
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    let bob = queue
+        .enqueue(Greet {
+            name: "bob".to_string(),
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    let workers = queue.workers(fast_options(1)).start();
+    for id in [alice, bob] {
+        wait_for(&db.pool, id, |job| job.status == JobState::Dead.as_str()).await;
+    }
+    workers.shutdown(Duration::from_secs(5)).await;
+
+    let (status, body) = send(
+        &app,
+        get_with_bearer("/api/admin/jobs?status=dead&kind=greet", &token),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let page: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(page["total"], 2);
+    assert_eq!(page["jobs"][0]["id"], bob);
+    assert_eq!(page["jobs"][0]["payload"], json!({ "name": "bob" }));
+    assert_eq!(
+        page["jobs"][0]["last_error"],
+        "no handler for job kind greet"
+    );
+
+    let uri = format!("/api/admin/jobs/{}", alice);
+    let (status, body) = send(&app, get_with_bearer(&uri, &token)).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(json_field(&body, "status"), "dead");
+
+    let (status, body) = send(&app, retry_request(alice, &token)).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let job: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(job["status"], "pending");
+    assert_eq!(job["attempts"], 0);
+
+    // Only dead jobs can be retried
+    let (status, _) = send(&app, retry_request(alice, &token)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // Bob has been queued again meanwhile, so retrying would duplicate him
+    queue
+        .enqueue(Greet {
+            name: "bob".to_string(),
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    let (status, body) = send(&app, retry_request(bob, &token)).await;
+    assert_eq!(status, StatusCode::CONFLICT, "{}", body);
+
+    let (status, _) = send(&app, get_with_bearer("/api/admin/jobs/9999", &token)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // A page whose offset would overflow is refused, not wrapped
+    for list in ["/api/admin/jobs", "/api/admin/audit-events"] {
+        let uri = format!("{}?page={}&per_page=200", list, i64::MAX);
+        let (status, body) = send(&app, get_with_bearer(&uri, &token)).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{}", body);
+        assert!(body.contains("page is too large"), "{}", body);
+    }
+}
@@ -0,0 +1,195 @@
+//! Per-route-group rate limits and their headers
+
+mod common;
+
+use std::{net::SocketAddr, sync::Arc};
+
+use axum::{
+    body::Body,
+    extract::ConnectInfo,
+    http::{header, HeaderMap, Request, StatusCode},
+    Router,
+};
+use chrono::Duration;
+use serde_json::json;
+use tower::ServiceExt;
+use webapp_backend::{
+    rate_limit::{KeyBy, Quota},
+    App, Config, FakeClock,
+};
+
+use common::{get, get_with_bearer, json_field, post_json, test_db, TestDb};
+
+fn app(db: &TestDb, config: Config, clock: Arc<FakeClock>) -> Router {
+    App::builder()
+        .config(config)
+        .pool(db.pool.clone())
+        .clock(clock)
+        .build()
+}
+
+/// `request` as if it came from `ip`
+fn from(ip: [u8; 4], mut request: Request<Body>) -> Request<Body> {
+    let peer = SocketAddr::from((ip, 4000));
+    request.extensions_mut().insert(ConnectInfo(peer));
+    request
+}
+
+async fn respond(app: &Router, request: Request<Body>) -> (StatusCode, HeaderMap, String) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let headers = response.headers().clone();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    (status, headers, String::from_utf8_lossy(&body).into_owned())
+}
+
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name).map(|value| value.to_str().unwrap())
+}
+
+fn login() -> Request<Body> {
+    let credentials = json!({ "email": "nobody@example.com", "password": "password123" });
+    post_json("/api/auth/login", credentials)
+}
+
+/// Register `username` and return its access token
+async fn register(app: &Router, username: &str) -> String {
+    let user = json!({
+        "username": username,
+        "email": format!("{}@example.com", username),
+        "password": "password123",
+    });
+    let (status, _, body) = respond(app, post_json("/api/auth/register", user)).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    json_field(&body, "access_token")
+}
+
+#[tokio::test]
+async fn test_auth_routes_are_limited_per_ip() {
+    let db = test_db();
+    let clock = Arc::new(FakeClock::default());
+    let mut config = db.config.clone();
+    config.rate_limit.auth.quota = Quota::per_minute(3);
+    let app = app(&db, config, clock.clone());
+
+    for remaining in ["2", "1", "0"] {
+        let (status, headers, _) = respond(&app, from([203, 0, 113, 7], login())).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(header(&headers, "ratelimit-limit"), Some("3"));
+        assert_eq!(header(&headers, "ratelimit-remaining"), Some(remaining));
+        assert_eq!(header(&headers, "ratelimit-policy"), Some("3;w=60"));
+    }
+
+    let (status, headers, problem) = respond(&app, from([203, 0, 113, 7], login())).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(json_field(&problem, "code"), "rate_limited");
+    assert_eq!(header(&headers, "retry-after"), Some("20"));
+    assert_eq!(header(&headers, "ratelimit-remaining"), Some("0"));
+    assert_eq!(header(&headers, "ratelimit-reset"), Some("60"));
+
+    // Other clients and the health check are unaffected
+    let (status, _, _) = respond(&app, from([198, 51, 100, 1], login())).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, headers, _) = respond(&app, from([203, 0, 113, 7], get("/health"))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(header(&headers, "ratelimit-limit").is_none());
+
+    // One request comes back every 20 seconds
+    clock.advance(Duration::seconds(20));
+    let (status, headers, _) = respond(&app, from([203, 0, 113, 7], login())).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(header(&headers, "ratelimit-remaining"), Some("0"));
+}
+
+#[tokio::test]
+async fn test_api_routes_are_limited_per_user() {
+    let db = test_db();
+    let mut config = db.config.clone();
+    config.rate_limit.api.quota = Quota::per_minute(2);
+    let app = app(&db, config, Arc::new(FakeClock::default()));
+    let alice = register(&app, "alice").await;
+    let bob = register(&app, "bob").await;
+
+    let me = |token: &str| from([203, 0, 113, 7], get_with_bearer("/api/auth/me", token));
+    for _ in 0..2 {
+        let (status, _, body) = respond(&app, me(&alice)).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+    }
+    let (status, _, _) = respond(&app, me(&alice)).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+
+    // Same address, different user
+    let (status, headers, _) = respond(&app, me(&bob)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(header(&headers, "ratelimit-remaining"), Some("1"));
+
+    // Without a session the address is counted instead
+    let (status, headers, _) = respond(&app, from([203, 0, 113, 7], get("/api/hello"))).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(header(&headers, "ratelimit-remaining"), Some("1"));
+}
+
+#[tokio::test]
+async fn test_policies_can_count_api_keys_or_be_turned_off() {
+    let db = test_db();
+    let mut config = db.config.clone();
+    config.rate_limit.api.quota = Quota::per_minute(1);
+    config.rate_limit.api.key_by = KeyBy::ApiKey;
+    config.rate_limit.api_keys = vec!["first".to_string(), "second".to_string()];
+    let app = app(&db, config.clone(), Arc::new(FakeClock::default()));
+
+    let hello = |key: &str| {
+        let request = Request::get("/api/hello")
+            .header("x-api-key", key)
+            .body(Body::empty())
+            .unwrap();
+        from([203, 0, 113, 7], request)
+    };
+    let (status, _, _) = respond(&app, hello("first")).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _, _) = respond(&app, hello("first")).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    let (status, _, _) = respond(&app, hello("second")).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Unknown keys share the address's quota instead of getting their own
+    let (status, _, _) = respond(&app, hello("made-up-1")).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _, _) = respond(&app, hello("made-up-2")).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+
+    config.rate_limit.enabled = false;
+    let app = self::app(&db, config, Arc::new(FakeClock::default()));
+    for _ in 0..3 {
+        let (status, headers, _) = respond(&app, hello("first")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(!headers.contains_key(header::RETRY_AFTER));
+        assert!(header(&headers, "ratelimit-limit").is_none());
+    }
+}
+
+/// Runs against the server at `TEST_REDIS_URL`, e.g. `redis://localhost:6379`
+#[cfg(feature = "redis")]
+#[tokio::test]
+async fn test_redis_store_matches_the_memory_store() {
+    use webapp_backend::rate_limit::{MemoryStore, RateLimitStore, RedisStore};
+
+    let url =
+        std::env::var("TEST_REDIS_URL").unwrap_or_else(|_| "redis://localhost:6379".to_string());
+    let redis = RedisStore::from_url(&url).unwrap();
+    let memory = MemoryStore::default();
+    let key = format!("rate_limit:test:{}", uuid::Uuid::new_v4().simple());
+    let quota = Quota::per_minute(2);
+
+    let now = chrono::Utc::now().timestamp_millis();
+    for at in [now, now, now, now + 30_000, now + 30_000] {
+        let expected = memory.acquire(&key, &quota, at).await.unwrap();
+        let outcome = redis
+            .acquire(&key, &quota, at)
+            .await
+            .unwrap_or_else(|e| panic!("{} at {}", e, url));
+        assert_eq!(outcome, expected);
+    }
+}
@@ -0,0 +1,197 @@
+//! The refresh token in an HttpOnly cookie instead of the JSON body
+
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, HeaderMap, Request, StatusCode},
+    Router,
+};
+use serde_json::json;
+use tower::ServiceExt;
+use webapp_backend::{auth::RefreshTokenTransport, App, Config};
+
+use common::{json_field, test_db, TestDb};
+
+fn app(db: &TestDb, config: Config) -> Router {
+    App::builder().config(config).pool(db.pool.clone()).build()
+}
+
+async fn respond(app: &Router, request: Request<Body>) -> (StatusCode, HeaderMap, String) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let headers = response.headers().clone();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    (status, headers, String::from_utf8_lossy(&body).into_owned())
+}
+
+fn post(uri: &str, headers: &[(&str, &str)], body: Option<serde_json::Value>) -> Request<Body> {
+    let mut request = Request::post(uri);
+    for (name, value) in headers {
+        request = request.header(*name, *value);
+    }
+    match body {
+        Some(body) => request
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap(),
+        None => request.body(Body::empty()).unwrap(),
+    }
+}
+
+/// The `Set-Cookie` value for `name`
+fn set_cookie<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get_all(header::SET_COOKIE)
+        .iter()
+        .map(|value| value.to_str().unwrap())
+        .find(|cookie| cookie.starts_with(&format!("{}=", name)))
+}
+
+fn has_field(body: &str, field: &str) -> bool {
+    let value: serde_json::Value = serde_json::from_str(body).unwrap();
+    value.get(field).is_some()
+}
+
+fn registration() -> serde_json::Value {
+    json!({ "username": "alice", "email": "alice@example.com", "password": "password123" })
+}
+
+fn credentials() -> serde_json::Value {
+    json!({ "email": "alice@example.com", "password": "password123" })
+}
+
+/// `Cookie` header with the refresh token and a CSRF token, and that token
+async fn cookies_with_csrf(app: &Router, refresh_cookie: &str) -> (String, String) {
+    // Bound to the refresh token's user, as no access token is sent
+    let refresh_cookie = refresh_cookie.split(';').next().unwrap();
+    let request = Request::get("/api/auth/csrf")
+        .header(header::COOKIE, refresh_cookie)
+        .body(Body::empty())
+        .unwrap();
+    let (_, headers, body) = respond(app, request).await;
+    let csrf = set_cookie(&headers, "csrf_token").unwrap();
+    let cookies = format!("{}; {}", refresh_cookie, csrf.split(';').next().unwrap());
+    (cookies, json_field(&body, "token"))
+}
+
+#[tokio::test]
+async fn test_cookie_transport_moves_the_refresh_token_out_of_the_body() {
+    let db = test_db();
+    let app = app(&db, db.config.clone());
+    let transport = [("x-refresh-token-transport", "cookie")];
+
+    for (uri, body) in [
+        ("/api/auth/register", registration()),
+        ("/api/auth/login", credentials()),
+    ] {
+        let (status, headers, body) = respond(&app, post(uri, &transport, Some(body))).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert!(!has_field(&body, "refresh_token"), "{}", body);
+        assert!(has_field(&body, "access_token"), "{}", body);
+
+        let cookie = set_cookie(&headers, "refresh_token").unwrap();
+        let (attributes, max_age) = cookie.rsplit_once("; Max-Age=").unwrap();
+        assert!(
+            attributes.ends_with("; HttpOnly; SameSite=Lax; Path=/api/auth"),
+            "{}",
+            cookie
+        );
+        // Secure even though the dev profile's session cookie is not
+        let max_age = max_age.strip_suffix("; Secure").unwrap();
+        // Until the refresh token expires, 30 days from now
+        let max_age: i64 = max_age.parse().unwrap();
+        assert!(
+            (30 * 24 * 60 * 60 - 5..=30 * 24 * 60 * 60).contains(&max_age),
+            "{}",
+            cookie
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_refresh_and_logout_read_the_cookie() {
+    let db = test_db();
+    let app = app(&db, db.config.clone());
+    let transport = ("x-refresh-token-transport", "cookie");
+
+    let (_, headers, _) = respond(
+        &app,
+        post("/api/auth/register", &[transport], Some(registration())),
+    )
+    .await;
+    let refresh_cookie = set_cookie(&headers, "refresh_token").unwrap().to_string();
+    let (cookies, token) = cookies_with_csrf(&app, &refresh_cookie).await;
+
+    // The cookie alone is not enough: it needs the CSRF token
+    let without_token = [("cookie", cookies.as_str()), transport];
+    let (status, _, body) = respond(&app, post("/api/auth/refresh", &without_token, None)).await;
+    assert_eq!(status, StatusCode::FORBIDDEN, "{}", body);
+    assert_eq!(json_field(&body, "code"), "csrf_failed");
+
+    let headers = [
+        ("cookie", cookies.as_str()),
+        ("x-csrf-token", &token),
+        transport,
+    ];
+    let (status, response_headers, body) =
+        respond(&app, post("/api/auth/refresh", &headers, None)).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert!(!has_field(&body, "refresh_token"), "{}", body);
+    assert!(set_cookie(&response_headers, "access_token").is_some());
+    assert!(set_cookie(&response_headers, "refresh_token").is_some());
+
+    // An empty JSON object works like an empty body
+    let (status, headers_out, _) =
+        respond(&app, post("/api/auth/logout", &headers, Some(json!({})))).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    for name in ["access_token", "refresh_token"] {
+        let cookie = set_cookie(&headers_out, name).unwrap();
+        assert!(cookie.contains("Max-Age=0"), "{}", cookie);
+    }
+
+    let (status, _, _) = respond(&app, post("/api/auth/refresh", &headers, None)).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_transport_is_configured_and_overridden_per_request() {
+    let db = test_db();
+    let config = Config {
+        refresh_token_transport: RefreshTokenTransport::Cookie,
+        ..db.config.clone()
+    };
+    let app = app(&db, config);
+
+    let (status, headers, body) =
+        respond(&app, post("/api/auth/register", &[], Some(registration()))).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert!(!has_field(&body, "refresh_token"), "{}", body);
+    assert!(set_cookie(&headers, "refresh_token").is_some());
+
+    // Native clients ask for the body
+    let body_transport = [("x-refresh-token-transport", "body")];
+    let (status, headers, body) = respond(
+        &app,
+        post("/api/auth/login", &body_transport, Some(credentials())),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert!(set_cookie(&headers, "refresh_token").is_none());
+    let refresh_token = json_field(&body, "refresh_token");
+
+    // ...and keep sending the token in the body, without a CSRF token
+    let body = json!({ "refresh_token": refresh_token });
+    let (status, _, body) =
+        respond(&app, post("/api/auth/refresh", &body_transport, Some(body))).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(json_field(&body, "refresh_token"), refresh_token);
+
+    let unknown = [("x-refresh-token-transport", "localstorage")];
+    let (status, _, body) =
+        respond(&app, post("/api/auth/login", &unknown, Some(credentials()))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(json_field(&body, "code"), "invalid_header");
+}
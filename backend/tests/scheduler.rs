@@ -0,0 +1,124 @@
+//! The built-in scheduled jobs and their admin status
+
+mod common;
+
+use std::{process::ExitCode, sync::Arc, time::Duration};
+
+use axum::{http::StatusCode, Router};
+use serde_json::json;
+use tokio_util::sync::CancellationToken;
+use webapp_backend::{
+    cli,
+    db::Db,
+    repositories::DieselSessionRepository,
+    scheduler::{Job, JobRegistry, PurgeExpiredTokens, Scheduler},
+    App, FakeClock,
+};
+
+use common::{get_with_bearer, json_field, post_json, send, test_db};
+
+/// Register a user and return the response body
+async fn register(app: &Router, username: &str) -> String {
+    let (status, body) = send(
+        app,
+        post_json(
+            "/api/auth/register",
+            json!({
+                "username": username,
+                "email": format!("{}@example.com", username),
+                "password": "password123",
+            }),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    body
+}
+
+#[tokio::test]
+async fn test_purge_deletes_only_expired_tokens() {
+    let db = test_db();
+    let clock = Arc::new(FakeClock::default());
+    let app = App::builder()
+        .config(db.config.clone())
+        .pool(db.pool.clone())
+        .clock(clock.clone())
+        .build();
+
+    let alice = json_field(&register(&app, "alice").await, "refresh_token");
+    clock.advance(chrono::Duration::days(20));
+    let bob = json_field(&register(&app, "bob").await, "refresh_token");
+    clock.advance(chrono::Duration::days(11));
+
+    let sessions = Arc::new(DieselSessionRepository);
+    let job = PurgeExpiredTokens::new(Db::single(db.pool.clone()), sessions, clock.clone());
+    let summary = job.run(CancellationToken::new()).await.unwrap();
+    assert_eq!(summary, "purged 1 expired refresh tokens");
+
+    // Alice's token is gone rather than merely expired
+    let (status, body) = send(
+        &app,
+        post_json("/api/auth/refresh", json!({ "refresh_token": alice })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(json_field(&body, "code"), "invalid_refresh_token");
+    let (status, _) = send(
+        &app,
+        post_json("/api/auth/refresh", json!({ "refresh_token": bob })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_admin_sees_last_run_of_scheduled_jobs() {
+    let db = test_db();
+    let jobs = JobRegistry::default();
+    let app = App::builder()
+        .config(db.config.clone())
+        .pool(db.pool.clone())
+        .jobs(jobs.clone())
+        .build();
+
+    register(&app, "admin").await;
+    assert_eq!(
+        cli::grant_admin(&db.pool, "admin@example.com"),
+        ExitCode::SUCCESS
+    );
+    let (_, body) = send(
+        &app,
+        post_json(
+            "/api/auth/login",
+            json!({ "email": "admin@example.com", "password": "password123" }),
+        ),
+    )
+    .await;
+    let access_token = json_field(&body, "access_token");
+
+    let sessions = Arc::new(DieselSessionRepository);
+    let scheduler = Scheduler::new(jobs)
+        .job(
+            PurgeExpiredTokens::new(
+                Db::single(db.pool.clone()),
+                sessions,
+                Arc::new(FakeClock::default()),
+            ),
+            "* * * * * *",
+            Duration::ZERO,
+        )
+        .unwrap()
+        .start();
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    scheduler.shutdown(Duration::from_secs(5)).await;
+
+    let (status, body) = send(&app, get_with_bearer("/api/admin/scheduler", &access_token)).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let statuses: serde_json::Value = serde_json::from_str(&body).unwrap();
+    let purge = &statuses[0];
+    assert_eq!(purge["name"], "purge_expired_tokens");
+    assert_eq!(purge["schedule"], "* * * * * *");
+    assert_eq!(purge["last_succeeded"], true);
+    assert_eq!(purge["last_message"], "purged 0 expired refresh tokens");
+    assert!(purge["runs"].as_u64().unwrap() >= 1);
+}
@@ -0,0 +1,230 @@
+//! Per-profile security headers, the configurable CORS policy and the
+//! session cookie's attributes
+
+mod common;
+
+use std::collections::BTreeMap;
+
+use axum::{
+    body::Body,
+    http::{header, HeaderMap, Method, Request, StatusCode},
+    Router,
+};
+use serde_json::json;
+use tower::ServiceExt;
+use webapp_backend::{
+    config::{Overrides, Profile},
+    App, Config,
+};
+
+use common::{get, post_json, send, test_db, TestDb};
+
+/// A config as `serve` would load it, pointed at the test database
+fn load(db: &TestDb, vars: &[(&str, &str)]) -> Config {
+    let env: BTreeMap<_, _> = [
+        ("APP_DATABASE__URL", db.config.database_url.as_str()),
+        ("JWT_SECRET", "0123456789abcdef0123456789abcdef"),
+        ("MAIL_TRANSPORT", "file"),
+        ("MAIL_DIR", "target/test-mail"),
+    ]
+    .into_iter()
+    .chain(vars.iter().copied())
+    .map(|(name, value)| (name.to_string(), value.to_string()))
+    .collect();
+    Config::load_from(&Overrides::default(), &env).unwrap()
+}
+
+fn app(db: &TestDb, config: Config) -> Router {
+    App::builder().config(config).pool(db.pool.clone()).build()
+}
+
+async fn respond(app: &Router, request: Request<Body>) -> (StatusCode, HeaderMap) {
+    let response = app.clone().oneshot(request).await.unwrap();
+    (response.status(), response.headers().clone())
+}
+
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name).map(|value| value.to_str().unwrap())
+}
+
+#[tokio::test]
+async fn test_security_header_defaults_per_profile() {
+    let db = test_db();
+
+    for profile in ["dev", "test", "prod"] {
+        let config = load(&db, &[("APP_PROFILE", profile)]);
+        let (status, headers) = respond(&app(&db, config), get("/health")).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let hsts = header(&headers, "strict-transport-security");
+        match profile {
+            "prod" => assert_eq!(hsts, Some("max-age=31536000; includeSubDomains")),
+            _ => assert_eq!(hsts, None, "{} sends HSTS", profile),
+        }
+        assert_eq!(
+            header(&headers, "content-security-policy"),
+            Some("default-src 'none'; frame-ancestors 'none'")
+        );
+        assert_eq!(header(&headers, "x-content-type-options"), Some("nosniff"));
+        assert_eq!(header(&headers, "referrer-policy"), Some("no-referrer"));
+        assert!(header(&headers, "permissions-policy").is_some_and(|p| p.contains("camera=()")));
+    }
+
+    // Headers can be changed or left out individually
+    let config = load(
+        &db,
+        &[
+            ("APP_PROFILE", "prod"),
+            ("APP_SECURITY_HEADERS__HSTS", ""),
+            ("APP_SECURITY_HEADERS__REFERRER_POLICY", "same-origin"),
+        ],
+    );
+    let (_, headers) = respond(&app(&db, config), get("/health")).await;
+    assert_eq!(header(&headers, "strict-transport-security"), None);
+    assert_eq!(header(&headers, "referrer-policy"), Some("same-origin"));
+}
+
+#[tokio::test]
+async fn test_api_docs_keep_their_own_content_security_policy() {
+    let db = test_db();
+    let config = load(&db, &[("API_DOCS", "true")]);
+
+    let (status, headers) = respond(&app(&db, config), get("/api/docs")).await;
+    assert_eq!(status, StatusCode::OK);
+    let policy = header(&headers, "content-security-policy").unwrap();
+    assert!(
+        policy.contains("script-src https://cdn.jsdelivr.net"),
+        "{}",
+        policy
+    );
+    assert_eq!(header(&headers, "x-content-type-options"), Some("nosniff"));
+}
+
+#[tokio::test]
+async fn test_cors_allows_only_configured_origins() {
+    let db = test_db();
+    let config = load(
+        &db,
+        &[
+            (
+                "APP_CORS__ALLOWED_ORIGINS",
+                "https://app.example.com, https://admin.example.com",
+            ),
+            ("APP_CORS__ALLOWED_METHODS", "GET,POST"),
+        ],
+    );
+    let app = app(&db, config);
+
+    let preflight = |origin: &str, method: &str| {
+        Request::builder()
+            .method(Method::OPTIONS)
+            .uri("/api/auth/me")
+            .header(header::ORIGIN, origin)
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, method)
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let (_, headers) = respond(&app, preflight("https://admin.example.com", "POST")).await;
+    assert_eq!(
+        header(&headers, "access-control-allow-origin"),
+        Some("https://admin.example.com")
+    );
+    assert_eq!(
+        header(&headers, "access-control-allow-credentials"),
+        Some("true")
+    );
+    assert_eq!(
+        header(&headers, "access-control-allow-methods"),
+        Some("GET,POST")
+    );
+
+    let (_, headers) = respond(&app, preflight("http://localhost:5173", "GET")).await;
+    assert_eq!(header(&headers, "access-control-allow-origin"), None);
+}
+
+#[tokio::test]
+async fn test_session_cookie_uses_configured_name_and_attributes() {
+    let db = test_db();
+    let config = load(
+        &db,
+        &[
+            ("APP_PROFILE", "prod"),
+            ("APP_COOKIE__NAME", "session"),
+            ("APP_COOKIE__DOMAIN", "example.com"),
+            ("APP_COOKIE__SAME_SITE", "strict"),
+        ],
+    );
+    assert_eq!(config.profile, Profile::Prod);
+    let app = app(&db, config);
+
+    let user = json!({
+        "username": "alice",
+        "email": "alice@example.com",
+        "password": "password123",
+    });
+    let (status, _) = respond(&app, post_json("/api/auth/register", user)).await;
+    assert_eq!(status, StatusCode::OK);
+    let credentials = json!({ "email": "alice@example.com", "password": "password123" });
+    let (status, headers) = respond(&app, post_json("/api/auth/login", credentials)).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let cookie = header(&headers, "set-cookie").unwrap();
+    assert!(cookie.starts_with("session="), "{}", cookie);
+    let attributes = "HttpOnly; SameSite=Strict; Path=/; Max-Age=900; Secure; Domain=example.com";
+    assert!(cookie.ends_with(attributes), "{}", cookie);
+
+    // The renamed cookie still authenticates
+    let session = cookie.split(';').next().unwrap();
+    let me = Request::builder()
+        .uri("/api/auth/me")
+        .header(header::COOKIE, format!("access_token=stale; {}", session))
+        .body(Body::empty())
+        .unwrap();
+    let (status, _) = send(&app, me).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_served_spec_names_the_configured_cookies() {
+    let db = test_db();
+    let config = load(
+        &db,
+        &[
+            ("APP_COOKIE__NAME", "session"),
+            ("APP_COOKIE__REFRESH_NAME", "session_refresh"),
+        ],
+    );
+    let app = app(&db, config);
+
+    let (status, body) = send(&app, get("/api/openapi.json")).await;
+    assert_eq!(status, StatusCode::OK);
+    let spec: serde_json::Value = serde_json::from_str(&body).unwrap();
+    let schemes = &spec["components"]["securitySchemes"];
+    assert_eq!(schemes["cookie_auth"]["name"], "session");
+    assert_eq!(schemes["refresh_cookie"]["name"], "session_refresh");
+}
+
+#[test]
+fn test_invalid_browser_policy_is_refused() {
+    let env: BTreeMap<_, _> = [
+        ("APP_CORS__ALLOWED_ORIGINS", "*,https://example.com/"),
+        ("APP_COOKIE__SAME_SITE", "none"),
+        ("APP_COOKIE__NAME", "my session"),
+    ]
+    .into_iter()
+    .map(|(name, value)| (name.to_string(), value.to_string()))
+    .collect();
+
+    let error = Config::load_from(&Overrides::default(), &env)
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("* cannot send cookies"), "{}", error);
+    assert!(
+        error.contains("\"https://example.com/\" is not an origin"),
+        "{}",
+        error
+    );
+    assert!(error.contains("none requires cookie.secure"), "{}", error);
+    assert!(error.contains("cookie.name = \"my session\""), "{}", error);
+}
@@ -0,0 +1,93 @@
+mod common;
+
+use std::sync::Arc;
+
+use axum::{http::StatusCode, Router};
+use chrono::Duration;
+use serde_json::json;
+use webapp_backend::{App, FakeClock};
+
+use common::{get_with_bearer, json_field, post_json, send, test_db, TestDb};
+
+fn app_with_clock(db: &TestDb, clock: Arc<FakeClock>) -> Router {
+    App::builder()
+        .config(db.config.clone())
+        .pool(db.pool.clone())
+        .clock(clock)
+        .build()
+}
+
+/// Register a user and return the response body
+async fn register(app: &Router) -> String {
+    let (status, body) = send(
+        app,
+        post_json(
+            "/api/auth/register",
+            json!({ "username": "alice", "email": "alice@example.com", "password": "password123" }),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    body
+}
+
+async fn refresh(app: &Router, refresh_token: &str) -> (StatusCode, String) {
+    send(
+        app,
+        post_json(
+            "/api/auth/refresh",
+            json!({ "refresh_token": refresh_token }),
+        ),
+    )
+    .await
+}
+
+#[tokio::test]
+async fn test_refresh_succeeds_before_expiry() {
+    let db = test_db();
+    let clock = Arc::new(FakeClock::default());
+    let app = app_with_clock(&db, clock.clone());
+
+    let refresh_token = json_field(&register(&app).await, "refresh_token");
+    clock.advance(Duration::days(29));
+
+    let (status, body) = refresh(&app, &refresh_token).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(json_field(&body, "refresh_token"), refresh_token);
+}
+
+#[tokio::test]
+async fn test_refresh_fails_after_expiry() {
+    let db = test_db();
+    let clock = Arc::new(FakeClock::default());
+    let app = app_with_clock(&db, clock.clone());
+
+    let refresh_token = json_field(&register(&app).await, "refresh_token");
+    clock.advance(Duration::days(31));
+
+    let (status, body) = refresh(&app, &refresh_token).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(json_field(&body, "code"), "refresh_token_expired");
+
+    // The expired token is deleted, so a retry no longer recognises it
+    let (status, body) = refresh(&app, &refresh_token).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(json_field(&body, "code"), "invalid_refresh_token");
+}
+
+#[tokio::test]
+async fn test_access_token_expires() {
+    let db = test_db();
+    let clock = Arc::new(FakeClock::default());
+    let app = app_with_clock(&db, clock.clone());
+
+    let access_token = json_field(&register(&app).await, "access_token");
+
+    let (status, _) = send(&app, get_with_bearer("/api/auth/me", &access_token)).await;
+    assert_eq!(status, StatusCode::OK);
+
+    clock.advance(Duration::minutes(17));
+    let (status, body) = send(&app, get_with_bearer("/api/auth/me", &access_token)).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(json_field(&body, "code"), "invalid_token");
+}
@@ -0,0 +1,189 @@
+//! `register`, `login` and `refresh` issue sessions the same way
+
+mod common;
+
+use std::net::SocketAddr;
+
+use axum::{
+    body::Body,
+    extract::ConnectInfo,
+    http::{header, HeaderMap, Request, StatusCode},
+    Router,
+};
+use serde_json::{json, Value};
+use tower::ServiceExt;
+use webapp_backend::{
+    repositories::{DieselSessionRepository, SessionRepository},
+    App,
+};
+
+use common::test_db;
+
+/// Keys of a JSON body, with the user object's keys nested
+fn body_shape(body: &Value) -> Vec<String> {
+    let mut keys = Vec::new();
+    for (key, value) in body.as_object().unwrap() {
+        keys.push(key.clone());
+        if let Some(fields) = value.as_object() {
+            keys.extend(fields.keys().map(|field| format!("{}.{}", key, field)));
+        }
+    }
+    keys.sort();
+    keys
+}
+
+/// Each `Set-Cookie` without its value; the refresh cookie's `Max-Age`
+/// counts down to its expiry, so it is left out too
+fn cookie_shape(headers: &HeaderMap) -> Vec<String> {
+    let mut cookies: Vec<String> = headers
+        .get_all(header::SET_COOKIE)
+        .iter()
+        .map(|cookie| {
+            let cookie = cookie.to_str().unwrap();
+            let (name, attributes) = cookie.split_once('=').unwrap();
+            let (_, attributes) = attributes.split_once("; ").unwrap();
+            let attributes: Vec<&str> = attributes
+                .split("; ")
+                .filter(|attribute| name != "refresh_token" || !attribute.starts_with("Max-Age="))
+                .collect();
+            format!("{}; {}", name, attributes.join("; "))
+        })
+        .collect();
+    cookies.sort();
+    cookies
+}
+
+struct Issued {
+    body: Value,
+    cookies: Vec<String>,
+    /// `name=value` pairs to send back
+    cookie_header: String,
+}
+
+async fn issue(app: &Router, uri: &str, transport: &str, cookie: &str, body: Value) -> Issued {
+    let mut request = Request::post(uri)
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(header::USER_AGENT, "session-test/1.0")
+        .header("x-refresh-token-transport", transport)
+        .extension(ConnectInfo(SocketAddr::from(([203, 0, 113, 7], 4000))));
+    if !cookie.is_empty() {
+        request = request.header(header::COOKIE, cookie);
+    }
+    let request = request.body(Body::from(body.to_string())).unwrap();
+
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let headers = response.headers().clone();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let body: Value = serde_json::from_slice(&bytes).unwrap();
+    assert_eq!(status, StatusCode::OK, "{} {}", uri, body);
+
+    let cookie_header = headers
+        .get_all(header::SET_COOKIE)
+        .iter()
+        .map(|cookie| cookie.to_str().unwrap().split(';').next().unwrap())
+        .collect::<Vec<_>>()
+        .join("; ");
+    Issued {
+        cookies: cookie_shape(&headers),
+        body,
+        cookie_header,
+    }
+}
+
+#[tokio::test]
+async fn test_every_flow_returns_the_same_session_shape() {
+    let db = test_db();
+    // CSRF is covered elsewhere; here the refresh cookie is read on its own
+    let mut config = db.config.clone();
+    config.csrf.enabled = false;
+    let app = App::builder().config(config).pool(db.pool.clone()).build();
+
+    for (transport, username) in [("body", "alice"), ("cookie", "bob")] {
+        let email = format!("{}@example.com", username);
+        let registration = json!({
+            "username": username,
+            "email": email,
+            "password": "password123",
+        });
+        let registered = issue(&app, "/api/auth/register", transport, "", registration).await;
+
+        let credentials = json!({ "email": email, "password": "password123" });
+        let logged_in = issue(&app, "/api/auth/login", transport, "", credentials).await;
+
+        let refresh_body = match transport {
+            "body" => json!({ "refresh_token": logged_in.body["refresh_token"] }),
+            _ => json!({}),
+        };
+        let refreshed = issue(
+            &app,
+            "/api/auth/refresh",
+            transport,
+            &logged_in.cookie_header,
+            refresh_body,
+        )
+        .await;
+
+        for (flow, issued) in [("login", &logged_in), ("refresh", &refreshed)] {
+            assert_eq!(
+                body_shape(&issued.body),
+                body_shape(&registered.body),
+                "{} body with {} transport",
+                flow,
+                transport
+            );
+            assert_eq!(
+                issued.cookies, registered.cookies,
+                "{} cookies with {} transport",
+                flow, transport
+            );
+        }
+
+        let expected_cookies = match transport {
+            "body" => vec!["access_token; HttpOnly; SameSite=Lax; Path=/; Max-Age=900"],
+            _ => vec![
+                "access_token; HttpOnly; SameSite=Lax; Path=/; Max-Age=900",
+                "refresh_token; HttpOnly; SameSite=Lax; Path=/api/auth; Secure",
+            ],
+        };
+        assert_eq!(registered.cookies, expected_cookies);
+        assert_eq!(
+            registered.body.get("refresh_token").is_some(),
+            transport == "body"
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_sessions_record_the_client_and_last_refresh() {
+    let db = test_db();
+    let app = App::builder()
+        .config(db.config.clone())
+        .pool(db.pool.clone())
+        .build();
+    let sessions = DieselSessionRepository;
+    let mut conn = db.pool.get().unwrap();
+
+    let registration = json!({
+        "username": "alice",
+        "email": "alice@example.com",
+        "password": "password123",
+    });
+    let registered = issue(&app, "/api/auth/register", "body", "", registration).await;
+    let refresh_token = registered.body["refresh_token"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let session = sessions.find(&mut conn, &refresh_token).unwrap().unwrap();
+    assert_eq!(session.ip_address.as_deref(), Some("203.0.113.7"));
+    assert_eq!(session.user_agent.as_deref(), Some("session-test/1.0"));
+    assert_eq!(session.last_used_at, None);
+
+    let body = json!({ "refresh_token": refresh_token });
+    issue(&app, "/api/auth/refresh", "body", "", body).await;
+    let session = sessions.find(&mut conn, &refresh_token).unwrap().unwrap();
+    assert!(session.last_used_at.is_some());
+}
@@ -0,0 +1,191 @@
+//! Soft-deleted users and the `updated_at` trigger
+
+mod common;
+
+use std::{process::ExitCode, time::Duration};
+
+use axum::{
+    body::Body,
+    http::{Method, Request, StatusCode},
+    Router,
+};
+use diesel::prelude::*;
+use serde_json::json;
+use webapp_backend::{cli, db::schema::users, models::User, App};
+
+use common::{get_with_bearer, json_field, post_json, send, test_db};
+
+fn admin_request(method: Method, uri: &str, token: &str) -> Request<Body> {
+    Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("authorization", format!("Bearer {}", token))
+        .body(Body::empty())
+        .unwrap()
+}
+
+/// Register a user and return their id and the body of the login response
+async fn register(app: &Router, username: &str) -> (i32, String) {
+    let (status, body) = send(
+        app,
+        post_json(
+            "/api/auth/register",
+            json!({
+                "username": username,
+                "email": format!("{}@example.com", username),
+                "password": "password123",
+            }),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let value: serde_json::Value = serde_json::from_str(&body).unwrap();
+    (value["user"]["id"].as_i64().unwrap() as i32, body)
+}
+
+async fn login(app: &Router, username: &str) -> (StatusCode, String) {
+    send(
+        app,
+        post_json(
+            "/api/auth/login",
+            json!({ "email": format!("{}@example.com", username), "password": "password123" }),
+        ),
+    )
+    .await
+}
+
+#[tokio::test]
+async fn test_soft_deleted_user_is_treated_as_nonexistent_until_restored() {
+    let db = test_db();
+    let app = App::builder()
+        .config(db.config.clone())
+        .pool(db.pool.clone())
+        .build();
+
+    register(&app, "admin").await;
+    assert_eq!(
+        cli::grant_admin(&db.pool, "admin@example.com"),
+        ExitCode::SUCCESS
+    );
+    let (_, body) = login(&app, "admin").await;
+    let admin_token = json_field(&body, "access_token");
+
+    let (alice_id, body) = register(&app, "alice").await;
+    let alice_access = json_field(&body, "access_token");
+    let alice_refresh = json_field(&body, "refresh_token");
+
+    let uri = format!("/api/admin/users/{}", alice_id);
+    let (status, body) = send(&app, admin_request(Method::DELETE, &uri, &admin_token)).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let (status, _) = send(&app, admin_request(Method::DELETE, &uri, &admin_token)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, _) = login(&app, "alice").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = send(
+        &app,
+        post_json(
+            "/api/auth/refresh",
+            json!({ "refresh_token": alice_refresh }),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = send(&app, get_with_bearer("/api/auth/me", &alice_access)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // The row is still there, and still holds on to its email
+    let (status, _) = send(
+        &app,
+        post_json(
+            "/api/auth/register",
+            json!({ "username": "alice2", "email": "alice@example.com", "password": "password123" }),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    let restore = format!("/api/admin/users/{}/restore", alice_id);
+    let (status, body) = send(&app, admin_request(Method::POST, &restore, &admin_token)).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let (status, _) = send(&app, admin_request(Method::POST, &restore, &admin_token)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, _) = login(&app, "alice").await;
+    assert_eq!(status, StatusCode::OK);
+    // Sessions revoked by the delete stay revoked
+    let (status, _) = send(
+        &app,
+        post_json(
+            "/api/auth/refresh",
+            json!({ "refresh_token": alice_refresh }),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_deleting_users_requires_admin() {
+    let db = test_db();
+    let app = App::builder()
+        .config(db.config.clone())
+        .pool(db.pool.clone())
+        .build();
+
+    let (alice_id, body) = register(&app, "alice").await;
+    let token = json_field(&body, "access_token");
+
+    let uri = format!("/api/admin/users/{}", alice_id);
+    let (status, _) = send(&app, admin_request(Method::DELETE, &uri, &token)).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+}
+
+#[test]
+fn test_updated_at_follows_every_update() {
+    let db = test_db();
+    let mut conn = db.pool.get().unwrap();
+
+    let user: User = diesel::insert_into(users::table)
+        .values((
+            users::username.eq("alice"),
+            users::email.eq("alice@example.com"),
+            users::password_hash.eq("hash"),
+        ))
+        .returning(User::as_returning())
+        .get_result(&mut conn)
+        .unwrap();
+
+    std::thread::sleep(Duration::from_millis(20));
+    let updated: User = diesel::update(users::table.find(user.id))
+        .set(users::is_admin.eq(true))
+        .returning(User::as_returning())
+        .get_result(&mut conn)
+        .unwrap();
+    // SQLite's trigger runs after the statement, so read the row back
+    let updated_at: chrono::NaiveDateTime = users::table
+        .find(user.id)
+        .select(users::updated_at)
+        .first(&mut conn)
+        .unwrap();
+    assert!(updated.is_admin);
+    assert!(
+        updated_at > user.updated_at,
+        "{} <= {}",
+        updated_at,
+        user.updated_at
+    );
+
+    // An explicit value wins over the trigger
+    let pinned = user.created_at;
+    diesel::update(users::table.find(user.id))
+        .set((users::is_active.eq(false), users::updated_at.eq(pinned)))
+        .execute(&mut conn)
+        .unwrap();
+    let updated_at: chrono::NaiveDateTime = users::table
+        .find(user.id)
+        .select(users::updated_at)
+        .first(&mut conn)
+        .unwrap();
+    assert_eq!(updated_at, pinned);
+}
@@ -0,0 +1,393 @@
+//! Webhook registration, signed deliveries, retries and redelivery
+
+mod common;
+
+use std::{
+    collections::VecDeque,
+    net::SocketAddr,
+    process::ExitCode,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use axum::{
+    body::Body,
+    extract::State,
+    http::{HeaderMap, Method, Request, StatusCode},
+    routing::post,
+    Router,
+};
+use serde_json::{json, Value};
+use webapp_backend::{
+    cli,
+    db::{Db, DbPool},
+    queue::{Queue, QueueOptions, WorkersHandle},
+    webhooks::{signature, DeliverWebhook, Deliverer},
+    App, SystemClock,
+};
+
+use common::{get_with_bearer, json_field, post_json, send, test_db};
+
+/// One request received by [`Receiver`]
+#[derive(Clone, Debug)]
+struct Hit {
+    headers: HeaderMap,
+    body: String,
+}
+
+impl Hit {
+    fn header(&self, name: &str) -> &str {
+        self.headers.get(name).unwrap().to_str().unwrap()
+    }
+
+    fn json(&self) -> Value {
+        serde_json::from_str(&self.body).unwrap()
+    }
+}
+
+/// A local endpoint that records requests and answers with queued statuses,
+/// then 200
+#[derive(Clone, Default)]
+struct Receiver {
+    hits: Arc<Mutex<Vec<Hit>>>,
+    statuses: Arc<Mutex<VecDeque<StatusCode>>>,
+}
+
+impl Receiver {
+    async fn start(statuses: &[StatusCode]) -> (Self, SocketAddr) {
+        let receiver = Self::default();
+        receiver.statuses.lock().unwrap().extend(statuses);
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new()
+            .route("/hook", post(Self::receive))
+            .with_state(receiver.clone());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        (receiver, addr)
+    }
+
+    async fn receive(State(receiver): State<Self>, headers: HeaderMap, body: String) -> StatusCode {
+        receiver.hits.lock().unwrap().push(Hit { headers, body });
+        receiver
+            .statuses
+            .lock()
+            .unwrap()
+            .pop_front()
+            .unwrap_or(StatusCode::OK)
+    }
+
+    fn hits(&self) -> Vec<Hit> {
+        self.hits.lock().unwrap().clone()
+    }
+
+    /// Wait until `count` requests arrived
+    async fn wait_for(&self, count: usize) -> Vec<Hit> {
+        for _ in 0..250 {
+            let hits = self.hits();
+            if hits.len() >= count {
+                return hits;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!(
+            "Expected {} webhook requests, got {}",
+            count,
+            self.hits().len()
+        );
+    }
+}
+
+/// The app and workers that deliver webhooks, retrying without delay
+fn start_app(pool: &DbPool, config: webapp_backend::Config) -> (Router, WorkersHandle) {
+    let queue = Queue::new(Db::single(pool.clone()), Arc::new(SystemClock));
+    let app = App::builder()
+        .config(config)
+        .pool(pool.clone())
+        .queue(queue.clone())
+        .build();
+
+    let deliverer = Deliverer::new(Db::single(pool.clone()), Arc::new(SystemClock));
+    let options = QueueOptions {
+        poll_interval: Duration::from_millis(20),
+        backoff_base: Duration::ZERO,
+        ..QueueOptions::default()
+    };
+    let workers = queue
+        .workers(options)
+        .handle(move |job: DeliverWebhook| {
+            let deliverer = deliverer.clone();
+            async move { deliverer.deliver(job).await }
+        })
+        .start();
+    (app, workers)
+}
+
+async fn register(app: &Router, username: &str) -> String {
+    let (status, body) = send(
+        app,
+        post_json(
+            "/api/auth/register",
+            json!({
+                "username": username,
+                "email": format!("{}@example.com", username),
+                "password": "password123",
+            }),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    json_field(&body, "access_token")
+}
+
+/// Register, promote and log in an administrator
+async fn admin_token(app: &Router, pool: &DbPool) -> String {
+    register(app, "admin").await;
+    assert_eq!(
+        cli::grant_admin(pool, "admin@example.com"),
+        ExitCode::SUCCESS
+    );
+
+    let (_, body) = send(
+        app,
+        post_json(
+            "/api/auth/login",
+            json!({ "email": "admin@example.com", "password": "password123" }),
+        ),
+    )
+    .await;
+    json_field(&body, "access_token")
+}
+
+fn admin_request(method: Method, uri: &str, token: &str, body: Option<Value>) -> Request<Body> {
+    let request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("authorization", format!("Bearer {}", token));
+    match body {
+        Some(body) => request
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap(),
+        None => request.body(Body::empty()).unwrap(),
+    }
+}
+
+/// Register an endpoint and return its id and secret
+async fn create_webhook(
+    app: &Router,
+    token: &str,
+    addr: SocketAddr,
+    events: &[&str],
+) -> (i64, String) {
+    let (status, body) = send(
+        app,
+        admin_request(
+            Method::POST,
+            "/api/admin/webhooks",
+            token,
+            Some(json!({ "url": format!("http://{}/hook", addr), "events": events })),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED, "{}", body);
+    let created: Value = serde_json::from_str(&body).unwrap();
+    let secret = created["secret"].as_str().unwrap().to_string();
+    assert!(secret.starts_with("whsec_"));
+    (created["endpoint"]["id"].as_i64().unwrap(), secret)
+}
+
+async fn delivery_page(app: &Router, token: &str, webhook: i64) -> Value {
+    let uri = format!("/api/admin/webhooks/{}/deliveries", webhook);
+    let (status, body) = send(app, get_with_bearer(&uri, token)).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    serde_json::from_str(&body).unwrap()
+}
+
+/// Poll a delivery until its status is `expected`
+async fn wait_for_delivery(app: &Router, token: &str, id: i64, expected: &str) -> Value {
+    let uri = format!("/api/admin/webhooks/deliveries/{}", id);
+    let mut detail = Value::Null;
+    for _ in 0..250 {
+        let (status, body) = send(app, get_with_bearer(&uri, token)).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        detail = serde_json::from_str(&body).unwrap();
+        if detail["delivery"]["status"] == expected {
+            return detail;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("Delivery {} never became {}: {}", id, expected, detail);
+}
+
+#[tokio::test]
+async fn test_subscribed_events_are_delivered_signed() {
+    let db = test_db();
+    let (receiver, addr) = Receiver::start(&[]).await;
+    let (app, workers) = start_app(&db.pool, db.config.clone());
+    let token = admin_token(&app, &db.pool).await;
+    let (webhook, secret) = create_webhook(&app, &token, addr, &["user.registered"]).await;
+
+    register(&app, "alice").await;
+    let hits = receiver.wait_for(1).await;
+    let alice = hits[0].json();
+    assert_eq!(alice["type"], "user.registered");
+    assert_eq!(alice["data"]["user"]["email"], "alice@example.com");
+    assert!(alice["data"]["user"].get("password_hash").is_none());
+    assert_eq!(hits[0].header("webhook-id"), alice["id"]);
+    assert_eq!(hits[0].header("webhook-event"), "user.registered");
+    assert_eq!(hits[0].header("content-type"), "application/json");
+
+    let header = hits[0].header("webhook-signature");
+    let now = chrono::Utc::now().timestamp();
+    let tolerance = Duration::from_secs(300);
+    assert_eq!(
+        signature::verify(&secret, header, &hits[0].body, now, tolerance),
+        Ok(())
+    );
+    assert!(signature::verify("whsec_wrong", header, &hits[0].body, now, tolerance).is_err());
+
+    // Not subscribed, so nothing is recorded for this endpoint
+    let alice_id = alice["data"]["user"]["id"].as_i64().unwrap();
+    let uri = format!("/api/admin/users/{}", alice_id);
+    let (status, _) = send(&app, admin_request(Method::DELETE, &uri, &token, None)).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let page = delivery_page(&app, &token, webhook).await;
+    assert_eq!(page["total"], 1);
+    let delivery = page["deliveries"][0]["id"].as_i64().unwrap();
+    wait_for_delivery(&app, &token, delivery, "succeeded").await;
+    workers.shutdown(Duration::from_secs(5)).await;
+    assert_eq!(receiver.hits().len(), 1);
+}
+
+#[tokio::test]
+async fn test_failed_deliveries_are_retried_and_every_attempt_logged() {
+    let db = test_db();
+    let failures = [
+        StatusCode::INTERNAL_SERVER_ERROR,
+        StatusCode::SERVICE_UNAVAILABLE,
+    ];
+    let (receiver, addr) = Receiver::start(&failures).await;
+    let (app, workers) = start_app(&db.pool, db.config.clone());
+    let token = admin_token(&app, &db.pool).await;
+    let (webhook, _) = create_webhook(&app, &token, addr, &["user.registered"]).await;
+
+    register(&app, "alice").await;
+    let hits = receiver.wait_for(3).await;
+    assert!(hits
+        .iter()
+        .all(|hit| hit.header("webhook-id") == hits[0].header("webhook-id")));
+    assert!(hits.iter().all(|hit| hit.body == hits[0].body));
+
+    let page = delivery_page(&app, &token, webhook).await;
+    let delivery = page["deliveries"][0]["id"].as_i64().unwrap();
+    let detail = wait_for_delivery(&app, &token, delivery, "succeeded").await;
+    workers.shutdown(Duration::from_secs(5)).await;
+
+    assert_eq!(detail["delivery"]["attempts"], 3);
+    let attempts = detail["attempts"].as_array().unwrap();
+    let statuses: Vec<_> = attempts
+        .iter()
+        .map(|a| a["response_status"].clone())
+        .collect();
+    assert_eq!(statuses, [json!(500), json!(503), json!(200)]);
+    assert_eq!(attempts[0]["succeeded"], false);
+    assert_eq!(attempts[2]["succeeded"], true);
+
+    let uri = format!("/api/admin/webhooks/{}/deliveries?status=failed", webhook);
+    let (_, body) = send(&app, get_with_bearer(&uri, &token)).await;
+    let failed: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(failed["total"], 0);
+}
+
+#[tokio::test]
+async fn test_unreachable_endpoints_record_the_error() {
+    let db = test_db();
+    let (app, workers) = start_app(&db.pool, db.config.clone());
+    let token = admin_token(&app, &db.pool).await;
+    // Nothing listens on port 9 locally
+    let addr: SocketAddr = "127.0.0.1:9".parse().unwrap();
+    let (webhook, _) = create_webhook(&app, &token, addr, &["user.registered"]).await;
+
+    register(&app, "alice").await;
+    let page = delivery_page(&app, &token, webhook).await;
+    let delivery = page["deliveries"][0]["id"].as_i64().unwrap();
+    let detail = wait_for_delivery(&app, &token, delivery, "failed").await;
+    workers.shutdown(Duration::from_secs(5)).await;
+
+    let attempt = &detail["attempts"][0];
+    assert_eq!(attempt["response_status"], Value::Null);
+    assert!(
+        !attempt["error"].as_str().unwrap().is_empty(),
+        "{}",
+        attempt
+    );
+}
+
+#[tokio::test]
+async fn test_admin_can_redeliver() {
+    let db = test_db();
+    let (receiver, addr) = Receiver::start(&[]).await;
+    let (app, workers) = start_app(&db.pool, db.config.clone());
+    let token = admin_token(&app, &db.pool).await;
+    let (webhook, _) = create_webhook(&app, &token, addr, &["user.registered"]).await;
+
+    register(&app, "alice").await;
+    receiver.wait_for(1).await;
+    let page = delivery_page(&app, &token, webhook).await;
+    let delivery = page["deliveries"][0]["id"].as_i64().unwrap();
+    wait_for_delivery(&app, &token, delivery, "succeeded").await;
+
+    let uri = format!("/api/admin/webhooks/deliveries/{}/redeliver", delivery);
+    let (status, body) = send(&app, admin_request(Method::POST, &uri, &token, None)).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(json_field(&body, "status"), "pending");
+
+    let hits = receiver.wait_for(2).await;
+    assert_eq!(hits[1].header("webhook-id"), hits[0].header("webhook-id"));
+    assert_eq!(hits[1].body, hits[0].body);
+    assert_ne!(hits[1].header("webhook-signature"), "");
+    let detail = wait_for_delivery(&app, &token, delivery, "succeeded").await;
+    assert_eq!(detail["attempts"].as_array().unwrap().len(), 2);
+    workers.shutdown(Duration::from_secs(5)).await;
+
+    let uri = "/api/admin/webhooks/deliveries/9999/redeliver";
+    let (status, _) = send(&app, admin_request(Method::POST, uri, &token, None)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_registration_is_validated_and_admin_only() {
+    let db = test_db();
+    let (app, _workers) = start_app(&db.pool, db.config.clone());
+    let token = admin_token(&app, &db.pool).await;
+
+    let (status, body) = send(&app, get_with_bearer("/api/admin/webhooks/events", &token)).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let events: Vec<Value> = serde_json::from_str(&body).unwrap();
+    assert!(events
+        .iter()
+        .any(|event| event["name"] == "user.email_changed"));
+
+    for invalid in [
+        json!({ "url": "ftp://example.com/hook", "events": ["user.registered"] }),
+        json!({ "url": "not a url", "events": ["user.registered"] }),
+        json!({ "url": "https://example.com/hook", "events": [] }),
+        json!({ "url": "https://example.com/hook", "events": ["user.exploded"] }),
+    ] {
+        let request = admin_request(Method::POST, "/api/admin/webhooks", &token, Some(invalid));
+        let (status, body) = send(&app, request).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{}", body);
+    }
+
+    let user = register(&app, "mallory").await;
+    let body = json!({ "url": "https://example.com/hook", "events": ["user.registered"] });
+    let request = admin_request(Method::POST, "/api/admin/webhooks", &user, Some(body));
+    let (status, _) = send(&app, request).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, body) = send(&app, get_with_bearer("/api/admin/webhooks", &token)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "[]");
+}
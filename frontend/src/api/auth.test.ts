@@ -0,0 +1,82 @@
+import { describe, it, expect, vi, beforeEach } from 'vitest'
+import { ApiError, AuthAPI } from './auth'
+
+const mockFetch = vi.fn()
+global.fetch = mockFetch
+
+function problemResponse(body: object, status: number) {
+  return new Response(JSON.stringify(body), {
+    status,
+    headers: { 'Content-Type': 'application/problem+json' },
+  })
+}
+
+describe('AuthAPI errors', () => {
+  beforeEach(() => {
+    mockFetch.mockReset()
+  })
+
+  it('should throw ApiError with the problem code', async () => {
+    mockFetch.mockResolvedValueOnce(
+      problemResponse(
+        {
+          type: 'urn:problem-type:invalid_credentials',
+          title: 'Unauthorized',
+          status: 401,
+          detail: 'Invalid email or password',
+          code: 'invalid_credentials',
+        },
+        401
+      )
+    )
+
+    const error = await AuthAPI.login({ email: 'a@example.com', password: 'wrong' }).catch(
+      (e) => e
+    )
+
+    expect(error).toBeInstanceOf(ApiError)
+    expect(error.status).toBe(401)
+    expect(error.code).toBe('invalid_credentials')
+    expect(error.message).toBe('Invalid email or password')
+  })
+
+  it('should expose per-field validation errors', async () => {
+    mockFetch.mockResolvedValueOnce(
+      problemResponse(
+        {
+          type: 'urn:problem-type:validation_failed',
+          title: 'Unprocessable Entity',
+          status: 422,
+          detail: 'Validation failed',
+          code: 'validation_failed',
+          errors: [
+            { field: 'email', code: 'email', message: 'Invalid email address' },
+            { field: 'password', code: 'length', message: 'Password must be at least 8 characters' },
+          ],
+        },
+        422
+      )
+    )
+
+    const error = await AuthAPI.register({
+      username: 'bob',
+      email: 'nope',
+      password: 'short',
+    }).catch((e) => e)
+
+    expect(error.code).toBe('validation_failed')
+    expect(error.fieldErrors.map((e: { field: string }) => e.field)).toEqual(['email', 'password'])
+    expect(error.message).toBe('Invalid email address. Password must be at least 8 characters')
+  })
+
+  it('should fall back to the text body for non-problem responses', async () => {
+    mockFetch.mockResolvedValueOnce(new Response('Bad Gateway', { status: 502 }))
+
+    const error = await AuthAPI.me().catch((e) => e)
+
+    expect(error).toBeInstanceOf(ApiError)
+    expect(error.status).toBe(502)
+    expect(error.code).toBe('unknown')
+    expect(error.message).toBe('Bad Gateway')
+  })
+})
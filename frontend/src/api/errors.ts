@@ -0,0 +1,34 @@
+import type { FieldError, ProblemDetails } from './generated'
+
+/** Error thrown by the API client when the backend rejects a request */
+export class ApiError extends Error {
+  readonly status: number
+  readonly code: string
+  readonly fieldErrors: FieldError[]
+
+  constructor(problem: ProblemDetails) {
+    const fieldMessages = problem.errors?.map((e) => e.message) ?? []
+    super(fieldMessages.length > 0 ? fieldMessages.join('. ') : problem.detail)
+    this.name = 'ApiError'
+    this.status = problem.status
+    this.code = problem.code
+    this.fieldErrors = problem.errors ?? []
+  }
+
+  static async fromResponse(response: Response): Promise<ApiError> {
+    const contentType = response.headers.get('Content-Type') ?? ''
+    if (contentType.startsWith('application/problem+json')) {
+      return new ApiError(await response.json())
+    }
+
+    // Proxies and the dev server can still answer with plain text
+    const text = await response.text()
+    return new ApiError({
+      type: 'about:blank',
+      title: response.statusText,
+      status: response.status,
+      detail: text || `HTTP ${response.status}`,
+      code: 'unknown',
+    })
+  }
+}
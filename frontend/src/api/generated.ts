@@ -0,0 +1,354 @@
+// This file is generated from the backend models and routes.
+// Do not edit it by hand; run `cargo run -- codegen` in `backend/` instead.
+
+import { ApiError } from './errors'
+
+export type ApiResponse = { message: string, timestamp: number, };
+
+export type RegisterRequest = { username: string, email: string, password: string, };
+
+export type UpdateProfileRequest = { username?: string, email?: string, };
+
+export type LoginRequest = { email: string, password: string, };
+
+export type RefreshRequest = { refresh_token?: string, };
+
+export type UserResponse = { id: number, username: string, email: string, created_at: string, };
+
+export type AuthResponse = { user: UserResponse, access_token: string, 
+/**
+ * Left out when the refresh token was set as a cookie instead
+ */
+refresh_token?: string, };
+
+export type CsrfTokenResponse = { token: string, 
+/**
+ * Header that must carry the token, e.g. `x-csrf-token`
+ */
+header: string, };
+
+export type FieldError = { field: string, code: string, message: string, };
+
+export type ProblemDetails = { type: string, title: string, status: number, detail: string, code: string, errors?: Array<FieldError>, };
+
+export type AuditEventResponse = { id: number, occurred_at: string, actor_user_id: number | null, action: string, target: string | null, ip_address: string | null, user_agent: string | null, outcome: string, metadata: Record<string, unknown>, hash: string, };
+
+export type AuditEventPage = { events: Array<AuditEventResponse>, page: number, per_page: number, total: number, };
+
+export type ChainBreak = "prev_hash_mismatch" | "hash_mismatch";
+
+export type ChainVerification = { "status": "intact", events: number, head_hash: string, } | { "status": "broken", event_id: number, reason: ChainBreak, };
+
+export type PoolStats = { max_size: number, 
+/**
+ * Open connections, busy or idle
+ */
+connections: number, idle_connections: number, 
+/**
+ * Callers currently queued for a connection
+ */
+waiting: number, 
+/**
+ * Connections handed out
+ */
+checkouts: number, 
+/**
+ * Checkouts that gave up after the pool's connection timeout
+ */
+timeouts: number, 
+/**
+ * Mean time from request to connection, in milliseconds
+ */
+wait_ms_avg: number, 
+/**
+ * Longest time from request to connection, in milliseconds
+ */
+wait_ms_max: number, };
+
+export type DbStats = { writer: PoolStats, reader: PoolStats, };
+
+export type JobStatus = { name: string, 
+/**
+ * Cron expression, seconds first
+ */
+schedule: string, running: boolean, next_run_at: string | null, last_started_at: string | null, last_finished_at: string | null, 
+/**
+ * Whether the last run succeeded; `None` before the first run
+ */
+last_succeeded: boolean | null, 
+/**
+ * Summary or error message of the last run
+ */
+last_message: string | null, last_duration_ms: number, runs: number, failures: number, 
+/**
+ * Ticks skipped because the previous run was still going
+ */
+skipped: number, };
+
+export type JobState = "pending" | "running" | "succeeded" | "dead";
+
+export type JobResponse = { id: number, kind: string, payload: unknown, status: string, attempts: number, max_attempts: number, 
+/**
+ * When the job is next due, if pending
+ */
+run_at: string, last_error: string | null, unique_key: string | null, created_at: string, updated_at: string, finished_at: string | null, };
+
+export type JobPage = { jobs: Array<JobResponse>, page: number, per_page: number, total: number, };
+
+export type WebhookEventInfo = { name: string, description: string, };
+
+export type CreateWebhookRequest = { 
+/**
+ * Receives a `POST` for every subscribed event
+ */
+url: string, description?: string, 
+/**
+ * Event types from `GET /api/admin/webhooks/events`
+ */
+events: Array<string>, };
+
+export type WebhookEndpointResponse = { id: number, url: string, description: string, events: Array<string>, active: boolean, created_at: string, updated_at: string, };
+
+export type CreatedWebhookResponse = { endpoint: WebhookEndpointResponse, 
+/**
+ * Key for verifying the `Webhook-Signature` header
+ */
+secret: string, };
+
+export type DeliveryStatus = "pending" | "succeeded" | "failed";
+
+export type WebhookDeliveryResponse = { id: number, endpoint_id: number, event_id: string, event_type: string, payload: unknown, status: string, attempts: number, last_attempt_at: string | null, created_at: string, updated_at: string, };
+
+export type WebhookDeliveryPage = { deliveries: Array<WebhookDeliveryResponse>, page: number, per_page: number, total: number, };
+
+export type WebhookAttemptResponse = { id: number, attempted_at: string, succeeded: boolean, 
+/**
+ * HTTP status, if the endpoint answered
+ */
+response_status: number | null, 
+/**
+ * Start of the response body
+ */
+response_body: string | null, 
+/**
+ * Why no response arrived, e.g. a timeout
+ */
+error: string | null, duration_ms: number, };
+
+export type WebhookDeliveryDetail = { delivery: WebhookDeliveryResponse, attempts: Array<WebhookAttemptResponse>, };
+
+export type QueryValue = string | number | boolean | null | undefined
+
+export interface ClientOptions {
+  /** Prefix for every request path, e.g. `https://api.example.com` */
+  baseUrl?: string
+  /** Alternative `fetch` implementation; defaults to the global one */
+  fetch?: typeof fetch
+  /** Extra headers sent with every request */
+  headers?: Record<string, string>
+}
+
+interface RequestOptions {
+  body?: unknown
+  query?: Record<string, QueryValue>
+  headers?: Record<string, string>
+  /** Send the CSRF token, for unsafe requests authenticated by the cookie */
+  csrf?: boolean
+}
+
+export class ApiClient {
+  private readonly options: ClientOptions
+  private csrf?: Promise<CsrfTokenResponse>
+
+  constructor(options: ClientOptions = {}) {
+    this.options = options
+  }
+
+  private async request<T>(
+    method: string,
+    path: string,
+    init: RequestOptions = {},
+    retry = true,
+  ): Promise<T> {
+    if (!init.csrf) {
+      return this.send(method, path, init)
+    }
+
+    this.csrf ??= this.send<CsrfTokenResponse>('GET', '/api/auth/csrf')
+    const { token, header } = await this.csrf.catch((error) => {
+      this.csrf = undefined
+      throw error
+    })
+    try {
+      const headers = { ...init.headers, [header]: token }
+      return await this.send(method, path, { ...init, headers })
+    } catch (error) {
+      // The cookie expired or was cleared: fetch a new token and retry once
+      if (!(error instanceof ApiError) || error.code !== 'csrf_failed') {
+        throw error
+      }
+      this.csrf = undefined
+      if (!retry) {
+        throw error
+      }
+      return this.request(method, path, init, false)
+    }
+  }
+
+  private async send<T>(method: string, path: string, init: RequestOptions = {}): Promise<T> {
+    const params = new URLSearchParams()
+    for (const [key, value] of Object.entries(init.query ?? {})) {
+      if (value !== undefined && value !== null) {
+        params.append(key, String(value))
+      }
+    }
+    const search = params.toString()
+
+    const doFetch = this.options.fetch ?? fetch
+    const response = await doFetch(`${this.options.baseUrl ?? ''}${path}${search ? `?${search}` : ''}`, {
+      method,
+      headers: {
+        'Content-Type': 'application/json',
+        ...this.options.headers,
+        ...init.headers,
+      },
+      body: init.body === undefined ? undefined : JSON.stringify(init.body),
+      credentials: 'include',
+    })
+
+    if (!response.ok) {
+      throw await ApiError.fromResponse(response)
+    }
+    if (response.status === 204) {
+      return undefined as T
+    }
+
+    const contentType = response.headers.get('Content-Type') ?? ''
+    return (contentType.includes('json') ? response.json() : response.text()) as Promise<T>
+  }
+
+  /** List audit events, newest first, with optional filters */
+  listAuditEvents(query: { actor_user_id?: number; action?: string; target?: string; outcome?: string; since?: string; until?: string; page?: number; per_page?: number } = {}): Promise<AuditEventPage> {
+    return this.request('GET', `/api/admin/audit-events`, { query })
+  }
+
+  /** Walk the audit hash chain and report whether it has been tampered with */
+  verifyAuditEvents(): Promise<ChainVerification> {
+    return this.request('GET', `/api/admin/audit-events/verify`)
+  }
+
+  /** Report database connection pool usage and checkout wait times */
+  poolStats(): Promise<DbStats> {
+    return this.request('GET', `/api/admin/db/pool`)
+  }
+
+  /** List queued jobs, newest first, with optional filters */
+  listJobs(query: { status?: JobState; kind?: string; page?: number; per_page?: number } = {}): Promise<JobPage> {
+    return this.request('GET', `/api/admin/jobs`, { query })
+  }
+
+  /** Show one queued job, including its last error */
+  getJob(id: number): Promise<JobResponse> {
+    return this.request('GET', `/api/admin/jobs/${encodeURIComponent(String(id))}`)
+  }
+
+  /** Run a dead job again with a fresh set of attempts */
+  retryJob(id: number): Promise<JobResponse> {
+    return this.request('POST', `/api/admin/jobs/${encodeURIComponent(String(id))}/retry`, { csrf: true })
+  }
+
+  /** Report the last run and next run of every scheduled job */
+  scheduledJobs(): Promise<Array<JobStatus>> {
+    return this.request('GET', `/api/admin/scheduler`)
+  }
+
+  /** Soft-delete a user and revoke their sessions */
+  deleteUser(id: number): Promise<UserResponse> {
+    return this.request('DELETE', `/api/admin/users/${encodeURIComponent(String(id))}`, { csrf: true })
+  }
+
+  /** Restore a soft-deleted user */
+  restoreUser(id: number): Promise<UserResponse> {
+    return this.request('POST', `/api/admin/users/${encodeURIComponent(String(id))}/restore`, { csrf: true })
+  }
+
+  /** List registered endpoints */
+  listWebhooks(): Promise<Array<WebhookEndpointResponse>> {
+    return this.request('GET', `/api/admin/webhooks`)
+  }
+
+  /** Register an endpoint; the response holds its signing secret */
+  createWebhook(body: CreateWebhookRequest): Promise<CreatedWebhookResponse> {
+    return this.request('POST', `/api/admin/webhooks`, { body, csrf: true })
+  }
+
+  /** Show a delivery with every attempt made for it */
+  getDelivery(id: number): Promise<WebhookDeliveryDetail> {
+    return this.request('GET', `/api/admin/webhooks/deliveries/${encodeURIComponent(String(id))}`)
+  }
+
+  /** Send a delivery again with its original event ID and payload */
+  redeliver(id: number): Promise<WebhookDeliveryResponse> {
+    return this.request('POST', `/api/admin/webhooks/deliveries/${encodeURIComponent(String(id))}/redeliver`, { csrf: true })
+  }
+
+  /** List the events endpoints can subscribe to */
+  listEvents(): Promise<Array<WebhookEventInfo>> {
+    return this.request('GET', `/api/admin/webhooks/events`)
+  }
+
+  /** Remove an endpoint along with its delivery log */
+  deleteWebhook(id: number): Promise<WebhookEndpointResponse> {
+    return this.request('DELETE', `/api/admin/webhooks/${encodeURIComponent(String(id))}`, { csrf: true })
+  }
+
+  /** List an endpoint's deliveries, newest first, with optional filters */
+  listDeliveries(id: number, query: { status?: DeliveryStatus; event_type?: string; page?: number; per_page?: number } = {}): Promise<WebhookDeliveryPage> {
+    return this.request('GET', `/api/admin/webhooks/${encodeURIComponent(String(id))}/deliveries`, { query })
+  }
+
+  /** Get a CSRF token */
+  csrfToken(): Promise<CsrfTokenResponse> {
+    return this.request('GET', `/api/auth/csrf`)
+  }
+
+  /** Login with email and password */
+  login(body: LoginRequest): Promise<AuthResponse> {
+    return this.request('POST', `/api/auth/login`, { body })
+  }
+
+  /** Logout - invalidate refresh token */
+  logout(body: RefreshRequest): Promise<void> {
+    return this.request('POST', `/api/auth/logout`, { body, csrf: true })
+  }
+
+  /** Get current authenticated user */
+  me(): Promise<UserResponse> {
+    return this.request('GET', `/api/auth/me`)
+  }
+
+  /** Update the current user's profile */
+  updateMe(ifMatch: string, body: UpdateProfileRequest): Promise<UserResponse> {
+    return this.request('PATCH', `/api/auth/me`, { body, headers: { 'If-Match': ifMatch }, csrf: true })
+  }
+
+  /** Refresh access token using refresh token */
+  refresh(body: RefreshRequest): Promise<AuthResponse> {
+    return this.request('POST', `/api/auth/refresh`, { body, csrf: true })
+  }
+
+  /** Register a new user */
+  register(body: RegisterRequest): Promise<AuthResponse> {
+    return this.request('POST', `/api/auth/register`, { body })
+  }
+
+  /** Example endpoint returning a greeting and the server time */
+  hello(): Promise<ApiResponse> {
+    return this.request('GET', `/api/hello`)
+  }
+
+  /** Health check */
+  healthCheck(): Promise<string> {
+    return this.request('GET', `/health`)
+  }
+}
@@ -17,9 +17,6 @@ export function AuthProvider({ children }: { children: ReactNode }) {
   const [user, setUser] = useState<User | null>(null)
   const [loading, setLoading] = useState(true)
   const [error, setError] = useState<string | null>(null)
-  const [refreshToken, setRefreshToken] = useState<string | null>(
-    localStorage.getItem('refresh_token')
-  )
 
   // Check if user is authenticated on mount
   useEffect(() => {
@@ -28,18 +25,12 @@ export function AuthProvider({ children }: { children: ReactNode }) {
         const currentUser = await AuthAPI.me()
         setUser(currentUser)
       } catch (err) {
-        // If token is invalid, try to refresh
-        if (refreshToken) {
-          try {
-            const response = await AuthAPI.refresh(refreshToken)
-            setUser(response.user)
-            localStorage.setItem('refresh_token', response.refresh_token)
-            setRefreshToken(response.refresh_token)
-          } catch (refreshErr) {
-            // Refresh failed, clear everything
-            localStorage.removeItem('refresh_token')
-            setRefreshToken(null)
-          }
+        // If token is invalid, try to refresh with the refresh token cookie
+        try {
+          const response = await AuthAPI.refresh()
+          setUser(response.user)
+        } catch (refreshErr) {
+          // No session to resume
         }
       } finally {
         setLoading(false)
@@ -55,8 +46,6 @@ export function AuthProvider({ children }: { children: ReactNode }) {
       setLoading(true)
       const response = await AuthAPI.login(data)
       setUser(response.user)
-      localStorage.setItem('refresh_token', response.refresh_token)
-      setRefreshToken(response.refresh_token)
     } catch (err) {
       setError(err instanceof Error ? err.message : 'Login failed')
       throw err
@@ -71,8 +60,6 @@ export function AuthProvider({ children }: { children: ReactNode }) {
       setLoading(true)
       const response = await AuthAPI.register(data)
       setUser(response.user)
-      localStorage.setItem('refresh_token', response.refresh_token)
-      setRefreshToken(response.refresh_token)
     } catch (err) {
       setError(err instanceof Error ? err.message : 'Registration failed')
       throw err
@@ -83,15 +70,11 @@ export function AuthProvider({ children }: { children: ReactNode }) {
 
   const logout = async () => {
     try {
-      if (refreshToken) {
-        await AuthAPI.logout(refreshToken)
-      }
+      await AuthAPI.logout()
     } catch (err) {
       console.error('Logout error:', err)
     } finally {
       setUser(null)
-      localStorage.removeItem('refresh_token')
-      setRefreshToken(null)
     }
   }
 